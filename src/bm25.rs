@@ -1,14 +1,20 @@
-use crate::indexer::CodeChunk;
+use crate::indexer::{normalized_identifiers, CodeChunk, ItemKind};
 
 use anyhow::{anyhow, Result};
 
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tantivy::collector::TopDocs;
+use tantivy::collector::{DocSetCollector, TopDocs};
 use tantivy::query::QueryParser;
 use tantivy::schema::*;
-use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use tantivy::{
+    Index, IndexReader, IndexSettings, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+};
+
+/// Boost applied to normalized identifier matches relative to plain keyword matches.
+const IDENTIFIER_BOOST: f32 = 2.0;
 
 /// Full-text search index using the BM25 ranking algorithm.
 ///
@@ -39,6 +45,16 @@ pub struct BM25Index {
     line_start_field: Field,
     line_end_field: Field,
     workspace_field: Field,
+    /// Normalized identifiers; absent in indexes created before the field existed
+    identifiers_field: Option<Field>,
+    /// Symbol name of each chunk; absent in indexes created before the field existed
+    symbol_field: Option<Field>,
+    /// Enclosing scope of each chunk, one value per name; absent in older indexes
+    scope_field: Option<Field>,
+    /// Item kind of each chunk (`function`, `class`, ...); absent in older indexes
+    item_kind_field: Option<Field>,
+    is_test_field: Option<Field>,
+    normalize_identifiers: bool,
 }
 
 /// A single search result from the BM25 index.
@@ -56,6 +72,14 @@ pub struct BM25Result {
     pub line_start: u64,
     /// Ending line number
     pub line_end: u64,
+    /// Name of the item the chunk defines, if recorded
+    pub symbol: Option<String>,
+    /// Enclosing classes, impls and namespaces, outermost first
+    pub scope: Vec<String>,
+    /// Function, class, statement or other item, if recorded
+    pub item_kind: Option<ItemKind>,
+    /// Whether the chunk comes from a test file, if recorded
+    pub is_test: Option<bool>,
     /// BM25 relevance score (higher is better)
     pub score: f32,
 }
@@ -81,13 +105,20 @@ impl BM25Index {
         schema_builder.add_u64_field("line_start", STORED);
         schema_builder.add_u64_field("line_end", STORED);
         schema_builder.add_text_field("workspace", STRING | STORED); // Workspace isolation
-
-        let schema = schema_builder.build();
-
-        let index = Index::open_or_create(
-            tantivy::directory::MmapDirectory::open(&index_path)?,
-            schema.clone(),
-        )?;
+        schema_builder.add_text_field("identifiers", TEXT); // Normalized identifiers
+        schema_builder.add_text_field("symbol", TEXT | STORED); // Defined item name
+        schema_builder.add_text_field("scope", STORED); // Enclosing containers
+        schema_builder.add_text_field("item_kind", STRING | STORED); // function, class, ...
+        schema_builder.add_bool_field("is_test", STORED); // From a test file
+
+        // Existing indexes keep the schema they were created with
+        let directory = tantivy::directory::MmapDirectory::open(&index_path)?;
+        let index = if Index::exists(&directory)? {
+            Index::open(directory)?
+        } else {
+            Index::create(directory, schema_builder.build(), IndexSettings::default())?
+        };
+        let schema = index.schema();
 
         let writer = if readonly {
             None
@@ -133,6 +164,11 @@ impl BM25Index {
         let line_start_field = schema.get_field("line_start")?;
         let line_end_field = schema.get_field("line_end")?;
         let workspace_field = schema.get_field("workspace")?;
+        let identifiers_field = schema.get_field("identifiers").ok();
+        let symbol_field = schema.get_field("symbol").ok();
+        let scope_field = schema.get_field("scope").ok();
+        let item_kind_field = schema.get_field("item_kind").ok();
+        let is_test_field = schema.get_field("is_test").ok();
 
         Ok(Self {
             index,
@@ -145,9 +181,34 @@ impl BM25Index {
             line_start_field,
             line_end_field,
             workspace_field,
+            identifiers_field,
+            symbol_field,
+            scope_field,
+            item_kind_field,
+            is_test_field,
+            normalize_identifiers: false,
         })
     }
 
+    /// Enables indexing and searching normalized identifiers, so `get_user_name`
+    /// matches a chunk defining `getUserName`.
+    ///
+    /// Indexes created before identifiers were supported must be rebuilt (`index --force`).
+    pub fn with_normalized_identifiers(mut self, enabled: bool) -> Self {
+        if enabled && self.identifiers_field.is_none() {
+            tracing::warn!(
+                "BM25 index has no identifiers field; re-index with --force to enable normalized identifiers"
+            );
+        }
+        self.normalize_identifiers = enabled;
+        self
+    }
+
+    fn active_identifiers_field(&self) -> Option<Field> {
+        self.identifiers_field
+            .filter(|_| self.normalize_identifiers)
+    }
+
     /// Indexes code chunks with workspace isolation.
     ///
     /// Deletes existing chunks with the same ID to prevent duplicates.
@@ -184,6 +245,23 @@ impl BM25Index {
             doc.add_u64(line_start_field, chunk.line_start as u64);
             doc.add_u64(line_end_field, chunk.line_end as u64);
             doc.add_text(workspace_field, workspace);
+            if let Some(field) = self.active_identifiers_field() {
+                doc.add_text(field, normalized_identifiers(&chunk.code).join(" "));
+            }
+            if let Some((field, symbol)) = self.symbol_field.zip(chunk.symbol.as_ref()) {
+                doc.add_text(field, symbol);
+            }
+            if let Some(field) = self.scope_field {
+                for name in &chunk.scope {
+                    doc.add_text(field, name);
+                }
+            }
+            if let Some(field) = self.item_kind_field {
+                doc.add_text(field, chunk.item_kind.as_str());
+            }
+            if let Some(field) = self.is_test_field {
+                doc.add_bool(field, chunk.is_test);
+            }
 
             writer.add_document(doc)?;
         }
@@ -272,6 +350,79 @@ impl BM25Index {
         Ok(())
     }
 
+    /// Filenames of the name-only asset entries (`CodeChunk::asset`) in `workspace`.
+    ///
+    /// Assets are the only documents at line 0, so they are found by their id suffix.
+    pub fn asset_files(&self, workspace: &str) -> Result<HashSet<String>> {
+        let searcher = self.reader.searcher();
+        let query = tantivy::query::BooleanQuery::new(vec![
+            (
+                tantivy::query::Occur::Must,
+                Box::new(tantivy::query::RegexQuery::from_pattern(
+                    ".*-0-0",
+                    self.id_field,
+                )?),
+            ),
+            (
+                tantivy::query::Occur::Must,
+                Box::new(tantivy::query::TermQuery::new(
+                    Term::from_field_text(self.workspace_field, workspace),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+        ]);
+
+        let mut files = HashSet::new();
+        for doc_address in searcher.search(&query, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            if let Some(filename) = doc.get_first(self.filename_field).and_then(|v| v.as_str()) {
+                files.insert(filename.to_string());
+            }
+        }
+        Ok(files)
+    }
+
+    /// Deletes the asset entries of `filenames`, leaving any chunks of those files.
+    ///
+    /// **Note**: This method does NOT commit changes. Caller must call `commit()` when done.
+    pub fn delete_assets(&self, filenames: &[String], workspace: &str) -> Result<()> {
+        if filenames.is_empty() {
+            return Ok(());
+        }
+
+        let writer_arc = self
+            .writer
+            .as_ref()
+            .ok_or(anyhow::anyhow!("Index is read-only"))?;
+        let writer = writer_arc
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock poisoned: {}", e))?;
+
+        let workspace_term = Term::from_field_text(self.workspace_field, workspace);
+        for filename in filenames {
+            let id_term = Term::from_field_text(self.id_field, &format!("{}-0-0", filename));
+            let query = tantivy::query::BooleanQuery::new(vec![
+                (
+                    tantivy::query::Occur::Must,
+                    Box::new(tantivy::query::TermQuery::new(
+                        id_term,
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+                (
+                    tantivy::query::Occur::Must,
+                    Box::new(tantivy::query::TermQuery::new(
+                        workspace_term.clone(),
+                        IndexRecordOption::Basic,
+                    )),
+                ),
+            ]);
+            writer.delete_query(Box::new(query))?;
+        }
+
+        Ok(())
+    }
+
     /// Commits all pending write operations to disk.
     ///
     /// This is an expensive I/O operation that flushes the entire write buffer.
@@ -337,6 +488,20 @@ impl BM25Index {
         query_str: &str,
         limit: usize,
         workspace: Option<&str>,
+    ) -> Result<Vec<BM25Result>> {
+        self.search_in_file(query_str, limit, workspace, None)
+    }
+
+    /// Like [`search`](Self::search), restricted to the file `path` names.
+    ///
+    /// `path` matches a stored filename exactly or as a trailing path, like
+    /// `CodeSearcher::with_in_file`, so the limit applies to hits from that file.
+    pub fn search_in_file(
+        &self,
+        query_str: &str,
+        limit: usize,
+        workspace: Option<&str>,
+        path: Option<&str>,
     ) -> Result<Vec<BM25Result>> {
         let searcher = self.reader.searcher();
         let id_field = self.id_field;
@@ -346,9 +511,30 @@ impl BM25Index {
         let line_end_field = self.line_end_field;
         let workspace_field = self.workspace_field;
 
-        let query_parser = QueryParser::for_index(&self.index, vec![code_field, filename_field]);
+        let mut fields = vec![code_field, filename_field];
+        fields.extend(self.symbol_field);
+        let query_parser = QueryParser::for_index(&self.index, fields);
         let mut query = query_parser.parse_query(query_str)?;
 
+        if let Some(field) = self.active_identifiers_field() {
+            let mut clauses: Vec<(tantivy::query::Occur, Box<dyn tantivy::query::Query>)> =
+                vec![(tantivy::query::Occur::Should, query)];
+            for ident in normalized_identifiers(query_str) {
+                let term_query = tantivy::query::TermQuery::new(
+                    Term::from_field_text(field, &ident),
+                    IndexRecordOption::WithFreqs,
+                );
+                clauses.push((
+                    tantivy::query::Occur::Should,
+                    Box::new(tantivy::query::BoostQuery::new(
+                        Box::new(term_query),
+                        IDENTIFIER_BOOST,
+                    )),
+                ));
+            }
+            query = Box::new(tantivy::query::BooleanQuery::new(clauses));
+        }
+
         if let Some(ws) = workspace {
             let term = Term::from_field_text(workspace_field, ws);
             let term_query = tantivy::query::TermQuery::new(term, IndexRecordOption::Basic);
@@ -358,6 +544,17 @@ impl BM25Index {
             ]));
         }
 
+        if let Some(path) = path {
+            let file_query = tantivy::query::RegexQuery::from_pattern(
+                &trailing_path_pattern(path),
+                filename_field,
+            )?;
+            query = Box::new(tantivy::query::BooleanQuery::new(vec![
+                (tantivy::query::Occur::Must, query),
+                (tantivy::query::Occur::Must, Box::new(file_query)),
+            ]));
+        }
+
         let top_docs = searcher.search(&query, &TopDocs::with_limit(limit))?;
 
         let mut results = Vec::new();
@@ -389,12 +586,40 @@ impl BM25Index {
                 .and_then(|v| v.as_u64())
                 .ok_or_else(|| anyhow!("Missing or invalid 'line_end' field in document"))?;
 
+            let symbol = self
+                .symbol_field
+                .and_then(|field| retrieved_doc.get_first(field))
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            let scope = self
+                .scope_field
+                .map(|field| {
+                    retrieved_doc
+                        .get_all(field)
+                        .filter_map(|v| v.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let item_kind = self
+                .item_kind_field
+                .and_then(|field| retrieved_doc.get_first(field))
+                .and_then(|v| v.as_str())
+                .and_then(|kind| ItemKind::parse(kind).ok());
+            let is_test = self
+                .is_test_field
+                .and_then(|field| retrieved_doc.get_first(field))
+                .and_then(|v| v.as_bool());
+
             results.push(BM25Result {
                 id,
                 filename,
                 code,
                 line_start,
                 line_end,
+                symbol,
+                scope,
+                item_kind,
+                is_test,
                 score,
             });
         }
@@ -403,6 +628,22 @@ impl BM25Index {
     }
 }
 
+/// Regex over raw filenames matching `path` exactly or after a path separator.
+fn trailing_path_pattern(path: &str) -> String {
+    let mut pattern = String::from("(.*[/\\\\])?");
+    for c in path.chars() {
+        match c {
+            '/' | '\\' => pattern.push_str("[/\\\\]"),
+            c if "\\.+*?()|[]{}^$#&-~".contains(c) => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            c => pattern.push(c),
+        }
+    }
+    pattern
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -435,6 +676,13 @@ mod tests {
                 line_end: 3,
                 last_modified: 0,
                 calls: vec![],
+                part: None,
+                is_test: false,
+                kind: Default::default(),
+                permissions: None,
+                symbol: None,
+                scope: Vec::new(),
+                item_kind: Default::default(),
             },
             CodeChunk {
                 filename: "test.py".to_string(),
@@ -443,6 +691,13 @@ mod tests {
                 line_end: 2,
                 last_modified: 0,
                 calls: vec![],
+                part: None,
+                is_test: false,
+                kind: Default::default(),
+                permissions: None,
+                symbol: None,
+                scope: Vec::new(),
+                item_kind: Default::default(),
             },
         ];
 
@@ -462,6 +717,137 @@ mod tests {
         assert!(results.iter().any(|r| r.filename == "test.py"));
     }
 
+    fn finds_camel_case_from_snake_case(normalize: bool) -> bool {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let db_path = temp_dir.path().to_str().unwrap();
+        let index = BM25Index::new(db_path, false, "log")
+            .expect("Failed to create index")
+            .with_normalized_identifiers(normalize);
+
+        let chunks = vec![CodeChunk {
+            filename: "session.js".to_string(),
+            code: "function getUserName() { return session.login; }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        }];
+        index
+            .add_chunks(&chunks, "default")
+            .expect("Failed to add chunks");
+        index.commit().expect("Failed to commit");
+        index.reader.reload().expect("Failed to reload");
+
+        index
+            .search("get_user_name", 10, Some("default"))
+            .expect("Search failed")
+            .iter()
+            .any(|r| r.filename == "session.js")
+    }
+
+    #[test]
+    fn test_normalized_identifier_search() {
+        assert!(!finds_camel_case_from_snake_case(false));
+        assert!(finds_camel_case_from_snake_case(true));
+    }
+
+    #[test]
+    fn test_search_in_file() {
+        let (index, _temp_dir) = setup_test_index();
+        let chunk = |filename: &str| CodeChunk {
+            filename: filename.to_string(),
+            code: "fn load_config() {}".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        };
+        let chunks = vec![
+            chunk("/repo/src/a.rs"),
+            chunk("/repo/src/data.rs"),
+            chunk("C:\\repo\\src\\a.rs"),
+            chunk("/repo/src/a+rs"),
+        ];
+        index
+            .add_chunks(&chunks, "default")
+            .expect("Failed to add chunks");
+        index.commit().expect("Failed to commit");
+        index.reader.reload().expect("Failed to reload");
+
+        let files = |path: &str| -> Vec<String> {
+            let mut files: Vec<String> = index
+                .search_in_file("load_config", 10, Some("default"), Some(path))
+                .expect("Search failed")
+                .into_iter()
+                .map(|r| r.filename)
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(files("a.rs"), vec!["/repo/src/a.rs", "C:\\repo\\src\\a.rs"]);
+        assert_eq!(
+            files("src/a.rs"),
+            vec!["/repo/src/a.rs", "C:\\repo\\src\\a.rs"]
+        );
+        assert_eq!(files("/repo/src/data.rs"), vec!["/repo/src/data.rs"]);
+        assert!(files("rc/a.rs").is_empty());
+    }
+
+    #[test]
+    fn test_asset_entries() {
+        let (index, _temp_dir) = setup_test_index();
+        let mut code = CodeChunk::asset("src/logo.png", 0);
+        code.code = "fn logo() {}".to_string();
+        code.line_start = 1;
+        code.line_end = 1;
+        let chunks = vec![
+            CodeChunk::asset("assets/logo.png", 0),
+            CodeChunk::asset("src/logo.png", 0),
+            code,
+        ];
+        index
+            .add_chunks(&chunks, "default")
+            .expect("Failed to add chunks");
+        index.commit().expect("Failed to commit");
+        index.reader.reload().expect("Failed to reload");
+
+        let assets = index.asset_files("default").expect("Listing failed");
+        assert_eq!(
+            assets,
+            HashSet::from(["assets/logo.png".to_string(), "src/logo.png".to_string()])
+        );
+        assert!(index.asset_files("other").unwrap().is_empty());
+
+        index
+            .delete_assets(&["src/logo.png".to_string()], "default")
+            .expect("Delete failed");
+        index.commit().expect("Failed to commit");
+        index.reader.reload().expect("Failed to reload");
+        assert_eq!(
+            index.asset_files("default").unwrap(),
+            HashSet::from(["assets/logo.png".to_string()])
+        );
+        // The file's chunks are kept
+        let hits = index.search("logo", 10, Some("default")).unwrap();
+        assert!(hits
+            .iter()
+            .any(|r| r.filename == "src/logo.png" && r.line_start == 1));
+    }
+
     #[test]
     fn test_deletion() {
         let (index, _temp_dir) = setup_test_index();
@@ -473,6 +859,13 @@ mod tests {
             line_end: 3,
             last_modified: 0,
             calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
         }];
         index
             .add_chunks(&chunks, "default")
@@ -0,0 +1,31 @@
+use colored::*;
+
+use crate::config::AppConfig;
+use crate::core::CodeRagError;
+
+/// Prints every set configuration key with its value and the source it came from.
+///
+/// `custom_path` is the `--config` file, read as when loading the configuration.
+pub fn show_sources(custom_path: Option<String>, json: bool) -> Result<(), CodeRagError> {
+    let sources = AppConfig::sources(custom_path)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&sources)?);
+        return Ok(());
+    }
+
+    let width = sources.iter().map(|s| s.key.len()).max().unwrap_or(0);
+    for entry in &sources {
+        let value = match &entry.value {
+            serde_json::Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        println!(
+            "{:width$} = {}  {}",
+            entry.key,
+            value,
+            format!("({})", entry.source).dimmed(),
+            width = width
+        );
+    }
+    Ok(())
+}
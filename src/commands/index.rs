@@ -1,16 +1,29 @@
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use ignore::WalkBuilder;
 use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde::Serialize;
 use tracing::{error, info, warn};
 
 use crate::bm25::BM25Index;
 use crate::config::AppConfig;
 use crate::core::CodeRagError;
-use crate::embedding::Embedder;
-use crate::indexer::CodeChunker;
+use crate::disk_space::{available_space, DiskMonitor, DiskSpaceCheck};
+use crate::embedding::{Embedder, EmbeddingCache, ModelCacheOptions};
+use crate::embedding_store::EmbeddingStore;
+use crate::git::GitSelection;
+use crate::index_meta::{verify_index, IndexMeta};
+use crate::indexer::{
+    call_graph_importance, is_binary_file, source_extension, special_file_extension, CodeChunk,
+    CodeChunker, FilePermissions,
+};
+use crate::remote::{is_remote, RemoteSource, SftpSource, SshAuth};
 use crate::storage::Storage;
 
 pub struct IndexOptions {
@@ -21,36 +34,305 @@ pub struct IndexOptions {
     pub workspace: String,
     pub batch_size: Option<usize>,
     pub threads: Option<usize>,
+    /// Allow an update to remove more than `max_stale_fraction` of the index
+    pub allow_mass_delete: bool,
+    /// Only index files selected from git, with `update` semantics
+    pub git: Option<GitSelection>,
+    /// Hide progress bars and log plain progress lines (also when stderr is not a terminal)
+    pub quiet: bool,
 }
 
-pub async fn index_codebase(options: IndexOptions, config: &AppConfig) -> Result<(), CodeRagError> {
+/// Outcome of an indexing run.
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    /// Files removed from the index because they no longer exist on disk
+    pub removed_files: Vec<String>,
+    pub metrics: IndexMetrics,
+}
+
+/// Machine-readable counters for an indexing run (`index --metrics-json`).
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct IndexMetrics {
+    pub workspace: String,
+    /// Files chunked and embedded in this run
+    pub files_indexed: usize,
+    /// Files left as-is because they were unchanged since the last run
+    pub files_unchanged: usize,
+    pub files_removed: usize,
+    pub chunks: usize,
+    /// Chunks run through the embedding model; the others reused an embedding
+    pub chunks_embedded: usize,
+    /// Chunks whose embedding was read from `embedding_cache_dir`
+    pub embedding_cache_hits: usize,
+    /// Size of the indexed files
+    pub bytes: u64,
+    /// Files or directory entries that could not be read or parsed
+    pub failures: usize,
+    pub duration_ms: u64,
+    /// Time spent pausing between batches at `priority = "low"`
+    pub throttled_ms: u64,
+}
+
+/// Pauses between indexing batches at `priority = "low"`, so a background run leaves
+/// CPU and disk time to other work.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    /// Pause per batch, relative to the time spent on the batch
+    pause_ratio: f32,
+    paused: Duration,
+}
+
+impl Throttle {
+    /// Throttles only at `"low"` priority; `"normal"` and `"high"` run batches back to back.
+    pub fn for_priority(priority: &str, pause_ratio: f32) -> Self {
+        let pause_ratio = if priority.eq_ignore_ascii_case("low") {
+            pause_ratio.max(0.0)
+        } else {
+            0.0
+        };
+        Self {
+            pause_ratio,
+            paused: Duration::ZERO,
+        }
+    }
+
+    /// Sleeps for `pause_ratio` times `busy`, the time the last batch took.
+    pub async fn pause_after(&mut self, busy: Duration) {
+        if self.pause_ratio <= 0.0 {
+            return;
+        }
+        let pause = busy.mul_f32(self.pause_ratio);
+        tokio::time::sleep(pause).await;
+        self.paused += pause;
+    }
+
+    /// Total time paused so far.
+    pub fn paused(&self) -> Duration {
+        self.paused
+    }
+}
+
+/// Chunks waiting to be embedded and stored.
+///
+/// It is full once it holds `max_chunks` chunks or, with a byte cap, once their code
+/// adds up to `max_bytes`, so a few huge chunks cannot hold as much memory as a full
+/// batch of them would.
+struct ChunkBuffer {
+    chunks: Vec<CodeChunk>,
+    bytes: usize,
+    max_chunks: usize,
+    /// `0` for no byte cap
+    max_bytes: usize,
+}
+
+impl ChunkBuffer {
+    fn new(max_chunks: usize, max_bytes: usize) -> Self {
+        Self {
+            chunks: Vec::new(),
+            bytes: 0,
+            max_chunks,
+            max_bytes,
+        }
+    }
+
+    fn extend(&mut self, chunks: Vec<CodeChunk>) {
+        self.bytes += chunks.iter().map(|c| c.code.len()).sum::<usize>();
+        self.chunks.extend(chunks);
+    }
+
+    fn is_full(&self) -> bool {
+        self.chunks.len() >= self.max_chunks || (self.max_bytes > 0 && self.bytes >= self.max_bytes)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.bytes = 0;
+    }
+}
+
+/// A local file found by the scan that needs chunking.
+struct ScannedFile {
+    path: PathBuf,
+    fname: String,
+    mtime: i64,
+    size: u64,
+    permissions: Option<FilePermissions>,
+    /// An older version is indexed and is deleted once this one is chunked
+    replaces: bool,
+}
+
+/// A scanned file with its chunks, or the reason it could not be chunked.
+type ChunkedFile = (ScannedFile, Result<Vec<CodeChunk>, String>);
+
+/// Files chunked per pool thread that may wait for the embedding batcher.
+const CHUNKED_FILES_PER_THREAD: usize = 4;
+
+/// Reads and chunks `file`; runs on the chunking pool.
+fn chunk_scanned(chunker: &CodeChunker, file: &ScannedFile) -> Result<Vec<CodeChunk>, String> {
+    let handle = fs::File::open(&file.path)
+        .map_err(|e| format!("Error opening file {}: {}", file.fname, e))?;
+    let mut reader = std::io::BufReader::new(handle);
+    let mut chunks = chunker
+        .chunk_file(&file.fname, &mut reader, file.mtime)
+        .map_err(|e| format!("Error chunking file {}: {}", file.fname, e))?;
+    for chunk in &mut chunks {
+        chunk.permissions = file.permissions;
+    }
+    Ok(chunks)
+}
+
+/// Chunks `files` on a pool of `threads` threads (one per core when unset) and returns
+/// the results as they are ready.
+///
+/// The channel is bounded, so chunking waits while the batcher is busy embedding, and
+/// stops once the receiver is dropped. Receiving awaits instead of blocking a runtime
+/// worker.
+fn spawn_chunking(
+    chunker: Arc<CodeChunker>,
+    files: Vec<ScannedFile>,
+    threads: Option<usize>,
+) -> Result<tokio::sync::mpsc::Receiver<ChunkedFile>, CodeRagError> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .thread_name(|i| format!("chunker-{}", i))
+        .build()
+        .map_err(|e| CodeRagError::Generic(format!("Could not start chunking threads: {}", e)))?;
+    let (tx, rx) =
+        tokio::sync::mpsc::channel(pool.current_num_threads() * CHUNKED_FILES_PER_THREAD);
+    std::thread::spawn(move || {
+        pool.install(|| {
+            let _ = files.into_par_iter().try_for_each_with(tx, |tx, file| {
+                let chunks = chunk_scanned(&chunker, &file);
+                tx.blocking_send((file, chunks))
+            });
+        });
+    });
+    Ok(rx)
+}
+
+/// How often a quiet run logs its progress.
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Plain progress lines for quiet runs, which hide the progress bars.
+struct ProgressLog {
+    enabled: bool,
+    last: Instant,
+}
+
+impl ProgressLog {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last: Instant::now(),
+        }
+    }
+
+    /// Logs the counters at most once per `PROGRESS_LOG_INTERVAL`.
+    fn tick(&mut self, processed: u64, metrics: &IndexMetrics) {
+        if !self.enabled || self.last.elapsed() < PROGRESS_LOG_INTERVAL {
+            return;
+        }
+        self.last = Instant::now();
+        info!(
+            "Progress: {} files processed ({} indexed, {} unchanged), {} chunks",
+            processed, metrics.files_indexed, metrics.files_unchanged, metrics.chunks
+        );
+    }
+}
+
+/// A spinner with `template`, or a hidden bar that still counts when `quiet`.
+fn spinner(quiet: bool, template: &str) -> Result<ProgressBar, CodeRagError> {
+    if quiet {
+        return Ok(ProgressBar::hidden());
+    }
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template(template)
+            .map_err(|e| CodeRagError::Tantivy(e.to_string()))?,
+    );
+    pb.enable_steady_tick(std::time::Duration::from_millis(120));
+    Ok(pb)
+}
+
+/// Finds package directories under `root` for `index --auto-workspaces`.
+///
+/// A package is a directory up to `max_depth` levels below `root` containing one of
+/// `markers` (e.g. `Cargo.toml`). Packages nested inside another package belong to it.
+/// Each is returned with a workspace name made from its relative path (`crates/core`
+/// becomes `crates-core`), sorted by name.
+pub fn discover_workspaces(
+    root: &Path,
+    markers: &[String],
+    max_depth: usize,
+) -> Vec<(String, PathBuf)> {
+    let mut dirs: Vec<PathBuf> = WalkBuilder::new(root)
+        .max_depth(Some(max_depth + 1))
+        .filter_entry(|e| e.file_name() != "node_modules")
+        .build()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.depth() >= 2 && entry.file_type().is_some_and(|ft| ft.is_file()))
+        .filter(|entry| markers.iter().any(|m| entry.file_name() == m.as_str()))
+        .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+
+    // Sorted order puts a package before anything nested inside it
+    let mut packages: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if !packages.iter().any(|p| dir.starts_with(p)) {
+            packages.push(dir);
+        }
+    }
+
+    packages
+        .into_iter()
+        .filter_map(|dir| {
+            let relative = dir.strip_prefix(root).ok()?;
+            let name = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("-");
+            Some((name, dir))
+        })
+        .collect()
+}
+
+pub async fn index_codebase(
+    options: IndexOptions,
+    config: &AppConfig,
+) -> Result<IndexSummary, CodeRagError> {
+    let started = Instant::now();
     let actual_path = options
         .path
         .unwrap_or_else(|| config.default_index_path.clone());
     let force = options.force;
-    let update = options.update;
+    // Git selections are incremental by nature
+    let update = options.update || options.git.is_some();
     let batch_size = options.batch_size;
     let workspace_arg = options.workspace.clone();
+    // Spinners only make sense on a terminal; elsewhere they litter logs
+    let quiet = options.quiet || !std::io::stderr().is_terminal();
 
     // Determine DB path and Table name based on Nested Strategy
     // 1. If explicit DB path provided (e.g. from start command), trust it and use "code_chunks".
-    // 2. If CLI default, nest the workspace if it's not "default".
+    // 2. If CLI default, use the workspace's configured db, else nest it if it's not "default".
     let (actual_db, table_name) = if let Some(p) = options.db_path {
         // When db_path is explicit (from start), always use "code_chunks" table
         (p, "code_chunks".to_string())
+    } else if workspace_arg == "code_chunks" {
+        (config.db_path.clone(), "code_chunks".to_string())
     } else {
-        let root = config.db_path.clone();
-        if workspace_arg == "default" || workspace_arg == "code_chunks" {
-            (root, "code_chunks".to_string())
-        } else {
-            (
-                Path::new(&root)
-                    .join(&workspace_arg)
-                    .to_string_lossy()
-                    .to_string(),
-                "code_chunks".to_string(),
-            )
-        }
+        (
+            config.workspace_db_path(&workspace_arg),
+            "code_chunks".to_string(),
+        )
     };
 
     if force {
@@ -60,45 +342,98 @@ pub async fn index_codebase(options: IndexOptions, config: &AppConfig) -> Result
         }
     }
 
+    // An existing index is only updated if it was built compatibly
+    let index_meta = IndexMeta::current(config);
+    let existing = Storage::table_exists_on_disk(Path::new(&actual_db), &table_name);
+    if existing {
+        verify_index(
+            Path::new(&actual_db),
+            &index_meta,
+            config.index_version_check,
+        )?;
+    }
+
     info!("Indexing path: {}", actual_path);
     let index_path = Path::new(&actual_path);
 
+    // Resolve the git selection before loading models so a bad repo/ref fails fast
+    let git_files = match &options.git {
+        Some(selection) => {
+            let files = crate::git::changed_files(index_path, selection)?;
+            info!(
+                "Git selection: {} changed, {} deleted files",
+                files.changed.len(),
+                files.deleted.len()
+            );
+            Some(files)
+        }
+        None => None,
+    };
+
+    // Connect to a remote source up front too; its files are read over SFTP
+    let remote = if is_remote(&actual_path) {
+        if git_files.is_some() {
+            return Err(CodeRagError::Generic(
+                "Git selections need a local path, not an ssh:// source".into(),
+            ));
+        }
+        let source = RemoteSource::parse(&actual_path)?;
+        info!(
+            "Connecting to {}@{}:{}",
+            source.user, source.host, source.port
+        );
+        Some(SftpSource::connect(&source, &SshAuth::from(config))?)
+    } else {
+        None
+    };
+
     // 1. Load Models with Spinner
-    let pb_model = ProgressBar::new_spinner();
-    pb_model.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.blue} {msg}")
-            .map_err(|e| CodeRagError::Tantivy(e.to_string()))?,
-    );
-    pb_model.enable_steady_tick(std::time::Duration::from_millis(120));
+    let pb_model = spinner(quiet, "{spinner:.blue} {msg}")?;
     pb_model.set_message("Loading embedding model...");
+    if quiet {
+        info!("Loading embedding model...");
+    }
 
-    let mut embedder = Embedder::new(
+    let mut embedder = Embedder::new_with_cache(
+        quiet,
         config.embedding_model.clone(),
         config.reranker_model.clone(),
         config.embedding_model_path.clone(),
         config.reranker_model_path.clone(),
         config.device.clone(),
-    )?;
+        ModelCacheOptions {
+            cache_dir: config.model_cache_dir.clone(),
+            offline: config.offline,
+        },
+    )?
+    .with_normalization(config.should_normalize_embeddings());
 
     pb_model.set_message("Warming up ONNX Runtime...");
-    let warmup_text = vec!["warmup".to_string()];
-    let _ = embedder.embed(warmup_text.clone(), None)?;
+    embedder.warmup(config.embedding_warmup_samples)?;
 
     pb_model.finish_with_message("Models loaded.");
 
     // 2. Initialize Storage
     let storage = Storage::new(&actual_db, &table_name)
         .await
-        .map_err(|e| CodeRagError::Database(e.to_string()))?;
+        .map_err(|e| CodeRagError::Database(e.to_string()))?
+        .with_shards(config.shard_count, config.shard_key);
     storage
         .init(embedder.dim())
         .await
         .map_err(|e| CodeRagError::Database(e.to_string()))?;
+    if config.index_file_permissions
+        && !storage
+            .has_column("file_mode")
+            .await
+            .map_err(|e| CodeRagError::Database(e.to_string()))?
+    {
+        warn!("Index predates file permissions; re-index with --force to record them");
+    }
 
     // 3. Initialize BM25 Index
     let bm25_index = match BM25Index::new(&actual_db, false, &config.merge_policy) {
-        Ok(idx) => idx,
+        Ok(idx) => idx.with_normalized_identifiers(config.normalize_identifiers),
         Err(e) => {
             warn!(
                 "Failed to initialize BM25 index: {}. Hybrid search may be degraded.",
@@ -108,39 +443,112 @@ pub async fn index_codebase(options: IndexOptions, config: &AppConfig) -> Result
         }
     };
 
-    let chunker = CodeChunker::new(config.chunk_size, config.chunk_overlap);
+    let chunker = Arc::new(
+        CodeChunker::new(config.chunk_size, config.chunk_overlap)
+            .with_traversal_limits(config.chunk_max_depth, config.chunk_max_nodes)
+            .with_min_chunk_lines(config.chunk_min_lines)
+            .with_part_metadata(config.chunk_part_metadata)
+            .with_line_splitting(config.chunk_split_on_lines)
+            .with_size_balancing(config.chunk_balance_target)
+            .with_comment_stripping(config.strip_comments.clone())
+            .with_index_root(index_path),
+    );
 
     // 4. Scan Files
     // 4. Setup Progress Bar & Walker
-    let pb_index = ProgressBar::new_spinner();
-    pb_index.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed_precise}] {pos} files processed ({msg})")
-            .map_err(|e| CodeRagError::Tantivy(e.to_string()))?,
-    );
-    pb_index.enable_steady_tick(std::time::Duration::from_millis(120));
+    let pb_index = spinner(
+        quiet,
+        "{spinner:.green} [{elapsed_precise}] {pos} files processed ({msg})",
+    )?;
     pb_index.set_message("Initializing...");
+    let mut progress_log = ProgressLog::new(quiet);
 
     let existing_files = if update {
         pb_index.set_message("Fetching existing metadata...");
         storage
-            .get_indexed_metadata(&table_name)
+            .get_indexed_metadata(&workspace_arg)
             .await
             .map_err(|e| CodeRagError::Database(e.to_string()))?
     } else {
         HashMap::new()
     };
+    // chmod changes neither the content nor the modification time, so permissions
+    // are compared too
+    let existing_permissions = if update && config.index_file_permissions {
+        storage
+            .get_indexed_permissions(&workspace_arg)
+            .await
+            .map_err(|e| CodeRagError::Database(e.to_string()))?
+    } else {
+        HashMap::new()
+    };
+    // Name-only entries are in BM25 alone, so they are listed from there
+    let existing_assets = if update {
+        bm25_index.asset_files(&workspace_arg).unwrap_or_else(|e| {
+            warn!("Could not list indexed asset names: {}", e);
+            Default::default()
+        })
+    } else {
+        Default::default()
+    };
 
-    let builder = WalkBuilder::new(index_path);
-    let walker = builder.build();
+    // Walk the whole tree, or only the files selected from git
+    let walk_roots: Vec<std::path::PathBuf> = match &git_files {
+        Some(files) => files.changed.iter().map(|p| index_path.join(p)).collect(),
+        None if remote.is_some() => Vec::new(),
+        None => vec![index_path.to_path_buf()],
+    };
+    let walker = walk_roots
+        .split_first()
+        .map(|(first, rest)| {
+            let mut builder = WalkBuilder::new(first);
+            for root in rest {
+                builder.add(root);
+            }
+            builder.build()
+        })
+        .into_iter()
+        .flatten();
 
     // 5. Indexing Loop (Streaming)
-    let mut chunks_buffer = Vec::new();
-    let mut pending_deletes = Vec::new();
-    let mut visited_files = std::collections::HashSet::new();
     let batch_size_val = batch_size.unwrap_or(256);
     tracing::info!("Using batch size: {}", batch_size_val);
+    let mut chunks_buffer = ChunkBuffer::new(batch_size_val, config.batch_max_bytes);
+    let mut pending_deletes = Vec::new();
+    let mut git_removed = Vec::new();
+    if let Some(files) = &git_files {
+        for path in &files.deleted {
+            let fname = index_path.join(path).to_string_lossy().to_string();
+            if existing_files.contains_key(&fname) {
+                pending_deletes.push(fname.clone());
+                git_removed.push(fname);
+            }
+        }
+    }
+    let mut visited_files = std::collections::HashSet::new();
+    // Added after the stale cleanup, which deletes by filename
+    let mut assets = Vec::new();
+    let mut metrics = IndexMetrics {
+        workspace: workspace_arg.clone(),
+        ..Default::default()
+    };
+    let mut embedding_cache = config.dedup_chunks.then(EmbeddingCache::new);
+    let mut embedding_store = config.embedding_cache_dir.as_ref().and_then(|dir| {
+        let model = format!(
+            "{} normalize={}",
+            index_meta.embedding_model, index_meta.normalize_embeddings
+        );
+        EmbeddingStore::open(Path::new(dir), &model, config.embedding_cache_max_bytes)
+            .map_err(|e| warn!("Embedding cache at {} is unavailable: {}", dir, e))
+            .ok()
+    });
+    let mut throttle = Throttle::for_priority(&config.priority, config.low_priority_pause_ratio);
+    let mut busy_since = Instant::now();
 
+    // Scan first: unchanged, oversized and asset files are settled here, the rest is
+    // chunked in parallel below
+    pb_index.set_message("Scanning files...");
+    let mut scanned = Vec::new();
     for result in walker {
         match result {
             Ok(entry) => {
@@ -154,109 +562,389 @@ pub async fn index_codebase(options: IndexOptions, config: &AppConfig) -> Result
                     continue;
                 }
 
-                let fname_short = path.file_name().unwrap_or_default().to_string_lossy();
-                pb_index.set_message(format!("Processing {}", fname_short));
-                pb_index.inc(1);
+                let ext = source_extension(path);
+                if !config.includes_extension(ext) {
+                    continue;
+                }
 
-                let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-                if CodeChunker::get_language(ext).is_none() {
+                let supported = CodeChunker::is_supported(ext);
+                if !supported && !config.index_asset_names {
+                    pb_index.inc(1);
                     continue;
                 }
 
-                if let Ok(metadata) = fs::metadata(path) {
-                    // OOM Protection: Skip large files
-                    if metadata.len() > config.max_file_size_bytes as u64 {
-                        warn!(
-                            "Skipping file {} (size: {} bytes) - exceeds limit of {} bytes",
-                            path_str,
-                            metadata.len(),
-                            config.max_file_size_bytes
-                        );
-                        continue;
+                let Ok(metadata) = fs::metadata(path) else {
+                    metrics.failures += 1;
+                    pb_index.inc(1);
+                    continue;
+                };
+                let modified = metadata
+                    .modified()
+                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+                let mtime = modified
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs() as i64;
+                let fname_str = path_str.to_string();
+
+                // OOM Protection: Skip large files
+                let too_large = metadata.len() > config.max_file_size_bytes as u64;
+                if too_large {
+                    warn!(
+                        "Skipping file {} (size: {} bytes) - exceeds limit of {} bytes",
+                        path_str,
+                        metadata.len(),
+                        config.max_file_size_bytes
+                    );
+                }
+
+                // The chunker skips binary content, so such files are named only too
+                let binary = supported
+                    && !too_large
+                    && config.index_asset_names
+                    && is_binary_file(path).unwrap_or(false);
+
+                // Content is not indexed: record the name only (BM25), if enabled
+                if !supported || too_large || binary {
+                    if config.index_asset_names {
+                        let mut asset = crate::indexer::CodeChunk::asset(&fname_str, mtime);
+                        asset.is_test = chunker.is_test_file(&fname_str);
+                        assets.push(asset);
                     }
+                    pb_index.inc(1);
+                    continue;
+                }
 
-                    let modified = metadata
-                        .modified()
-                        .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
-                    let mtime = modified
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs() as i64;
-                    let fname_str = path_str.to_string();
-
-                    // Track visited files for stale cleanup
-                    visited_files.insert(fname_str.clone());
-
-                    if update {
-                        if let Some(stored_mtime) = existing_files.get(&fname_str) {
-                            if *stored_mtime == mtime {
-                                continue; // Unchanged
-                            }
-                            // File changed, mark old version for deletion
-                            pending_deletes.push(fname_str.clone());
-                        }
+                // Track visited files for stale cleanup
+                visited_files.insert(fname_str.clone());
+
+                let permissions = config
+                    .index_file_permissions
+                    .then(|| FilePermissions::from_metadata(&metadata))
+                    .flatten();
+                let permissions_changed = permissions.is_some()
+                    && existing_permissions.get(&fname_str) != permissions.as_ref();
+                let stored_mtime = existing_files.get(&fname_str).filter(|_| update);
+                if stored_mtime == Some(&mtime) && !permissions_changed {
+                    metrics.files_unchanged += 1;
+                    pb_index.inc(1);
+                    continue; // Unchanged
+                }
+
+                scanned.push(ScannedFile {
+                    path: path.to_path_buf(),
+                    fname: fname_str,
+                    mtime,
+                    size: metadata.len(),
+                    permissions,
+                    replaces: stored_mtime.is_some(),
+                });
+            }
+            Err(err) => {
+                metrics.failures += 1;
+                warn!("Error walking directory: {}", err);
+            }
+        }
+    }
+    progress_log.tick(pb_index.position(), &metrics);
+
+    // Refuse to start when the index would not fit, before anything is written. Only
+    // the files about to be (re)indexed count, so an update is sized by its changes
+    let disk_check = DiskSpaceCheck::from_config(config);
+    if let Some(check) = &disk_check {
+        let corpus = scanned.iter().map(|file| file.size).sum();
+        check.ensure(
+            Path::new(&actual_db),
+            corpus,
+            available_space(Path::new(&actual_db)),
+        )?;
+    }
+    let mut disk_monitor = disk_check.map(|check| DiskMonitor::new(check, Path::new(&actual_db)));
+    let mut low_disk = None;
+
+    // Chunks arrive from the pool in any file order; batches are still embedded and
+    // stored one at a time
+    let mut chunked = spawn_chunking(
+        Arc::clone(&chunker),
+        scanned,
+        options.threads.or(config.threads),
+    )?;
+    while let Some((file, result)) = chunked.recv().await {
+        let fname_short = file.path.file_name().unwrap_or_default().to_string_lossy();
+        pb_index.set_message(format!("Processing {}", fname_short));
+        pb_index.inc(1);
+        progress_log.tick(pb_index.position(), &metrics);
+
+        if file.replaces {
+            // File changed, mark old version for deletion
+            pending_deletes.push(file.fname.clone());
+        }
+        match result {
+            Ok(new_chunks) => {
+                metrics.files_indexed += 1;
+                metrics.chunks += new_chunks.len();
+                metrics.bytes += file.size;
+                chunks_buffer.extend(new_chunks);
+            }
+            Err(e) => {
+                metrics.failures += 1;
+                warn!("{}", e);
+            }
+        }
+
+        if chunks_buffer.is_full() || pending_deletes.len() >= batch_size_val {
+            if let Err(e) = disk_monitor
+                .as_mut()
+                .map_or(Ok(()), DiskMonitor::before_batch)
+            {
+                low_disk = Some(e);
+                break;
+            }
+            let mut ctx = IndexingContext {
+                embedder: &mut embedder,
+                storage: &storage,
+                bm25_index: &bm25_index,
+                pb: &pb_index,
+                workspace: &workspace_arg,
+                embedding_cache: embedding_cache.as_mut(),
+                embedding_store: embedding_store.as_mut(),
+                chunks_embedded: &mut metrics.chunks_embedded,
+                chunker: &chunker,
+            };
+            process_batch(&mut chunks_buffer, &mut pending_deletes, &mut ctx).await?;
+            throttle.pause_after(busy_since.elapsed()).await;
+            busy_since = Instant::now();
+        }
+    }
+
+    // Files of a remote source, stored as `host:/path`
+    if let Some(sftp) = remote.as_ref().filter(|_| low_disk.is_none()) {
+        pb_index.set_message("Listing remote files...");
+        for file in sftp.list_files()? {
+            let fname_str = sftp.source().qualify(&file.path);
+            if config.exclusions.iter().any(|ex| fname_str.contains(ex)) {
+                continue;
+            }
+            let remote_path = Path::new(&file.path);
+            let ext = special_file_extension(remote_path)
+                .or_else(|| remote_path.extension().and_then(|s| s.to_str()))
+                .unwrap_or("");
+            if !config.includes_extension(ext) || !CodeChunker::is_supported(ext) {
+                continue;
+            }
+
+            let fname_short = Path::new(&file.path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            pb_index.set_message(format!("Processing {}", fname_short));
+            pb_index.inc(1);
+            progress_log.tick(pb_index.position(), &metrics);
+
+            if file.size > config.max_file_size_bytes as u64 {
+                warn!(
+                    "Skipping file {} (size: {} bytes) - exceeds limit of {} bytes",
+                    fname_str, file.size, config.max_file_size_bytes
+                );
+                continue;
+            }
+
+            visited_files.insert(fname_str.clone());
+            if update {
+                if let Some(stored_mtime) = existing_files.get(&fname_str) {
+                    if *stored_mtime == file.mtime {
+                        metrics.files_unchanged += 1;
+                        continue;
                     }
+                    pending_deletes.push(fname_str.clone());
+                }
+            }
 
-                    if let Ok(file) = fs::File::open(path) {
-                        let mut reader = std::io::BufReader::new(file);
-                        match chunker.chunk_file(&fname_str, &mut reader, mtime) {
-                            Ok(new_chunks) => chunks_buffer.extend(new_chunks),
-                            Err(e) => warn!("Error chunking file {}: {}", fname_str, e),
+            match sftp.read(&file.path) {
+                Ok(content) => {
+                    let mut reader = std::io::Cursor::new(content);
+                    match chunker.chunk_file(&fname_str, &mut reader, file.mtime) {
+                        Ok(new_chunks) => {
+                            metrics.files_indexed += 1;
+                            metrics.chunks += new_chunks.len();
+                            metrics.bytes += file.size;
+                            chunks_buffer.extend(new_chunks);
+                        }
+                        Err(e) => {
+                            metrics.failures += 1;
+                            warn!("Error chunking file {}: {}", fname_str, e);
                         }
                     }
                 }
+                Err(e) => {
+                    metrics.failures += 1;
+                    warn!("Error reading remote file {}: {}", fname_str, e);
+                }
+            }
 
-                if chunks_buffer.len() >= batch_size_val || pending_deletes.len() >= batch_size_val
+            if chunks_buffer.is_full() || pending_deletes.len() >= batch_size_val {
+                if let Err(e) = disk_monitor
+                    .as_mut()
+                    .map_or(Ok(()), DiskMonitor::before_batch)
                 {
-                    let mut ctx = IndexingContext {
-                        embedder: &mut embedder,
-                        storage: &storage,
-                        bm25_index: &bm25_index,
-                        pb: &pb_index,
-                        workspace: &workspace_arg,
-                    };
-                    process_batch(&mut chunks_buffer, &mut pending_deletes, &mut ctx).await?;
+                    low_disk = Some(e);
+                    break;
                 }
+                let mut ctx = IndexingContext {
+                    embedder: &mut embedder,
+                    storage: &storage,
+                    bm25_index: &bm25_index,
+                    pb: &pb_index,
+                    workspace: &workspace_arg,
+                    embedding_cache: embedding_cache.as_mut(),
+                    embedding_store: embedding_store.as_mut(),
+                    chunks_embedded: &mut metrics.chunks_embedded,
+                    chunker: &chunker,
+                };
+                process_batch(&mut chunks_buffer, &mut pending_deletes, &mut ctx).await?;
+                throttle.pause_after(busy_since.elapsed()).await;
+                busy_since = Instant::now();
             }
-            Err(err) => warn!("Error walking directory: {}", err),
         }
     }
 
-    if !chunks_buffer.is_empty() || !pending_deletes.is_empty() {
+    // The notes file sits in a hidden directory the walker skips, so read it directly
+    let notes_path = index_path.join(&config.notes_file);
+    let notes_selected = match &git_files {
+        Some(files) => files
+            .changed
+            .iter()
+            .any(|p| index_path.join(p) == notes_path),
+        None => true,
+    };
+    if config.index_notes && notes_selected && low_disk.is_none() {
+        if let Ok(metadata) = fs::metadata(&notes_path) {
+            let mtime = metadata
+                .modified()
+                .unwrap_or(std::time::SystemTime::UNIX_EPOCH)
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+            let fname_str = notes_path.to_string_lossy().to_string();
+            visited_files.insert(fname_str.clone());
+
+            let unchanged = update && existing_files.get(&fname_str) == Some(&mtime);
+            if unchanged {
+                metrics.files_unchanged += 1;
+            } else {
+                if update && existing_files.contains_key(&fname_str) {
+                    pending_deletes.push(fname_str.clone());
+                }
+                match fs::read_to_string(&notes_path) {
+                    Ok(content) => {
+                        let notes = CodeChunk::notes(&fname_str, index_path, &content, mtime);
+                        info!("Indexing {} notes from {}", notes.len(), fname_str);
+                        metrics.files_indexed += 1;
+                        metrics.chunks += notes.len();
+                        metrics.bytes += metadata.len();
+                        chunks_buffer.extend(notes);
+                    }
+                    Err(e) => {
+                        metrics.failures += 1;
+                        warn!("Error reading notes file {}: {}", fname_str, e);
+                    }
+                }
+            }
+        }
+    }
+
+    let pending = !chunks_buffer.is_empty() || !pending_deletes.is_empty();
+    if low_disk.is_none() && pending {
+        low_disk = disk_monitor
+            .as_mut()
+            .and_then(|monitor| monitor.before_last_batch().err());
+    }
+    if low_disk.is_none() && pending {
         let mut ctx = IndexingContext {
             embedder: &mut embedder,
             storage: &storage,
             bm25_index: &bm25_index,
             pb: &pb_index,
             workspace: &workspace_arg,
+            embedding_cache: embedding_cache.as_mut(),
+            embedding_store: embedding_store.as_mut(),
+            chunks_embedded: &mut metrics.chunks_embedded,
+            chunker: &chunker,
         };
         process_batch(&mut chunks_buffer, &mut pending_deletes, &mut ctx).await?;
     }
 
     // 6. Stale File Cleanup (Post-Indexing)
-    if update {
-        let stale_files: Vec<String> = existing_files
+    let mut summary = IndexSummary::default();
+    let mut blocked = None;
+    let mut stale_assets: Vec<String> = Vec::new();
+    if let Some(e) = low_disk {
+        // Files never reached are not stale; keep what was written and stop
+        error!("Indexing stopped early: {}", e);
+        blocked = Some(e);
+    } else if let Some(files) = &git_files {
+        // Only part of the tree was walked; deletions come from git instead
+        summary.removed_files = git_removed;
+        stale_assets = files
+            .deleted
+            .iter()
+            .map(|path| index_path.join(path).to_string_lossy().replace('\\', "/"))
+            .filter(|name| existing_assets.contains(name))
+            .collect();
+    } else if update {
+        // Also drops entries of files whose content is indexed again
+        let visited_assets: std::collections::HashSet<&str> =
+            assets.iter().map(|a| a.filename.as_str()).collect();
+        stale_assets = existing_assets
+            .iter()
+            .filter(|name| !visited_assets.contains(name.as_str()))
+            .cloned()
+            .collect();
+
+        let mut stale_files: Vec<String> = existing_files
             .keys()
             .filter(|f| !visited_files.contains(*f))
             .cloned()
             .collect();
+        stale_files.sort();
 
-        if !stale_files.is_empty() {
+        let fraction = stale_files.len() as f32 / existing_files.len().max(1) as f32;
+        if fraction > config.max_stale_fraction && !options.allow_mass_delete {
+            // Most likely a wrong --path; keep the index intact
+            blocked = Some(CodeRagError::Generic(format!(
+                "Update would remove {} of {} indexed files ({:.0}%) from '{}', above max_stale_fraction {}. \
+                 Check --path or pass --allow-mass-delete",
+                stale_files.len(),
+                existing_files.len(),
+                fraction * 100.0,
+                actual_path,
+                config.max_stale_fraction
+            )));
+        } else if !stale_files.is_empty() {
             info!("Found {} stale files to remove.", stale_files.len());
             pb_index.set_message("Cleaning up stale files...");
 
             // Process in batches
             for chunk in stale_files.chunks(batch_size_val) {
                 let batch: Vec<String> = chunk.to_vec();
-                if let Err(e) = storage.batch_delete_files(&batch, &table_name).await {
+                if let Err(e) = storage.batch_delete_files(&batch, &workspace_arg).await {
                     error!("Error removing stale files from storage: {}", e);
                 }
-                if let Err(e) = bm25_index.batch_delete_files(&batch, &table_name) {
+                if let Err(e) = bm25_index.batch_delete_files(&batch, &workspace_arg) {
                     error!("Error removing stale files from BM25: {}", e);
                 }
             }
+            summary.removed_files = stale_files;
         }
     }
+    if blocked.is_none() {
+        if let Err(e) = bm25_index.delete_assets(&stale_assets, &workspace_arg) {
+            error!("Error removing stale asset names from BM25: {}", e);
+        }
+    }
+    if let Err(e) = bm25_index.add_chunks(&assets, &workspace_arg) {
+        warn!("Error indexing asset names: {}", e);
+    }
 
     // Commit BM25 index once at the end (single expensive I/O operation)
     pb_index.set_message("Committing BM25 index...");
@@ -266,12 +954,85 @@ pub async fn index_codebase(options: IndexOptions, config: &AppConfig) -> Result
 
     pb_index.finish_with_message("Indexing complete.");
 
+    if let Some(cache) = &embedding_cache {
+        info!(
+            "Embedded {} unique chunks; reused embeddings for {} duplicate chunks.",
+            cache.computed(),
+            cache.reused()
+        );
+    }
+    if let Some(store) = &embedding_store {
+        metrics.embedding_cache_hits = store.hits();
+        info!(
+            "Read {} embeddings from the embedding cache; stored {} new ones.",
+            store.hits(),
+            store.computed()
+        );
+        match store.prune() {
+            Ok(0) => {}
+            Ok(removed) => info!("Pruned {} least recently used cached embeddings", removed),
+            Err(e) => warn!("Failed to prune the embedding cache: {}", e),
+        }
+    }
+
     info!("Optimizing index (creating filename index)...");
     if let Err(e) = storage.create_filename_index().await {
         warn!("Optimization warning: {}", e);
     }
 
-    Ok(())
+    if config.importance_boost > 0.0 {
+        match update_importance(&storage, &workspace_arg).await {
+            Ok(scored) => info!("Computed call-graph importance for {} chunks", scored),
+            Err(e) => warn!("Failed to compute call-graph importance: {}", e),
+        }
+    }
+
+    // Only a new index is stamped; an old one keeps what it was built with
+    if !existing {
+        if let Err(e) = index_meta.write(Path::new(&actual_db)) {
+            warn!("Could not record the index metadata: {}", e);
+        }
+    }
+
+    metrics.files_removed = summary.removed_files.len();
+    metrics.duration_ms = started.elapsed().as_millis() as u64;
+    metrics.throttled_ms = throttle.paused().as_millis() as u64;
+    info!(
+        workspace = %metrics.workspace,
+        files_indexed = metrics.files_indexed,
+        files_unchanged = metrics.files_unchanged,
+        files_removed = metrics.files_removed,
+        chunks = metrics.chunks,
+        chunks_embedded = metrics.chunks_embedded,
+        embedding_cache_hits = metrics.embedding_cache_hits,
+        bytes = metrics.bytes,
+        failures = metrics.failures,
+        duration_ms = metrics.duration_ms,
+        throttled_ms = metrics.throttled_ms,
+        "Index run complete"
+    );
+    summary.metrics = metrics;
+
+    if let Some(e) = blocked {
+        return Err(e);
+    }
+
+    Ok(summary)
+}
+
+/// Post-index pass: recomputes the call-graph importance of every chunk in `workspace`.
+///
+/// Any change can move callers between files, so the whole workspace is rescored.
+/// Returns the number of chunks with a non-zero importance.
+async fn update_importance(storage: &Storage, workspace: &str) -> anyhow::Result<usize> {
+    if !storage.has_column("importance").await? {
+        warn!("Index predates call-graph importance; re-index with --force to enable it");
+        return Ok(0);
+    }
+    let nodes = storage.call_graph(workspace).await?;
+    let importance = call_graph_importance(&nodes);
+    storage.set_importance(workspace, &importance).await?;
+    Ok(importance.len())
 }
 
 struct IndexingContext<'a> {
@@ -280,10 +1041,18 @@ struct IndexingContext<'a> {
     bm25_index: &'a BM25Index,
     pb: &'a ProgressBar,
     workspace: &'a str,
+    /// Set when `dedup_chunks` is enabled
+    embedding_cache: Option<&'a mut EmbeddingCache>,
+    /// Set when `embedding_cache_dir` is configured
+    embedding_store: Option<&'a mut EmbeddingStore>,
+    /// Counts the chunks the model embeds
+    chunks_embedded: &'a mut usize,
+    /// Builds the embedded text of each chunk
+    chunker: &'a CodeChunker,
 }
 
 async fn process_batch(
-    chunks: &mut Vec<crate::indexer::CodeChunk>,
+    buffer: &mut ChunkBuffer,
     pending_deletes: &mut Vec<String>,
     ctx: &mut IndexingContext<'_>,
 ) -> Result<(), CodeRagError> {
@@ -305,14 +1074,35 @@ async fn process_batch(
         pending_deletes.clear();
     }
 
-    if chunks.is_empty() {
+    if buffer.is_empty() {
         return Ok(());
     }
+    let chunks = &buffer.chunks;
 
     ctx.pb.set_message("Embedding batch...");
-    let texts: Vec<String> = chunks.iter().map(|c| c.code.clone()).collect();
+    let texts: Vec<String> = chunks
+        .iter()
+        .map(|c| ctx.chunker.embedding_text(c))
+        .collect();
 
-    match ctx.embedder.embed(texts, None) {
+    // Duplicates within the run are dropped first, then stored embeddings are read
+    let embedder = &*ctx.embedder;
+    let chunks_embedded = &mut *ctx.chunks_embedded;
+    let mut run_model = |texts: Vec<String>| {
+        *chunks_embedded += texts.len();
+        embedder.embed(texts, None)
+    };
+    let store = ctx.embedding_store.as_deref_mut();
+    let embed = |texts: Vec<String>| match store {
+        Some(store) => store.embed_with(texts, &mut run_model),
+        None => run_model(texts),
+    };
+    let embedded = match ctx.embedding_cache.as_deref_mut() {
+        Some(cache) => cache.embed_with(texts, embed),
+        None => embed(texts),
+    };
+
+    match embedded {
         Ok(embeddings) => {
             let ids: Vec<String> = chunks
                 .iter()
@@ -324,10 +1114,11 @@ async fn process_batch(
             let ends: Vec<i32> = chunks.iter().map(|c| c.line_end as i32).collect();
             let mtimes: Vec<i64> = chunks.iter().map(|c| c.last_modified).collect();
             let calls: Vec<Vec<String>> = chunks.iter().map(|c| c.calls.clone()).collect();
+            let metadata = chunks.iter().map(|c| c.metadata()).collect();
 
             if let Err(e) = ctx
                 .storage
-                .add_chunks(
+                .add_chunks_with_metadata(
                     ctx.workspace,
                     ids,
                     filenames,
@@ -337,6 +1128,7 @@ async fn process_batch(
                     mtimes,
                     calls,
                     embeddings,
+                    metadata,
                 )
                 .await
             {
@@ -348,6 +1140,50 @@ async fn process_batch(
         }
         Err(e) => error!("Error generating embeddings: {}", e),
     }
-    chunks.clear();
+    buffer.clear();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(bytes: usize) -> CodeChunk {
+        CodeChunk {
+            filename: "big.rs".to_string(),
+            code: "x".repeat(bytes),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: Vec::new(),
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_chunk_buffer_flushes_on_byte_cap_before_count_cap() {
+        let mut buffer = ChunkBuffer::new(256, 1_000_000);
+        buffer.extend(vec![chunk(400_000), chunk(400_000)]);
+        assert!(!buffer.is_full());
+        buffer.extend(vec![chunk(400_000)]);
+        assert!(buffer.is_full(), "3 chunks should reach the 1 MB cap");
+        assert_eq!(buffer.chunks.len(), 3);
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.bytes, 0);
+
+        // Without a byte cap only the count matters
+        let mut uncapped = ChunkBuffer::new(4, 0);
+        uncapped.extend((0..3).map(|_| chunk(400_000)).collect());
+        assert!(!uncapped.is_full());
+        uncapped.extend(vec![chunk(1)]);
+        assert!(uncapped.is_full());
+    }
+}
@@ -1,10 +1,10 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::io::{self, Write};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex, OwnedSemaphorePermit, Semaphore};
 use tracing::{error, info};
 
 use crate::commands::search::create_searcher;
@@ -46,19 +46,42 @@ pub async fn run(config: &AppConfig) -> Result<()> {
         searcher: Mutex::new(None),
     });
 
-    let stdin = tokio::io::stdin();
-    let mut reader = BufReader::new(stdin);
-    let mut line = String::new();
-
     eprintln!("✓ MCP Server started successfully (stdio transport)");
 
-    loop {
-        line.clear();
-        let bytes_read = reader.read_line(&mut line).await?;
-        if bytes_read == 0 {
-            break; // EOF
-        }
+    serve(
+        BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+        state,
+    )
+    .await
+}
+
+/// A response line (`None` for notifications) with the position of its request and
+/// the in-flight permit it holds until written.
+type Outgoing = (u64, Option<String>, OwnedSemaphorePermit);
 
+/// Reads requests line by line and handles them concurrently.
+///
+/// At most `mcp_max_in_flight` requests are handled or waiting to be written at once;
+/// reading pauses until one finishes. All output goes through a single writer task so
+/// responses are never interleaved.
+async fn serve<R, W>(reader: R, writer: W, state: Arc<McpState>) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let max_in_flight = state.config.mcp_max_in_flight.max(1);
+    let permits = Arc::new(Semaphore::new(max_in_flight));
+    let (tx, rx) = mpsc::channel::<Outgoing>(max_in_flight);
+    let writer_task = tokio::spawn(write_responses(
+        rx,
+        writer,
+        state.config.mcp_ordered_responses,
+    ));
+
+    let mut lines = reader.lines();
+    let mut position = 0;
+    while let Some(line) = lines.next_line().await? {
         let request: Request = match serde_json::from_str(&line) {
             Ok(req) => req,
             Err(e) => {
@@ -67,20 +90,99 @@ pub async fn run(config: &AppConfig) -> Result<()> {
             }
         };
 
+        let permit = permits.clone().acquire_owned().await?;
         let state_clone = state.clone();
+        let tx = tx.clone();
+        let request_position = position;
+        position += 1;
         // Spawn each request handling to avoid blocking parsing of next line
-        // though stdio is sequential usually.
         tokio::spawn(async move {
-            if let Err(e) = handle_request(request, state_clone).await {
-                error!("Error handling request: {}", e);
-            }
+            let id = request.id.clone();
+            // A separate task, so a panicking handler still fills its slot in ordered mode
+            let response = match tokio::spawn(handle_request(request, state_clone)).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(e)) => {
+                    error!("Error handling request: {}", e);
+                    None
+                }
+                Err(e) => {
+                    error!("Request handler failed: {}", e);
+                    internal_error(id)
+                }
+            };
+            // Only fails if the writer stopped; `serve` returns its error
+            let _ = tx.send((request_position, response, permit)).await;
         });
     }
 
+    // Let in-flight requests finish before the writer shuts down
+    drop(tx);
+    writer_task.await?
+}
+
+/// Writes each response as one line, in request order when `ordered` is set.
+async fn write_responses<W>(
+    mut rx: mpsc::Receiver<Outgoing>,
+    mut writer: W,
+    ordered: bool,
+) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut pending = BTreeMap::new();
+    let mut next_position = 0;
+
+    while let Some((position, response, permit)) = rx.recv().await {
+        if !ordered {
+            write_line(&mut writer, response).await?;
+            drop(permit);
+            continue;
+        }
+
+        // Hold early responses (and their permits) until the ones before them are written
+        pending.insert(position, (response, permit));
+        while let Some((response, permit)) = pending.remove(&next_position) {
+            write_line(&mut writer, response).await?;
+            drop(permit);
+            next_position += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// The response to a request whose handler panicked (`None` for notifications).
+fn internal_error(id: Option<Value>) -> Option<String> {
+    id.as_ref()?;
+    let response = Response {
+        jsonrpc: "2.0".to_string(),
+        result: None,
+        error: Some(Error {
+            code: -32603,
+            message: "Internal error".to_string(),
+            data: None,
+        }),
+        id,
+    };
+    serde_json::to_string(&response).ok()
+}
+
+async fn write_line<W>(writer: &mut W, line: Option<String>) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let Some(line) = line else {
+        return Ok(());
+    };
+    let mut bytes = line.into_bytes();
+    bytes.push(b'\n');
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
     Ok(())
 }
 
-async fn handle_request(req: Request, state: Arc<McpState>) -> Result<()> {
+/// Handles one request and returns its serialized response (`None` for notifications).
+async fn handle_request(req: Request, state: Arc<McpState>) -> Result<Option<String>> {
     let mut response = Response {
         jsonrpc: "2.0".to_string(),
         result: None,
@@ -107,7 +209,7 @@ async fn handle_request(req: Request, state: Arc<McpState>) -> Result<()> {
             }));
         }
         "notifications/initialized" => {
-            return Ok(());
+            return Ok(None);
         }
         "tools/list" => {
             response.result = Some(json!({
@@ -213,19 +315,16 @@ async fn handle_request(req: Request, state: Arc<McpState>) -> Result<()> {
                     data: None,
                 });
             } else {
-                return Ok(());
+                return Ok(None);
             }
         }
     }
 
     if req.id.is_some() {
-        let response_str = serde_json::to_string(&response)?;
-        let mut stdout = io::stdout();
-        writeln!(stdout, "{}", response_str)?;
-        stdout.flush()?;
+        Ok(Some(serde_json::to_string(&response)?))
+    } else {
+        Ok(None)
     }
-
-    Ok(())
 }
 
 async fn perform_search(
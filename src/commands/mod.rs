@@ -1,3 +1,4 @@
+pub mod config;
 pub mod index;
 pub mod mcp;
 pub mod search;
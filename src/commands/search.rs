@@ -1,16 +1,27 @@
 use colored::*;
 use std::fs;
-use tracing::{error, warn};
+use std::path::Path;
+use tracing::{error, info, warn};
 
 use crate::bm25::BM25Index;
 use crate::config::AppConfig;
 use crate::core::CodeRagError;
-use crate::embedding::Embedder;
+use crate::embedding::{Embedder, ModelCacheOptions};
+use crate::index_meta::{verify_index, IndexMeta};
+use crate::indexer::{call_target, ChunkKind, ItemKind};
 use crate::llm::client::OllamaClient;
 use crate::llm::expander::QueryExpander;
-use crate::reporting::generate_html_report;
-use crate::search::CodeSearcher;
-use crate::storage::Storage;
+use crate::llm::ResultExplainer;
+use crate::reporting::{
+    format_numbered, format_prompt, format_rerank_comparison, generate_html_report,
+};
+use crate::result_cache::ResultCache;
+use crate::search::{
+    aggregate_by_file, CallerRef, CodeSearcher, Confidence, ConfidenceThresholds, GrepOptions,
+    MultiQueryRerank, QueryRouting, RerankDisagreement, ScoreAggregation, ScoreCalibration,
+    SearchResult, SnippetWindows, Subsystem, TestFilter,
+};
+use crate::storage::{AsOf, ShardKey, Storage};
 use std::sync::Arc;
 
 pub struct SearchOptions {
@@ -20,13 +31,94 @@ pub struct SearchOptions {
     pub json: bool,
     pub ext: Option<String>,
     pub dir: Option<String>,
+    /// Only search chunks of this file
+    pub in_file: Option<String>,
+    /// Only return chunks spanning at least this many lines
+    pub min_lines: Option<usize>,
+    /// Only return chunks of files with all these permission bits
+    pub required_mode: Option<u32>,
+    /// Only return chunks of this item kind (function, class, ...)
+    pub item_kind: Option<ItemKind>,
     pub no_rerank: bool,
     pub workspace: Option<String>,
 
     pub max_tokens: Option<usize>,
     pub expand: bool,
+    /// Return one entry per file, combining chunk scores with this method
+    pub aggregate: Option<ScoreAggregation>,
+    /// Maximum number of chunks from any one file
+    pub max_per_file: Option<usize>,
+    /// Search a previous version of the index
+    pub as_of: Option<AsOf>,
+    /// Attach up to this many call sites of each result's function
+    pub show_callers: Option<usize>,
+    /// Print Markdown with absolute line numbers (`--format numbered`)
+    pub numbered: bool,
+    /// Keep or drop chunks from test files
+    pub test_filter: TestFilter,
+    /// Include each result's embedding vector in `--json` output
+    pub include_vectors: bool,
+    /// Print the results as an LLM prompt within the token budget
+    pub prompt: bool,
+    /// Show the results with and without reranking side by side
+    pub compare_rerank: bool,
 }
 
+/// JSON output row for `--show-callers`.
+#[derive(serde::Serialize)]
+struct ResultWithCallers<'a> {
+    #[serde(flatten)]
+    result: &'a SearchResult,
+    callers: &'a [CallerRef],
+}
+
+/// Everything that changes the outcome of `search_with_stats`; its JSON form is the
+/// result cache key, so a setting missing here would serve stale results.
+#[derive(serde::Serialize)]
+struct CacheKey<'a> {
+    query: &'a str,
+    limit: usize,
+    ext: Option<&'a str>,
+    dir: Option<&'a str>,
+    in_file: Option<&'a str>,
+    min_lines: Option<usize>,
+    required_mode: Option<u32>,
+    item_kind: Option<ItemKind>,
+    no_rerank: bool,
+    workspace: Option<&'a str>,
+    max_tokens: Option<usize>,
+    max_per_file: Option<usize>,
+    test_filter: TestFilter,
+    include_vectors: bool,
+    vector_weight: f32,
+    bm25_weight: f32,
+    rrf_k: f32,
+    rerank_blend: f32,
+    rerank_skip_extensions: &'a [String],
+    rerank_cascade_k: Option<usize>,
+    rerank_disagreement: Option<RerankDisagreement>,
+    multi_query_rerank: Option<MultiQueryRerank>,
+    query_routing: Option<QueryRouting>,
+    score_calibration: ScoreCalibration,
+    confidence: Option<ConfidenceThresholds>,
+    snippets: Option<SnippetWindows>,
+    importance_boost: f32,
+    path_boost: f32,
+    embedding_model: &'a str,
+    embedding_model_path: Option<&'a str>,
+    reranker_model: &'a str,
+    reranker_model_path: Option<&'a str>,
+    normalize_embeddings: bool,
+    normalize_identifiers: bool,
+    keyword_fallback: bool,
+    shard_count: usize,
+    shard_key: ShardKey,
+}
+
+/// Chunks fetched per requested file when aggregating, so files are ranked on
+/// more than the top `limit` chunks.
+const AGGREGATE_FETCH_FACTOR: usize = 5;
+
 pub async fn search_codebase(
     query: String,
     options: SearchOptions,
@@ -39,18 +131,45 @@ pub async fn search_codebase(
         json,
         ext,
         dir,
+        in_file,
+        min_lines,
+        required_mode,
+        item_kind,
         no_rerank,
         workspace,
 
         max_tokens,
         expand,
+        aggregate,
+        max_per_file,
+        as_of,
+        show_callers,
+        numbered,
+        test_filter,
+        include_vectors,
+        prompt,
+        compare_rerank,
     } = options;
 
     let actual_limit = limit.unwrap_or(config.default_limit);
-    let base_db = db_path.unwrap_or_else(|| config.db_path.clone());
+    let max_tokens = max_tokens.or(config.default_max_tokens);
+    let max_tokens = if prompt {
+        Some(max_tokens.unwrap_or(config.prompt_max_tokens))
+    } else {
+        max_tokens
+    };
+    // Diagnostics go to stderr so stdout is just the requested output
+    let plain_stdout = json || prompt;
+    let base_db = db_path.clone().unwrap_or_else(|| config.db_path.clone());
     let workspace_name = workspace.clone().unwrap_or_else(|| "default".to_string());
 
-    let (actual_db, table_name) = if workspace_name == "default" {
+    let (actual_db, table_name) = if db_path.is_none() {
+        // Honour a workspace's custom db location from the config
+        (
+            config.workspace_db_path(&workspace_name),
+            "code_chunks".to_string(),
+        )
+    } else if workspace_name == "default" {
         (base_db.clone(), "code_chunks".to_string())
     } else {
         (
@@ -71,9 +190,8 @@ pub async fn search_codebase(
             for entry in entries.flatten() {
                 if entry.path().is_dir() {
                     if let Some(name) = entry.file_name().to_str() {
-                        // Only list directories that contain code_chunks.lance
-                        let table_path = entry.path().join("code_chunks.lance");
-                        if table_path.exists() {
+                        // Only list directories that contain a code_chunks table
+                        if Storage::table_exists_on_disk(&entry.path(), "code_chunks") {
                             available.push(name.to_string());
                         }
                     }
@@ -82,8 +200,9 @@ pub async fn search_codebase(
         }
 
         // Also check if default workspace exists
-        let default_table = std::path::Path::new(&base_db).join("code_chunks.lance");
-        if default_table.exists() && !available.contains(&"default".to_string()) {
+        let default_table =
+            Storage::table_exists_on_disk(std::path::Path::new(&base_db), "code_chunks");
+        if default_table && !available.contains(&"default".to_string()) {
             available.insert(0, "default".to_string());
         }
 
@@ -107,78 +226,323 @@ pub async fn search_codebase(
         return Err(CodeRagError::Database(error_msg));
     }
 
+    verify_index(
+        workspace_path,
+        &IndexMeta::current(config),
+        config.index_version_check,
+    )?;
+
     let storage = Storage::new(&actual_db, &table_name)
         .await
-        .map_err(|e| CodeRagError::Database(e.to_string()))?;
-
-    // Silence embedder logs if outputting JSON
-    let embedder = if json {
-        Embedder::new_with_quiet(
-            true,
-            config.embedding_model.clone(),
-            config.reranker_model.clone(),
-            config.embedding_model_path.clone(),
-            config.reranker_model_path.clone(),
-            config.device.clone(),
-        )?
+        .map_err(|e| CodeRagError::Database(e.to_string()))?
+        .with_shards(config.shard_count, config.shard_key);
+
+    if let Some(as_of) = as_of {
+        let version = storage
+            .checkout(as_of)
+            .await
+            .map_err(|e| CodeRagError::Database(e.to_string()))?;
+        if !json {
+            println!("Using index version {}", version);
+        }
+    }
+
+    let chunk_limit = if aggregate.is_some() {
+        actual_limit * AGGREGATE_FETCH_FACTOR
     } else {
-        Embedder::new(
-            config.embedding_model.clone(),
-            config.reranker_model.clone(),
-            config.embedding_model_path.clone(),
-            config.reranker_model_path.clone(),
-            config.device.clone(),
-        )?
+        actual_limit
     };
 
-    // Initialize BM25 Index (Optional)
-    let bm25_index = BM25Index::new(&actual_db, true, "log").ok();
-    if bm25_index.is_none() {
-        warn!("BM25 index could not be opened. Falling back to pure vector search.");
-        warn!("BM25 index could not be opened. Falling back to pure vector search.");
+    // Query expansion depends on an external LLM, so its results are not reused
+    let cache = (config.result_cache && as_of.is_none() && !expand && !compare_rerank)
+        .then(|| ResultCache::for_database(Path::new(&actual_db)));
+    let cache_version = match &cache {
+        Some(_) => Some(
+            storage
+                .version()
+                .await
+                .map_err(|e| CodeRagError::Database(e.to_string()))?,
+        ),
+        None => None,
+    };
+    let cache_key = serde_json::to_string(&CacheKey {
+        query: &query,
+        limit: chunk_limit,
+        ext: ext.as_deref(),
+        dir: dir.as_deref(),
+        in_file: in_file.as_deref(),
+        min_lines,
+        required_mode,
+        item_kind,
+        no_rerank,
+        workspace: workspace.as_deref(),
+        max_tokens,
+        max_per_file,
+        test_filter,
+        include_vectors,
+        vector_weight: config.vector_weight,
+        bm25_weight: config.bm25_weight,
+        rrf_k: config.rrf_k,
+        rerank_blend: config.rerank_blend,
+        rerank_skip_extensions: &config.rerank_skip_extensions,
+        rerank_cascade_k: config.rerank_cascade_k,
+        rerank_disagreement: config.rerank_disagreement(),
+        multi_query_rerank: config.multi_query_rerank(),
+        query_routing: config.query_routing(),
+        score_calibration: config.score_calibration,
+        confidence: config.confidence(),
+        snippets: config.snippets(),
+        importance_boost: config.importance_boost,
+        path_boost: config.path_boost,
+        embedding_model: &config.embedding_model,
+        embedding_model_path: config.embedding_model_path.as_deref(),
+        reranker_model: &config.reranker_model,
+        reranker_model_path: config.reranker_model_path.as_deref(),
+        normalize_embeddings: config.should_normalize_embeddings(),
+        normalize_identifiers: config.normalize_identifiers,
+        keyword_fallback: config.keyword_fallback,
+        shard_count: config.shard_count,
+        shard_key: config.shard_key,
+    })?;
+    let cached = cache
+        .as_ref()
+        .zip(cache_version)
+        .and_then(|(cache, version)| cache.get(version, &cache_key));
+    if cached.is_some() {
+        info!("Serving results from the result cache");
     }
 
-    // Initialize Query Expander (Optional)
-    let expander = if config.llm_enabled {
-        let client = OllamaClient::new(&config.llm_host, &config.llm_model);
-        Some(Arc::new(QueryExpander::new(Arc::new(client))))
+    // A cache hit needs no models or keyword index
+    let (embedder, bm25_index, expander) = if cached.is_some() {
+        (None, None, None)
     } else {
-        None
+        // Silence embedder logs if outputting JSON
+        let embedder = load_embedder(config, json)?;
+
+        // Initialize BM25 Index (Optional)
+        // The keyword index keeps no history, so it would not match an older version
+        let bm25_index = if as_of.is_some() {
+            warn!("Keyword search is disabled with --as-of; using vector search only.");
+            None
+        } else {
+            BM25Index::new(&actual_db, true, "log")
+                .ok()
+                .map(|idx| idx.with_normalized_identifiers(config.normalize_identifiers))
+        };
+        if bm25_index.is_none() && as_of.is_none() {
+            warn!("BM25 index could not be opened. Falling back to pure vector search.");
+            warn!("BM25 index could not be opened. Falling back to pure vector search.");
+        }
+
+        // Initialize Query Expander (Optional)
+        let expander = if config.llm_enabled {
+            let client = OllamaClient::new(&config.llm_host, &config.llm_model);
+            Some(Arc::new(
+                QueryExpander::new(Arc::new(client))
+                    .with_probe_timeout(std::time::Duration::from_millis(config.llm_timeout_ms))
+                    .with_circuit_breaker(config.llm_circuit_breaker()),
+            ))
+        } else {
+            None
+        };
+        (embedder, bm25_index, expander)
     };
 
     let searcher = CodeSearcher::new(
         Some(Arc::new(storage)),
-        Some(Arc::new(embedder)),
+        embedder.map(Arc::new),
         bm25_index.map(Arc::new),
         expander,
         config.vector_weight,
         config.bm25_weight,
         config.rrf_k as f64,
-    );
+    )
+    .with_rerank_blend(config.rerank_blend)
+    .with_rerank_skip_extensions(config.rerank_skip_extensions.clone())
+    .with_rerank_batch_size(config.rerank_batch_size)
+    .with_rerank_cascade_k(config.rerank_cascade_k)
+    .with_rerank_disagreement(config.rerank_disagreement())
+    .with_multi_query_rerank(config.multi_query_rerank())
+    .with_query_routing(config.query_routing())
+    .with_score_calibration(config.score_calibration)
+    .with_confidence(config.confidence())
+    .with_snippets(config.snippets())
+    .with_importance_boost(config.importance_boost)
+    .with_path_boost(config.path_boost)
+    .with_max_per_file(max_per_file)
+    .with_test_filter(test_filter)
+    .with_in_file(in_file)
+    .with_min_lines(min_lines)
+    .with_required_mode(required_mode)
+    .with_item_kind(item_kind)
+    .with_include_vectors(include_vectors)
+    .with_keyword_fallback(config.keyword_fallback);
 
-    if !json {
+    if !plain_stdout {
         println!("Searching for: '{}'", query);
     }
 
-    let search_results = searcher
-        .semantic_search(
-            &query,
-            actual_limit,
-            ext,
-            dir,
-            no_rerank,
-            workspace,
-            max_tokens,
-            expand,
-        )
-        .await
-        .map_err(|e| CodeRagError::Search(e.to_string()))?;
+    if compare_rerank {
+        let comparison = searcher
+            .compare_rerank(&query, actual_limit, ext, dir, workspace)
+            .await
+            .map_err(|e| CodeRagError::Search(e.to_string()))?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&comparison)?);
+        } else {
+            print!("\n{}", format_rerank_comparison(&comparison));
+        }
+        return Ok(());
+    }
 
-    if json {
+    let outcome = match cached {
+        Some(outcome) => outcome,
+        None => {
+            let outcome = searcher
+                .search_with_stats(
+                    &query,
+                    chunk_limit,
+                    ext,
+                    dir,
+                    no_rerank,
+                    workspace.clone(),
+                    max_tokens,
+                    expand,
+                )
+                .await
+                .map_err(|e| CodeRagError::Search(e.to_string()))?;
+            // Results missing a subsystem, like the keyword-only fallback, are not worth keeping
+            if let (Some(cache), Some(version)) = (&cache, cache_version) {
+                if !outcome.degraded && outcome.unavailable.is_empty() {
+                    if let Err(e) = cache.put(version, &cache_key, &outcome) {
+                        warn!("Could not write the result cache: {}", e);
+                    }
+                }
+            }
+            outcome
+        }
+    };
+    let mut search_results = outcome.results;
+
+    // Explanations are written after caching; they are not part of the ranking
+    if config.explain_results && !search_results.is_empty() {
+        let client = OllamaClient::new(&config.llm_host, &config.llm_model);
+        let explainer = ResultExplainer::new(Arc::new(client)).with_top_k(config.explain_top_k);
+        explainer.explain(&query, &mut search_results).await;
+    }
+
+    // Call sites per result, aligned with `search_results`
+    let mut callers: Vec<Vec<CallerRef>> = Vec::new();
+    if let Some(max_callers) = show_callers {
+        for res in &search_results {
+            let found = searcher
+                .find_callers(res, max_callers, workspace.as_deref())
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Caller lookup failed for {}: {}", res.filename, e);
+                    Vec::new()
+                });
+            callers.push(found);
+        }
+    }
+
+    if outcome.degraded {
+        let notice = "Degraded mode: embedding model unavailable, showing keyword matches only";
+        if plain_stdout {
+            eprintln!("{}", notice);
+        } else {
+            println!("{}", notice.yellow());
+        }
+    }
+    // Degraded mode has its own notice, and --as-of turns keyword search off on purpose
+    let unavailable: Vec<String> = outcome
+        .unavailable
+        .iter()
+        .filter(|&&s| match s {
+            Subsystem::Embedder | Subsystem::Reranker if outcome.degraded => false,
+            Subsystem::Bm25 => as_of.is_none(),
+            _ => true,
+        })
+        .map(|s| s.to_string())
+        .collect();
+    if !unavailable.is_empty() {
+        let notice = format!(
+            "Degraded: {} unavailable for this search",
+            unavailable.join(", ")
+        );
+        if plain_stdout {
+            eprintln!("{}", notice);
+        } else {
+            println!("{}", notice.yellow());
+        }
+    }
+
+    if !outcome.expansion_terms.is_empty() {
+        let terms = format!(
+            "Expanded query with: {}",
+            outcome.expansion_terms.join(", ")
+        );
+        if plain_stdout {
+            eprintln!("{}", terms);
+        } else {
+            println!("{}", terms.dimmed());
+        }
+    }
+
+    // Budget summary goes to stderr in JSON mode so stdout stays a plain result array
+    if let (Some(stats), Some(budget)) = (&outcome.context, max_tokens) {
+        let summary = format!(
+            "Context: {} of {} tokens used, {} chunks dropped",
+            stats.context_tokens_used, budget, stats.chunks_dropped
+        );
+        if plain_stdout {
+            eprintln!("{}", summary);
+        } else {
+            println!("{}", summary.dimmed());
+        }
+    }
+
+    if let Some(method) = aggregate {
+        let mut files = aggregate_by_file(&search_results, method);
+        files.truncate(actual_limit);
+        if json {
+            println!("{}", serde_json::to_string_pretty(&files)?);
+        } else {
+            for file in files {
+                println!(
+                    "\n{} {} (Score: {:.4}, {} chunks)",
+                    "Rank".bold(),
+                    file.rank.to_string().cyan(),
+                    file.score,
+                    file.chunk_count
+                );
+                println!(
+                    "{} {}:{}-{}",
+                    "File:".bold(),
+                    file.filename.yellow(),
+                    file.line_start,
+                    file.line_end
+                );
+            }
+        }
+    } else if json && show_callers.is_some() {
+        let rows: Vec<ResultWithCallers> = search_results
+            .iter()
+            .zip(&callers)
+            .map(|(result, callers)| ResultWithCallers { result, callers })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else if json {
         println!("{}", serde_json::to_string_pretty(&search_results)?);
     } else if html {
-        let report = generate_html_report(&query, &search_results)
-            .map_err(|e| CodeRagError::Search(e.to_string()))?;
+        let report = generate_html_report(
+            &query,
+            &search_results,
+            config.report_max_code_bytes,
+            config.highlight_calls,
+            config.report_interactive,
+        )
+        .map_err(|e| CodeRagError::Search(e.to_string()))?;
         let report_path = "results.html";
         fs::write(report_path, report).map_err(CodeRagError::Io)?;
         println!(
@@ -186,13 +550,29 @@ pub async fn search_codebase(
             "HTML Report generated:".green().bold(),
             report_path
         );
+    } else if numbered {
+        print!(
+            "{}",
+            format_numbered(&search_results, config.highlight_calls)
+        );
+    } else if prompt {
+        let text = format_prompt(&query, &search_results, &config.prompt_preamble)
+            .map_err(|e| CodeRagError::Search(e.to_string()))?;
+        print!("{}", text);
     } else {
-        for res in search_results {
+        for (i, res) in search_results.into_iter().enumerate() {
+            let confidence = match res.confidence {
+                Some(Confidence::High) => ", confidence: high",
+                Some(Confidence::Medium) => ", confidence: medium",
+                Some(Confidence::Low) => ", confidence: low",
+                None => "",
+            };
             println!(
-                "\n{} {} (Score: {:.4})",
+                "\n{} {} (Score: {:.4}{})",
                 "Rank".bold(),
                 res.rank.to_string().cyan(),
-                res.score
+                res.score,
+                confidence
             );
             println!(
                 "{} {}:{}-{}",
@@ -201,8 +581,57 @@ pub async fn search_codebase(
                 res.line_start,
                 res.line_end
             );
-            let snippet: String = res.code.lines().take(10).collect::<Vec<&str>>().join("\n");
-            println!("{}\n{}", "---".dimmed(), snippet);
+            if let Some(symbol) = &res.symbol {
+                let qualified: Vec<&str> = res
+                    .scope
+                    .iter()
+                    .map(String::as_str)
+                    .chain([symbol.as_str()])
+                    .collect();
+                println!("{} {}", "Symbol:".bold(), qualified.join("::").cyan());
+            }
+            if let Some(part) = res.part {
+                println!(
+                    "{} part {} of {} (lines {}-{})",
+                    "Fragment:".bold(),
+                    part.index,
+                    part.count,
+                    part.parent_line_start,
+                    part.parent_line_end
+                );
+            }
+            if let ChunkKind::Note { anchor } = &res.kind {
+                println!(
+                    "{} {}:{}-{}",
+                    "Note on:".bold(),
+                    anchor.filename.yellow(),
+                    anchor.line_start,
+                    anchor.line_end
+                );
+            }
+            if let Some(found) = callers.get(i).filter(|c| !c.is_empty()) {
+                let sites: Vec<String> = found
+                    .iter()
+                    .map(|c| format!("{}:{}-{}", c.filename, c.line_start, c.line_end))
+                    .collect();
+                println!("{} {}", "Called from:".bold(), sites.join(", "));
+            }
+            if let Some(explanation) = &res.explanation {
+                println!("{} {}", "Why:".bold(), explanation);
+            }
+            if res.snippets.is_empty() {
+                let snippet: String = res.code.lines().take(10).collect::<Vec<&str>>().join("\n");
+                println!("{}\n{}", "---".dimmed(), snippet);
+            }
+            for snippet in &res.snippets {
+                println!(
+                    "{} lines {}-{}\n{}",
+                    "---".dimmed(),
+                    snippet.line_start,
+                    snippet.line_end,
+                    snippet.code
+                );
+            }
             println!("{}", "---".dimmed());
         }
     }
@@ -210,22 +639,56 @@ pub async fn search_codebase(
     Ok(())
 }
 
-pub fn grep_codebase(pattern: String, json: bool, config: &AppConfig) -> Result<(), CodeRagError> {
+pub async fn grep_codebase(
+    pattern: String,
+    json: bool,
+    options: GrepOptions,
+    indexed: bool,
+    workspace: Option<String>,
+    config: &AppConfig,
+) -> Result<(), CodeRagError> {
+    let storage = if indexed {
+        let workspace_name = workspace.as_deref().unwrap_or("default");
+        let db = config.workspace_db_path(workspace_name);
+        if !Path::new(&db).exists() {
+            return Err(CodeRagError::Database(format!(
+                "Workspace '{}' is not indexed. Run 'code-rag index' first.",
+                workspace_name
+            )));
+        }
+        let storage = Storage::new(&db, "code_chunks")
+            .await
+            .map_err(|e| CodeRagError::Database(e.to_string()))?
+            .with_shards(config.shard_count, config.shard_key);
+        Some(Arc::new(storage))
+    } else {
+        None
+    };
+
     let searcher = CodeSearcher::new(
-        None,
+        storage,
         None,
         None,
         None,
         config.vector_weight,
         config.bm25_weight,
         config.rrf_k as f64,
-    );
+    )
+    .with_rerank_blend(config.rerank_blend)
+    .with_rerank_skip_extensions(config.rerank_skip_extensions.clone());
 
     if !json {
         println!("Grepping for: '{}'", pattern);
     }
 
-    match searcher.grep_search(&pattern, ".") {
+    let result = if indexed {
+        searcher
+            .indexed_grep(&pattern, options, workspace.as_deref())
+            .await
+    } else {
+        searcher.grep_search_with(&pattern, ".", options)
+    };
+    match result {
         Ok(matches) => {
             if json {
                 println!("{}", serde_json::to_string_pretty(&matches)?);
@@ -241,6 +704,90 @@ pub fn grep_codebase(pattern: String, json: bool, config: &AppConfig) -> Result<
     Ok(())
 }
 
+/// Lists the indexed chunks that call `name`, without loading any model.
+pub async fn callers_codebase(
+    name: String,
+    json: bool,
+    workspace: Option<String>,
+    config: &AppConfig,
+) -> Result<(), CodeRagError> {
+    let workspace_name = workspace.as_deref().unwrap_or("default");
+    let db = config.workspace_db_path(workspace_name);
+    if !Path::new(&db).exists() {
+        return Err(CodeRagError::Database(format!(
+            "Workspace '{}' is not indexed. Run 'code-rag index' first.",
+            workspace_name
+        )));
+    }
+    verify_index(
+        Path::new(&db),
+        &IndexMeta::current(config),
+        config.index_version_check,
+    )?;
+    let storage = Storage::new(&db, "code_chunks")
+        .await
+        .map_err(|e| CodeRagError::Database(e.to_string()))?
+        .with_shards(config.shard_count, config.shard_key);
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        None,
+        None,
+        None,
+        config.vector_weight,
+        config.bm25_weight,
+        config.rrf_k as f64,
+    );
+    let callers = searcher
+        .find_callers_of(&name, workspace.as_deref())
+        .await
+        .map_err(|e| CodeRagError::Search(e.to_string()))?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&callers)?);
+        return Ok(());
+    }
+    if callers.is_empty() {
+        println!("No indexed chunk calls '{}'.", name);
+        return Ok(());
+    }
+    println!("Callers of '{}':", name);
+    for caller in callers {
+        println!(
+            "\n{} {}:{}-{}",
+            "File:".bold(),
+            caller.filename.yellow(),
+            caller.line_start,
+            caller.line_end
+        );
+        if let Some(symbol) = &caller.symbol {
+            let qualified: Vec<&str> = caller
+                .scope
+                .iter()
+                .map(String::as_str)
+                .chain([symbol.as_str()])
+                .collect();
+            println!("{} {}", "Symbol:".bold(), qualified.join("::").cyan());
+        }
+        let calls: Vec<&str> = caller
+            .calls
+            .iter()
+            .filter(|call| call_target(call) == name)
+            .map(String::as_str)
+            .collect();
+        println!("{} {}", "Calls:".bold(), calls.join(", "));
+        let snippet: String = caller
+            .code
+            .lines()
+            .take(10)
+            .collect::<Vec<&str>>()
+            .join("\n");
+        println!("{}\n{}", "---".dimmed(), snippet);
+    }
+
+    Ok(())
+}
+
 /// Helper to create a CodeSearcher instance for API/MCP usage.
 /// This skips the CLI spinners/logging but performs the same initialization.
 pub async fn create_searcher(
@@ -249,26 +796,29 @@ pub async fn create_searcher(
 ) -> Result<CodeSearcher, CodeRagError> {
     let actual_db = db_path.unwrap_or_else(|| config.db_path.clone());
 
+    verify_index(
+        Path::new(&actual_db),
+        &IndexMeta::current(config),
+        config.index_version_check,
+    )?;
     let storage = Storage::new(&actual_db, "code_chunks")
         .await
-        .map_err(|e| CodeRagError::Database(e.to_string()))?;
+        .map_err(|e| CodeRagError::Database(e.to_string()))?
+        .with_shards(config.shard_count, config.shard_key);
 
     // Use quiet mode for Embedder to avoid polluting stdout/logs too much
-    let embedder = Embedder::new_with_quiet(
-        true,
-        config.embedding_model.clone(),
-        config.reranker_model.clone(),
-        config.embedding_model_path.clone(),
-        config.reranker_model_path.clone(),
-        config.device.clone(),
-    )?;
+    let embedder = load_embedder(config, true)?;
 
-    let bm25_index = BM25Index::new(&actual_db, true, "log").ok();
+    let bm25_index = BM25Index::new(&actual_db, true, "log")
+        .ok()
+        .map(|idx| idx.with_normalized_identifiers(config.normalize_identifiers));
 
     let expander = if config.llm_enabled {
         let client = crate::llm::client::OllamaClient::new(&config.llm_host, &config.llm_model);
         Some(std::sync::Arc::new(
-            crate::llm::expander::QueryExpander::new(std::sync::Arc::new(client)),
+            crate::llm::expander::QueryExpander::new(std::sync::Arc::new(client))
+                .with_probe_timeout(std::time::Duration::from_millis(config.llm_timeout_ms))
+                .with_circuit_breaker(config.llm_circuit_breaker()),
         ))
     } else {
         None
@@ -276,11 +826,53 @@ pub async fn create_searcher(
 
     Ok(CodeSearcher::new(
         Some(std::sync::Arc::new(storage)),
-        Some(std::sync::Arc::new(embedder)),
+        embedder.map(std::sync::Arc::new),
         bm25_index.map(std::sync::Arc::new),
         expander,
         config.vector_weight,
         config.bm25_weight,
         config.rrf_k as f64,
-    ))
+    )
+    .with_rerank_blend(config.rerank_blend)
+    .with_rerank_skip_extensions(config.rerank_skip_extensions.clone())
+    .with_rerank_batch_size(config.rerank_batch_size)
+    .with_rerank_cascade_k(config.rerank_cascade_k)
+    .with_rerank_disagreement(config.rerank_disagreement())
+    .with_multi_query_rerank(config.multi_query_rerank())
+    .with_query_routing(config.query_routing())
+    .with_score_calibration(config.score_calibration)
+    .with_confidence(config.confidence())
+    .with_snippets(config.snippets())
+    .with_importance_boost(config.importance_boost)
+    .with_path_boost(config.path_boost)
+    .with_keyword_fallback(config.keyword_fallback))
+}
+
+/// Loads the embedding model, or returns `None` when it cannot be loaded and
+/// `keyword_fallback` is set, so searches run on keywords alone.
+fn load_embedder(config: &AppConfig, quiet: bool) -> Result<Option<Embedder>, CodeRagError> {
+    match Embedder::new_with_cache(
+        quiet,
+        config.embedding_model.clone(),
+        config.reranker_model.clone(),
+        config.embedding_model_path.clone(),
+        config.reranker_model_path.clone(),
+        config.device.clone(),
+        ModelCacheOptions {
+            cache_dir: config.model_cache_dir.clone(),
+            offline: config.offline,
+        },
+    ) {
+        Ok(embedder) => Ok(Some(
+            embedder.with_normalization(config.should_normalize_embeddings()),
+        )),
+        Err(e) if config.keyword_fallback => {
+            warn!(
+                "Embedding model unavailable: {}. Falling back to keyword search.",
+                e
+            );
+            Ok(None)
+        }
+        Err(e) => Err(CodeRagError::Embedding(e.to_string())),
+    }
 }
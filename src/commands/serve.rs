@@ -2,6 +2,7 @@ use tracing::info;
 
 use crate::config::AppConfig;
 use crate::core::CodeRagError;
+use crate::index_meta::IndexMeta;
 use crate::server::start_server;
 
 pub async fn serve_api(
@@ -14,19 +15,77 @@ pub async fn serve_api(
     let actual_port = port.unwrap_or(config.server_port);
     let actual_host = host.unwrap_or_else(|| config.server_host.clone());
 
-    info!("Starting server at {}:{}", actual_host, actual_port);
+    match &config.server_socket_path {
+        Some(path) => info!("Starting server on unix socket {}", path),
+        None => info!("Starting server at {}:{}", actual_host, actual_port),
+    }
     start_server(crate::server::ServerStartConfig {
         host: actual_host,
         port: actual_port,
+        socket_path: config.server_socket_path.clone(),
+        max_concurrent_requests: config.server_max_concurrent_requests,
+        cors_allowed_origins: config.server_cors_allowed_origins.clone(),
+        embed_max_in_flight: config.server_embed_max_in_flight,
         db_path: actual_db,
+        workspace_db_paths: config.workspace_db_overrides(),
         embedding_model: config.embedding_model.clone(),
         reranker_model: config.reranker_model.clone(),
         embedding_model_path: config.embedding_model_path.clone(),
         reranker_model_path: config.reranker_model_path.clone(),
+        model_cache_dir: config.model_cache_dir.clone(),
+        normalize_embeddings: config.should_normalize_embeddings(),
+        offline: config.offline,
         device: config.device.clone(),
+        vector_weight: config.vector_weight,
+        bm25_weight: config.bm25_weight,
+        rrf_k: config.rrf_k as f64,
+        rerank_blend: config.rerank_blend,
+        rerank_skip_extensions: config.rerank_skip_extensions.clone(),
+        rerank_batch_size: config.rerank_batch_size,
+        rerank_cascade_k: config.rerank_cascade_k,
+        rerank_disagreement: config.rerank_disagreement(),
+        multi_query_rerank: config.multi_query_rerank(),
+        query_routing: config.query_routing(),
+        score_calibration: config.score_calibration,
+        confidence: config.confidence(),
+        snippets: config.snippets(),
+        importance_boost: config.importance_boost,
+        path_boost: config.path_boost,
+        prompt_preamble: config.prompt_preamble.clone(),
+        prompt_max_tokens: config.prompt_max_tokens,
+        normalize_identifiers: config.normalize_identifiers,
+        default_limit: config.default_limit,
+        default_max_tokens: config.default_max_tokens,
+        keyword_fallback: config.keyword_fallback,
+        msgpack: config.server_msgpack,
+        reload_config: config.reload_config.then(|| config.clone()),
+        warmup: config.server_warmup,
+        preload_workspaces: if config.server_preload_workspaces {
+            let mut names: Vec<String> = config
+                .workspaces
+                .keys()
+                .filter(|name| name.as_str() != "default")
+                .cloned()
+                .collect();
+            names.sort();
+            names
+        } else {
+            Vec::new()
+        },
+        preload_concurrency: config.server_preload_concurrency,
+        cursor_ttl_secs: config.server_cursor_ttl_secs,
+        cursor_max_results: config.server_cursor_max_results,
+        shard_count: config.shard_count,
+        shard_key: config.shard_key,
+        index_meta: Some(IndexMeta::current(config)),
+        index_version_check: config.index_version_check,
         llm_enabled: config.llm_enabled,
         llm_host: config.llm_host.clone(),
+        llm_timeout_ms: config.llm_timeout_ms,
+        llm_circuit_breaker: config.llm_circuit_breaker(),
         llm_model: config.llm_model.clone(),
+        explain_results: config.explain_results,
+        explain_top_k: config.explain_top_k,
     })
     .await
     .map_err(|e| CodeRagError::Server(e.to_string()))?;
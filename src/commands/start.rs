@@ -5,6 +5,7 @@ use tracing::{error, info};
 
 use crate::commands::{mcp, serve, watch};
 use crate::config::AppConfig;
+use crate::storage::Storage;
 
 pub async fn run(config: &AppConfig) -> Result<()> {
     if !config.enable_server && !config.enable_mcp && !config.enable_watch {
@@ -24,23 +25,15 @@ pub async fn run(config: &AppConfig) -> Result<()> {
             config
                 .workspaces
                 .iter()
-                .map(|(name, path)| (name.clone(), path.clone()))
+                .map(|(name, w)| (name.clone(), w.source().to_string()))
                 .collect()
         };
 
         for (name, source_path) in workspaces_to_index {
-            let db_path = if name == "default" {
-                config.db_path.clone()
-            } else {
-                Path::new(&config.db_path)
-                    .join(&name)
-                    .to_string_lossy()
-                    .to_string()
-            };
-
-            // Check if workspace is empty (no code_chunks.lance table)
-            let lance_table_path = Path::new(&db_path).join("code_chunks.lance");
-            if !lance_table_path.exists() {
+            let db_path = config.workspace_db_path(&name);
+
+            // Check if workspace is empty (no code_chunks table)
+            if !Storage::table_exists_on_disk(Path::new(&db_path), "code_chunks") {
                 info!(
                     "Workspace '{}' is empty. Triggering initial indexing from '{}'...",
                     name, source_path
@@ -55,6 +48,9 @@ pub async fn run(config: &AppConfig) -> Result<()> {
                     force: false,            // Don't force reindex
                     batch_size: Some(config.batch_size),
                     threads: config.threads,
+                    allow_mass_delete: false,
+                    git: None,
+                    quiet: true,
                 };
 
                 if let Err(e) = crate::commands::index::index_codebase(index_opts, config).await {
@@ -83,11 +79,7 @@ pub async fn run(config: &AppConfig) -> Result<()> {
             index_targets.push(PathBuf::from(&config.db_path));
         } else {
             for name in config.workspaces.keys() {
-                if name == "default" {
-                    index_targets.push(PathBuf::from(&config.db_path));
-                } else {
-                    index_targets.push(Path::new(&config.db_path).join(name));
-                }
+                index_targets.push(PathBuf::from(config.workspace_db_path(name)));
             }
         }
 
@@ -130,6 +122,10 @@ pub async fn run(config: &AppConfig) -> Result<()> {
 
     // 3. Start Watcher
     if config.enable_watch {
+        // The server's reloader covers the watchers too; without one, reload for them
+        if config.reload_config && !config.enable_server {
+            crate::server::config_reload::spawn_config_reloader(config.clone(), None)?;
+        }
         if config.workspaces.is_empty() {
             let config_clone = config.clone();
             set.spawn(async move {
@@ -140,18 +136,13 @@ pub async fn run(config: &AppConfig) -> Result<()> {
                     .context("Watcher task failed")
             });
         } else {
-            for (name, path_str) in &config.workspaces {
+            for (name, workspace) in &config.workspaces {
                 let config_clone = config.clone();
                 let name = name.clone();
-                let path_to_watch = path_str.clone();
+                let path_to_watch = workspace.source().to_string();
 
-                // Replicate logic from specific WorkspaceManager to align DB paths
-                let db_path_buf = if name == "default" {
-                    PathBuf::from(&config.db_path)
-                } else {
-                    Path::new(&config.db_path).join(&name)
-                };
-                let db_path = db_path_buf.to_string_lossy().to_string();
+                // Same DB location as indexing and the WorkspaceManager
+                let db_path = config.workspace_db_path(&name);
 
                 set.spawn(async move {
                     info!(
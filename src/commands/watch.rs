@@ -3,10 +3,11 @@ use tracing::{error, info};
 use crate::bm25::BM25Index;
 use crate::config::AppConfig;
 use crate::core::CodeRagError;
-use crate::embedding::Embedder;
+use crate::embedding::{Embedder, ModelCacheOptions};
 use crate::indexer::CodeChunker;
 use crate::storage::Storage;
-use crate::watcher::start_watcher;
+use crate::watcher::{set_exclusions, start_watcher};
+use std::path::Path;
 
 pub async fn watch_codebase(
     path: Option<String>,
@@ -20,34 +21,48 @@ pub async fn watch_codebase(
     info!("Initializing watcher for path: {}", actual_path);
 
     // 1. Initialize Components
-    let embedder = Embedder::new(
+    let embedder = Embedder::new_with_cache(
+        false,
         config.embedding_model.clone(),
         config.reranker_model.clone(),
         config.embedding_model_path.clone(),
         config.reranker_model_path.clone(),
         config.device.clone(),
-    )?;
+        ModelCacheOptions {
+            cache_dir: config.model_cache_dir.clone(),
+            offline: config.offline,
+        },
+    )?
+    .with_normalization(config.should_normalize_embeddings());
     embedder
         .init_reranker()
         .map_err(|e: fastembed::Error| CodeRagError::Embedding(e.to_string()))?;
 
     let storage = Storage::new(&actual_db, &workspace)
         .await
-        .map_err(|e| CodeRagError::Database(e.to_string()))?;
+        .map_err(|e| CodeRagError::Database(e.to_string()))?
+        .with_shards(config.shard_count, config.shard_key);
     storage
         .init(embedder.dim())
         .await
         .map_err(|e| CodeRagError::Database(e.to_string()))?; // Ensure schema
 
     let bm25_index = match BM25Index::new(&actual_db, false, &config.merge_policy) {
-        Ok(idx) => idx,
+        Ok(idx) => idx.with_normalized_identifiers(config.normalize_identifiers),
         Err(e) => {
             error!("Failed to initialize BM25 index: {}", e);
             return Err(CodeRagError::Tantivy(e.to_string()));
         }
     };
 
-    let chunker = CodeChunker::new(config.chunk_size, config.chunk_overlap);
+    let chunker = CodeChunker::new(config.chunk_size, config.chunk_overlap)
+        .with_traversal_limits(config.chunk_max_depth, config.chunk_max_nodes)
+        .with_min_chunk_lines(config.chunk_min_lines)
+        .with_part_metadata(config.chunk_part_metadata)
+        .with_line_splitting(config.chunk_split_on_lines)
+        .with_size_balancing(config.chunk_balance_target)
+        .with_comment_stripping(config.strip_comments.clone())
+        .with_index_root(Path::new(&actual_path));
 
     info!(
         "✓ File Watcher started successfully for workspace '{}'",
@@ -55,13 +70,16 @@ pub async fn watch_codebase(
     );
 
     // 2. Start Watcher
+    set_exclusions(config.exclusions.clone());
     start_watcher(
         &actual_path,
+        &config.watch_subpaths,
         storage,
         embedder,
         bm25_index,
         chunker,
         workspace,
+        config.index_file_permissions,
     )
     .await
     .map_err(|e| CodeRagError::Generic(e.to_string()))?;
@@ -1,16 +1,105 @@
-use config::{Config, ConfigError, Environment, File};
-use serde::Deserialize;
-use std::path::PathBuf;
+use crate::llm::CircuitBreakerPolicy;
+use crate::search::{
+    ConfidenceThresholds, MultiQueryRerank, QueryRouting, RerankCombine, RerankDisagreement,
+    ScoreCalibration, SnippetWindows,
+};
+use crate::storage::ShardKey;
+use config::builder::DefaultState;
+use config::{Config, ConfigBuilder, ConfigError, Environment, File, Source};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// A configured workspace: a source path, or a table that also sets where its index lives.
+///
+/// ```toml
+/// [workspaces]
+/// small = "/src/small"
+/// large = { source = "/src/large", db = "/mnt/fast/large-index" }
+/// ```
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkspaceConfig {
+    Source(String),
+    Detailed(WorkspaceLocation),
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceLocation {
+    pub source: String,
+    /// Index directory; defaults to `db_path/<name>`
+    pub db: Option<String>,
+}
+
+impl WorkspaceConfig {
+    /// Path of the code indexed into this workspace.
+    pub fn source(&self) -> &str {
+        match self {
+            Self::Source(source) => source,
+            Self::Detailed(location) => &location.source,
+        }
+    }
+
+    /// Custom index directory, if one is configured.
+    pub fn db(&self) -> Option<&str> {
+        match self {
+            Self::Source(_) => None,
+            Self::Detailed(location) => location.db.as_deref(),
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub db_path: String,
     pub default_index_path: String,
+    /// Tables each workspace index is spread over (1 = unsharded)
+    pub shard_count: usize,
+    /// How files are assigned to shards (directory, file)
+    pub shard_key: ShardKey,
     pub default_limit: usize,
+    pub default_max_tokens: Option<usize>,
+    /// Serve keyword-only results when the embedding model cannot be loaded or fails
+    pub keyword_fallback: bool,
     pub server_host: String,
     pub server_port: u16,
+    pub server_socket_path: Option<String>,
+    /// Search requests served at once; extra requests get 503 (unset = unlimited)
+    pub server_max_concurrent_requests: Option<usize>,
+    /// Web origins allowed to call the API from a browser (unset = any origin)
+    pub server_cors_allowed_origins: Option<Vec<String>>,
+    /// Embedding and reranking calls run at once across all server searches
+    pub server_embed_max_in_flight: usize,
+    /// Send search responses as MessagePack to clients whose `Accept` header asks for it
+    pub server_msgpack: bool,
+    /// Warm up the search pipeline on the default workspace at server start
+    pub server_warmup: bool,
+    /// Load every configured workspace at startup instead of on its first request
+    pub server_preload_workspaces: bool,
+    /// Workspaces loaded at once during the startup preload
+    pub server_preload_concurrency: usize,
+    /// Seconds an unused search cursor stays valid
+    pub server_cursor_ttl_secs: u64,
+    /// Results captured for a paginated search, across all of its pages
+    pub server_cursor_max_results: usize,
     pub exclusions: Vec<String>,
+    /// Only index files with these extensions (empty = all supported files)
+    pub include_extensions: Vec<String>,
+    /// Tree-sitter grammars loaded at startup: file extension to shared library path
+    #[serde(default)]
+    pub custom_grammars: std::collections::HashMap<String, String>,
+    /// Per file extension (`*` for the rest), whether comments are left out of embeddings
+    #[serde(default)]
+    pub strip_comments: std::collections::HashMap<String, bool>,
+    /// Index each detected package as its own workspace when none are configured
+    pub auto_workspaces: bool,
+    /// When no workspaces are configured, index each git worktree as its own workspace
+    pub worktree_workspaces: bool,
+    /// Files whose presence marks a directory as a package
+    pub auto_workspace_markers: Vec<String>,
+    /// How many directory levels below the index path to look for packages
+    pub auto_workspace_depth: usize,
     pub log_level: String,
     pub log_format: String,
     pub log_to_file: bool,
@@ -19,31 +108,187 @@ pub struct AppConfig {
     pub reranker_model: String,
     pub embedding_model_path: Option<String>,
     pub reranker_model_path: Option<String>,
+    pub model_cache_dir: Option<String>,
+    /// Code snippets embedded before indexing (0 = single short string)
+    pub embedding_warmup_samples: usize,
+    /// L2-normalize embeddings; defaults to on for user-defined models only
+    pub normalize_embeddings: Option<bool>,
+    pub offline: bool,
     pub chunk_size: usize,
     pub chunk_overlap: usize,
+    pub chunk_max_depth: usize,
+    pub chunk_max_nodes: usize,
+    pub chunk_min_lines: usize,
+    /// Record part number and parent range on chunks split from large units
+    pub chunk_part_metadata: bool,
+    /// Split large units at line ends rather than exact character offsets
+    pub chunk_split_on_lines: bool,
+    /// Chunk size the post-chunking balancing pass aims for (0 = off)
+    pub chunk_balance_target: usize,
     pub max_file_size_bytes: usize,
+    pub index_asset_names: bool,
+    /// Record each file's permission bits and owner (Unix only)
+    pub index_file_permissions: bool,
+    /// What to do when an index was built with another format version or model
+    pub index_version_check: crate::index_meta::IndexVersionCheck,
+    pub normalize_identifiers: bool,
+    /// Embed identical chunk text once per indexing run
+    pub dedup_chunks: bool,
+    /// Keep embeddings on disk here across indexing runs (unset = no persistent cache)
+    pub embedding_cache_dir: Option<String>,
+    /// Prune the least recently used embeddings beyond this size after indexing (0 = no cap)
+    pub embedding_cache_max_bytes: u64,
+    /// Refuse to index unless this much disk space stays free (0 = no headroom)
+    pub min_free_disk_bytes: u64,
+    /// Projected index size per byte of source, for the free-space check (0 = no projection)
+    pub index_space_factor: f32,
+    /// Re-check free space every this many batches while indexing (0 = only before)
+    pub disk_space_recheck_batches: usize,
+    /// Index prose notes from `notes_file` alongside the code they are anchored to
+    pub index_notes: bool,
+    /// JSON Lines notes file, relative to the indexed path
+    pub notes_file: String,
+    /// Private key for `ssh://` index sources (unset = password env var, then agent)
+    pub ssh_identity_file: Option<String>,
+    /// known_hosts file checked for `ssh://` index sources (unset = ~/.ssh/known_hosts)
+    pub ssh_known_hosts: Option<String>,
+    pub max_stale_fraction: f32,
     pub vector_weight: f32,
     pub bm25_weight: f32,
     pub rrf_k: f32,
+    pub rerank_blend: f32,
+    pub rerank_skip_extensions: Vec<String>,
+    /// Candidates per reranker call (unset = all in one call)
+    pub rerank_batch_size: Option<usize>,
+    /// Only rerank this many top candidates by fused score (unset = all)
+    pub rerank_cascade_k: Option<usize>,
+    /// Warn when the reranked order's rank correlation with retrieval falls below this
+    pub rerank_disagreement_threshold: Option<f32>,
+    /// On such a disagreement, rerank a candidate pool twice as large once
+    pub rerank_disagreement_refetch: bool,
+    /// Also rerank expanded searches against each expansion query, combining by max or mean
+    pub multi_query_rerank: Option<RerankCombine>,
+    /// Cap on queries × candidates scored by multi-query reranking
+    pub multi_query_rerank_max_pairs: usize,
+    /// Shift weight toward BM25 for identifier-like queries and toward vectors for prose
+    pub query_routing: bool,
+    pub query_routing_boost: f32,
+    pub query_routing_prose_min_words: usize,
+    /// How `normalized_score` is derived from raw scores (minmax, softmax)
+    pub score_calibration: ScoreCalibration,
+    /// Label results high/medium/low from their normalized score
+    pub confidence_buckets: bool,
+    pub confidence_high: f32,
+    pub confidence_medium: f32,
+    /// Query-relevant windows added to each result (0 = none)
+    pub snippet_windows: usize,
+    /// Lines per snippet window
+    pub snippet_window_lines: usize,
+    /// Ranking boost for chunks whose function is called from many places (0 = off)
+    pub importance_boost: f32,
+    /// Ranking boost for chunks whose file path mentions the query's words (0 = off)
+    pub path_boost: f32,
     pub merge_policy: String, // "log", "sum", "replace"
+    pub report_max_code_bytes: usize,
+    /// Mark where a result's calls occur in HTML reports and `--format numbered`
+    pub highlight_calls: bool,
+    /// Embed the results and a client-side filter box in HTML reports
+    pub report_interactive: bool,
+    /// Preamble template of `search --prompt`; `{{ query }}` is the search query
+    pub prompt_preamble: String,
+    /// Token budget of `search --prompt` when no `--max-tokens` is given
+    pub prompt_max_tokens: usize,
+    /// Reuse `search` outcomes stored on disk until the index changes
+    pub result_cache: bool,
     pub telemetry_enabled: bool,
     pub telemetry_endpoint: String,
     pub device: String, // "auto", "cpu", "cuda", "metal"
     pub batch_size: usize,
+    /// Also flush an indexing batch once its chunks hold this many bytes of code (0 = no cap)
+    pub batch_max_bytes: usize,
     pub threads: Option<usize>,
     pub priority: String, // "low", "normal", "high"
+    /// At low priority, pause this many times as long as each indexing batch took
+    pub low_priority_pause_ratio: f32,
     pub llm_enabled: bool,
     pub llm_model: String,
     pub llm_host: String,
+    pub llm_timeout_ms: u64,
+    /// Consecutive expansion failures that pause expansion (0 = never pause)
+    pub llm_breaker_failures: u32,
+    /// The failures must happen within this many seconds of the first one
+    pub llm_breaker_window_secs: u64,
+    /// Seconds expansion stays paused before the LLM is tried again
+    pub llm_breaker_cooldown_secs: u64,
+    /// Have the LLM explain in one sentence why each top result matches
+    pub explain_results: bool,
+    /// Results explained per search when `explain_results` is on
+    pub explain_top_k: usize,
 
     // Service Flags
     pub enable_server: bool,
     pub enable_mcp: bool,
     pub enable_watch: bool,
+    /// Directories under the watched path to watch instead of all of it
+    pub watch_subpaths: Vec<String>,
+    /// MCP requests handled at once; reading stdin pauses while the limit is reached
+    pub mcp_max_in_flight: usize,
+    /// Write MCP responses in request order instead of as soon as they are ready
+    pub mcp_ordered_responses: bool,
 
     // Multi-Workspace
     #[serde(default)]
-    pub workspaces: std::collections::HashMap<String, String>,
+    pub workspaces: std::collections::HashMap<String, WorkspaceConfig>,
+
+    /// Watch the config file and hot-reload search settings in long-running services
+    pub reload_config: bool,
+
+    /// Config file this configuration was loaded from, if any
+    #[serde(skip)]
+    pub source_path: Option<PathBuf>,
+
+    /// The `--config` path it was loaded with (`None` for the standard locations)
+    #[serde(skip)]
+    pub config_arg: Option<String>,
+}
+
+/// Where the value of a configuration key came from, lowest precedence first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigSource {
+    /// Built-in default
+    Default,
+    /// `~/.config/code-rag/code-rag.toml`
+    UserFile,
+    /// `code-rag.toml` in the current directory
+    CwdFile,
+    /// The file given with `--config`
+    ConfigFile,
+    /// A `CODE_RAG__*` environment variable
+    Env,
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Default => "default",
+            Self::UserFile => "user-file",
+            Self::CwdFile => "cwd-file",
+            Self::ConfigFile => "config-file",
+            Self::Env => "env",
+        })
+    }
+}
+
+/// Configuration sources above the defaults, each with where it comes from.
+type Layers = Vec<(ConfigSource, Box<dyn Source + Send + Sync>)>;
+
+/// A configuration key's effective value and the source it came from (`config sources`).
+#[derive(Debug, Clone, Serialize)]
+pub struct KeySource {
+    pub key: String,
+    pub value: serde_json::Value,
+    pub source: ConfigSource,
 }
 
 impl AppConfig {
@@ -54,98 +299,404 @@ impl AppConfig {
 
     /// Load config from a specific file path
     pub fn from_path(custom_path: Option<String>) -> Result<Self, ConfigError> {
-        // Set all defaults
-        let mut builder = Config::builder()
+        let (layers, source_path) = Self::layers(custom_path.clone())?;
+        let layers: Vec<Box<dyn Source + Send + Sync>> =
+            layers.into_iter().map(|(_, layer)| layer).collect();
+
+        // Build and deserialize with helpful error messages
+        let config = Self::defaults()?.add_source(layers).build()?;
+
+        let mut app_config: Self = config.try_deserialize().map_err(|e| {
+            // Provide helpful error for unknown fields
+            let err_msg = e.to_string();
+            if err_msg.contains("unknown field") {
+                ConfigError::Message(format!(
+                    "Invalid configuration key found.\n{}\n\nPlease check your config file for typos.\nRun 'code-rag --help' to see valid options.",
+                    err_msg
+                ))
+            } else {
+                e
+            }
+        })?;
+        app_config.source_path = source_path;
+        app_config.config_arg = custom_path;
+        Ok(app_config)
+    }
+
+    /// The files [`from_path`](Self::from_path) reads for this configuration, whether
+    /// or not they exist: the `--config` file, or the user and current directory files.
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        match &self.config_arg {
+            Some(path) => vec![PathBuf::from(path)],
+            None => dirs::config_dir()
+                .map(|dir| dir.join("code-rag").join("code-rag.toml"))
+                .into_iter()
+                .chain([PathBuf::from("code-rag.toml")])
+                .collect(),
+        }
+    }
+
+    /// The effective value of every key that is set, and the source that set it.
+    ///
+    /// Sources are read as in [`from_path`](Self::from_path); a key set by several
+    /// comes from the one with the highest precedence. Keys nested in tables, like a
+    /// single workspace, are reported as their top-level key.
+    pub fn sources(custom_path: Option<String>) -> Result<Vec<KeySource>, ConfigError> {
+        let defaults = Self::defaults()?;
+        let mut origins: std::collections::HashMap<String, ConfigSource> = defaults
+            .build_cloned()?
+            .collect()?
+            .into_keys()
+            .map(|key| (key, ConfigSource::Default))
+            .collect();
+
+        let (layers, _) = Self::layers(custom_path)?;
+        for (source, layer) in &layers {
+            for key in layer.collect()?.into_keys() {
+                let top = key.split('.').next().unwrap_or(&key).to_string();
+                origins.insert(top, *source);
+            }
+        }
+
+        let layers: Vec<Box<dyn Source + Send + Sync>> =
+            layers.into_iter().map(|(_, layer)| layer).collect();
+        let merged = defaults.add_source(layers).build()?.collect()?;
+        let mut sources: Vec<KeySource> = merged
+            .into_iter()
+            .map(|(key, value)| {
+                Ok(KeySource {
+                    source: origins.get(&key).copied().unwrap_or(ConfigSource::Default),
+                    value: value.try_deserialize()?,
+                    key,
+                })
+            })
+            .collect::<Result<_, ConfigError>>()?;
+        sources.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(sources)
+    }
+
+    /// The configuration sources above the defaults, lowest precedence first, and the
+    /// config file in use, if any.
+    fn layers(custom_path: Option<String>) -> Result<(Layers, Option<PathBuf>), ConfigError> {
+        let mut layers: Layers = Vec::new();
+
+        // Load from file (custom path OR defaults)
+        let mut source_path = None;
+        if let Some(path) = custom_path {
+            // Custom config file specified via --config
+            let path_buf = PathBuf::from(&path);
+
+            if !path_buf.exists() {
+                return Err(ConfigError::Message(format!(
+                    "Config file not found: {}",
+                    path
+                )));
+            }
+
+            if path_buf.extension().and_then(|s| s.to_str()) != Some("toml") {
+                return Err(ConfigError::Message(format!(
+                    "Config file must have .toml extension: {}",
+                    path
+                )));
+            }
+
+            layers.push((
+                ConfigSource::ConfigFile,
+                Box::new(File::from(path_buf.clone())),
+            ));
+            source_path = Some(path_buf);
+        } else {
+            // No custom path - try standard locations
+            // 1. File: ~/.config/code-rag/code-rag.toml (User Config)
+            if let Some(mut home) = dirs::config_dir() {
+                home.push("code-rag");
+                home.push("code-rag.toml");
+                layers.push((
+                    ConfigSource::UserFile,
+                    Box::new(File::from(home).required(false)),
+                ));
+            }
+
+            // 2. File: code-rag.toml (Current Directory) - takes precedence
+            if PathBuf::from("code-rag.toml").exists() {
+                layers.push((ConfigSource::CwdFile, Box::new(File::with_name("code-rag"))));
+                source_path = Some(PathBuf::from("code-rag.toml"));
+            }
+        }
+
+        // 3. Environment: CODE_RAG__KEY=VALUE (always checked, highest precedence)
+        layers.push((
+            ConfigSource::Env,
+            Box::new(Environment::with_prefix("CODE_RAG").separator("__")),
+        ));
+        Ok((layers, source_path))
+    }
+
+    /// Built-in defaults of every key that has one.
+    fn defaults() -> Result<ConfigBuilder<DefaultState>, ConfigError> {
+        Config::builder()
             .set_default("db_path", "./.lancedb")?
             .set_default("default_index_path", ".")?
+            .set_default("shard_count", 1)?
+            .set_default("shard_key", "directory")?
             .set_default("default_limit", 5)?
+            .set_default("keyword_fallback", true)?
             .set_default("server_host", "127.0.0.1")?
             .set_default("server_port", 3000)?
+            .set_default("server_embed_max_in_flight", 4)?
+            .set_default("server_msgpack", true)?
+            .set_default("server_warmup", false)?
+            .set_default("server_preload_workspaces", false)?
+            .set_default("server_preload_concurrency", 4)?
+            .set_default("server_cursor_ttl_secs", 300)?
+            .set_default("server_cursor_max_results", 500)?
             .set_default("exclusions", Vec::<String>::new())?
+            .set_default("auto_workspaces", false)?
+            .set_default("worktree_workspaces", false)?
+            .set_default(
+                "auto_workspace_markers",
+                vec!["Cargo.toml", "package.json", "pyproject.toml", "go.mod"],
+            )?
+            .set_default("auto_workspace_depth", 3)?
+            .set_default("include_extensions", Vec::<String>::new())?
+            .set_default(
+                "custom_grammars",
+                std::collections::HashMap::<String, String>::new(),
+            )?
+            .set_default(
+                "strip_comments",
+                std::collections::HashMap::<String, bool>::new(),
+            )?
             .set_default("log_level", "warn")? // Changed from "info" to "warn"
             .set_default("log_format", "text")?
             .set_default("log_to_file", false)?
             .set_default("log_dir", "logs")?
             .set_default("embedding_model", "nomic-embed-text-v1.5")?
             .set_default("reranker_model", "bge-reranker-base")?
+            .set_default("offline", false)?
+            .set_default("embedding_warmup_samples", 0)?
             .set_default("chunk_size", 1024)?
             .set_default("chunk_overlap", 128)?
+            .set_default("chunk_max_depth", crate::indexer::DEFAULT_MAX_DEPTH as u64)?
+            .set_default("chunk_max_nodes", crate::indexer::DEFAULT_MAX_NODES as u64)?
+            .set_default("chunk_min_lines", 1)?
+            .set_default("chunk_part_metadata", true)?
+            .set_default("chunk_split_on_lines", true)?
+            .set_default("chunk_balance_target", 0)?
             .set_default("max_file_size_bytes", 10 * 1024 * 1024)?
+            .set_default("index_asset_names", false)?
+            .set_default("index_file_permissions", false)?
+            .set_default("index_version_check", "warn")?
+            .set_default("normalize_identifiers", false)?
+            .set_default("dedup_chunks", false)?
+            .set_default("embedding_cache_max_bytes", 1024_u64 * 1024 * 1024)?
+            .set_default("min_free_disk_bytes", 512_u64 * 1024 * 1024)?
+            .set_default("index_space_factor", 3.0)?
+            .set_default("disk_space_recheck_batches", 10)?
+            .set_default("index_notes", true)?
+            .set_default("notes_file", ".code-rag/notes.jsonl")?
+            .set_default("max_stale_fraction", 0.5)?
             .set_default("vector_weight", 1.0)?
             .set_default("bm25_weight", 1.0)?
             .set_default("rrf_k", 60.0)?
+            .set_default("rerank_blend", 1.0)?
+            .set_default("rerank_skip_extensions", Vec::<String>::new())?
+            .set_default("rerank_disagreement_refetch", false)?
+            .set_default("multi_query_rerank_max_pairs", 200)?
+            .set_default("query_routing", false)?
+            .set_default("query_routing_boost", 2.0)?
+            .set_default("query_routing_prose_min_words", 4)?
+            .set_default("score_calibration", "minmax")?
+            .set_default("confidence_buckets", false)?
+            .set_default("confidence_high", 0.8)?
+            .set_default("confidence_medium", 0.5)?
+            .set_default("snippet_windows", 0)?
+            .set_default("snippet_window_lines", 7)?
+            .set_default("importance_boost", 0.0)?
+            .set_default("path_boost", 0.0)?
             .set_default("merge_policy", "log")?
+            .set_default(
+                "report_max_code_bytes",
+                crate::reporting::DEFAULT_REPORT_MAX_CODE_BYTES as u64,
+            )?
+            .set_default("highlight_calls", false)?
+            .set_default("report_interactive", false)?
+            .set_default("prompt_preamble", crate::reporting::DEFAULT_PROMPT_PREAMBLE)?
+            .set_default("prompt_max_tokens", 4000)?
+            .set_default("result_cache", false)?
             .set_default("telemetry_enabled", false)?
             .set_default("telemetry_endpoint", "http://localhost:4317")?
             .set_default("device", "auto")?
             .set_default("batch_size", 256)?
+            .set_default("batch_max_bytes", 16 * 1024 * 1024)?
             .set_default("priority", "normal")?
+            .set_default("low_priority_pause_ratio", 1.0)?
             .set_default("llm_enabled", false)?
             .set_default("llm_model", "mistral")?
             .set_default("llm_host", "http://localhost:11434")?
+            .set_default("llm_timeout_ms", 2000)?
+            .set_default("llm_breaker_failures", 5)?
+            .set_default("llm_breaker_window_secs", 60)?
+            .set_default("llm_breaker_cooldown_secs", 30)?
+            .set_default("explain_results", false)?
+            .set_default(
+                "explain_top_k",
+                crate::llm::explainer::DEFAULT_EXPLAIN_TOP_K as u64,
+            )?
             .set_default("enable_server", false)?
             .set_default("enable_mcp", false)?
             .set_default("enable_watch", false)?
+            .set_default("watch_subpaths", Vec::<String>::new())?
+            .set_default("mcp_max_in_flight", 8)?
+            .set_default("mcp_ordered_responses", false)?
+            .set_default("reload_config", false)?
             .set_default(
                 "workspaces",
                 std::collections::HashMap::<String, String>::new(),
-            )?;
+            )
+    }
 
-        // Load from file (custom path OR defaults)
-        if let Some(path) = custom_path {
-            // Custom config file specified via --config
-            let path_buf = PathBuf::from(&path);
+    /// Index directory of a workspace.
+    ///
+    /// Uses the workspace's configured `db` if set; otherwise `default` lives in
+    /// `db_path` itself and every other workspace in `db_path/<name>`.
+    pub fn workspace_db_path(&self, name: &str) -> String {
+        if let Some(db) = self.workspaces.get(name).and_then(|w| w.db()) {
+            return db.to_string();
+        }
+        if name == "default" {
+            self.db_path.clone()
+        } else {
+            Path::new(&self.db_path)
+                .join(name)
+                .to_string_lossy()
+                .to_string()
+        }
+    }
 
-            if !path_buf.exists() {
-                return Err(ConfigError::Message(format!(
-                    "Config file not found: {}",
-                    path
-                )));
-            }
+    /// Custom index directories of the workspaces that configure one.
+    pub fn workspace_db_overrides(&self) -> std::collections::HashMap<String, String> {
+        self.workspaces
+            .iter()
+            .filter_map(|(name, w)| w.db().map(|db| (name.clone(), db.to_string())))
+            .collect()
+    }
 
-            if path_buf.extension().and_then(|s| s.to_str()) != Some("toml") {
-                return Err(ConfigError::Message(format!(
-                    "Config file must have .toml extension: {}",
-                    path
-                )));
-            }
+    /// Query routing settings, when `query_routing` is enabled.
+    pub fn query_routing(&self) -> Option<QueryRouting> {
+        self.query_routing.then_some(QueryRouting {
+            boost: self.query_routing_boost,
+            prose_min_words: self.query_routing_prose_min_words,
+        })
+    }
 
-            builder = builder.add_source(File::from(path_buf));
-        } else {
-            // No custom path - try standard locations
-            // 1. File: ~/.config/code-rag/code-rag.toml (User Config)
-            if let Some(mut home) = dirs::config_dir() {
-                home.push("code-rag");
-                home.push("code-rag.toml");
-                builder = builder.add_source(File::from(home).required(false));
-            }
+    /// Reranker disagreement check, when `rerank_disagreement_threshold` is set.
+    pub fn rerank_disagreement(&self) -> Option<RerankDisagreement> {
+        self.rerank_disagreement_threshold
+            .map(|threshold| RerankDisagreement {
+                threshold,
+                refetch: self.rerank_disagreement_refetch,
+            })
+    }
 
-            // 2. File: code-rag.toml (Current Directory) - takes precedence
-            if PathBuf::from("code-rag.toml").exists() {
-                builder = builder.add_source(File::with_name("code-rag"));
-            }
-        }
+    /// Multi-query reranking, when `multi_query_rerank` is set.
+    pub fn multi_query_rerank(&self) -> Option<MultiQueryRerank> {
+        self.multi_query_rerank.map(|combine| MultiQueryRerank {
+            combine,
+            max_pairs: self.multi_query_rerank_max_pairs,
+        })
+    }
 
-        // 3. Environment: CODE_RAG__KEY=VALUE (always checked, lowest precedence)
-        builder = builder.add_source(Environment::with_prefix("CODE_RAG").separator("__"));
+    /// Circuit breaker of query expansion, unless `llm_breaker_failures` is 0.
+    pub fn llm_circuit_breaker(&self) -> Option<CircuitBreakerPolicy> {
+        (self.llm_breaker_failures > 0).then(|| CircuitBreakerPolicy {
+            failure_threshold: self.llm_breaker_failures,
+            window: std::time::Duration::from_secs(self.llm_breaker_window_secs),
+            cooldown: std::time::Duration::from_secs(self.llm_breaker_cooldown_secs),
+        })
+    }
 
-        // Build and deserialize with helpful error messages
-        let config = builder.build()?;
+    /// Confidence bucket thresholds, when `confidence_buckets` is enabled.
+    pub fn confidence(&self) -> Option<ConfidenceThresholds> {
+        self.confidence_buckets.then_some(ConfidenceThresholds {
+            high: self.confidence_high,
+            medium: self.confidence_medium,
+        })
+    }
 
-        config.try_deserialize().map_err(|e| {
-            // Provide helpful error for unknown fields
-            let err_msg = e.to_string();
-            if err_msg.contains("unknown field") {
-                ConfigError::Message(format!(
-                    "Invalid configuration key found.\n{}\n\nPlease check your config file for typos.\nRun 'code-rag --help' to see valid options.",
-                    err_msg
-                ))
-            } else {
-                e
-            }
+    /// Snippet windows of each result, unless `snippet_windows` is 0.
+    pub fn snippets(&self) -> Option<SnippetWindows> {
+        (self.snippet_windows > 0).then_some(SnippetWindows {
+            count: self.snippet_windows,
+            lines: self.snippet_window_lines.max(1),
         })
     }
 
+    /// Whether files with extension `ext` pass the `include_extensions` allowlist.
+    pub fn includes_extension(&self, ext: &str) -> bool {
+        self.include_extensions.is_empty()
+            || self
+                .include_extensions
+                .iter()
+                .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(ext))
+    }
+
+    /// Whether embeddings are L2-normalized after inference.
+    ///
+    /// Unless set explicitly, only user-defined models (`embedding_model_path`) are normalized.
+    pub fn should_normalize_embeddings(&self) -> bool {
+        self.normalize_embeddings
+            .unwrap_or(self.embedding_model_path.is_some())
+    }
+
+    /// Lists settings that differ from `other` but only take effect after a restart.
+    pub fn restart_required_changes(&self, other: &AppConfig) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        macro_rules! check {
+            ($($field:ident),*) => {
+                $(if self.$field != other.$field {
+                    changed.push(stringify!($field));
+                })*
+            };
+        }
+        check!(
+            db_path,
+            shard_count,
+            shard_key,
+            server_host,
+            server_port,
+            server_socket_path,
+            server_max_concurrent_requests,
+            server_cors_allowed_origins,
+            server_embed_max_in_flight,
+            server_warmup,
+            server_preload_workspaces,
+            server_preload_concurrency,
+            server_cursor_ttl_secs,
+            server_cursor_max_results,
+            custom_grammars,
+            embedding_model,
+            reranker_model,
+            embedding_model_path,
+            reranker_model_path,
+            model_cache_dir,
+            normalize_embeddings,
+            offline,
+            device,
+            llm_enabled,
+            llm_model,
+            llm_host,
+            llm_timeout_ms,
+            llm_breaker_failures,
+            llm_breaker_window_secs,
+            llm_breaker_cooldown_secs,
+            explain_results,
+            explain_top_k,
+            workspaces
+        );
+        changed
+    }
+
     /// For backward compatibility - old load function
     pub fn load(include_files: bool) -> Result<Self, ConfigError> {
         if include_files {
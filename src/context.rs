@@ -1,5 +1,6 @@
 use crate::search::SearchResult;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use tiktoken_rs::cl100k_base;
 
 #[derive(Debug, Clone)]
@@ -15,6 +16,22 @@ pub struct MergedChunk {
     pub calls: Vec<String>,
 }
 
+/// Summary of how a token budget was spent by [`ContextOptimizer`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContextStats {
+    /// Total tokens of the selected chunks
+    pub context_tokens_used: usize,
+    /// Merged chunks excluded because they did not fit the budget
+    pub chunks_dropped: usize,
+}
+
+/// Selected chunks together with budget statistics.
+#[derive(Debug, Clone)]
+pub struct OptimizedContext {
+    pub chunks: Vec<MergedChunk>,
+    pub stats: ContextStats,
+}
+
 pub struct ContextOptimizer {
     token_limit: usize,
 }
@@ -26,8 +43,16 @@ impl ContextOptimizer {
 
     /// Merges and selects chunks to fit within the token budget.
     pub fn optimize(&self, results: Vec<SearchResult>) -> Result<Vec<MergedChunk>> {
+        Ok(self.optimize_with_stats(results)?.chunks)
+    }
+
+    /// Like [`optimize`](Self::optimize), but also reports tokens used and chunks dropped.
+    pub fn optimize_with_stats(&self, results: Vec<SearchResult>) -> Result<OptimizedContext> {
         if results.is_empty() {
-            return Ok(Vec::new());
+            return Ok(OptimizedContext {
+                chunks: Vec::new(),
+                stats: ContextStats::default(),
+            });
         }
 
         // 1. Group by filename and sort by line number
@@ -107,6 +132,7 @@ impl ContextOptimizer {
 
         let mut final_selection = Vec::new();
         let mut current_tokens = 0;
+        let mut chunks_dropped = 0;
 
         for chunk in all_merged {
             let tokens = bpe.encode_with_special_tokens(&chunk.code).len();
@@ -116,6 +142,7 @@ impl ContextOptimizer {
             } else {
                 // If we implemented a "soft" break (trimming the chunk), we could fit partial here.
                 // For now, strict exclusion.
+                chunks_dropped += 1;
                 continue;
             }
         }
@@ -131,7 +158,13 @@ impl ContextOptimizer {
             }
         });
 
-        Ok(final_selection)
+        Ok(OptimizedContext {
+            chunks: final_selection,
+            stats: ContextStats {
+                context_tokens_used: current_tokens,
+                chunks_dropped,
+            },
+        })
     }
 
     fn from_single(res: &SearchResult) -> MergedChunk {
@@ -164,6 +197,19 @@ mod tests {
             line_end: 12,
             last_modified: 100,
             calls: vec!["call1".into()],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         };
         let r2 = SearchResult {
             rank: 2,
@@ -174,6 +220,19 @@ mod tests {
             line_end: 16,
             last_modified: 101,
             calls: vec!["call2".into()],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         };
 
         let optimizer = ContextOptimizer::new(1000);
@@ -199,6 +258,19 @@ mod tests {
             line_end: 10,
             last_modified: 100,
             calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         };
 
         let optimizer = ContextOptimizer::new(10); // Very small budget
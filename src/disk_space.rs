@@ -0,0 +1,182 @@
+//! Free disk space checks, so indexing stops before a full disk leaves a half-written
+//! index behind.
+
+use crate::config::AppConfig;
+use crate::core::CodeRagError;
+use std::path::{Path, PathBuf};
+use sysinfo::Disks;
+
+/// Bytes available to the current user on the disk holding `path`, or `None` when the
+/// disk cannot be determined.
+///
+/// `path` need not exist yet; its closest existing ancestor is looked up.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let mut existing = path.to_path_buf();
+    while !existing.exists() {
+        existing = existing.parent()?.to_path_buf();
+    }
+    let existing = existing.canonicalize().ok()?;
+    let disks = Disks::new_with_refreshed_list();
+    // The disk mounted deepest along the path holds it
+    disks
+        .list()
+        .iter()
+        .filter(|disk| existing.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().components().count())
+        .map(|disk| disk.available_space())
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{} MB", bytes / (1024 * 1024))
+}
+
+/// Free space required before and during indexing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiskSpaceCheck {
+    /// Space that must stay free once the index has grown
+    pub min_free_bytes: u64,
+    /// Projected index growth per byte of source indexed
+    pub space_factor: f32,
+    /// Re-check every this many batches while indexing (0 = only before)
+    pub recheck_batches: usize,
+}
+
+impl DiskSpaceCheck {
+    /// The configured check, unless `min_free_disk_bytes` and `index_space_factor` are
+    /// both 0.
+    pub fn from_config(config: &AppConfig) -> Option<Self> {
+        let check = Self {
+            min_free_bytes: config.min_free_disk_bytes,
+            space_factor: config.index_space_factor.max(0.0),
+            recheck_batches: config.disk_space_recheck_batches,
+        };
+        (check.min_free_bytes > 0 || check.space_factor > 0.0).then_some(check)
+    }
+
+    /// Space an index of `corpus_bytes` of source is expected to take.
+    pub fn projected_bytes(&self, corpus_bytes: u64) -> u64 {
+        (corpus_bytes as f64 * self.space_factor as f64) as u64
+    }
+
+    /// Fails when indexing `corpus_bytes` into `db_path` would leave less than
+    /// `min_free_bytes` of the `available` space. Unknown space passes.
+    pub fn ensure(
+        &self,
+        db_path: &Path,
+        corpus_bytes: u64,
+        available: Option<u64>,
+    ) -> Result<(), CodeRagError> {
+        let Some(available) = available else {
+            return Ok(());
+        };
+        let projected = self.projected_bytes(corpus_bytes);
+        let required = projected.saturating_add(self.min_free_bytes);
+        if available >= required {
+            return Ok(());
+        }
+        Err(CodeRagError::Generic(format!(
+            "Not enough disk space for the index at {}: {} free, but about {} needed \
+             ({} of index for {} of source, plus min_free_disk_bytes {}). \
+             Free up space or lower min_free_disk_bytes / index_space_factor",
+            db_path.display(),
+            megabytes(available),
+            megabytes(required),
+            megabytes(projected),
+            megabytes(corpus_bytes),
+            megabytes(self.min_free_bytes)
+        )))
+    }
+}
+
+/// Re-checks free space between indexing batches.
+#[derive(Debug)]
+pub struct DiskMonitor {
+    check: DiskSpaceCheck,
+    db_path: PathBuf,
+    batches: usize,
+}
+
+impl DiskMonitor {
+    pub fn new(check: DiskSpaceCheck, db_path: &Path) -> Self {
+        Self {
+            check,
+            db_path: db_path.to_path_buf(),
+            batches: 0,
+        }
+    }
+
+    /// Called before each batch is written; every `recheck_batches` batches, fails
+    /// when less than `min_free_bytes` is left.
+    pub fn before_batch(&mut self) -> Result<(), CodeRagError> {
+        self.batches += 1;
+        let every = self.check.recheck_batches;
+        if every == 0 || !self.batches.is_multiple_of(every) {
+            return Ok(());
+        }
+        self.check
+            .ensure(&self.db_path, 0, available_space(&self.db_path))
+    }
+
+    /// Called before the final flush, which is checked whatever the batch count, so
+    /// short runs are not left unchecked.
+    pub fn before_last_batch(&mut self) -> Result<(), CodeRagError> {
+        self.check
+            .ensure(&self.db_path, 0, available_space(&self.db_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check() -> DiskSpaceCheck {
+        DiskSpaceCheck {
+            min_free_bytes: 100 * 1024 * 1024,
+            space_factor: 4.0,
+            recheck_batches: 10,
+        }
+    }
+
+    #[test]
+    fn test_insufficient_projected_space_is_refused() {
+        let db = Path::new("/data/index");
+        let corpus = 50 * 1024 * 1024;
+
+        // 200 MB of index plus 100 MB headroom needs 300 MB
+        let err = check()
+            .ensure(db, corpus, Some(250 * 1024 * 1024))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("250 MB free"), "{}", err);
+        assert!(err.contains("about 300 MB needed"), "{}", err);
+
+        assert!(check().ensure(db, corpus, Some(300 * 1024 * 1024)).is_ok());
+        // Space that cannot be determined does not block indexing
+        assert!(check().ensure(db, corpus, None).is_ok());
+    }
+
+    #[test]
+    fn test_last_batch_is_always_checked() {
+        let dir = tempfile::TempDir::new().unwrap();
+        if available_space(dir.path()).is_none() {
+            return;
+        }
+        let full = DiskSpaceCheck {
+            min_free_bytes: u64::MAX,
+            ..check()
+        };
+        let mut monitor = DiskMonitor::new(full, dir.path());
+        assert!(monitor.before_batch().is_ok());
+        assert!(monitor.before_last_batch().is_err());
+    }
+
+    #[test]
+    fn test_available_space_of_missing_path_uses_ancestor() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let missing = dir.path().join("not").join("created");
+        assert_eq!(
+            available_space(&missing).is_some(),
+            available_space(dir.path()).is_some()
+        );
+    }
+}
@@ -9,8 +9,33 @@ use ort::execution_providers::CUDAExecutionProvider;
 #[cfg(feature = "metal")]
 use ort::execution_providers::CoreMLExecutionProvider;
 
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Controls where named models are cached and whether they may be downloaded.
+#[derive(Debug, Clone, Default)]
+pub struct ModelCacheOptions {
+    /// Directory used as the Hugging Face download cache (fastembed default when `None`)
+    pub cache_dir: Option<String>,
+    /// When true, a model missing from the cache is an error instead of a download
+    pub offline: bool,
+}
+
+impl ModelCacheOptions {
+    fn resolve_dir(&self) -> PathBuf {
+        self.cache_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(fastembed::get_cache_dir()))
+    }
+}
 
 /// Text embedding and result reranker.
 ///
@@ -20,7 +45,204 @@ pub struct Embedder {
     reranker: std::sync::Mutex<Option<TextRerank>>,
     reranker_model_name: String,
     reranker_model_path: Option<String>,
+    cache: ModelCacheOptions,
     dim: usize,
+    /// L2-normalize embeddings after inference
+    normalize: bool,
+    /// Norm of the warmup embedding, used to detect unnormalized model output
+    sample_norm: Option<f32>,
+}
+
+/// Deviation from unit length tolerated before an embedding counts as unnormalized.
+const NORM_TOLERANCE: f32 = 1e-3;
+
+/// Code snippets of typical chunk length, used to warm up the model.
+const WARMUP_SNIPPETS: &[&str] = &[
+    "fn parse_config(path: &Path) -> Result<Config> {\n    let text = fs::read_to_string(path)?;\n    toml::from_str(&text).map_err(Into::into)\n}",
+    "def load_users(session, active=True):\n    query = session.query(User).filter(User.active == active)\n    return [user.to_dict() for user in query.all()]",
+    "export async function fetchItems(url: string): Promise<Item[]> {\n  const response = await fetch(url);\n  if (!response.ok) throw new Error(response.statusText);\n  return response.json();\n}",
+    "func (s *Server) handleSearch(w http.ResponseWriter, r *http.Request) {\n\tquery := r.URL.Query().Get(\"q\")\n\tresults := s.index.Search(query, 10)\n\tjson.NewEncoder(w).Encode(results)\n}",
+    "public List<Order> findOrders(Customer customer) {\n    return orders.stream()\n        .filter(o -> o.getCustomerId() == customer.getId())\n        .collect(Collectors.toList());\n}",
+];
+
+/// Builds `count` warmup inputs by cycling through representative code snippets.
+///
+/// A count of 0 yields the single short string used for a minimal warmup.
+pub fn warmup_texts(count: usize) -> Vec<String> {
+    if count == 0 {
+        return vec!["warmup".to_string()];
+    }
+    WARMUP_SNIPPETS
+        .iter()
+        .cycle()
+        .take(count)
+        .enumerate()
+        .map(|(i, snippet)| format!("// sample {}\n{}", i, snippet))
+        .collect()
+}
+
+fn l2_norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Scales each embedding to unit length; zero vectors are left unchanged.
+fn normalize_embeddings(embeddings: &mut [Vec<f32>]) {
+    for v in embeddings.iter_mut() {
+        let norm = l2_norm(v);
+        if norm > f32::EPSILON {
+            v.iter_mut().for_each(|x| *x /= norm);
+        }
+    }
+}
+
+/// Embeddings an [`EmbeddingCache`] keeps by default before evicting the least recently
+/// used.
+pub const DEFAULT_EMBEDDING_CACHE_ENTRIES: usize = 100_000;
+
+/// SHA-256 digest of a chunk text.
+type TextDigest = [u8; 32];
+
+/// Per-run cache of embeddings keyed by the SHA-256 digest of the chunk text.
+///
+/// Identical code in several files (vendored copies, generated stubs, ...) is embedded
+/// once; every chunk still gets its own row, so file locations are kept. At most
+/// `capacity` embeddings are kept, least recently used first out.
+#[derive(Debug)]
+pub struct EmbeddingCache {
+    entries: LruCache<TextDigest, Vec<f32>>,
+    computed: usize,
+    reused: usize,
+}
+
+impl Default for EmbeddingCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EmbeddingCache {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_EMBEDDING_CACHE_ENTRIES)
+    }
+
+    /// A cache keeping at most `capacity` embeddings (at least one).
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::new(NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN)),
+            computed: 0,
+            reused: 0,
+        }
+    }
+
+    fn key(text: &str) -> TextDigest {
+        Sha256::digest(text.as_bytes()).into()
+    }
+
+    /// Returns embeddings for `texts`, calling `embed` only for texts not seen before.
+    pub fn embed_with<F>(&mut self, texts: Vec<String>, embed: F) -> Result<Vec<Vec<f32>>>
+    where
+        F: FnOnce(Vec<String>) -> Result<Vec<Vec<f32>>>,
+    {
+        let keys: Vec<TextDigest> = texts.iter().map(|t| Self::key(t)).collect();
+
+        // Embeddings for this call, so evictions while it runs lose nothing
+        let mut found: HashMap<TextDigest, Vec<f32>> = HashMap::new();
+        let mut missing_keys = Vec::new();
+        let mut missing = Vec::new();
+        let mut queued = HashSet::new();
+        for (key, text) in keys.iter().zip(texts) {
+            if found.contains_key(key) || queued.contains(key) {
+                continue;
+            }
+            match self.entries.get(key) {
+                Some(embedding) => {
+                    found.insert(*key, embedding.clone());
+                }
+                None => {
+                    queued.insert(*key);
+                    missing_keys.push(*key);
+                    missing.push(text);
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            let embeddings = embed(missing)?;
+            if embeddings.len() != missing_keys.len() {
+                anyhow::bail!(
+                    "Expected {} embeddings, got {}",
+                    missing_keys.len(),
+                    embeddings.len()
+                );
+            }
+            for (key, embedding) in missing_keys.iter().zip(embeddings) {
+                self.entries.put(*key, embedding.clone());
+                found.insert(*key, embedding);
+            }
+        }
+        self.computed += missing_keys.len();
+        self.reused += keys.len() - missing_keys.len();
+
+        Ok(keys.iter().map(|k| found[k].clone()).collect())
+    }
+
+    /// Number of embeddings computed by the model.
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+
+    /// Number of chunks that reused an embedding already computed in this run.
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+}
+
+/// Caps how many embedding or reranking calls run at once across all searchers sharing it.
+///
+/// Each call holds a permit while the model runs, so model buffers stay bounded however
+/// many requests are in flight. Callers wait for a permit before starting.
+#[derive(Debug)]
+pub struct EmbedLimiter {
+    permits: Arc<Semaphore>,
+    active: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl EmbedLimiter {
+    /// Allows `max_in_flight` concurrent calls (at least one).
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_in_flight.max(1))),
+            active: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs `f` on the blocking thread pool once a permit is available.
+    pub async fn run_blocking<T, F>(self: &Arc<Self>, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await?;
+        let limiter = self.clone();
+        // The permit moves into the task so it is held until the model call returns,
+        // even if the awaiting request is dropped
+        Ok(tokio::task::spawn_blocking(move || {
+            let active = limiter.active.fetch_add(1, Ordering::SeqCst) + 1;
+            limiter.peak.fetch_max(active, Ordering::SeqCst);
+            let output = f();
+            limiter.active.fetch_sub(1, Ordering::SeqCst);
+            drop(permit);
+            output
+        })
+        .await?)
+    }
+
+    /// Most calls that ran at the same time so far.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
 }
 
 fn load_tokenizer_files(path: &Path) -> std::io::Result<TokenizerFiles> {
@@ -32,6 +254,52 @@ fn load_tokenizer_files(path: &Path) -> std::io::Result<TokenizerFiles> {
     })
 }
 
+/// Checks whether every file of a Hugging Face model is present in an hf-hub style cache.
+///
+/// The layout is `models--{org}--{name}/refs/main` pointing at a commit hash, with the
+/// files stored under `snapshots/{hash}/`. No network access is performed.
+fn is_model_cached(cache_dir: &Path, model_code: &str, files: &[String]) -> bool {
+    let repo_dir = cache_dir.join(format!("models--{}", model_code.replace('/', "--")));
+    let commit = match fs::read_to_string(repo_dir.join("refs").join("main")) {
+        Ok(c) => c.trim().to_string(),
+        Err(_) => return false,
+    };
+    let snapshot = repo_dir.join("snapshots").join(commit);
+    files.iter().all(|f| snapshot.join(f).exists())
+}
+
+fn ensure_cached(cache_dir: &Path, model_code: &str, files: &[String]) -> Result<()> {
+    if !is_model_cached(cache_dir, model_code, files) {
+        anyhow::bail!(
+            "Model '{}' is not cached in '{}' and offline mode is enabled. \
+            Run once with offline = false to download it, or point model_cache_dir at a populated cache.",
+            model_code,
+            cache_dir.display()
+        );
+    }
+    Ok(())
+}
+
+fn reranker_files(model: &RerankerModel) -> (String, Vec<String>) {
+    let info = TextRerank::get_model_info(model);
+    let mut files = vec![info.model_file];
+    files.extend(info.additional_files);
+    files.extend(tokenizer_file_names());
+    (info.model_code, files)
+}
+
+fn tokenizer_file_names() -> Vec<String> {
+    [
+        "tokenizer.json",
+        "config.json",
+        "special_tokens_map.json",
+        "tokenizer_config.json",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
 impl Embedder {
     pub fn new(
         embedding_model: String,
@@ -57,6 +325,30 @@ impl Embedder {
         embedding_model_path: Option<String>,
         reranker_model_path: Option<String>,
         device: String,
+    ) -> Result<Self> {
+        Self::new_with_cache(
+            quiet,
+            embedding_model,
+            reranker_model,
+            embedding_model_path,
+            reranker_model_path,
+            device,
+            ModelCacheOptions::default(),
+        )
+    }
+
+    /// Creates an embedder with an explicit model cache location and offline policy.
+    ///
+    /// In offline mode, named models must already be present in the cache; a miss
+    /// returns an error immediately instead of attempting a download.
+    pub fn new_with_cache(
+        quiet: bool,
+        embedding_model: String,
+        reranker_model: String,
+        embedding_model_path: Option<String>,
+        reranker_model_path: Option<String>,
+        device: String,
+        cache: ModelCacheOptions,
     ) -> Result<Self> {
         let providers = match device.to_lowercase().as_str() {
             "cuda" => {
@@ -134,22 +426,35 @@ impl Embedder {
                 }
             };
 
+            let cache_dir = cache.resolve_dir();
+            if cache.offline {
+                let info = TextEmbedding::get_model_info(&model_enum)?;
+                let mut files = vec![info.model_file.clone()];
+                files.extend(info.additional_files.iter().cloned());
+                files.extend(tokenizer_file_names());
+                ensure_cached(&cache_dir, &info.model_code, &files)?;
+            }
+
             let mut options = InitOptions::new(model_enum);
-            options.show_download_progress = !quiet;
+            options.show_download_progress = !quiet && !cache.offline;
             options.execution_providers = providers;
+            options.cache_dir = cache_dir;
 
             TextEmbedding::try_new(options)?
         };
 
         // Determine embedding dimension dynamically
-        let dim = match model.embed(vec!["warmup".to_string()], Some(1)) {
-            Ok(vec) => vec.first().map(|v| v.len()).unwrap_or(768),
+        let (dim, sample_norm) = match model.embed(vec!["warmup".to_string()], Some(1)) {
+            Ok(vec) => (
+                vec.first().map(|v| v.len()).unwrap_or(768),
+                vec.first().map(|v| l2_norm(v)),
+            ),
             Err(e) => {
                 tracing::warn!(
                     "Failed to determine model dimension, defaulting to 768: {}",
                     e
                 );
-                768
+                (768, None)
             }
         };
 
@@ -165,11 +470,16 @@ impl Embedder {
         };
 
         let mut rerank_init_options = RerankInitOptions::default();
-        rerank_init_options.model_name = model_enum;
-        rerank_init_options.show_download_progress = !quiet;
-        if let Some(ref path) = reranker_model_path {
-            rerank_init_options.cache_dir = PathBuf::from(path);
+        rerank_init_options.show_download_progress = !quiet && !cache.offline;
+        rerank_init_options.cache_dir = match reranker_model_path {
+            Some(ref path) => PathBuf::from(path),
+            None => cache.resolve_dir(),
+        };
+        if cache.offline {
+            let (model_code, files) = reranker_files(&model_enum);
+            ensure_cached(&rerank_init_options.cache_dir, &model_code, &files)?;
         }
+        rerank_init_options.model_name = model_enum;
 
         let reranker = Some(TextRerank::try_new(rerank_init_options)?);
 
@@ -178,16 +488,40 @@ impl Embedder {
             reranker: std::sync::Mutex::new(reranker),
             reranker_model_name: reranker_model,
             reranker_model_path,
+            cache,
             dim,
+            normalize: false,
+            sample_norm,
         })
     }
 
+    /// Enables L2 normalization of embeddings after inference.
+    ///
+    /// Vector search assumes unit-length embeddings; when normalization is off and the
+    /// model's output is not unit-length, a warning is logged.
+    pub fn with_normalization(mut self, enabled: bool) -> Self {
+        self.normalize = enabled;
+        if let Some(norm) = self.sample_norm {
+            if !enabled && (norm - 1.0).abs() > NORM_TOLERANCE {
+                tracing::warn!(
+                    "Embedding model output is not unit-length (norm {:.3}); search quality will suffer. \
+                     Set normalize_embeddings = true",
+                    norm
+                );
+            }
+        }
+        self
+    }
+
     pub fn embed(&self, texts: Vec<String>, batch_size: Option<usize>) -> Result<Vec<Vec<f32>>> {
-        let embeddings = self
+        let mut embeddings = self
             .model
             .lock()
             .map_err(|e| anyhow::anyhow!("Embedder lock poisoned: {}", e))?
             .embed(texts, batch_size)?;
+        if self.normalize {
+            normalize_embeddings(&mut embeddings);
+        }
         Ok(embeddings)
     }
 
@@ -195,6 +529,12 @@ impl Embedder {
         self.dim
     }
 
+    /// Embeds `samples` representative code snippets in one batch so the first real
+    /// batch runs at steady-state speed; 0 runs a minimal single-string warmup.
+    pub fn warmup(&self, samples: usize) -> Result<()> {
+        self.embed(warmup_texts(samples), None).map(|_| ())
+    }
+
     pub fn init_reranker(&self) -> Result<()> {
         let mut reranker_guard = self
             .reranker
@@ -214,10 +554,16 @@ impl Embedder {
             };
 
             let mut rerank_init_options = RerankInitOptions::default();
-            rerank_init_options.model_name = model_enum;
-            if let Some(path) = self.reranker_model_path.as_ref() {
-                rerank_init_options.cache_dir = PathBuf::from(path);
+            rerank_init_options.show_download_progress = !self.cache.offline;
+            rerank_init_options.cache_dir = match self.reranker_model_path.as_ref() {
+                Some(path) => PathBuf::from(path),
+                None => self.cache.resolve_dir(),
+            };
+            if self.cache.offline {
+                let (model_code, files) = reranker_files(&model_enum);
+                ensure_cached(&rerank_init_options.cache_dir, &model_code, &files)?;
             }
+            rerank_init_options.model_name = model_enum;
 
             *reranker_guard = Some(TextRerank::try_new(rerank_init_options)?);
         }
@@ -257,3 +603,95 @@ impl Embedder {
         } // guard dropped here
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warmup_texts() {
+        assert_eq!(warmup_texts(0), vec!["warmup".to_string()]);
+
+        let texts = warmup_texts(12);
+        assert_eq!(texts.len(), 12);
+        // Realistic lengths, and no two inputs identical
+        assert!(texts.iter().all(|t| t.len() > 80));
+        let unique: std::collections::HashSet<_> = texts.iter().collect();
+        assert_eq!(unique.len(), texts.len());
+    }
+
+    #[test]
+    fn test_normalize_embeddings_unit_norm() {
+        // Output of a stub model that does not normalize
+        let mut embeddings = vec![vec![3.0, 4.0], vec![10.0, -2.0, 7.5, 0.1], vec![0.0, 0.0]];
+        assert!(embeddings
+            .iter()
+            .take(2)
+            .all(|v| (l2_norm(v) - 1.0).abs() > NORM_TOLERANCE));
+
+        normalize_embeddings(&mut embeddings);
+
+        assert_eq!(embeddings[0], vec![0.6, 0.8]);
+        assert!((l2_norm(&embeddings[1]) - 1.0).abs() < NORM_TOLERANCE);
+        // Zero vectors cannot be normalized and are left as-is
+        assert_eq!(embeddings[2], vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_embedding_cache_embeds_duplicates_once() {
+        let func = "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}".to_string();
+        let mut cache = EmbeddingCache::new();
+        let mut calls = Vec::new();
+        let mut stub = |texts: Vec<String>| -> Result<Vec<Vec<f32>>> {
+            calls.push(texts.clone());
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        };
+
+        // a.rs and b.rs both define the same function, in separate batches
+        let first = cache
+            .embed_with(vec![func.clone(), "fn a() {}".to_string()], &mut stub)
+            .unwrap();
+        let second = cache
+            .embed_with(vec!["fn b() {}".to_string(), func.clone()], &mut stub)
+            .unwrap();
+
+        assert_eq!(first[0], second[1]);
+        assert_eq!(
+            calls.iter().filter(|batch| batch.contains(&func)).count(),
+            1
+        );
+        assert_eq!(cache.computed(), 3);
+        assert_eq!(cache.reused(), 1);
+
+        // Duplicates within one batch are also embedded once
+        let mut fresh = EmbeddingCache::new();
+        let out = fresh
+            .embed_with(vec![func.clone(), func.clone()], |texts| {
+                assert_eq!(texts.len(), 1);
+                Ok(vec![vec![1.0]; texts.len()])
+            })
+            .unwrap();
+        assert_eq!(out.len(), 2);
+        assert_eq!(fresh.reused(), 1);
+    }
+
+    #[test]
+    fn test_embedding_cache_evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::with_capacity(2);
+        let embed = |texts: Vec<String>| -> Result<Vec<Vec<f32>>> {
+            Ok(texts.iter().map(|t| vec![t.len() as f32]).collect())
+        };
+        // More distinct texts than the capacity still all get their embedding
+        let out = cache
+            .embed_with(vec!["a".into(), "bb".into(), "ccc".into()], embed)
+            .unwrap();
+        assert_eq!(out, vec![vec![1.0], vec![2.0], vec![3.0]]);
+
+        // "a" was evicted and is embedded again; "ccc" is still cached
+        cache
+            .embed_with(vec!["a".into(), "ccc".into()], embed)
+            .unwrap();
+        assert_eq!(cache.computed(), 4);
+        assert_eq!(cache.reused(), 1);
+    }
+}
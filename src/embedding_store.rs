@@ -0,0 +1,241 @@
+//! Embeddings kept on disk across indexing runs, so re-indexing unchanged code with the
+//! same model skips the model.
+
+use anyhow::Result;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// Bytes before the vector in an entry file: a second hash of the key, against collisions.
+const CHECK_BYTES: usize = 8;
+
+fn hash(value: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Content-addressed embeddings of one model, stored as one file per chunk text.
+///
+/// Entries live under `<root>/<model hash>/`, so another model (or the same model with
+/// other normalization) never reads them. Storing and pruning are best effort: an entry
+/// that cannot be read is embedded again, one that cannot be written is only logged.
+#[derive(Debug)]
+pub struct EmbeddingStore {
+    root: PathBuf,
+    dir: PathBuf,
+    model: String,
+    /// Size of all entries under `root` that [`EmbeddingStore::prune`] keeps (0 = no cap)
+    max_bytes: u64,
+    hits: usize,
+    computed: usize,
+}
+
+impl EmbeddingStore {
+    /// Opens the store under `root` for `model`, an identifier of the model and every
+    /// setting that changes its vectors.
+    pub fn open(root: &Path, model: &str, max_bytes: u64) -> Result<Self> {
+        let dir = root.join(format!("{:016x}", hash(model)));
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            dir,
+            model: model.to_string(),
+            max_bytes,
+            hits: 0,
+            computed: 0,
+        })
+    }
+
+    fn entry_path(&self, text: &str) -> (PathBuf, u64) {
+        let key = hash((&self.model, text));
+        let check = hash((text, &self.model));
+        let name = format!("{:016x}", key);
+        (self.dir.join(&name[..2]).join(name + ".bin"), check)
+    }
+
+    fn read(path: &Path, check: u64) -> Option<Vec<f32>> {
+        let data = fs::read(path).ok()?;
+        if data.len() < CHECK_BYTES || !(data.len() - CHECK_BYTES).is_multiple_of(4) {
+            return None;
+        }
+        let (stored, vector) = data.split_at(CHECK_BYTES);
+        if u64::from_le_bytes(stored.try_into().ok()?) != check {
+            return None;
+        }
+        // Mark the entry as recently used so pruning keeps it
+        if let Ok(file) = fs::File::options().write(true).open(path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(
+            vector
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        )
+    }
+
+    fn write(path: &Path, check: u64, vector: &[f32]) -> Result<()> {
+        if let Some(shard) = path.parent() {
+            fs::create_dir_all(shard)?;
+        }
+        let mut data = Vec::with_capacity(CHECK_BYTES + vector.len() * 4);
+        data.extend_from_slice(&check.to_le_bytes());
+        for value in vector {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        // Write then rename so a concurrent run never reads a partial entry
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Returns embeddings for `texts`, calling `embed` only for texts not stored yet
+    /// and storing what it returns.
+    pub fn embed_with<F>(&mut self, texts: Vec<String>, embed: F) -> Result<Vec<Vec<f32>>>
+    where
+        F: FnOnce(Vec<String>) -> Result<Vec<Vec<f32>>>,
+    {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        let mut missing = Vec::new();
+        let mut missing_entries = Vec::new();
+        for (i, text) in texts.into_iter().enumerate() {
+            let (path, check) = self.entry_path(&text);
+            match Self::read(&path, check) {
+                Some(vector) => embeddings.push(Some(vector)),
+                None => {
+                    embeddings.push(None);
+                    missing_entries.push((i, path, check));
+                    missing.push(text);
+                }
+            }
+        }
+        self.hits += embeddings.len() - missing.len();
+
+        if !missing.is_empty() {
+            let computed = embed(missing)?;
+            if computed.len() != missing_entries.len() {
+                anyhow::bail!(
+                    "Expected {} embeddings, got {}",
+                    missing_entries.len(),
+                    computed.len()
+                );
+            }
+            self.computed += computed.len();
+            for ((i, path, check), vector) in missing_entries.into_iter().zip(computed) {
+                if let Err(e) = Self::write(&path, check, &vector) {
+                    warn!("Could not store embedding in {}: {}", path.display(), e);
+                }
+                embeddings[i] = Some(vector);
+            }
+        }
+
+        Ok(embeddings.into_iter().flatten().collect())
+    }
+
+    /// Removes the least recently used entries (of any model) until the store fits
+    /// `max_bytes`. Returns the number of entries removed.
+    pub fn prune(&self) -> Result<usize> {
+        if self.max_bytes == 0 {
+            return Ok(0);
+        }
+        let mut entries = Vec::new();
+        collect_entries(&self.root, &mut entries)?;
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_bytes {
+            return Ok(0);
+        }
+
+        entries.sort_by_key(|(used, _, _)| *used);
+        let mut removed = 0;
+        for (_, size, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Number of chunks whose embedding was read from the store.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Number of embeddings computed by the model and stored.
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+}
+
+/// Last use, size and path of every entry file below `dir`.
+fn collect_entries(dir: &Path, entries: &mut Vec<(SystemTime, u64, PathBuf)>) -> Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_entries(&path, entries)?;
+        } else if path.extension().is_some_and(|ext| ext == "bin") {
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((used, metadata.len(), path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stub(texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|t| vec![t.len() as f32, 0.5]).collect())
+    }
+
+    #[test]
+    fn test_second_run_reads_stored_embeddings() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let texts = vec!["fn a() {}".to_string(), "fn bb() {}".to_string()];
+
+        let mut first = EmbeddingStore::open(dir.path(), "model-a", 0).unwrap();
+        let expected = first.embed_with(texts.clone(), stub).unwrap();
+        assert_eq!((first.computed(), first.hits()), (2, 0));
+
+        // A new run with the same model embeds nothing
+        let mut second = EmbeddingStore::open(dir.path(), "model-a", 0).unwrap();
+        let out = second
+            .embed_with(texts.clone(), |_| panic!("embedded a stored chunk"))
+            .unwrap();
+        assert_eq!(out, expected);
+        assert_eq!((second.computed(), second.hits()), (0, 2));
+
+        // Another model does not see those entries
+        let mut other = EmbeddingStore::open(dir.path(), "model-b", 0).unwrap();
+        other.embed_with(texts, stub).unwrap();
+        assert_eq!(other.computed(), 2);
+    }
+
+    #[test]
+    fn test_prune_keeps_store_under_cap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        // Each entry holds a check hash and two floats
+        let entry_bytes = (CHECK_BYTES + 8) as u64;
+        let mut store = EmbeddingStore::open(dir.path(), "model-a", 2 * entry_bytes).unwrap();
+        let texts: Vec<String> = (0..5).map(|i| format!("fn f{}() {{}}", i)).collect();
+        store.embed_with(texts, stub).unwrap();
+
+        assert_eq!(store.prune().unwrap(), 3);
+        let mut entries = Vec::new();
+        collect_entries(dir.path(), &mut entries).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(store.prune().unwrap(), 0);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::core::CodeRagError;
+use git2::{Delta, DiffOptions, Repository, StatusOptions};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Which files to take from the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitSelection {
+    /// Modified, staged and untracked files in the working tree
+    Changed,
+    /// Files that differ between a ref (commit, branch, tag) and the working tree
+    Since(String),
+}
+
+/// Files selected from git, relative to the indexed directory.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChangedFiles {
+    /// Files that exist and should be (re)indexed
+    pub changed: Vec<PathBuf>,
+    /// Files that were removed and should be dropped from the index
+    pub deleted: Vec<PathBuf>,
+}
+
+fn git_error(e: git2::Error) -> CodeRagError {
+    CodeRagError::Generic(format!("Git error: {}", e.message()))
+}
+
+/// Lists the files under `root` selected by `selection`.
+///
+/// Fails if `root` is not inside a git working tree. Files outside `root` are ignored.
+pub fn changed_files(root: &Path, selection: &GitSelection) -> Result<ChangedFiles, CodeRagError> {
+    let repo = Repository::discover(root).map_err(|_| {
+        CodeRagError::Generic(format!("{} is not inside a git repository", root.display()))
+    })?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| CodeRagError::Generic("Bare git repositories are not supported".into()))?
+        .canonicalize()?;
+    let root = root.canonicalize()?;
+
+    // (path relative to the repository, deleted)
+    let mut entries: Vec<(PathBuf, bool)> = Vec::new();
+    match selection {
+        GitSelection::Changed => {
+            let mut opts = StatusOptions::new();
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .include_ignored(false);
+            let statuses = repo.statuses(Some(&mut opts)).map_err(git_error)?;
+            for entry in statuses.iter() {
+                let status = entry.status();
+                if status.is_ignored() {
+                    continue;
+                }
+                if let Some(path) = entry.path() {
+                    let deleted = status.is_wt_deleted() || status.is_index_deleted();
+                    entries.push((PathBuf::from(path), deleted));
+                }
+            }
+        }
+        GitSelection::Since(reference) => {
+            let tree = repo
+                .revparse_single(reference)
+                .and_then(|obj| obj.peel_to_tree())
+                .map_err(git_error)?;
+            let mut opts = DiffOptions::new();
+            opts.include_untracked(true).recurse_untracked_dirs(true);
+            let diff = repo
+                .diff_tree_to_workdir_with_index(Some(&tree), Some(&mut opts))
+                .map_err(git_error)?;
+            for delta in diff.deltas() {
+                let deleted = delta.status() == Delta::Deleted;
+                let file = if deleted {
+                    delta.old_file()
+                } else {
+                    delta.new_file()
+                };
+                if let Some(path) = file.path() {
+                    entries.push((path.to_path_buf(), deleted));
+                }
+            }
+        }
+    }
+
+    let mut files = ChangedFiles::default();
+    for (path, deleted) in entries {
+        let Ok(relative) = workdir
+            .join(&path)
+            .strip_prefix(&root)
+            .map(Path::to_path_buf)
+        else {
+            continue;
+        };
+        if deleted {
+            files.deleted.push(relative);
+        } else {
+            files.changed.push(relative);
+        }
+    }
+    files.changed.sort();
+    files.changed.dedup();
+    files.deleted.sort();
+    files.deleted.dedup();
+    Ok(files)
+}
+
+/// A checked-out tree of a repository, indexed as its own workspace by
+/// `index --worktrees`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worktree {
+    /// Workspace name: the branch with `/` replaced by `-`, or the worktree's
+    /// directory name when its HEAD is detached. Names that would collide, or be
+    /// `default`, get a short hash of the branch (or directory) appended.
+    pub name: String,
+    /// Branch checked out, `None` when detached
+    pub branch: Option<String>,
+    /// Directory to index: the indexed path's counterpart in this worktree
+    pub path: PathBuf,
+}
+
+fn worktree_of(repo: &Repository, path: PathBuf) -> Worktree {
+    let branch = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(str::to_string));
+    let name = match &branch {
+        Some(branch) => branch.replace('/', "-"),
+        None => repo
+            .workdir()
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "detached".to_string()),
+    };
+    Worktree { name, branch, path }
+}
+
+/// Appends a short hash to names shared by several worktrees, and to `default`.
+///
+/// `feature/auth` and `feature-auth` both become `feature-auth`, and detached worktrees
+/// can share a directory name. The hash comes from the branch or, when detached, the
+/// path, so a worktree keeps its workspace across runs.
+fn disambiguate(trees: &mut [Worktree]) -> Result<(), CodeRagError> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for tree in trees.iter() {
+        *counts.entry(tree.name.clone()).or_default() += 1;
+    }
+    for tree in trees.iter_mut() {
+        if counts[&tree.name] > 1 || tree.name == "default" {
+            let source = match &tree.branch {
+                Some(branch) => branch.clone(),
+                None => tree.path.to_string_lossy().to_string(),
+            };
+            let digest = Sha256::digest(source.as_bytes());
+            let suffix: String = digest[..4].iter().map(|b| format!("{:02x}", b)).collect();
+            tree.name = format!("{}-{}", tree.name, suffix);
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    for tree in trees.iter() {
+        if !seen.insert(tree.name.as_str()) {
+            return Err(CodeRagError::Generic(format!(
+                "Two worktrees map to workspace '{}'; index them with --workspace instead",
+                tree.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Lists the main working tree and every linked worktree of the repository at `root`.
+///
+/// When `root` is a subdirectory of its working tree, each worktree's path is the same
+/// subdirectory in that worktree. Worktrees whose directory is missing (pruned, or on
+/// an unmounted disk) are skipped. Sorted by name.
+pub fn worktrees(root: &Path) -> Result<Vec<Worktree>, CodeRagError> {
+    let repo = Repository::discover(root).map_err(|_| {
+        CodeRagError::Generic(format!("{} is not inside a git repository", root.display()))
+    })?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| CodeRagError::Generic("Bare git repositories are not supported".into()))?
+        .canonicalize()?;
+    let relative = root
+        .canonicalize()?
+        .strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    // Collecting the components drops the trailing separator of git's workdir paths
+    let in_tree = |dir: &Path| dir.join(&relative).components().collect::<PathBuf>();
+
+    // Linked worktrees are listed by the repository that owns them
+    let main = Repository::open(repo.commondir()).map_err(git_error)?;
+    let mut trees = Vec::new();
+    if let Some(dir) = main.workdir() {
+        trees.push(worktree_of(&main, in_tree(dir)));
+    }
+    for name in main.worktrees().map_err(git_error)?.iter().flatten() {
+        let Ok(linked) = main.find_worktree(name) else {
+            continue;
+        };
+        if linked.validate().is_err() {
+            continue;
+        }
+        let Ok(linked_repo) = Repository::open_from_worktree(&linked) else {
+            continue;
+        };
+        trees.push(worktree_of(&linked_repo, in_tree(linked.path())));
+    }
+    trees.retain(|tree| tree.path.is_dir());
+    disambiguate(&mut trees)?;
+    trees.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(trees)
+}
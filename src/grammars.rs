@@ -0,0 +1,136 @@
+use crate::indexer::CodeChunker;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+use tree_sitter::{Language, LANGUAGE_VERSION, MIN_COMPATIBLE_LANGUAGE_VERSION};
+
+/// Grammars loaded from shared libraries, by file extension.
+fn registry() -> &'static RwLock<HashMap<String, Language>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Language>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// The loaded custom grammar for `extension`, if any.
+pub fn custom_language(extension: &str) -> Option<Language> {
+    registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(extension)
+        .cloned()
+}
+
+/// Whether `extension` is parsed by a custom grammar.
+pub fn is_custom(extension: &str) -> bool {
+    registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains_key(extension)
+}
+
+/// Name of the function a grammar library exports, derived from its file name.
+///
+/// `libtree-sitter-my-dsl.so` and `tree-sitter-my-dsl.dll` export `tree_sitter_my_dsl`,
+/// as generated by `tree-sitter generate`.
+pub fn grammar_symbol(library: &Path) -> Option<String> {
+    let name = library.file_name()?.to_str()?;
+    let stem = name.split('.').next()?;
+    let stem = stem.strip_prefix("lib").unwrap_or(stem);
+    let language = stem.strip_prefix("tree-sitter-").unwrap_or(stem);
+    if language.is_empty() {
+        return None;
+    }
+    Some(format!("tree_sitter_{}", language.replace('-', "_")))
+}
+
+/// Loads the grammar exported by the shared library at `library`.
+///
+/// # Safety
+///
+/// The library's initializers run, and its grammar function is trusted to return a
+/// valid `TSLanguage`; only load grammars built with `tree-sitter generate`.
+unsafe fn load_grammar(library: &Path) -> Result<Language, String> {
+    let symbol =
+        grammar_symbol(library).ok_or_else(|| "cannot derive the grammar name".to_string())?;
+    let lib = libloading::Library::new(library).map_err(|e| e.to_string())?;
+    let language_fn = lib
+        .get::<unsafe extern "C" fn() -> *const tree_sitter::ffi::TSLanguage>(symbol.as_bytes())
+        .map_err(|e| format!("no `{}` symbol: {}", symbol, e))?;
+
+    let raw = language_fn();
+    if raw.is_null() {
+        return Err(format!("`{}` returned no grammar", symbol));
+    }
+    let language = Language::from_raw(raw);
+    let version = language.version();
+    if !(MIN_COMPATIBLE_LANGUAGE_VERSION..=LANGUAGE_VERSION).contains(&version) {
+        return Err(format!(
+            "grammar ABI version {} is not supported (expected {}-{})",
+            version, MIN_COMPATIBLE_LANGUAGE_VERSION, LANGUAGE_VERSION
+        ));
+    }
+
+    // The grammar's tables live in the library, which must stay loaded for good
+    std::mem::forget(lib);
+    Ok(language)
+}
+
+/// Loads `custom_grammars` (file extension to shared library path) for chunking.
+///
+/// Grammars that fail to load, or that name an extension with a built-in grammar, are
+/// skipped with a warning. Returns the number of grammars registered.
+pub fn load_custom_grammars(grammars: &HashMap<String, String>) -> usize {
+    let mut loaded = 0;
+    for (extension, library) in grammars {
+        let extension = extension.trim_start_matches('.');
+        if CodeChunker::builtin_language(extension).is_some() {
+            tracing::warn!(
+                "Skipping custom grammar {} for .{}: the extension has a built-in grammar",
+                library,
+                extension
+            );
+            continue;
+        }
+
+        // SAFETY: grammar libraries are configured by the user, like any plugin
+        match unsafe { load_grammar(Path::new(library)) } {
+            Ok(language) => {
+                tracing::info!("Loaded custom grammar for .{} from {}", extension, library);
+                registry()
+                    .write()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(extension.to_string(), language);
+                loaded += 1;
+            }
+            Err(e) => tracing::warn!(
+                "Skipping custom grammar {} for .{}: {}",
+                library,
+                extension,
+                e
+            ),
+        }
+    }
+    loaded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grammar_symbol_from_library_name() {
+        let symbol = |name: &str| grammar_symbol(Path::new(name));
+        assert_eq!(
+            symbol("/opt/grammars/libtree-sitter-my-dsl.so").as_deref(),
+            Some("tree_sitter_my_dsl")
+        );
+        assert_eq!(
+            symbol("tree-sitter-toml.dll").as_deref(),
+            Some("tree_sitter_toml")
+        );
+        assert_eq!(
+            symbol("libtree-sitter-nim.0.1.dylib").as_deref(),
+            Some("tree_sitter_nim")
+        );
+        assert_eq!(symbol("libtree-sitter-.so"), None);
+    }
+}
@@ -0,0 +1,366 @@
+//! Format version and key settings an index was built with, stored next to it so a
+//! newer binary or changed configuration can tell when the index no longer matches.
+
+use crate::config::AppConfig;
+use crate::core::CodeRagError;
+use crate::storage::ShardKey;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// File under a database that holds its [`IndexMeta`].
+pub const INDEX_META_FILE: &str = "index_meta.json";
+
+/// Version of the stored index layout: chunk ids, columns and how text is stored.
+///
+/// Bump it whenever an index written before the change is read incorrectly after it.
+pub const INDEX_FORMAT_VERSION: u32 = 1;
+
+/// What to do when an index was built with an incompatible format or settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexVersionCheck {
+    /// Log the differences and carry on
+    #[default]
+    Warn,
+    /// Refuse to use the index until it is rebuilt
+    Error,
+    /// Do not compare
+    Off,
+}
+
+/// Format version and settings recorded when an index is created.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexMeta {
+    pub format_version: u32,
+    /// Version of code-rag that created the index
+    pub code_rag_version: String,
+    /// Embedding model name, or its path for a user-defined model
+    pub embedding_model: String,
+    pub normalize_embeddings: bool,
+    pub normalize_identifiers: bool,
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+    // Settings recorded later default to their values from before, for older metadata
+    #[serde(default = "default_shard_count")]
+    pub shard_count: usize,
+    #[serde(default)]
+    pub shard_key: ShardKey,
+    /// Per file extension, whether comments were left out of embeddings
+    #[serde(default)]
+    pub strip_comments: BTreeMap<String, bool>,
+    #[serde(default)]
+    pub chunk_balance_target: usize,
+}
+
+fn default_shard_count() -> usize {
+    1
+}
+
+/// A setting whose value differs between an index and the current configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaMismatch {
+    pub setting: &'static str,
+    pub indexed: String,
+    pub current: String,
+    /// Whether searches on the index give wrong results, rather than only
+    /// chunks that differ from newly indexed ones
+    pub breaking: bool,
+}
+
+impl std::fmt::Display for MetaMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is {} in the index but {} now",
+            self.setting, self.indexed, self.current
+        )
+    }
+}
+
+impl IndexMeta {
+    /// The metadata an index created now with `config` gets.
+    pub fn current(config: &AppConfig) -> Self {
+        Self {
+            format_version: INDEX_FORMAT_VERSION,
+            code_rag_version: env!("CARGO_PKG_VERSION").to_string(),
+            embedding_model: config
+                .embedding_model_path
+                .clone()
+                .unwrap_or_else(|| config.embedding_model.clone()),
+            normalize_embeddings: config.should_normalize_embeddings(),
+            normalize_identifiers: config.normalize_identifiers,
+            chunk_size: config.chunk_size,
+            chunk_overlap: config.chunk_overlap,
+            shard_count: config.shard_count,
+            shard_key: config.shard_key,
+            strip_comments: config
+                .strip_comments
+                .iter()
+                .map(|(ext, strip)| (ext.clone(), *strip))
+                .collect(),
+            chunk_balance_target: config.chunk_balance_target,
+        }
+    }
+
+    /// Reads the metadata of the database at `db_path`; `None` when it has none.
+    pub fn read(db_path: &Path) -> Result<Option<Self>> {
+        let path = db_path.join(INDEX_META_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    /// Stores the metadata in the database at `db_path`.
+    pub fn write(&self, db_path: &Path) -> Result<()> {
+        fs::create_dir_all(db_path)?;
+        fs::write(
+            db_path.join(INDEX_META_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Settings of this (stored) metadata that differ from `current`.
+    pub fn mismatches(&self, current: &IndexMeta) -> Vec<MetaMismatch> {
+        let mut mismatches = Vec::new();
+        let mut compare = |setting, indexed: String, now: String, breaking| {
+            if indexed != now {
+                mismatches.push(MetaMismatch {
+                    setting,
+                    indexed,
+                    current: now,
+                    breaking,
+                });
+            }
+        };
+        compare(
+            "index format version",
+            self.format_version.to_string(),
+            current.format_version.to_string(),
+            true,
+        );
+        compare(
+            "embedding_model",
+            self.embedding_model.clone(),
+            current.embedding_model.clone(),
+            true,
+        );
+        compare(
+            "normalize_embeddings",
+            self.normalize_embeddings.to_string(),
+            current.normalize_embeddings.to_string(),
+            true,
+        );
+        compare(
+            "normalize_identifiers",
+            self.normalize_identifiers.to_string(),
+            current.normalize_identifiers.to_string(),
+            false,
+        );
+        compare(
+            "chunk_size",
+            self.chunk_size.to_string(),
+            current.chunk_size.to_string(),
+            false,
+        );
+        compare(
+            "chunk_overlap",
+            self.chunk_overlap.to_string(),
+            current.chunk_overlap.to_string(),
+            false,
+        );
+        // Chunks are stored in the table their shard key picks
+        compare(
+            "shard_count",
+            self.shard_count.to_string(),
+            current.shard_count.to_string(),
+            true,
+        );
+        compare(
+            "shard_key",
+            format!("{:?}", self.shard_key).to_lowercase(),
+            format!("{:?}", current.shard_key).to_lowercase(),
+            true,
+        );
+        compare(
+            "strip_comments",
+            format!("{:?}", self.strip_comments),
+            format!("{:?}", current.strip_comments),
+            false,
+        );
+        compare(
+            "chunk_balance_target",
+            self.chunk_balance_target.to_string(),
+            current.chunk_balance_target.to_string(),
+            false,
+        );
+        mismatches
+    }
+}
+
+/// Compares the index at `db_path` with `current` before it is searched or updated.
+///
+/// Differences are logged, and with [`IndexVersionCheck::Error`] breaking ones are
+/// returned as an error that suggests re-indexing. Indexes created before metadata
+/// was recorded cannot be compared and are only noted in the log.
+pub fn verify_index(
+    db_path: &Path,
+    current: &IndexMeta,
+    check: IndexVersionCheck,
+) -> Result<(), CodeRagError> {
+    if check == IndexVersionCheck::Off {
+        return Ok(());
+    }
+    let stored = match IndexMeta::read(db_path) {
+        Ok(Some(stored)) => stored,
+        Ok(None) => {
+            info!(
+                "Index at {} has no format metadata (created by an older code-rag); \
+                 re-index with `code-rag index --force` so it can be checked",
+                db_path.display()
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            warn!("Could not read the index metadata: {}", e);
+            return Ok(());
+        }
+    };
+
+    let mismatches = stored.mismatches(current);
+    let breaking: Vec<String> = mismatches
+        .iter()
+        .filter(|m| m.breaking)
+        .map(|m| m.to_string())
+        .collect();
+    if check == IndexVersionCheck::Error && !breaking.is_empty() {
+        return Err(CodeRagError::Database(format!(
+            "Index at {} is incompatible with this code-rag ({}): {}. \
+             Re-index with `code-rag index --force`",
+            db_path.display(),
+            env!("CARGO_PKG_VERSION"),
+            breaking.join("; ")
+        )));
+    }
+    for mismatch in &mismatches {
+        if mismatch.breaking {
+            warn!(
+                "Index at {} may give wrong results: {}; re-index with `code-rag index --force`",
+                db_path.display(),
+                mismatch
+            );
+        } else {
+            warn!(
+                "Index at {} was built with other settings: {}; new chunks will differ \
+                 until it is re-indexed",
+                db_path.display(),
+                mismatch
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> IndexMeta {
+        IndexMeta {
+            format_version: INDEX_FORMAT_VERSION,
+            code_rag_version: "0.1.0".to_string(),
+            embedding_model: "nomic-embed-text-v1.5".to_string(),
+            normalize_embeddings: false,
+            normalize_identifiers: false,
+            chunk_size: 1024,
+            chunk_overlap: 128,
+            shard_count: 1,
+            shard_key: ShardKey::Directory,
+            strip_comments: BTreeMap::new(),
+            chunk_balance_target: 0,
+        }
+    }
+
+    #[test]
+    fn test_old_format_version_is_reported() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let old = IndexMeta {
+            format_version: 0,
+            chunk_size: 512,
+            ..meta()
+        };
+        old.write(dir.path()).unwrap();
+        assert_eq!(IndexMeta::read(dir.path()).unwrap(), Some(old.clone()));
+
+        let mismatches = old.mismatches(&meta());
+        assert_eq!(mismatches.len(), 2);
+        assert!(mismatches[0].breaking);
+        assert_eq!(
+            mismatches[0].to_string(),
+            format!(
+                "index format version is 0 in the index but {} now",
+                INDEX_FORMAT_VERSION
+            )
+        );
+        assert!(!mismatches[1].breaking);
+
+        let err = verify_index(dir.path(), &meta(), IndexVersionCheck::Error)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("index format version is 0"), "{}", err);
+        assert!(err.contains("--force"), "{}", err);
+        assert!(!err.contains("chunk_size"), "{}", err);
+
+        assert!(verify_index(dir.path(), &meta(), IndexVersionCheck::Warn).is_ok());
+        assert!(verify_index(dir.path(), &meta(), IndexVersionCheck::Off).is_ok());
+    }
+
+    #[test]
+    fn test_shard_layout_is_breaking() {
+        let sharded = IndexMeta {
+            shard_count: 4,
+            shard_key: ShardKey::File,
+            strip_comments: BTreeMap::from([("rs".to_string(), true)]),
+            chunk_balance_target: 800,
+            ..meta()
+        };
+        let mismatches = sharded.mismatches(&meta());
+        let found: Vec<(&str, bool)> = mismatches.iter().map(|m| (m.setting, m.breaking)).collect();
+        assert_eq!(
+            found,
+            [
+                ("shard_count", true),
+                ("shard_key", true),
+                ("strip_comments", false),
+                ("chunk_balance_target", false)
+            ]
+        );
+        assert_eq!(
+            mismatches[1].to_string(),
+            "shard_key is file in the index but directory now"
+        );
+
+        // Metadata written before these settings were recorded reads as the old defaults
+        let old = r#"{"format_version":1,"code_rag_version":"0.1.0",
+            "embedding_model":"nomic-embed-text-v1.5","normalize_embeddings":false,
+            "normalize_identifiers":false,"chunk_size":1024,"chunk_overlap":128}"#;
+        let old: IndexMeta = serde_json::from_str(old).unwrap();
+        assert!(old.mismatches(&meta()).is_empty());
+    }
+
+    #[test]
+    fn test_matching_or_missing_metadata_passes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(IndexMeta::read(dir.path()).unwrap(), None);
+        assert!(verify_index(dir.path(), &meta(), IndexVersionCheck::Error).is_ok());
+
+        meta().write(dir.path()).unwrap();
+        assert!(meta().mismatches(&meta()).is_empty());
+        assert!(verify_index(dir.path(), &meta(), IndexVersionCheck::Error).is_ok());
+    }
+}
@@ -1,6 +1,185 @@
+use crate::grammars;
+use std::collections::BTreeSet;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
-use tree_sitter::{Language, Node, Parser};
+use tree_sitter::{Language, Node, Parser, Point};
+
+/// Where a sub-chunk sits within the semantic unit it was split from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkPart {
+    /// 1-based position of this part
+    pub index: usize,
+    /// Number of parts the unit was split into
+    pub count: usize,
+    /// First line of the whole unit (1-indexed)
+    pub parent_line_start: usize,
+    /// Last line of the whole unit (1-indexed)
+    pub parent_line_end: usize,
+}
+
+/// Permission bits and owner of an indexed file (`index_file_permissions`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FilePermissions {
+    /// Permission bits, including setuid, setgid and sticky (e.g. `0o755`)
+    pub mode: u32,
+    /// Numeric id of the owning user
+    pub owner: u32,
+}
+
+impl FilePermissions {
+    /// Reads the permissions from file metadata; `None` where the platform has no
+    /// Unix permission bits.
+    pub fn from_metadata(metadata: &std::fs::Metadata) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Some(Self {
+                mode: metadata.mode() & 0o7777,
+                owner: metadata.uid(),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            None
+        }
+    }
+
+    /// Parses an octal mode such as `002`, `0o755` or `4000`.
+    pub fn parse_mode(mode: &str) -> Result<u32, String> {
+        let digits = mode.strip_prefix("0o").unwrap_or(mode);
+        match u32::from_str_radix(digits, 8) {
+            Ok(bits) if bits <= 0o7777 => Ok(bits),
+            _ => Err(format!(
+                "invalid permission mode '{}' (expected octal, e.g. 002)",
+                mode
+            )),
+        }
+    }
+
+    /// Whether every bit of `mask` is set, e.g. `0o002` for world-writable files.
+    pub fn has_bits(&self, mask: u32) -> bool {
+        self.mode & mask == mask
+    }
+}
+
+/// The code range a note (`kind: note`) is attached to.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NoteAnchor {
+    /// File the note is about, joined to the indexed path like code chunk filenames
+    pub filename: String,
+    /// First line of the annotated range (1-indexed)
+    pub line_start: usize,
+    /// Last line of the annotated range (1-indexed)
+    pub line_end: usize,
+}
+
+/// What a chunk holds; serialized as a `kind` tag (`"code"` or `"note"`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ChunkKind {
+    /// Source code (or a file name) taken from the indexed file
+    #[default]
+    Code,
+    /// A prose note from the notes file, anchored to a range of code
+    Note { anchor: NoteAnchor },
+}
+
+/// The sort of item a code chunk holds, from its tree-sitter node; stored as
+/// `item_kind`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    /// A function, method or function prototype
+    Function,
+    /// A class, struct, enum, interface, trait, impl block or module
+    Class,
+    /// Top-level script logic
+    Statement,
+    /// Anything else, e.g. constants, config pairs, style rules and prose
+    #[default]
+    Other,
+}
+
+impl ItemKind {
+    /// The kind of a chunk made from a node of tree-sitter kind `node_kind`;
+    /// `is_script` for top-level statements of script languages.
+    pub fn of_node(node_kind: &str, is_script: bool) -> Self {
+        if is_script {
+            return Self::Statement;
+        }
+        match node_kind {
+            "function_item"
+            | "function_definition"
+            | "function_statement"
+            | "function_declaration"
+            | "method_declaration"
+            | "method_definition"
+            | "arrow_function"
+            | "method"
+            | "FnProto"
+            | "function"
+            | "init_declaration" => Self::Function,
+            "impl_item"
+            | "struct_item"
+            | "enum_item"
+            | "mod_item"
+            | "class_definition"
+            | "type_declaration"
+            | "class_specifier"
+            | "struct_specifier"
+            | "class_declaration"
+            | "interface_declaration"
+            | "record_declaration"
+            | "class"
+            | "module"
+            | "contract_declaration"
+            | "interface_definition"
+            | "library_definition"
+            | "object_declaration"
+            | "companion_object"
+            | "protocol_declaration"
+            | "object_definition"
+            | "trait_definition"
+            | "enum_definition" => Self::Class,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Function => "function",
+            Self::Class => "class",
+            Self::Statement => "statement",
+            Self::Other => "other",
+        }
+    }
+
+    /// Parses `function`, `class`, `statement` or `other` (e.g. `search --kind`).
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "function" => Ok(Self::Function),
+            "class" => Ok(Self::Class),
+            "statement" => Ok(Self::Statement),
+            "other" => Ok(Self::Other),
+            _ => Err(format!(
+                "Unknown kind '{}': expected function, class, statement or other",
+                s
+            )),
+        }
+    }
+}
+
+/// One line of the notes file (`notes_file`).
+#[derive(Debug, serde::Deserialize)]
+struct NoteEntry {
+    file: String,
+    line_start: usize,
+    line_end: Option<usize>,
+    note: String,
+}
 
 /// A single logical unit of code extracted from a source file.
 ///
@@ -18,6 +197,121 @@ pub struct CodeChunk {
     pub last_modified: i64,
     /// List of function/method calls identified within this chunk
     pub calls: Vec<String>,
+    /// Set when the chunk is one part of a unit too large to embed whole
+    pub part: Option<ChunkPart>,
+    /// Whether the chunk comes from a test file (see `is_test_path`)
+    pub is_test: bool,
+    /// Code, or a note anchored to code
+    pub kind: ChunkKind,
+    /// Permissions of the source file, when recorded (`index_file_permissions`)
+    pub permissions: Option<FilePermissions>,
+    /// Name of the function, class or other item the chunk defines, when it has one
+    pub symbol: Option<String>,
+    /// Names of the enclosing classes, impls and namespaces, outermost first
+    pub scope: Vec<String>,
+    /// Function, class, script statement or other item
+    pub item_kind: ItemKind,
+}
+
+/// Optional per-chunk columns stored next to the core chunk fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkMetadata {
+    pub part: Option<ChunkPart>,
+    pub is_test: bool,
+    pub kind: ChunkKind,
+    pub permissions: Option<FilePermissions>,
+    pub symbol: Option<String>,
+    pub scope: Vec<String>,
+    pub item_kind: ItemKind,
+}
+
+impl ChunkMetadata {
+    /// The anchor of a note chunk.
+    pub fn anchor(&self) -> Option<&NoteAnchor> {
+        match &self.kind {
+            ChunkKind::Code => None,
+            ChunkKind::Note { anchor } => Some(anchor),
+        }
+    }
+}
+
+impl CodeChunk {
+    pub fn metadata(&self) -> ChunkMetadata {
+        ChunkMetadata {
+            part: self.part,
+            is_test: self.is_test,
+            kind: self.kind.clone(),
+            permissions: self.permissions,
+            symbol: self.symbol.clone(),
+            scope: self.scope.clone(),
+            item_kind: self.item_kind,
+        }
+    }
+
+    /// Creates a name-only entry for a file whose content is not indexed
+    /// (binary, unsupported or oversized), so it can still be found by name.
+    ///
+    /// The normalized path is used as the text so keyword search can match its parts.
+    pub fn asset(filename: &str, mtime: i64) -> Self {
+        let normalized = filename.replace("\\", "/");
+        Self {
+            filename: normalized.clone(),
+            code: normalized,
+            line_start: 0,
+            line_end: 0,
+            last_modified: mtime,
+            calls: Vec::new(),
+            part: None,
+            is_test: is_test_path(filename),
+            kind: ChunkKind::Code,
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: ItemKind::Other,
+        }
+    }
+
+    /// Parses a notes file: one JSON object per line with `file` (relative to `root`),
+    /// `line_start`, optional `line_end` and `note`.
+    ///
+    /// Each note becomes a chunk of the notes file itself (`filename`, at the note's line)
+    /// whose text is the note and whose kind carries the anchor. Blank lines are skipped;
+    /// malformed lines are skipped with a warning.
+    pub fn notes(filename: &str, root: &Path, content: &str, mtime: i64) -> Vec<Self> {
+        let normalized = filename.replace("\\", "/");
+        content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| match serde_json::from_str::<NoteEntry>(line) {
+                Ok(entry) => Some(Self {
+                    filename: normalized.clone(),
+                    code: entry.note,
+                    line_start: i + 1,
+                    line_end: i + 1,
+                    last_modified: mtime,
+                    calls: Vec::new(),
+                    part: None,
+                    is_test: false,
+                    kind: ChunkKind::Note {
+                        anchor: NoteAnchor {
+                            filename: root.join(&entry.file).to_string_lossy().replace('\\', "/"),
+                            line_start: entry.line_start,
+                            line_end: entry.line_end.unwrap_or(entry.line_start),
+                        },
+                    },
+                    permissions: None,
+                    symbol: None,
+                    scope: Vec::new(),
+                    item_kind: ItemKind::Other,
+                }),
+                Err(e) => {
+                    tracing::warn!("Skipping note on line {} of {}: {}", i + 1, filename, e);
+                    None
+                }
+            })
+            .collect()
+    }
 }
 
 /// Handles the semantic chunking of source code files using Tree-sitter.
@@ -29,6 +323,404 @@ pub struct CodeChunker {
     pub max_chunk_size: usize,
     /// Number of bytes to overlap between adjacent chunks when splitting large blocks
     pub chunk_overlap: usize,
+    /// Maximum AST depth visited; deeper subtrees are skipped
+    pub max_depth: usize,
+    /// Maximum number of AST nodes visited per file before traversal stops
+    pub max_nodes: usize,
+    /// Top-level script statements spanning fewer lines are merged with adjacent ones
+    pub min_chunk_lines: usize,
+    /// Record `ChunkPart` metadata on chunks split from an oversized unit
+    pub record_parts: bool,
+    /// Split oversized units at line ends instead of exact character offsets
+    pub split_on_lines: bool,
+    /// Chunk size in bytes the balancing pass evens chunks out towards (0 = off)
+    pub balance_target: usize,
+    /// Per extension (`*` for all others), whether comments are left out of the
+    /// embedded text
+    pub strip_comments: std::collections::HashMap<String, bool>,
+    /// Directory being indexed, with `/` separators; test files are recognized from the
+    /// path below it
+    pub index_root: Option<String>,
+}
+
+/// Whether files with `extension` are prose (Markdown, plain text, reStructuredText),
+/// chunked by heading instead of with a grammar.
+pub fn is_text_extension(extension: &str) -> bool {
+    matches!(extension, "md" | "txt" | "rst")
+}
+
+/// Extension of the grammar for a script whose `#!` line, the first line of `source`,
+/// names a known interpreter (`#!/usr/bin/env python3` gives `py`).
+pub fn shebang_extension(source: &str) -> Option<&'static str> {
+    let line = source.lines().next()?.strip_prefix("#!")?;
+    let mut args = line.split_whitespace();
+    let mut interpreter = args.next()?.rsplit('/').next()?;
+    if interpreter == "env" {
+        interpreter = args.find(|arg| !arg.starts_with('-'))?;
+    }
+    // python3.12 is python
+    match interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.') {
+        "sh" | "bash" | "zsh" | "ksh" | "dash" => Some("sh"),
+        "python" => Some("py"),
+        "ruby" => Some("rb"),
+        "node" | "nodejs" => Some("js"),
+        "php" => Some("php"),
+        "pwsh" | "powershell" => Some("ps1"),
+        "elixir" => Some("exs"),
+        _ => None,
+    }
+}
+
+/// Extension of the grammar for well-known files named without a useful extension.
+/// Dockerfiles and Makefiles are mostly shell commands, so they are parsed as Bash.
+pub fn special_file_extension(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    // Dockerfile.dev and the like
+    let is_dockerfile = ["Dockerfile", "Containerfile"].iter().any(|base| {
+        name.strip_prefix(base)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    });
+    (is_dockerfile || matches!(name, "Makefile" | "makefile" | "GNUmakefile")).then_some("sh")
+}
+
+/// Extension that picks the grammar for the file at `path`: the one for a well-known
+/// file name, its own, or for a file without one, the extension its `#!` line implies.
+/// Empty when there is none.
+pub fn source_extension(path: &Path) -> &str {
+    if let Some(ext) = special_file_extension(path) {
+        return ext;
+    }
+    match path.extension() {
+        Some(ext) => ext.to_str().unwrap_or(""),
+        None => {
+            let mut first_line = [0u8; 256];
+            std::fs::File::open(path)
+                .and_then(|mut file| file.read(&mut first_line))
+                .ok()
+                .and_then(|n| shebang_extension(&String::from_utf8_lossy(&first_line[..n])))
+                .unwrap_or("")
+        }
+    }
+}
+
+/// Share of non-text bytes in the start of a file above which it is treated as binary.
+const BINARY_BYTE_RATIO: f32 = 0.3;
+
+/// Whether `sample`, the start of a file, looks binary: more than 30% of it is control
+/// characters other than whitespace, or bytes that are not valid UTF-8.
+///
+/// A stray null byte, e.g. in a string literal, does not make a file binary.
+fn looks_binary(sample: &[u8]) -> bool {
+    let non_text: usize = sample
+        .utf8_chunks()
+        .map(|chunk| {
+            let control = chunk
+                .valid()
+                .bytes()
+                .filter(|&b| {
+                    (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f
+                })
+                .count();
+            control + chunk.invalid().len()
+        })
+        .sum();
+    non_text as f32 > sample.len() as f32 * BINARY_BYTE_RATIO
+}
+
+/// Whether the file at `path` looks binary from its first KB, as [`CodeChunker::chunk_file`]
+/// decides before skipping it.
+pub fn is_binary_file(path: &Path) -> std::io::Result<bool> {
+    let mut sample = Vec::with_capacity(1024);
+    std::fs::File::open(path)?
+        .take(1024)
+        .read_to_end(&mut sample)?;
+    Ok(utf16_byte_order(&sample).is_none() && looks_binary(&sample))
+}
+
+/// Byte order of a UTF-16 file, from its byte order mark: `Some(true)` for little
+/// endian.
+fn utf16_byte_order(sample: &[u8]) -> Option<bool> {
+    match sample.get(..2)? {
+        [0xff, 0xfe] => Some(true),
+        [0xfe, 0xff] => Some(false),
+        _ => None,
+    }
+}
+
+/// UTF-8 text of UTF-16 `bytes` (after the byte order mark).
+fn decode_utf16(bytes: &[u8], little_endian: bool) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            let pair = [pair[0], pair[1]];
+            if little_endian {
+                u16::from_le_bytes(pair)
+            } else {
+                u16::from_be_bytes(pair)
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Splits prose into sections at Markdown headings, lines starting with `#` outside
+/// fenced code blocks. Yields the 1-based line each section starts on and its lines.
+fn text_sections(text: &str) -> Vec<(usize, Vec<&str>)> {
+    let mut sections = Vec::new();
+    let (mut first_line, mut lines) = (1, Vec::new());
+    let mut in_fence = false;
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        } else if !in_fence && line.starts_with('#') && !lines.is_empty() {
+            sections.push((first_line, std::mem::take(&mut lines)));
+            first_line = i + 1;
+        }
+        lines.push(line);
+    }
+    if !lines.is_empty() {
+        sections.push((first_line, lines));
+    }
+    sections
+}
+
+/// Whether `filename` looks like test code, judged from its path alone. Pass the path
+/// relative to the indexed directory, as [`CodeChunker::is_test_file`] does, or the
+/// directories above the project count too.
+///
+/// Matches files under a `test`, `tests`, `__tests__` or `spec` directory, and names like
+/// `foo_test.rs`, `foo_test.go`, `test_foo.py`, `conftest.py`, `foo.spec.ts`,
+/// `foo.test.js` and `FooTest.java`.
+pub fn is_test_path(filename: &str) -> bool {
+    let normalized = filename.replace('\\', "/");
+    let mut components: Vec<&str> = normalized.split('/').collect();
+    let name = components.pop().unwrap_or_default();
+
+    if components
+        .iter()
+        .any(|dir| matches!(*dir, "test" | "tests" | "__tests__" | "spec"))
+    {
+        return true;
+    }
+
+    let stem = name.split('.').next().unwrap_or_default();
+    stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_tests")
+        || stem.ends_with("Test")
+        || stem.ends_with("Tests")
+        || stem == "conftest"
+        || name.contains(".spec.")
+        || name.contains(".test.")
+}
+
+/// Normalizes an identifier so naming conventions compare equal.
+///
+/// `getUserName`, `get_user_name` and `GetUserName` all become `getusername`.
+pub fn normalize_identifier(ident: &str) -> String {
+    ident
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Extracts the multi-word identifiers (`snake_case`, `camelCase`, `PascalCase`,
+/// `kebab-case`) in `code`, normalized with [`normalize_identifier`] and deduplicated.
+///
+/// Single-word identifiers are skipped since they already match as plain keywords.
+pub fn normalized_identifiers(code: &str) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    code.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .map(|token| token.trim_matches(|c| c == '_' || c == '-'))
+        .filter(|token| token.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .filter(|token| is_multi_word(token))
+        .map(normalize_identifier)
+        .filter(|ident| seen.insert(ident.clone()))
+        .collect()
+}
+
+fn is_multi_word(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    bytes.iter().any(|&b| b == b'_' || b == b'-')
+        || bytes
+            .windows(2)
+            .any(|w| w[0].is_ascii_lowercase() && w[1].is_ascii_uppercase())
+}
+
+/// Name of the first function or method defined in a chunk, if any.
+///
+/// The chunk is re-parsed on its own, using the grammar for `filename`'s extension.
+pub fn defined_symbol(filename: &str, code: &str) -> Option<String> {
+    let ext = Path::new(filename).extension()?.to_str()?;
+    let language = CodeChunker::get_language(ext)?;
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+    let tree = parser.parse(code, None)?;
+
+    // Breadth-first, so the outermost definition wins over nested closures
+    let mut queue = std::collections::VecDeque::from([tree.root_node()]);
+    let mut visited = 0;
+    while let Some(node) = queue.pop_front() {
+        visited += 1;
+        if visited > 10_000 {
+            break;
+        }
+        if matches!(
+            node.kind(),
+            "function_item"
+                | "function_definition"
+                | "function_declaration"
+                | "function_statement"
+                | "method_declaration"
+                | "method_definition"
+                | "method"
+        ) {
+            if let Some(name) = node.child_by_field_name("name") {
+                return name.utf8_text(code.as_bytes()).ok().map(str::to_string);
+            }
+        }
+        let mut cursor = node.walk();
+        queue.extend(node.named_children(&mut cursor));
+    }
+    None
+}
+
+/// Node kinds that hold comments in the grammar for `ext`.
+fn comment_kinds(ext: &str) -> &'static [&'static str] {
+    match ext {
+        "rs" | "java" => &["line_comment", "block_comment"],
+        "js" | "jsx" | "ts" | "tsx" => &["comment", "html_comment"],
+        "css" => &["comment", "js_comment"],
+        "hs" => &["comment", "haddock"],
+        _ => &["comment"],
+    }
+}
+
+/// `code` with its comments removed, along with lines left empty by them.
+///
+/// The chunk is re-parsed on its own with the grammar for `ext`. Returns `None` when
+/// it has no grammar or nothing but comments would remain.
+pub fn strip_comments(ext: &str, code: &str) -> Option<String> {
+    let language = CodeChunker::get_language(ext)?;
+    let mut parser = Parser::new();
+    parser.set_language(&language).ok()?;
+    let tree = parser.parse(code, None)?;
+    let kinds = comment_kinds(ext);
+
+    let mut comments = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        if kinds.contains(&node.kind()) {
+            comments.push(node.byte_range());
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    if comments.is_empty() {
+        return Some(code.to_string());
+    }
+    comments.sort_by_key(|r| r.start);
+
+    let mut stripped = String::with_capacity(code.len());
+    let mut pos = 0;
+    for range in comments {
+        if range.start >= pos {
+            stripped.push_str(&code[pos..range.start]);
+            pos = range.end;
+        }
+    }
+    stripped.push_str(&code[pos..]);
+
+    let kept: Vec<&str> = stripped
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    (!kept.is_empty()).then(|| kept.join("\n"))
+}
+
+/// A chunk's place in the call graph, as read back from the index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallGraphNode {
+    pub id: String,
+    /// Function the chunk defines, see [`defined_symbol`]
+    pub symbol: Option<String>,
+    pub calls: Vec<String>,
+}
+
+/// Importance of each chunk in the call graph, in `(0, 1]`.
+///
+/// A chunk's in-degree is the number of other chunks whose `calls` include the function
+/// it defines, by the last segment of the call's path (`self.db.query` calls `query`). Importance is `ln(1 + in-degree)` divided by the largest such value, so a
+/// few extra callers matter more than the hundredth. Chunks nobody calls are left out.
+pub fn call_graph_importance(nodes: &[CallGraphNode]) -> std::collections::HashMap<String, f32> {
+    let mut callers: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+    for node in nodes {
+        let unique: std::collections::HashSet<&str> =
+            node.calls.iter().map(|call| call_target(call)).collect();
+        for call in unique {
+            *callers.entry(call).or_insert(0) += 1;
+        }
+    }
+
+    let in_degrees: Vec<(&str, usize)> = nodes
+        .iter()
+        .filter_map(|node| {
+            let symbol = node.symbol.as_deref()?;
+            let count = callers.get(symbol).copied().unwrap_or(0);
+            // Recursion does not make a function central
+            let recursive = node.calls.iter().any(|c| call_target(c) == symbol);
+            let degree = count - usize::from(recursive);
+            (degree > 0).then_some((node.id.as_str(), degree))
+        })
+        .collect();
+
+    let max = in_degrees.iter().map(|(_, d)| *d).max().unwrap_or(0);
+    let scale = (1.0 + max as f32).ln();
+    in_degrees
+        .into_iter()
+        .map(|(id, degree)| (id.to_string(), (1.0 + degree as f32).ln() / scale))
+        .collect()
+}
+
+/// Name of the function a recorded call refers to: the last segment of its path, so
+/// `self.db.query` and `std::fs::read` give `query` and `read`.
+pub fn call_target(call: &str) -> &str {
+    call.rsplit(['.', ':']).next().unwrap_or(call)
+}
+
+/// Offset of the signature line within a chunk: the first line that is not part of a
+/// leading comment or attribute.
+fn signature_offset(code: &str) -> usize {
+    code.lines()
+        .position(|line| {
+            let line = line.trim_start();
+            !line.is_empty()
+                && !["//", "/*", "*", "#", "@", "--", "<!--"]
+                    .iter()
+                    .any(|prefix| line.starts_with(prefix))
+        })
+        .unwrap_or(0)
+}
+
+/// Default maximum AST depth visited by the chunker.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+/// Default maximum number of AST nodes visited per file.
+pub const DEFAULT_MAX_NODES: usize = 1_000_000;
+
+/// Per-file traversal bookkeeping used to enforce the depth and node limits.
+#[derive(Default)]
+struct TraversalBudget {
+    visited: usize,
+    depth_warned: bool,
+    exhausted: bool,
+    /// Index and start byte of the last script chunk, while it is below `min_chunk_lines`
+    script_run: Option<(usize, usize)>,
+    /// Names of the containers enclosing the current node
+    scope: Vec<String>,
 }
 
 impl Default for CodeChunker {
@@ -42,10 +734,122 @@ impl CodeChunker {
         Self {
             max_chunk_size,
             chunk_overlap,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_nodes: DEFAULT_MAX_NODES,
+            min_chunk_lines: 1,
+            record_parts: true,
+            split_on_lines: true,
+            balance_target: 0,
+            strip_comments: Default::default(),
+            index_root: None,
         }
     }
 
+    /// Sets the maximum AST depth and node-visit budget used while traversing a file.
+    pub fn with_traversal_limits(mut self, max_depth: usize, max_nodes: usize) -> Self {
+        self.max_depth = max_depth;
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Sets the minimum line span of top-level script chunks.
+    ///
+    /// Consecutive shorter statements are coalesced into one chunk (up to `max_chunk_size`);
+    /// `1` keeps every statement as its own chunk.
+    pub fn with_min_chunk_lines(mut self, min_chunk_lines: usize) -> Self {
+        self.min_chunk_lines = min_chunk_lines.max(1);
+        self
+    }
+
+    /// Sets whether split chunks carry their part number and the parent unit's range.
+    pub fn with_part_metadata(mut self, enabled: bool) -> Self {
+        self.record_parts = enabled;
+        self
+    }
+
+    /// Sets whether oversized units are split at line ends (default) or at exact
+    /// `max_chunk_size` character offsets.
+    ///
+    /// Lines longer than `max_chunk_size` are still cut mid-line.
+    pub fn with_line_splitting(mut self, enabled: bool) -> Self {
+        self.split_on_lines = enabled;
+        self
+    }
+
+    /// Sets the chunk size, in bytes, that a pass after chunking aims for: large units
+    /// are split and small neighboring ones merged. `0` keeps the units as parsed.
+    pub fn with_size_balancing(mut self, target: usize) -> Self {
+        self.balance_target = target;
+        self
+    }
+
+    /// Sets, per file extension, whether comments are left out of the text that is
+    /// embedded; a `*` entry applies to extensions not listed. Stored code is unchanged.
+    pub fn with_comment_stripping(
+        mut self,
+        strip_comments: std::collections::HashMap<String, bool>,
+    ) -> Self {
+        self.strip_comments = strip_comments;
+        self
+    }
+
+    /// Sets the directory being indexed, so only the path below it decides whether a
+    /// file is test code.
+    pub fn with_index_root(mut self, root: &Path) -> Self {
+        let root = root.to_string_lossy().replace('\\', "/");
+        self.index_root = Some(root.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Whether `filename` is test code, judged by [`is_test_path`] from its path below
+    /// the index root. A checkout that itself sits under a `tests` directory is not all
+    /// tests.
+    pub fn is_test_file(&self, filename: &str) -> bool {
+        let normalized = filename.replace('\\', "/");
+        let relative = self
+            .index_root
+            .as_deref()
+            .and_then(|root| normalized.strip_prefix(root))
+            .and_then(|rest| rest.strip_prefix('/'))
+            .unwrap_or(&normalized);
+        is_test_path(relative)
+    }
+
+    /// The text embedded for `chunk`: its code, without comments if stripping is
+    /// enabled for its language.
+    pub fn embedding_text(&self, chunk: &CodeChunk) -> String {
+        if !matches!(chunk.kind, ChunkKind::Code) {
+            return chunk.code.clone();
+        }
+        let ext = Path::new(&chunk.filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("");
+        let strip = self
+            .strip_comments
+            .get(ext)
+            .or_else(|| self.strip_comments.get("*"))
+            .copied()
+            .unwrap_or(false);
+        if !strip {
+            return chunk.code.clone();
+        }
+        strip_comments(ext, &chunk.code).unwrap_or_else(|| chunk.code.clone())
+    }
+
+    /// Whether files with `extension` are chunked: they have a grammar or are prose.
+    pub fn is_supported(extension: &str) -> bool {
+        is_text_extension(extension) || Self::get_language(extension).is_some()
+    }
+
+    /// Grammar for files with `extension`: a built-in one, or one loaded from
+    /// `custom_grammars`.
     pub fn get_language(extension: &str) -> Option<Language> {
+        Self::builtin_language(extension).or_else(|| grammars::custom_language(extension))
+    }
+
+    /// Grammar compiled into code-rag for `extension`.
+    pub fn builtin_language(extension: &str) -> Option<Language> {
         match extension {
             "rs" => Some(tree_sitter_rust::LANGUAGE.into()),
             "py" => Some(tree_sitter_python::LANGUAGE.into()),
@@ -69,6 +873,9 @@ impl CodeChunker {
             "ex" | "exs" => Some(tree_sitter_elixir::LANGUAGE.into()),
             "hs" => Some(tree_sitter_haskell::LANGUAGE.into()),
             "sol" => Some(tree_sitter_solidity::LANGUAGE.into()),
+            "kt" | "kts" => Some(tree_sitter_kotlin_ng::LANGUAGE.into()),
+            "swift" => Some(tree_sitter_swift::LANGUAGE.into()),
+            "scala" | "sc" => Some(tree_sitter_scala::LANGUAGE.into()),
             _ => None,
         }
     }
@@ -81,26 +888,43 @@ impl CodeChunker {
     ) -> std::io::Result<Vec<CodeChunk>> {
         let normalized_filename = filename.replace("\\", "/");
         let path = Path::new(&normalized_filename);
-        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
 
+        // Check for binary content
+        let mut check_buf = [0u8; 1024];
+        let bytes_read = reader.read(&mut check_buf)?;
+        reader.seek(SeekFrom::Start(0))?;
+        let sample = &check_buf[..bytes_read];
+
+        // Extensionless scripts are chunked with the grammar their shebang names
+        let ext = match (special_file_extension(path), path.extension()) {
+            (Some(ext), _) => ext,
+            (None, Some(ext)) => ext.to_str().unwrap_or(""),
+            (None, None) => shebang_extension(&String::from_utf8_lossy(sample)).unwrap_or(""),
+        };
         let language = match Self::get_language(ext) {
-            Some(l) => l,
+            Some(l) => Some(l),
+            None if is_text_extension(ext) => None,
             None => return Ok(vec![]),
         };
 
-        let mut parser = Parser::new();
-        if parser.set_language(&language).is_err() {
-            tracing::error!("Could not set language for extension: {}", ext);
+        if let Some(little_endian) = utf16_byte_order(sample) {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let text = decode_utf16(&bytes[2..], little_endian);
+            return self.chunk_file(filename, &mut std::io::Cursor::new(text), mtime);
+        }
+        if looks_binary(sample) {
+            tracing::debug!("Skipping binary file: {}", filename);
             return Ok(vec![]);
         }
 
-        // Check for binary content
-        let mut check_buf = [0u8; 1024];
-        let bytes_read = reader.read(&mut check_buf)?;
-        reader.seek(SeekFrom::Start(0))?;
+        let Some(language) = language else {
+            return self.chunk_text(&normalized_filename, reader, mtime);
+        };
 
-        if check_buf[..bytes_read].contains(&0) {
-            tracing::debug!("Skipping binary file: {}", filename);
+        let mut parser = Parser::new();
+        if parser.set_language(&language).is_err() {
+            tracing::error!("Could not set language for extension: {}", ext);
             return Ok(vec![]);
         }
 
@@ -135,6 +959,7 @@ impl CodeChunker {
         };
 
         let root = tree.root_node();
+        let mut budget = TraversalBudget::default();
 
         self.traverse(
             &root,
@@ -144,8 +969,86 @@ impl CodeChunker {
             ext,
             mtime,
             0,
+            &mut budget,
         )?;
 
+        if budget.exhausted {
+            tracing::warn!(
+                "Stopped chunking {} after visiting {} AST nodes (limit reached); kept {} chunks",
+                normalized_filename,
+                budget.visited,
+                chunks.len()
+            );
+        }
+
+        if self.balance_target > 0 && !chunks.is_empty() {
+            reader.seek(SeekFrom::Start(0))?;
+            let mut source = Vec::new();
+            reader.read_to_end(&mut source)?;
+            chunks = self.balance_chunks(chunks, &String::from_utf8_lossy(&source));
+        }
+
+        if self.is_test_file(&normalized_filename) {
+            for chunk in &mut chunks {
+                chunk.is_test = true;
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Chunks prose without a grammar: one chunk per heading section, cut to
+    /// `max_chunk_size` like an oversized code unit. Chunks have no calls and take the
+    /// heading as their symbol.
+    fn chunk_text<R: Read + Seek>(
+        &self,
+        filename: &str,
+        reader: &mut R,
+        mtime: i64,
+    ) -> std::io::Result<Vec<CodeChunk>> {
+        let mut source = Vec::new();
+        reader.read_to_end(&mut source)?;
+        let text = String::from_utf8_lossy(&source);
+        let mut chunks = Vec::new();
+        for (first_line, lines) in text_sections(&text) {
+            let Some(first) = lines.iter().position(|l| !l.trim().is_empty()) else {
+                continue;
+            };
+            let last = lines
+                .iter()
+                .rposition(|l| !l.trim().is_empty())
+                .unwrap_or(first);
+            let heading = lines[first]
+                .strip_prefix('#')
+                .map(|h| h.trim_start_matches('#').trim().to_string())
+                .filter(|h| !h.is_empty());
+            let unit = CodeChunk {
+                filename: filename.to_string(),
+                code: lines[first..=last].join("\n"),
+                line_start: first_line + first,
+                line_end: first_line + last,
+                last_modified: mtime,
+                calls: Vec::new(),
+                part: None,
+                is_test: false,
+                kind: Default::default(),
+                permissions: None,
+                symbol: heading,
+                scope: Vec::new(),
+                item_kind: Default::default(),
+            };
+            if unit.code.len() > self.max_chunk_size {
+                chunks.extend(self.split_chunk(unit, self.max_chunk_size));
+            } else {
+                chunks.push(unit);
+            }
+        }
+
+        if self.is_test_file(filename) {
+            for chunk in &mut chunks {
+                chunk.is_test = true;
+            }
+        }
         Ok(chunks)
     }
 
@@ -159,7 +1062,29 @@ impl CodeChunker {
         ext: &str,
         mtime: i64,
         depth: usize,
+        budget: &mut TraversalBudget,
     ) -> std::io::Result<()> {
+        if budget.exhausted {
+            return Ok(());
+        }
+        if budget.visited >= self.max_nodes {
+            budget.exhausted = true;
+            return Ok(());
+        }
+        budget.visited += 1;
+
+        if depth > self.max_depth {
+            if !budget.depth_warned {
+                budget.depth_warned = true;
+                tracing::warn!(
+                    "AST depth limit ({}) exceeded in {}; skipping deeper nodes",
+                    self.max_depth,
+                    filename
+                );
+            }
+            return Ok(());
+        }
+
         let kind = node.kind();
 
         let is_script_lang = matches!(
@@ -167,10 +1092,12 @@ impl CodeChunker {
             "py" | "js" | "ts" | "jsx" | "tsx" | "rb" | "lua" | "sh" | "bash" | "ps1"
         );
 
-        let is_semantic_chunk = matches!(
-            kind,
-            // Rust
-            "function_item" | "impl_item" | "struct_item" | "enum_item" | "mod_item" | "const_item" | "static_item" |
+        // Anonymous nodes are keywords, like the `class` token of a Python class
+        let is_semantic_chunk = node.is_named()
+            && matches!(
+                kind,
+                // Rust
+                "function_item" | "impl_item" | "struct_item" | "enum_item" | "mod_item" | "const_item" | "static_item" |
             // Python, C/C++, generic, Bash, PS1
              "function_definition" | "class_definition" | "function_statement" |
             // Go
@@ -198,8 +1125,11 @@ impl CodeChunker {
             // Haskell
              "signature" | "function" |
             // Solidity
-             "contract_declaration" | "interface_definition" | "library_definition"
-        );
+             "contract_declaration" | "interface_definition" | "library_definition" |
+            // Kotlin / Swift / Scala
+             "object_declaration" | "companion_object" | "protocol_declaration" | "init_declaration" |
+             "object_definition" | "trait_definition" | "enum_definition"
+            );
 
         let is_ruby_module = ext == "rb" && kind == "module";
 
@@ -224,8 +1154,30 @@ impl CodeChunker {
                     | "for_expression" // Bash/PS1 extras
             );
 
-        let is_chunkable = is_semantic_chunk || is_ruby_module || is_script_chunk;
+        // Custom grammars use their own node names, so each top-level node is a unit
+        let is_custom_chunk = depth == 1 && node.is_named() && grammars::is_custom(ext);
+
+        let is_chunkable =
+            is_semantic_chunk || is_ruby_module || is_script_chunk || is_custom_chunk;
 
+        // Members of Kotlin objects, Swift protocols and Scala traits sit in a
+        // body node (`class_body`, `template_body`), so descend into those too
+        let is_container = kind.contains("class")
+            || kind.contains("impl")
+            || kind.contains("struct")
+            || matches!(
+                kind,
+                "object_declaration"
+                    | "companion_object"
+                    | "protocol_declaration"
+                    | "object_definition"
+                    | "trait_definition"
+                    | "enum_definition"
+            )
+            || kind == "element"
+            || kind == "stylesheet";
+
+        let mut container = None;
         if is_chunkable {
             // Restore debug printing for S-expressions
             tracing::trace!(
@@ -235,7 +1187,12 @@ impl CodeChunker {
                 node.end_byte()
             );
 
-            let start_byte = node.start_byte();
+            // Doc comments are siblings of the item they document, so take them along
+            let (start_byte, start_position) = if is_semantic_chunk {
+                Self::documented_start(node)
+            } else {
+                (node.start_byte(), node.start_position())
+            };
             let end_byte = node.end_byte();
 
             // Read content from file/reader
@@ -257,60 +1214,307 @@ impl CodeChunker {
                 reader.read_exact(&mut buf)?;
                 let chunk_content = String::from_utf8_lossy(&buf).to_string();
 
-                let start_position = node.start_position();
                 let end_position = node.end_position();
 
                 // Extract calls
-                let calls = self.find_calls(node, reader)?;
-
-                if chunk_content.len() > self.max_chunk_size {
-                    let sub_chunks = self.split_text(&chunk_content);
-                    for sub_code in sub_chunks {
-                        chunks.push(CodeChunk {
-                            filename: filename.to_string(),
-                            code: sub_code,
-                            line_start: start_position.row + 1,
-                            line_end: end_position.row + 1,
-                            last_modified: mtime,
-                            calls: calls.clone(),
-                        });
-                    }
+                let calls = self.find_calls(node, reader, depth)?;
+                let symbol = if is_semantic_chunk || is_ruby_module {
+                    self.symbol_name(node, reader)?
+                } else {
+                    None
+                };
+                let item_kind = if is_custom_chunk && !is_semantic_chunk {
+                    ItemKind::Other
+                } else {
+                    ItemKind::of_node(kind, is_script_chunk)
+                };
+
+                let unit = CodeChunk {
+                    filename: filename.to_string(),
+                    code: chunk_content,
+                    line_start: start_position.row + 1,
+                    line_end: end_position.row + 1,
+                    last_modified: mtime,
+                    calls,
+                    part: None,
+                    is_test: false,
+                    kind: Default::default(),
+                    permissions: None,
+                    symbol,
+                    scope: budget.scope.clone(),
+                    item_kind,
+                };
+
+                if is_container {
+                    // Emitted once its members are, see `insert_container`
+                    budget.script_run = None;
+                    container = Some((chunks.len(), unit));
+                } else if unit.code.len() > self.max_chunk_size {
+                    chunks.extend(self.split_chunk(unit, self.max_chunk_size));
+                } else if is_script_chunk
+                    && self.merge_script_chunk(
+                        reader,
+                        chunks,
+                        budget,
+                        (end_byte, unit.line_end),
+                        &unit.calls,
+                    )?
+                {
+                    // Coalesced into the preceding short statement(s)
                 } else {
-                    chunks.push(CodeChunk {
-                        filename: filename.to_string(),
-                        code: chunk_content,
-                        line_start: start_position.row + 1,
-                        line_end: end_position.row + 1,
-                        last_modified: mtime,
-                        calls,
-                    });
+                    let line_count = unit.line_end - unit.line_start + 1;
+                    budget.script_run = (is_script_chunk && line_count < self.min_chunk_lines)
+                        .then_some((chunks.len(), start_byte));
+                    chunks.push(unit);
                 }
 
-                let is_container = kind.contains("class")
-                    || kind.contains("impl")
-                    || kind.contains("struct")
-                    || kind == "element"
-                    || kind == "stylesheet";
                 if !is_container {
                     return Ok(());
                 }
             }
         }
 
+        // Chunks inside a class, impl or namespace are scoped by its name
+        let is_namespace = matches!(
+            kind,
+            "namespace_definition" | "namespace_declaration" | "file_scoped_namespace_declaration"
+        );
+        let scope_name = if (is_chunkable && is_container) || is_namespace {
+            self.scope_name(node, reader)?
+        } else {
+            None
+        };
+        let scoped = scope_name.is_some();
+        budget.scope.extend(scope_name);
+
         let mut cursor = node.walk();
         for child in node.children(&mut cursor) {
-            self.traverse(&child, reader, filename, chunks, ext, mtime, depth + 1)?;
+            self.traverse(
+                &child,
+                reader,
+                filename,
+                chunks,
+                ext,
+                mtime,
+                depth + 1,
+                budget,
+            )?;
         }
 
+        if scoped {
+            budget.scope.pop();
+        }
+        if let Some((index, unit)) = container {
+            self.insert_container(chunks, index, unit);
+        }
         Ok(())
     }
 
+    /// Inserts the chunk of a class, impl or similar container at `index`, ahead of the
+    /// member chunks emitted after it.
+    ///
+    /// The members already hold the method bodies, so the container keeps the lines
+    /// above its first member (doc comments, the header and leading fields) followed by
+    /// the signature line of each direct member, giving an outline of the type. A
+    /// container without member chunks is kept whole.
+    fn insert_container(&self, chunks: &mut Vec<CodeChunk>, index: usize, mut unit: CodeChunk) {
+        let mut members: Vec<&CodeChunk> = chunks[index..].iter().collect();
+        members.sort_by_key(|c| c.line_start);
+        if let Some(first_member) = members.first().map(|c| c.line_start) {
+            let mut kept: BTreeSet<usize> =
+                (unit.line_start..first_member.max(unit.line_start)).collect();
+            // Chunks nested in an earlier member are not part of the outline
+            let mut covered_to = 0;
+            for member in members {
+                if member.line_start <= covered_to {
+                    continue;
+                }
+                covered_to = member.line_end;
+                kept.insert(member.line_start + signature_offset(&member.code));
+            }
+
+            let lines: Vec<&str> = unit.code.lines().collect();
+            let outline: Vec<&str> = kept
+                .into_iter()
+                .filter_map(|line| lines.get(line - unit.line_start).copied())
+                .collect();
+            let outline = outline.join("\n");
+            let outline = outline.trim_end();
+            if outline.trim().is_empty() {
+                return;
+            }
+            unit.code = outline.to_string();
+            unit.calls
+                .retain(|call| outline.contains(call_target(call)));
+        }
+
+        if unit.code.len() > self.max_chunk_size {
+            let parts = self.split_chunk(unit, self.max_chunk_size);
+            chunks.splice(index..index, parts);
+        } else {
+            chunks.insert(index, unit);
+        }
+    }
+
+    /// Cuts `unit` into overlapping parts of at most `window` characters.
+    fn split_chunk(&self, unit: CodeChunk, window: usize) -> Vec<CodeChunk> {
+        let chars: Vec<char> = unit.code.chars().collect();
+        let ranges = if self.split_on_lines {
+            self.line_split_ranges(&chars, window)
+        } else {
+            self.split_ranges(chars.len(), window)
+        };
+        let count = ranges.len();
+        let parent_line_start = unit.line_start;
+        let parent_line_end = unit.line_end;
+
+        // Parts start in increasing order, so newlines are counted once
+        let (mut counted_to, mut line) = (0, parent_line_start);
+        let mut parts = Vec::with_capacity(count);
+        for (index, (start, end)) in ranges.into_iter().enumerate() {
+            line += chars[counted_to..start]
+                .iter()
+                .filter(|&&c| c == '\n')
+                .count();
+            counted_to = start;
+            let piece = &chars[start..end];
+            let inner_lines = piece[..piece.len().saturating_sub(1)]
+                .iter()
+                .filter(|&&c| c == '\n')
+                .count();
+            parts.push(CodeChunk {
+                filename: unit.filename.clone(),
+                code: piece.iter().collect(),
+                line_start: line,
+                line_end: line + inner_lines,
+                last_modified: unit.last_modified,
+                calls: unit.calls.clone(),
+                part: (self.record_parts && count > 1).then_some(ChunkPart {
+                    index: index + 1,
+                    count,
+                    parent_line_start,
+                    parent_line_end,
+                }),
+                is_test: false,
+                kind: unit.kind.clone(),
+                permissions: None,
+                symbol: unit.symbol.clone(),
+                scope: unit.scope.clone(),
+                item_kind: unit.item_kind,
+            });
+        }
+        parts
+    }
+
+    /// Evens chunk sizes out around `balance_target` bytes.
+    ///
+    /// Whole units well above the target are split into parts of about the target size,
+    /// and runs of adjacent units with the same parent (top-level items, or the methods
+    /// of one class) are merged, with the source lines between them, while the merged
+    /// chunk stays within the target. Parts of split units are never merged.
+    fn balance_chunks(&self, chunks: Vec<CodeChunk>, source: &str) -> Vec<CodeChunk> {
+        let target = self.balance_target;
+        let mut balanced = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            if chunk.part.is_some() || chunk.code.len() <= target + target / 2 {
+                balanced.push(chunk);
+                continue;
+            }
+            let pieces = chunk.code.len().div_ceil(target);
+            let chars: Vec<char> = chunk.code.chars().collect();
+            let mut window = (chars.len() + (pieces - 1) * self.chunk_overlap).div_ceil(pieces);
+            // Cutting at line ends shortens parts; widen the window until they fit in
+            // `pieces` rather than leave a sliver of a last part
+            while self.split_on_lines
+                && window < chars.len()
+                && self.line_split_ranges(&chars, window).len() > pieces
+            {
+                window += window.div_ceil(8);
+            }
+            balanced.extend(self.split_chunk(chunk, window));
+        }
+
+        let lines: Vec<&str> = source.split_inclusive('\n').collect();
+        let mut merged: Vec<CodeChunk> = Vec::with_capacity(balanced.len());
+        // Position in `merged` of the chunk being extended; units with the same scope
+        // share a parent
+        let mut run: Option<usize> = None;
+        for chunk in balanced {
+            let mergeable = chunk.part.is_none() && matches!(chunk.kind, ChunkKind::Code);
+            if let Some(last) = run.filter(|_| mergeable) {
+                let prev = &merged[last];
+                if prev.scope == chunk.scope
+                    && prev.line_end < chunk.line_start
+                    && chunk.line_end <= lines.len()
+                {
+                    let code = lines[prev.line_start - 1..chunk.line_end].concat();
+                    let code = code.trim_end_matches(['\r', '\n']);
+                    if code.len() <= target {
+                        let prev = &mut merged[last];
+                        prev.code = code.to_string();
+                        prev.line_end = chunk.line_end;
+                        prev.calls.extend(chunk.calls);
+                        if prev.symbol != chunk.symbol {
+                            prev.symbol = None;
+                        }
+                        if prev.item_kind != chunk.item_kind {
+                            prev.item_kind = ItemKind::Other;
+                        }
+                        continue;
+                    }
+                }
+            }
+            run = mergeable.then_some(merged.len());
+            merged.push(chunk);
+        }
+        merged
+    }
+
+    /// Extends the pending short script chunk up to `end` (byte, line), if there is one.
+    ///
+    /// Returns `false` when the statement must start a new chunk instead.
+    fn merge_script_chunk<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        chunks: &mut [CodeChunk],
+        budget: &mut TraversalBudget,
+        (end_byte, line_end): (usize, usize),
+        calls: &[String],
+    ) -> std::io::Result<bool> {
+        let Some((index, run_start)) = budget.script_run else {
+            return Ok(false);
+        };
+        // Only merge with the chunk emitted immediately before this statement
+        if index + 1 != chunks.len() || end_byte.saturating_sub(run_start) > self.max_chunk_size {
+            budget.script_run = None;
+            return Ok(false);
+        }
+
+        reader.seek(SeekFrom::Start(run_start as u64))?;
+        let mut buf = vec![0u8; end_byte - run_start];
+        reader.read_exact(&mut buf)?;
+
+        let chunk = &mut chunks[index];
+        chunk.code = String::from_utf8_lossy(&buf).to_string();
+        chunk.line_end = line_end;
+        chunk.calls.extend_from_slice(calls);
+
+        if chunk.line_end - chunk.line_start + 1 >= self.min_chunk_lines {
+            budget.script_run = None;
+        }
+        Ok(true)
+    }
+
     fn find_calls<R: Read + Seek>(
         &self,
         node: &Node,
         reader: &mut R,
+        depth: usize,
     ) -> std::io::Result<Vec<String>> {
         let mut calls = Vec::new();
+        if depth >= self.max_depth {
+            return Ok(calls);
+        }
         let mut cursor = node.walk();
 
         // Simple recursive search looking for call-like nodes
@@ -323,64 +1527,248 @@ impl CodeChunker {
                 }
             }
             // Recurse
-            calls.extend(self.find_calls(&child, reader)?);
+            calls.extend(self.find_calls(&child, reader, depth + 1)?);
         }
         Ok(calls)
     }
 
+    /// Name of the item `node` defines: its `name` field, or the identifier at the end
+    /// of its `declarator` chain (C/C++), else the first identifier child. `None` for
+    /// nodes without a direct name, such as `impl` blocks and arrow functions.
+    fn symbol_name<R: Read + Seek>(
+        &self,
+        node: &Node,
+        reader: &mut R,
+    ) -> std::io::Result<Option<String>> {
+        // Elixir `def` calls name their item inside the arguments
+        if node.kind() == "call" {
+            return Ok(None);
+        }
+        let mut current = *node;
+        loop {
+            if let Some(name) = current.child_by_field_name("name") {
+                current = name;
+                break;
+            }
+            match current.child_by_field_name("declarator") {
+                Some(declarator) => current = declarator,
+                None if current.id() == node.id() => return self.extract_name(node, reader),
+                None if current.kind().ends_with("identifier") => break,
+                None => return Ok(None),
+            }
+        }
+
+        reader.seek(SeekFrom::Start(current.start_byte() as u64))?;
+        let mut buf = vec![0u8; current.end_byte().saturating_sub(current.start_byte())];
+        reader.read_exact(&mut buf)?;
+        Ok(Some(String::from_utf8_lossy(&buf).to_string()))
+    }
+
+    /// Start of `node` including the comments on the lines directly above it, and
+    /// the attributes between those and the item. Comments trailing the previous item's
+    /// last line stay with that item.
+    fn documented_start(node: &Node) -> (usize, Point) {
+        // `export function f` and decorated Python definitions wrap the item
+        let mut current = match node.parent() {
+            Some(parent)
+                if matches!(parent.kind(), "export_statement" | "decorated_definition") =>
+            {
+                parent
+            }
+            _ => *node,
+        };
+        // Rust doc comments end after their newline, at column 0 of the next row
+        let last_row = |n: Node| {
+            let end = n.end_position();
+            if end.column == 0 && end.row > n.start_position().row {
+                end.row - 1
+            } else {
+                end.row
+            }
+        };
+        let mut start = (node.start_byte(), node.start_position());
+        while let Some(prev) = current.prev_sibling() {
+            let is_doc = prev.kind().contains("comment") || prev.kind() == "attribute_item";
+            if !is_doc || last_row(prev) + 1 < current.start_position().row {
+                break;
+            }
+            let is_trailing = prev
+                .prev_sibling()
+                .is_some_and(|before| last_row(before) == prev.start_position().row);
+            if is_trailing {
+                break;
+            }
+            start = (prev.start_byte(), prev.start_position());
+            current = prev;
+        }
+        start
+    }
+
+    /// Name a container gives the chunks inside it: its symbol name, or for an `impl`
+    /// block the type it implements.
+    fn scope_name<R: Read + Seek>(
+        &self,
+        node: &Node,
+        reader: &mut R,
+    ) -> std::io::Result<Option<String>> {
+        let Some(ty) = node
+            .child_by_field_name("type")
+            .filter(|_| node.kind() == "impl_item")
+        else {
+            return self.symbol_name(node, reader);
+        };
+        reader.seek(SeekFrom::Start(ty.start_byte() as u64))?;
+        let mut buf = vec![0u8; ty.end_byte().saturating_sub(ty.start_byte())];
+        reader.read_exact(&mut buf)?;
+        Ok(Some(String::from_utf8_lossy(&buf).to_string()))
+    }
+
     fn extract_name<R: Read + Seek>(
         &self,
         node: &Node,
         reader: &mut R,
     ) -> std::io::Result<Option<String>> {
+        // Ruby calls name their method and receiver in fields
+        if let Some(method) = node.child_by_field_name("method") {
+            let method = self.call_path(&method, reader)?;
+            return Ok(Some(match node.child_by_field_name("receiver") {
+                Some(receiver) => format!("{}.{}", self.call_path(&receiver, reader)?, method),
+                None => method,
+            }));
+        }
+
         // Heuristic: finding the 'function' or 'identifier' child
         let mut cursor = node.walk();
         for child in node.children(&mut cursor) {
             let k = child.kind();
-            if matches!(k, "identifier" | "field_expression" | "scoped_identifier") {
-                let start = child.start_byte();
-                let end = child.end_byte();
-
-                reader.seek(SeekFrom::Start(start as u64))?;
-                let len = end.saturating_sub(start);
-                let mut buf = vec![0u8; len];
-                reader.read_exact(&mut buf)?;
-
-                return Ok(Some(String::from_utf8_lossy(&buf).to_string()));
+            if matches!(
+                k,
+                "identifier"
+                    | "field_expression"
+                    | "scoped_identifier"
+                    | "attribute"
+                    | "member_expression"
+                    | "selector_expression"
+            ) {
+                return Ok(Some(self.call_path(&child, reader)?));
             }
         }
         Ok(None)
     }
 
+    /// Full path of a callee, receiver included: `self.db.query`, `std::fs::read`.
+    ///
+    /// Paths are rebuilt from their parts, so line breaks in method chains are dropped;
+    /// a receiver that is itself a call keeps its `()`, as in `builder().build`.
+    fn call_path<R: Read + Seek>(&self, node: &Node, reader: &mut R) -> std::io::Result<String> {
+        let fields = match node.kind() {
+            "field_expression" => Some(("value", ".", "field")),
+            "scoped_identifier" => Some(("path", "::", "name")),
+            "attribute" => Some(("object", ".", "attribute")),
+            "member_expression" => Some(("object", ".", "property")),
+            "selector_expression" => Some(("operand", ".", "field")),
+            _ => None,
+        };
+        if let Some((receiver, separator, name)) = fields {
+            if let Some(name) = node.child_by_field_name(name) {
+                let name = self.call_path(&name, reader)?;
+                // `::std::fs` has no path before its first separator
+                let receiver = match node.child_by_field_name(receiver) {
+                    Some(receiver) => self.call_path(&receiver, reader)?,
+                    None => String::new(),
+                };
+                return Ok(format!("{}{}{}", receiver, separator, name));
+            }
+        }
+        if matches!(node.kind(), "call_expression" | "call") {
+            if let Some(callee) = self.extract_name(node, reader)? {
+                return Ok(format!("{}()", callee));
+            }
+        }
+
+        reader.seek(SeekFrom::Start(node.start_byte() as u64))?;
+        let mut buf = vec![0u8; node.end_byte().saturating_sub(node.start_byte())];
+        reader.read_exact(&mut buf)?;
+        Ok(String::from_utf8_lossy(&buf).split_whitespace().collect())
+    }
+
+    /// Splits `text` into the windows given by `split_ranges`.
+    #[cfg(test)]
     fn split_text(&self, text: &str) -> Vec<String> {
         if text.len() <= self.max_chunk_size {
             return vec![text.to_string()];
         }
 
-        let mut chunks = Vec::new();
         let chars: Vec<char> = text.chars().collect();
-        let total_chars = chars.len();
+        let ranges = if self.split_on_lines {
+            self.line_split_ranges(&chars, self.max_chunk_size)
+        } else {
+            self.split_ranges(chars.len(), self.max_chunk_size)
+        };
+        ranges
+            .into_iter()
+            .map(|(start, end)| chars[start..end].iter().collect())
+            .collect()
+    }
+
+    /// Character ranges of the overlapping windows of `window` characters a text is
+    /// cut into.
+    fn split_ranges(&self, total_chars: usize, window: usize) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
         let mut start = 0;
 
         while start < total_chars {
-            let end = std::cmp::min(start + self.max_chunk_size, total_chars);
-            let s: String = chars[start..end].iter().collect();
-            chunks.push(s);
+            let end = std::cmp::min(start + window, total_chars);
+            ranges.push((start, end));
 
             if end == total_chars {
                 break;
             }
 
             // Ensure we move forward and respect overlap
-            let step = if self.max_chunk_size > self.chunk_overlap {
-                self.max_chunk_size - self.chunk_overlap
+            let step = if window > self.chunk_overlap {
+                window - self.chunk_overlap
             } else {
                 1
             };
             start += step;
         }
 
-        chunks
+        ranges
+    }
+
+    /// Like `split_ranges`, but each window ends after a newline where it has one.
+    ///
+    /// Windows overlap by the whole lines that start within the last `chunk_overlap`
+    /// characters of the previous window.
+    fn line_split_ranges(&self, chars: &[char], window: usize) -> Vec<(usize, usize)> {
+        let total_chars = chars.len();
+        let mut ranges = Vec::new();
+        let mut start = 0;
+
+        while start < total_chars {
+            let limit = std::cmp::min(start + window.max(1), total_chars);
+            if limit == total_chars {
+                ranges.push((start, total_chars));
+                break;
+            }
+
+            // Cut mid-line only when a single line exceeds the window
+            let end = chars[start..limit]
+                .iter()
+                .rposition(|&c| c == '\n')
+                .map_or(limit, |i| start + i + 1);
+            ranges.push((start, end));
+
+            let overlap_from = end.saturating_sub(self.chunk_overlap).max(start + 1);
+            let mid_line = chars[end - 1] != '\n';
+            start = (overlap_from..end)
+                .find(|&i| chars[i - 1] == '\n')
+                // A line longer than the window is split like plain text, overlap included
+                .unwrap_or(if mid_line { overlap_from } else { end });
+        }
+
+        ranges
     }
 }
 
@@ -391,6 +1779,7 @@ mod tests {
 
     #[test]
     fn test_chunk_overlap() {
+        // A single line longer than the window falls back to character splitting
         let chunker = CodeChunker::new(10, 2);
         let text = "1234567890EXTRA"; // 15 chars
         let chunks = chunker.split_text(text);
@@ -399,6 +1788,11 @@ mod tests {
         assert_eq!(chunks[0], "1234567890");
         assert_eq!(chunks[1], "90EXTRA");
         assert!(chunks[1].starts_with("90"));
+
+        // Otherwise chunks end on line breaks and carry whole trailing lines over
+        let chunker = CodeChunker::new(12, 6);
+        let chunks = chunker.split_text("a;\nb();\nc();\nd;\n");
+        assert_eq!(chunks, vec!["a;\nb();\n", "b();\nc();\n", "c();\nd;\n"]);
     }
 
     #[test]
@@ -421,6 +1815,14 @@ mod tests {
         assert_eq!(chunks.len(), 2);
         assert_eq!(chunks[0], "12345");
         assert_eq!(chunks[1], "67890");
+
+        // A line that exactly fills the window is not cut
+        let chunks = chunker.split_text("abcd\nefgh\n");
+        assert_eq!(chunks, vec!["abcd\n", "efgh\n"]);
+        let chunks = chunker
+            .with_line_splitting(false)
+            .split_text("abcd\nefgh\n");
+        assert_eq!(chunks, vec!["abcd\n", "efgh\n"]);
     }
 
     #[test]
@@ -432,6 +1834,541 @@ mod tests {
         assert_eq!(chunks[0], "Short text");
     }
 
+    #[test]
+    fn test_split_function_records_parts() {
+        let chunker = CodeChunker::new(60, 10);
+        let body: String = (1..=8)
+            .map(|i| format!("    let value_{} = compute({});\n", i, i))
+            .collect();
+        let code = format!("// header\nfn large() {{\n{}}}\n", body);
+        let mut cursor = Cursor::new(code.as_bytes());
+
+        let chunks = chunker.chunk_file("large.rs", &mut cursor, 0).unwrap();
+        let parts: Vec<ChunkPart> = chunks.iter().filter_map(|c| c.part).collect();
+        assert_eq!(parts.len(), chunks.len());
+        let count = parts.len();
+        assert!(count > 2);
+
+        for (i, (chunk, part)) in chunks.iter().zip(&parts).enumerate() {
+            assert_eq!((part.index, part.count), (i + 1, count));
+            // The function and the comment above it span lines 1-11 of the file
+            assert_eq!((part.parent_line_start, part.parent_line_end), (1, 11));
+            assert!(chunk.line_start >= 1 && chunk.line_end <= 11);
+            assert!(chunk.line_start <= chunk.line_end);
+        }
+        assert_eq!(chunks[0].line_start, 1);
+        assert_eq!(chunks[count - 1].line_end, 11);
+        assert!(chunks[count - 1].code.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_split_large_function_on_line_boundaries() {
+        let chunker = CodeChunker::new(100, 40);
+        let body: String = (1..=12)
+            .map(|i| format!("    let value_{} = compute({});\n", i, i))
+            .collect();
+        let code = format!("fn large() {{\n{}}}\n", body);
+        let mut cursor = Cursor::new(code.as_bytes());
+
+        let chunks = chunker.chunk_file("large.rs", &mut cursor, 0).unwrap();
+        assert!(chunks.len() > 2);
+        let lines: Vec<&str> = code.lines().collect();
+        for chunk in &chunks {
+            assert!(chunk.code.chars().count() <= 100);
+            // Every part is made of whole source lines
+            let expected = lines[chunk.line_start - 1..chunk.line_end].join("\n");
+            assert_eq!(chunk.code.trim_end_matches('\n'), expected);
+        }
+        assert!(chunks[0].code.starts_with("fn large()"));
+        assert!(chunks.last().unwrap().code.trim_end().ends_with('}'));
+
+        // Lines starting in the last `chunk_overlap` characters are repeated
+        for pair in chunks.windows(2) {
+            assert!(pair[1].line_start <= pair[0].line_end);
+        }
+
+        // Character splitting cuts mid-line
+        let mut cursor = Cursor::new(code.as_bytes());
+        let chunks = chunker
+            .with_line_splitting(false)
+            .chunk_file("large.rs", &mut cursor, 0)
+            .unwrap();
+        assert!(chunks
+            .iter()
+            .any(|c| !c.code.ends_with('\n') && c.line_end < lines.len()));
+    }
+
+    #[test]
+    fn test_size_balancing_evens_out_chunk_sizes() {
+        let target = 400;
+        let getters: String = (1..=24)
+            .map(|i| {
+                format!("fn field_{i}(config: &Config) -> u32 {{\n    config.field_{i}\n}}\n\n")
+            })
+            .collect();
+        let body: String = (1..=60)
+            .map(|i| format!("    total += compute_step({}, total);\n", i))
+            .collect();
+        let code = format!(
+            "{getters}fn run_all() -> u32 {{\n    let mut total = 0;\n{body}    total\n}}\n"
+        );
+        let sizes =
+            |chunks: &[CodeChunk]| -> Vec<usize> { chunks.iter().map(|c| c.code.len()).collect() };
+        let outliers = |sizes: &[usize]| {
+            sizes
+                .iter()
+                .filter(|&&s| s < target / 4 || s > target * 2)
+                .count()
+        };
+        let deviation = |sizes: &[usize]| {
+            sizes.iter().map(|&s| s.abs_diff(target)).sum::<usize>() / sizes.len()
+        };
+
+        let chunker = CodeChunker::new(4096, 0);
+        let plain = chunker
+            .chunk_file("lib.rs", &mut Cursor::new(code.as_bytes()), 0)
+            .unwrap();
+        let chunker = chunker.with_size_balancing(target);
+        let balanced = chunker
+            .chunk_file("lib.rs", &mut Cursor::new(code.as_bytes()), 0)
+            .unwrap();
+
+        let (before, after) = (sizes(&plain), sizes(&balanced));
+        assert_eq!(outliers(&before), 25);
+        assert_eq!(outliers(&after), 0, "{:?}", after);
+        assert!(deviation(&after) * 3 < deviation(&before));
+        assert!(after.iter().all(|&s| s <= target * 3 / 2), "{:?}", after);
+
+        // Merged chunks are the source lines they span; the large function is split
+        let lines: Vec<&str> = code.lines().collect();
+        for chunk in balanced.iter().filter(|c| c.part.is_none()) {
+            let expected = lines[chunk.line_start - 1..chunk.line_end].join("\n");
+            assert_eq!(chunk.code, expected);
+        }
+        assert!(balanced[0].calls.is_empty());
+        let parts: Vec<&CodeChunk> = balanced.iter().filter(|c| c.part.is_some()).collect();
+        assert!(parts.len() >= 4);
+        assert!(parts[0].code.starts_with("fn run_all()"));
+        assert_eq!(
+            balanced.last().unwrap().part.unwrap().parent_line_end,
+            lines.len()
+        );
+        assert!(balanced
+            .iter()
+            .any(|c| c.calls.contains(&"compute_step".to_string())));
+    }
+
+    #[test]
+    fn test_test_files_judged_below_index_root() {
+        let chunker = CodeChunker::default().with_index_root(Path::new("/home/ci/tests/app/"));
+        assert!(!chunker.is_test_file("/home/ci/tests/app/src/main.rs"));
+        assert!(chunker.is_test_file("/home/ci/tests/app/tests/cli.rs"));
+        assert!(chunker.is_test_file("/home/ci/tests/app/src/parser_test.go"));
+        // Outside the root, the whole path counts
+        assert!(chunker.is_test_file("/home/ci/tests/application/src/main.rs"));
+
+        let chunks = chunker
+            .chunk_file(
+                "/home/ci/tests/app/src/lib.rs",
+                &mut Cursor::new("fn run() {}\n"),
+                0,
+            )
+            .unwrap();
+        assert!(!chunks[0].is_test);
+    }
+
+    #[test]
+    fn test_is_test_path() {
+        for path in [
+            "tests/foo.rs",
+            "crates/core/tests/integration.rs",
+            "src/parser_test.go",
+            "pkg/test_utils.py",
+            "conftest.py",
+            "web/src/app.spec.ts",
+            "web/src/button.test.jsx",
+            "src/main/java/FooTest.java",
+            "src\\__tests__\\index.js",
+        ] {
+            assert!(is_test_path(path), "{} should be a test file", path);
+        }
+        for path in [
+            "src/foo.rs",
+            "src/latest.rs",
+            "src/contest.py",
+            "lib/testing.go",
+        ] {
+            assert!(!is_test_path(path), "{} should not be a test file", path);
+        }
+    }
+
+    #[test]
+    fn test_deeply_nested_input_respects_depth_limit() {
+        let chunker = CodeChunker::default().with_traversal_limits(64, DEFAULT_MAX_NODES);
+        let depth = 20_000;
+        let code = format!("[{{\"k\": 1}}, {}{}]", "[".repeat(depth), "]".repeat(depth));
+        let mut cursor = Cursor::new(code);
+
+        let chunks = chunker.chunk_file("nested.json", &mut cursor, 0).unwrap();
+        assert!(chunks.iter().any(|c| c.code.contains("\"k\"")));
+    }
+
+    #[test]
+    fn test_node_budget_stops_traversal() {
+        let chunker = CodeChunker::default().with_traversal_limits(DEFAULT_MAX_DEPTH, 100);
+        let code = format!("[{}]", vec!["{\"k\": 1}"; 5000].join(","));
+        let mut cursor = Cursor::new(code);
+
+        let chunks = chunker.chunk_file("wide.json", &mut cursor, 0).unwrap();
+        assert!(!chunks.is_empty());
+        assert!(chunks.len() < 100);
+    }
+
+    #[test]
+    fn test_min_chunk_lines_coalesces_script_statements() {
+        let script: String = (1..=9).map(|i| format!("echo \"step {}\"\n", i)).collect();
+
+        let chunks = CodeChunker::default()
+            .chunk_file("deploy.sh", &mut Cursor::new(script.clone()), 0)
+            .unwrap();
+        assert_eq!(chunks.len(), 9);
+
+        let chunks = CodeChunker::default()
+            .with_min_chunk_lines(4)
+            .chunk_file("deploy.sh", &mut Cursor::new(script), 0)
+            .unwrap();
+        let spans: Vec<(usize, usize)> =
+            chunks.iter().map(|c| (c.line_start, c.line_end)).collect();
+        assert_eq!(spans, vec![(1, 4), (5, 8), (9, 9)]);
+        assert!(chunks[0].code.contains("step 1") && chunks[0].code.contains("step 4"));
+    }
+
+    #[test]
+    fn test_normalized_identifiers() {
+        let idents =
+            normalized_identifiers("fn getUserName(user_id: u32) { GetUserName(); x + 1 }");
+        assert_eq!(idents, vec!["getusername", "userid"]);
+        assert_eq!(normalize_identifier("get_user_name"), "getusername");
+        assert_eq!(normalize_identifier("get-user-name"), "getusername");
+    }
+
+    #[test]
+    fn test_defined_symbol() {
+        assert_eq!(
+            defined_symbol("lib.rs", "pub fn parse_config(path: &str) { load(path) }"),
+            Some("parse_config".to_string())
+        );
+        assert_eq!(
+            defined_symbol(
+                "app.py",
+                "def handle_request(req):\n    return respond(req)\n"
+            ),
+            Some("handle_request".to_string())
+        );
+        assert_eq!(
+            defined_symbol("lib.rs", "struct Config { path: String }"),
+            None
+        );
+        assert_eq!(defined_symbol("notes.txt", "fn looks_like_code() {}"), None);
+    }
+
+    #[test]
+    fn test_chunks_record_symbol_names() {
+        let chunker = CodeChunker::default();
+        let code = "struct Config { path: String }\n\nimpl Config {\n    fn load(path: &str) -> Self { todo!() }\n}\n";
+        let chunks = chunker
+            .chunk_file("config.rs", &mut Cursor::new(code), 0)
+            .unwrap();
+        let symbols: Vec<Option<&str>> = chunks.iter().map(|c| c.symbol.as_deref()).collect();
+        // The impl block names no item of its own
+        assert_eq!(symbols, vec![Some("Config"), None, Some("load")]);
+
+        let code = "static int *parse_header(const char *buf) {\n    return 0;\n}\n";
+        let chunks = chunker
+            .chunk_file("parse.cpp", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert_eq!(chunks[0].symbol.as_deref(), Some("parse_header"));
+    }
+
+    #[test]
+    fn test_markdown_chunked_by_heading() {
+        let chunker = CodeChunker::new(80, 10);
+        let code = "Intro line.\n\n# Install\n\nRun the installer.\n\n```sh\n# not a heading\nmake install\n```\n\n## Usage\nStart it with `code-rag serve` and point a client at it.\nThen index a project before searching.\n";
+        let chunks = chunker
+            .chunk_file("docs/README.md", &mut Cursor::new(code), 0)
+            .unwrap();
+        let sections: Vec<(usize, usize, Option<&str>)> = chunks
+            .iter()
+            .map(|c| (c.line_start, c.line_end, c.symbol.as_deref()))
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                (1, 1, None),
+                (3, 10, Some("Install")),
+                (12, 13, Some("Usage")),
+                (14, 14, Some("Usage")),
+            ]
+        );
+        assert!(chunks[1].code.ends_with("make install\n```"));
+        assert!(chunks.iter().all(|c| c.calls.is_empty()));
+        assert!(chunks.iter().all(|c| c.code.len() <= 80));
+    }
+
+    #[test]
+    fn test_chunks_include_preceding_doc_comments() {
+        let chunker = CodeChunker::default();
+        let code = "fn first() {} // trailing\n/// Loads the config.\n/// Fails when missing.\n#[inline]\nfn load() {}\n\n// Unrelated note\n\nfn other() {}\n";
+        let chunks = chunker
+            .chunk_file("config.rs", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].code, "fn first() {}");
+        assert!(chunks[1]
+            .code
+            .starts_with("/// Loads the config.\n/// Fails"));
+        assert!(chunks[1].code.ends_with("#[inline]\nfn load() {}"));
+        assert_eq!(chunks[1].line_start, 2);
+        // A blank line separates a comment from the item below it
+        assert_eq!(chunks[2].code, "fn other() {}");
+        assert_eq!(chunks[2].line_start, 9);
+    }
+
+    #[test]
+    fn test_chunks_record_enclosing_scope() {
+        let chunker = CodeChunker::default();
+        let code = "class Calculator:\n    def add(self, a, b):\n        return a + b\n\ndef add(a, b):\n    return a + b\n";
+        let chunks = chunker
+            .chunk_file("calc.py", &mut Cursor::new(code), 0)
+            .unwrap();
+        let scoped: Vec<(Option<&str>, Vec<String>)> = chunks
+            .iter()
+            .map(|c| (c.symbol.as_deref(), c.scope.clone()))
+            .collect();
+        assert_eq!(
+            scoped,
+            vec![
+                (Some("Calculator"), vec![]),
+                (Some("add"), vec!["Calculator".to_string()]),
+                (Some("add"), vec![]),
+            ]
+        );
+
+        // Methods of an impl block are scoped by the implemented type
+        let code = "impl Config {\n    fn load() -> Self { todo!() }\n}\n";
+        let chunks = chunker
+            .chunk_file("config.rs", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert_eq!(chunks[1].scope, vec!["Config".to_string()]);
+    }
+
+    #[test]
+    fn test_extensionless_script_chunked_by_shebang() {
+        let chunker = CodeChunker::default();
+        let code = "#!/usr/bin/env bash\nset -e\n\ndeploy() {\n    rsync -a dist/ host:/srv\n}\n";
+        let chunks = chunker
+            .chunk_file("scripts/deploy", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert!(chunks.iter().any(|c| c.symbol.as_deref() == Some("deploy")));
+
+        assert_eq!(shebang_extension("#!/usr/bin/python3.12 -u\n"), Some("py"));
+        assert_eq!(
+            shebang_extension("#!/usr/bin/env -S node --harmony"),
+            Some("js")
+        );
+        assert_eq!(shebang_extension("#!/usr/bin/awk -f"), None);
+        assert_eq!(shebang_extension("echo no shebang"), None);
+        let no_script = chunker
+            .chunk_file("LICENSE", &mut Cursor::new("MIT License\n\nCopyright\n"), 0)
+            .unwrap();
+        assert!(no_script.is_empty());
+    }
+
+    #[test]
+    fn test_special_file_names_are_chunked() {
+        let chunker = CodeChunker::default();
+        let code = "FROM rust:1.80 AS build\nWORKDIR /app\nRUN cargo build --release\n";
+        let chunks = chunker
+            .chunk_file("docker/Dockerfile", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert!(!chunks.is_empty());
+        assert!(chunks.iter().any(|c| c.code.contains("cargo build")));
+
+        assert_eq!(
+            special_file_extension(Path::new("Dockerfile.dev")),
+            Some("sh")
+        );
+        assert_eq!(
+            special_file_extension(Path::new("src/Makefile")),
+            Some("sh")
+        );
+        assert_eq!(special_file_extension(Path::new("Dockerfiles.md")), None);
+        assert_eq!(source_extension(Path::new("CMakeLists.txt")), "txt");
+    }
+
+    #[test]
+    fn test_chunks_record_item_kind() {
+        let chunker = CodeChunker::default();
+        let code = "class Calculator:\n    def add(self, a, b):\n        return a + b\n\ndef main():\n    pass\n\nmain()\n";
+        let chunks = chunker
+            .chunk_file("calc.py", &mut Cursor::new(code), 0)
+            .unwrap();
+        let kinds: Vec<ItemKind> = chunks.iter().map(|c| c.item_kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ItemKind::Class,
+                ItemKind::Function,
+                ItemKind::Function,
+                ItemKind::Statement
+            ]
+        );
+        assert_eq!(ItemKind::parse("Function"), Ok(ItemKind::Function));
+        assert!(ItemKind::parse("method").is_err());
+    }
+
+    #[test]
+    fn test_container_chunks_outline_members() {
+        let chunker = CodeChunker::default();
+        let code = "/// Settings\nimpl Config {\n    /// Reads it\n    fn load() {\n        read();\n    }\n    #[inline]\n    fn save() {\n        write();\n    }\n}\n";
+        let chunks = chunker
+            .chunk_file("config.rs", &mut Cursor::new(code), 0)
+            .unwrap();
+        let ranges: Vec<(usize, usize)> =
+            chunks.iter().map(|c| (c.line_start, c.line_end)).collect();
+        assert_eq!(ranges, vec![(1, 11), (3, 6), (7, 10)]);
+        // The header and one signature line per member, without the bodies
+        assert_eq!(
+            chunks[0].code,
+            "/// Settings\nimpl Config {\n    fn load() {\n    fn save() {"
+        );
+        assert!(chunks[0].calls.is_empty());
+    }
+
+    #[test]
+    fn test_calls_keep_receiver_and_path() {
+        let chunker = CodeChunker::default();
+        let code = "fn run(&self) {\n    let rows = self.db.query(\"users\");\n    let text = std::fs::read_to_string(path);\n    self.cache\n        .entry(key)\n        .or_default();\n}\n";
+        let chunks = chunker
+            .chunk_file("lib.rs", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert_eq!(
+            chunks[0].calls,
+            vec![
+                "self.db.query",
+                "std::fs::read_to_string",
+                "self.cache.entry().or_default",
+                "self.cache.entry",
+            ]
+        );
+        assert_eq!(call_target("self.db.query"), "query");
+        assert_eq!(call_target("std::fs::read_to_string"), "read_to_string");
+        assert_eq!(call_target("load"), "load");
+    }
+
+    #[test]
+    fn test_call_graph_importance() {
+        let node = |id: &str, symbol: Option<&str>, calls: &[&str]| CallGraphNode {
+            id: id.to_string(),
+            symbol: symbol.map(str::to_string),
+            calls: calls.iter().map(|c| c.to_string()).collect(),
+        };
+        let nodes = vec![
+            node("popular", Some("load"), &[]),
+            node("rare", Some("save"), &[]),
+            node("recursive", Some("walk"), &["walk"]),
+            node("a", Some("run_a"), &["load", "load"]),
+            node("b", Some("run_b"), &["load", "save"]),
+            node("c", None, &["load"]),
+        ];
+        let importance = call_graph_importance(&nodes);
+
+        assert_eq!(importance.get("popular"), Some(&1.0));
+        let rare = importance["rare"];
+        assert!((rare - 2f32.ln() / 4f32.ln()).abs() < 1e-6);
+        // Uncalled and only self-called chunks get no importance
+        assert!(!importance.contains_key("recursive"));
+        assert!(!importance.contains_key("a"));
+        assert!(call_graph_importance(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_embedding_text_strips_comments_per_language() {
+        let chunker = CodeChunker::default()
+            .with_comment_stripping([("java".to_string(), true), ("sh".to_string(), false)].into());
+        let chunk = |filename: &str, code: &str| CodeChunk {
+            filename: filename.to_string(),
+            code: code.to_string(),
+            line_start: 1,
+            line_end: code.lines().count(),
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: ChunkKind::Code,
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        };
+
+        let java = chunk(
+            "src/Retry.java",
+            "/**\n * Licensed under the Apache License.\n */\nclass Retry {\n    // attempts so far\n    int attempts = 0; // trailing\n}\n",
+        );
+        assert_eq!(
+            chunker.embedding_text(&java),
+            "class Retry {\n    int attempts = 0;\n}"
+        );
+
+        let shell = chunk(
+            "deploy.sh",
+            "# Roll back if the health check fails\n./rollback.sh\n",
+        );
+        assert_eq!(chunker.embedding_text(&shell), shell.code);
+
+        // Unlisted extensions keep their comments unless `*` says otherwise
+        let rust = chunk("src/lib.rs", "// helper\nfn helper() {}\n");
+        assert_eq!(chunker.embedding_text(&rust), rust.code);
+        let chunker = chunker.with_comment_stripping([("*".to_string(), true)].into());
+        assert_eq!(chunker.embedding_text(&rust), "fn helper() {}");
+        assert_eq!(
+            chunker.embedding_text(&chunk("src/lib.rs", "// only a comment\n")),
+            "// only a comment\n"
+        );
+    }
+
+    #[test]
+    fn test_utf16_file_is_decoded() {
+        let chunker = CodeChunker::default();
+        let code = "fn wide() {\n    println!(\"utf-16\");\n}\n";
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(code.encode_utf16().flat_map(u16::to_le_bytes));
+
+        let chunks = chunker
+            .chunk_file("wide.rs", &mut Cursor::new(bytes), 0)
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].code, code.trim_end());
+        assert_eq!((chunks[0].line_start, chunks[0].line_end), (1, 3));
+    }
+
+    #[test]
+    fn test_null_byte_in_string_is_not_binary() {
+        let chunker = CodeChunker::default();
+        let code = "fn terminator() -> &'static str {\n    \"end\0\"\n}\n";
+        let chunks = chunker
+            .chunk_file("nul.rs", &mut Cursor::new(code), 0)
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].code.contains('\0'));
+
+        // Random bytes without a single null are still binary
+        let noise: Vec<u8> = (0..1024u32).map(|i| (i * 97 % 251 + 1) as u8).collect();
+        assert!(!noise.contains(&0));
+        assert!(looks_binary(&noise));
+    }
+
     #[test]
     fn test_binary_file_skip() {
         let chunker = CodeChunker::default();
@@ -3,11 +3,18 @@ pub mod commands;
 pub mod config;
 pub mod context;
 pub mod core;
+pub mod disk_space;
 pub mod embedding;
+pub mod embedding_store;
+pub mod git;
+pub mod grammars;
+pub mod index_meta;
 pub mod indexer;
 pub mod llm;
 pub mod ops;
+pub mod remote;
 pub mod reporting;
+pub mod result_cache;
 pub mod search;
 pub mod server;
 pub mod storage;
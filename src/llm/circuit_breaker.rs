@@ -0,0 +1,171 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// When a [`CircuitBreaker`] stops calling a failing LLM, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerPolicy {
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    /// The failures must all happen within this period of the first one
+    pub window: Duration,
+    /// Time the breaker stays open before one call may probe the LLM again
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    failures: u32,
+    first_failure: Option<Instant>,
+    opened_at: Option<Instant>,
+    /// When a call started probing the LLM after the cooldown
+    probe_started: Option<Instant>,
+}
+
+/// Stops calls to an LLM that keeps failing, so requests do not each wait for it.
+///
+/// Closed, every call is allowed. After `failure_threshold` consecutive failures within
+/// `window` it opens and allows none for `cooldown`, then half-opens: a single call
+/// probes the LLM, closing the breaker on success and reopening it on failure. A probe
+/// that reports nothing within another `cooldown`, e.g. because its request was
+/// cancelled, is given up and the next call probes instead.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    policy: CircuitBreakerPolicy,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(policy: CircuitBreakerPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether a call may go to the LLM now. Past the cooldown, only the first caller
+    /// gets `true` until it reports its outcome or its probe times out.
+    pub fn allow(&self) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return true;
+        };
+        let Some(opened_at) = state.opened_at else {
+            return true;
+        };
+        let probe_pending = state
+            .probe_started
+            .is_some_and(|started| started.elapsed() < self.policy.cooldown);
+        if opened_at.elapsed() >= self.policy.cooldown && !probe_pending {
+            state.probe_started = Some(Instant::now());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the breaker is open (or half-open, waiting for its probe).
+    pub fn is_open(&self) -> bool {
+        self.state.lock().is_ok_and(|s| s.opened_at.is_some())
+    }
+
+    pub fn record_success(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if state.opened_at.is_some() {
+            tracing::info!("LLM is answering again; query expansion resumed");
+        }
+        *state = BreakerState::default();
+    }
+
+    pub fn record_failure(&self) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        if state.opened_at.is_some() {
+            // The probe failed; stay open for another cooldown
+            state.opened_at = Some(now);
+            state.probe_started = None;
+            return;
+        }
+
+        let in_window = state
+            .first_failure
+            .is_some_and(|first| now.duration_since(first) <= self.policy.window);
+        if !in_window {
+            state.failures = 0;
+            state.first_failure = Some(now);
+        }
+        state.failures += 1;
+        if state.failures >= self.policy.failure_threshold {
+            tracing::warn!(
+                "LLM failed {} times in a row; skipping query expansion for {:?}",
+                state.failures,
+                self.policy.cooldown
+            );
+            state.opened_at = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerPolicy {
+            failure_threshold: 2,
+            window: Duration::from_secs(60),
+            cooldown,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_threshold_and_probes_after_cooldown() {
+        let breaker = breaker(Duration::from_millis(50));
+        breaker.record_failure();
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(60));
+        // One probe at a time
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        breaker.record_failure();
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn test_abandoned_probe_times_out() {
+        let breaker = breaker(Duration::from_millis(50));
+        breaker.record_failure();
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(60));
+        // The probe's caller goes away without reporting an outcome
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let breaker = breaker(Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+    }
+}
@@ -7,6 +7,11 @@ use ollama_rs::{generation::completion::request::GenerationRequest, Ollama};
 pub trait LlmClient: Send + Sync {
     /// Generates text based on the given prompt.
     async fn generate(&self, prompt: &str) -> Result<String>;
+
+    /// Checks that the backend is reachable.
+    async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 /// Client for interacting with a local Ollama instance.
@@ -55,6 +60,14 @@ impl LlmClient for OllamaClient {
 
         Ok(response.response)
     }
+
+    async fn health_check(&self) -> Result<()> {
+        self.client
+            .list_local_models()
+            .await
+            .map(|_| ())
+            .map_err(|e| anyhow::anyhow!("Ollama is unreachable: {}", e))
+    }
 }
 
 #[cfg(test)]
@@ -84,4 +97,14 @@ pub mod mocks {
                 .clone())
         }
     }
+
+    /// A client whose every generation fails, like an unreachable backend.
+    pub struct FailingLlmClient;
+
+    #[async_trait]
+    impl LlmClient for FailingLlmClient {
+        async fn generate(&self, _prompt: &str) -> Result<String> {
+            Err(anyhow::anyhow!("connection refused"))
+        }
+    }
 }
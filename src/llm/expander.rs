@@ -1,16 +1,80 @@
-use crate::llm::LlmClient;
+use crate::llm::{CircuitBreaker, CircuitBreakerPolicy, LlmClient};
 use anyhow::Result;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+/// Default time allowed for the LLM reachability probe.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Service for expanding user queries into multiple related search terms.
 pub struct QueryExpander {
     llm_client: Arc<dyn LlmClient>,
+    probe_timeout: Duration,
+    healthy: OnceCell<bool>,
+    breaker: Option<CircuitBreaker>,
 }
 
 impl QueryExpander {
     /// Creates a new QueryExpander with the given LLM client.
     pub fn new(llm_client: Arc<dyn LlmClient>) -> Self {
-        Self { llm_client }
+        Self {
+            llm_client,
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
+            healthy: OnceCell::new(),
+            breaker: None,
+        }
+    }
+
+    /// Sets how long the reachability probe may take before the LLM is considered down.
+    pub fn with_probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Stops expanding for a while once the LLM keeps failing (`None` = always try).
+    pub fn with_circuit_breaker(mut self, policy: Option<CircuitBreakerPolicy>) -> Self {
+        self.breaker = policy.map(CircuitBreaker::new);
+        self
+    }
+
+    /// Whether an expansion may call the LLM now; `false` while the circuit breaker
+    /// is open after repeated failures.
+    pub fn available(&self) -> bool {
+        self.breaker.as_ref().is_none_or(|b| b.allow())
+    }
+
+    /// Probes the LLM on first call and caches the result for the session.
+    ///
+    /// When the probe fails, expansion should be skipped instead of paying the
+    /// request timeout on every query.
+    pub async fn check_health(&self) -> bool {
+        *self
+            .healthy
+            .get_or_init(|| async {
+                match tokio::time::timeout(self.probe_timeout, self.llm_client.health_check())
+                    .await
+                {
+                    Ok(Ok(())) => true,
+                    Ok(Err(e)) => {
+                        tracing::warn!("{}. Query expansion disabled for this session.", e);
+                        false
+                    }
+                    Err(_) => {
+                        tracing::warn!(
+                            "LLM did not respond within {:?}. Query expansion disabled for this session.",
+                            self.probe_timeout
+                        );
+                        false
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Result of the reachability probe, or `None` if it has not run yet.
+    pub fn health(&self) -> Option<bool> {
+        self.healthy.get().copied()
     }
 
     /// Expands a single query into a list of related search terms.
@@ -29,7 +93,20 @@ impl QueryExpander {
             query
         );
 
-        let response = self.llm_client.generate(&prompt).await?;
+        let response = match self.llm_client.generate(&prompt).await {
+            Ok(response) => {
+                if let Some(breaker) = &self.breaker {
+                    breaker.record_success();
+                }
+                response
+            }
+            Err(e) => {
+                if let Some(breaker) = &self.breaker {
+                    breaker.record_failure();
+                }
+                return Err(e);
+            }
+        };
 
         // Parse comma-separated response
         let mut terms: Vec<String> = response
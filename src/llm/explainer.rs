@@ -0,0 +1,166 @@
+use crate::llm::LlmClient;
+use crate::search::SearchResult;
+use futures_util::future::join_all;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default number of top results that get an explanation.
+pub const DEFAULT_EXPLAIN_TOP_K: usize = 3;
+/// Default time allowed for one explanation before it is left out.
+pub const DEFAULT_EXPLAIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Characters of a result's code included in its prompt.
+const MAX_PROMPT_CODE_CHARS: usize = 2000;
+
+/// Service for writing a one-sentence explanation of why a result matches a query.
+pub struct ResultExplainer {
+    llm_client: Arc<dyn LlmClient>,
+    top_k: usize,
+    timeout: Duration,
+}
+
+impl ResultExplainer {
+    /// Creates a new ResultExplainer with the given LLM client.
+    pub fn new(llm_client: Arc<dyn LlmClient>) -> Self {
+        Self {
+            llm_client,
+            top_k: DEFAULT_EXPLAIN_TOP_K,
+            timeout: DEFAULT_EXPLAIN_TIMEOUT,
+        }
+    }
+
+    /// Sets how many of the top results are explained.
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    /// Sets how long one explanation may take before it is left out.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets `explanation` on the first `top_k` results, generating them concurrently.
+    ///
+    /// A result whose generation fails, times out or comes back empty keeps no
+    /// explanation. Returns the number of results explained.
+    pub async fn explain(&self, query: &str, results: &mut [SearchResult]) -> usize {
+        let count = self.top_k.min(results.len());
+        let explanations = join_all(
+            results[..count]
+                .iter()
+                .map(|result| self.explain_one(query, result)),
+        )
+        .await;
+
+        let mut explained = 0;
+        for (result, explanation) in results.iter_mut().zip(explanations) {
+            explained += usize::from(explanation.is_some());
+            result.explanation = explanation;
+        }
+        explained
+    }
+
+    async fn explain_one(&self, query: &str, result: &SearchResult) -> Option<String> {
+        let code: String = result.code.chars().take(MAX_PROMPT_CODE_CHARS).collect();
+        let prompt = format!(
+            "You are a coding assistant. In one short sentence, explain why the code below is relevant to the search query.
+
+            Query: '{}'
+            File: {} (lines {}-{})
+            ```
+            {}
+            ```
+
+            Return ONLY the sentence. Do not repeat the query or the code.",
+            query, result.filename, result.line_start, result.line_end, code
+        );
+
+        match tokio::time::timeout(self.timeout, self.llm_client.generate(&prompt)).await {
+            Ok(Ok(response)) => parse_explanation(&response),
+            Ok(Err(e)) => {
+                tracing::warn!("Could not explain {}: {}", result.filename, e);
+                None
+            }
+            Err(_) => {
+                tracing::warn!(
+                    "Explaining {} took longer than {:?}; leaving it out",
+                    result.filename,
+                    self.timeout
+                );
+                None
+            }
+        }
+    }
+}
+
+/// The first non-empty line of `response`, without surrounding quotes.
+fn parse_explanation(response: &str) -> Option<String> {
+    let line = response.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let line = line.trim_matches(|c| c == '"' || c == '\'').trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::client::mocks::{FailingLlmClient, MockLlmClient};
+
+    fn result(filename: &str) -> SearchResult {
+        SearchResult {
+            rank: 1,
+            score: 0.5,
+            normalized_score: 0.0,
+            filename: filename.to_string(),
+            code: "fn parse_config() {}".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explains_top_results() {
+        let client = Arc::new(MockLlmClient::new(
+            "\n\"It loads the settings the query asks about.\"\nExtra notes",
+        ));
+        let explainer = ResultExplainer::new(client as Arc<dyn LlmClient>).with_top_k(2);
+        let mut results = vec![result("a.rs"), result("b.rs"), result("c.rs")];
+
+        assert_eq!(explainer.explain("config loading", &mut results).await, 2);
+        for result in &results[..2] {
+            assert_eq!(
+                result.explanation.as_deref(),
+                Some("It loads the settings the query asks about.")
+            );
+        }
+        assert_eq!(results[2].explanation, None);
+    }
+
+    #[tokio::test]
+    async fn test_llm_failure_leaves_results_unexplained() {
+        let explainer = ResultExplainer::new(Arc::new(FailingLlmClient));
+        let mut results = vec![result("a.rs"), result("b.rs")];
+
+        assert_eq!(explainer.explain("config loading", &mut results).await, 0);
+        assert!(results.iter().all(|r| r.explanation.is_none()));
+        assert_eq!(results[0].filename, "a.rs");
+
+        // An empty answer is no explanation either
+        let explainer = ResultExplainer::new(Arc::new(MockLlmClient::new("  \n")));
+        assert_eq!(explainer.explain("config loading", &mut results).await, 0);
+    }
+}
@@ -1,8 +1,12 @@
+pub mod circuit_breaker;
 pub mod client;
 pub mod expander;
+pub mod explainer;
 
 #[cfg(test)]
 mod tests;
 
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakerPolicy};
 pub use client::{LlmClient, OllamaClient};
 pub use expander::QueryExpander;
+pub use explainer::ResultExplainer;
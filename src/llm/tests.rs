@@ -36,3 +36,15 @@ async fn test_expand_query_parse_error_fallback() {
     assert!(expanded.contains(&"query".to_string()));
     assert!(expanded.contains(&"invalid format".to_string()));
 }
+
+#[tokio::test]
+async fn test_unreachable_host_disables_expansion() {
+    // Nothing listens on port 1, so the probe fails fast with a refused connection
+    let client = crate::llm::OllamaClient::new("http://127.0.0.1:1", "mistral");
+    let expander = QueryExpander::new(Arc::new(client))
+        .with_probe_timeout(std::time::Duration::from_millis(500));
+
+    assert_eq!(expander.health(), None);
+    assert!(!expander.check_health().await);
+    assert_eq!(expander.health(), Some(false));
+}
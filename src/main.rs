@@ -3,6 +3,10 @@ use clap::{Parser, Subcommand};
 
 use code_rag::commands::{index, search, serve, watch};
 use code_rag::config::AppConfig;
+use code_rag::git::GitSelection;
+use code_rag::indexer::{FilePermissions, ItemKind};
+use code_rag::search::{GrepOptions, TestFilter};
+use code_rag::storage::AsOf;
 use code_rag::telemetry::{init_telemetry, AppMode};
 
 #[cfg(windows)]
@@ -64,17 +68,50 @@ enum Commands {
         #[arg(long)]
         batch_size: Option<usize>,
 
-        /// Max number of threads
+        /// Threads chunking files in parallel (default: one per CPU core)
         #[arg(long)]
         threads: Option<usize>,
 
         /// Process priority (low, normal, high)
         #[arg(long)]
         priority: Option<String>,
+
+        /// Allow an update to remove more than `max_stale_fraction` of the index
+        #[arg(long)]
+        allow_mass_delete: bool,
+
+        /// Only index these file extensions, comma-separated (overrides `include_extensions`)
+        #[arg(long, value_delimiter = ',')]
+        ext: Vec<String>,
+
+        /// Only index files modified, staged or untracked in git (implies --update)
+        #[arg(long, conflicts_with_all = ["force", "git_diff"])]
+        git_changed: bool,
+
+        /// Only index files changed since a git ref, e.g. main or HEAD~3 (implies --update)
+        #[arg(long, value_name = "REF", conflicts_with = "force")]
+        git_diff: Option<String>,
+
+        /// Print a JSON summary of each run (files, chunks, bytes, duration) to stderr
+        #[arg(long)]
+        metrics_json: bool,
+
+        /// Index each package (a directory with a marker such as Cargo.toml) as its own workspace
+        #[arg(long, conflicts_with = "workspace")]
+        auto_workspaces: bool,
+
+        /// Index each git worktree (one per checked-out branch) as its own workspace
+        #[arg(long, conflicts_with_all = ["workspace", "auto_workspaces"])]
+        worktrees: bool,
+
+        /// Hide progress bars and log plain progress lines (automatic without a terminal)
+        #[arg(short, long)]
+        quiet: bool,
     },
     /// Search the indexed codebase semantically
     Search {
         /// The search query
+        #[arg(value_parser = parse_query)]
         query: String,
 
         /// Limit the number of results
@@ -97,6 +134,23 @@ enum Commands {
         #[arg(long)]
         dir: Option<String>,
 
+        /// Only search within this file (exact path or trailing part of it, e.g. src/a.rs)
+        #[arg(long, value_name = "PATH")]
+        in_file: Option<String>,
+
+        /// Only return chunks spanning at least N lines
+        #[arg(long, value_name = "N")]
+        min_lines: Option<usize>,
+
+        /// Only return chunks of files with all these permission bits (octal, e.g. 002
+        /// for world-writable); needs index_file_permissions
+        #[arg(long, value_name = "MODE", value_parser = FilePermissions::parse_mode)]
+        perm: Option<u32>,
+
+        /// Only return chunks of this kind: function, class, statement or other
+        #[arg(long, value_name = "KIND", value_parser = ItemKind::parse)]
+        kind: Option<ItemKind>,
+
         /// Disable reranking (faster)
         #[arg(long)]
         no_rerank: bool,
@@ -116,6 +170,69 @@ enum Commands {
         /// Expand query using local LLM
         #[arg(long)]
         expand: bool,
+
+        /// Group results, one entry per file (file)
+        #[arg(long, value_parser = ["file"], conflicts_with = "html")]
+        aggregate: Option<String>,
+
+        /// How chunk scores are combined per file with --aggregate (max, sum)
+        #[arg(long, value_parser = ["max", "sum"], default_value = "max")]
+        aggregate_score: String,
+
+        /// Return at most N chunks from any one file
+        #[arg(long)]
+        max_per_file: Option<usize>,
+
+        /// Search a previous index version (number or RFC 3339 timestamp)
+        #[arg(long, value_name = "VERSION|TIMESTAMP")]
+        as_of: Option<String>,
+
+        /// List call sites of each result's function (at most N, default 5)
+        #[arg(
+            long,
+            value_name = "N",
+            num_args = 0..=1,
+            default_missing_value = "5",
+            conflicts_with_all = ["aggregate", "html"]
+        )]
+        show_callers: Option<usize>,
+
+        /// Output format: numbered (Markdown with absolute line numbers, for LLM edits)
+        #[arg(
+            long,
+            value_parser = ["numbered"],
+            conflicts_with_all = ["json", "html", "aggregate", "show_callers"]
+        )]
+        format: Option<String>,
+
+        /// Leave out chunks from test files
+        #[arg(long, conflicts_with = "only_tests")]
+        exclude_tests: bool,
+
+        /// Return only chunks from test files
+        #[arg(long)]
+        only_tests: bool,
+
+        /// Include each result's embedding vector in the JSON output
+        #[arg(long, requires = "json", conflicts_with = "aggregate")]
+        include_vectors: bool,
+
+        /// Print the results as an LLM prompt that fits --max-tokens (default: prompt_max_tokens)
+        #[arg(
+            long,
+            conflicts_with_all = ["json", "html", "aggregate", "show_callers", "format"]
+        )]
+        prompt: bool,
+
+        /// Show the results with and without reranking side by side
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "no_rerank", "html", "aggregate", "show_callers", "format", "prompt",
+                "max_tokens", "expand", "include_vectors"
+            ]
+        )]
+        compare_rerank: bool,
     },
     /// Fast regex-based text search (no embeddings)
     Grep {
@@ -125,6 +242,36 @@ enum Commands {
         /// Output as JSON
         #[arg(long)]
         json: bool,
+
+        /// Treat the pattern as a literal string, not a regex
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Match case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Search the indexed code instead of walking the files on disk
+        #[arg(long)]
+        indexed: bool,
+
+        /// Workspace to search with --indexed (default: "default")
+        #[arg(short, long, requires = "indexed")]
+        workspace: Option<String>,
+    },
+    /// List the indexed chunks that call a function (no embeddings)
+    Callers {
+        /// Function name; calls through a receiver or path (`self.db.query`) match on
+        /// their last segment
+        name: String,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Workspace to look in (default: "default")
+        #[arg(short, long)]
+        workspace: Option<String>,
     },
     /// Start the REST API server only
     Serve {
@@ -135,6 +282,10 @@ enum Commands {
         /// Host to bind to (default: 127.0.0.1)
         #[arg(long)]
         host: Option<String>,
+
+        /// Listen on a Unix domain socket instead of TCP (Unix only)
+        #[arg(long)]
+        unix_socket: Option<String>,
     },
     /// Watch codebase for changes and auto-reindex
     Watch {
@@ -145,20 +296,41 @@ enum Commands {
         /// Workspace name (default: "default")
         #[arg(short, long, default_value = "default")]
         workspace: String,
+
+        /// Only watch this directory under the path (repeatable; overrides watch_subpaths)
+        #[arg(long = "subpath")]
+        subpaths: Vec<String>,
     },
     /// Start the Model Context Protocol (MCP) server for AI assistants
     Mcp,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
     /// Start unified services (Server + MCP + Watch) based on config flags\n    ///\n    /// Starts all enabled services concurrently based on your configuration:\n    ///   - enable_server = true  → HTTP API on configured port\n    ///   - enable_mcp = true     → MCP server via stdio\n    ///   - enable_watch = true   → File watcher for auto-indexing\n    ///\n    /// EXAMPLE:\n    ///   code-rag --config code-rag.toml start
     Start,
 }
 
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Show the value of every set key and which source it came from
+    /// (default, user-file, cwd-file, config-file or env)
+    Sources {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 1. Parse Arguments First
     let args = Args::parse();
 
     // 2. Load Configuration (with optional custom path from --config)
-    let config = AppConfig::from_path(args.config).context("Failed to load configuration")?;
+    let config =
+        AppConfig::from_path(args.config.clone()).context("Failed to load configuration")?;
 
     // 3. Setup Telemetry
     // If command is Serve or Start, we use Server mode (OTLP), otherwise CLI mode (Chrome/Local)
@@ -171,6 +343,7 @@ async fn main() -> anyhow::Result<()> {
     // Note: init_telemetry internally handles logging initialization for now,
     // replacing the old init_logging function.
     let _guard = init_telemetry(app_mode, &config).context("Failed to initialize telemetry")?;
+    code_rag::grammars::load_custom_grammars(&config.custom_grammars);
 
     // 4. Execute Command
     match args.command {
@@ -183,6 +356,14 @@ async fn main() -> anyhow::Result<()> {
             batch_size,
             threads,
             priority,
+            allow_mass_delete,
+            ext,
+            git_changed,
+            git_diff,
+            metrics_json,
+            auto_workspaces,
+            worktrees,
+            quiet,
         } => {
             let mut config = config.clone();
             if let Some(d) = device {
@@ -192,15 +373,14 @@ async fn main() -> anyhow::Result<()> {
                 config.priority = p;
             }
             if let Some(t) = threads {
-                tracing::warn!(
-                    "Thread limit {} requested but not yet implemented - using default thread pool",
-                    t
-                );
                 config.threads = Some(t);
             }
             if let Some(bs) = batch_size {
                 config.batch_size = bs;
             }
+            if !ext.is_empty() {
+                config.include_extensions = ext;
+            }
 
             // Apply process priority
             // NOTE: `apply_process_priority` is not defined in the provided context.
@@ -209,16 +389,64 @@ async fn main() -> anyhow::Result<()> {
             // Apply process priority
             apply_process_priority(&config.priority);
 
+            let git_selection = match git_diff {
+                Some(reference) => Some(GitSelection::Since(reference)),
+                None if git_changed => Some(GitSelection::Changed),
+                None => None,
+            };
+
             // Determine which workspaces to index
+            let use_worktrees =
+                worktrees || (config.worktree_workspaces && config.workspaces.is_empty());
+            let use_auto =
+                auto_workspaces || (config.auto_workspaces && config.workspaces.is_empty());
             let targets = if let Some(w) = workspace {
                 // Specific workspace requested
                 vec![(w, path)]
+            } else if use_worktrees {
+                // One workspace per checked-out branch
+                let root = path.unwrap_or_else(|| config.default_index_path.clone());
+                let trees = code_rag::git::worktrees(std::path::Path::new(&root))?;
+                for tree in &trees {
+                    println!(
+                        "Workspace '{}': {} ({})",
+                        tree.name,
+                        tree.path.display(),
+                        tree.branch.as_deref().unwrap_or("detached HEAD")
+                    );
+                }
+                trees
+                    .into_iter()
+                    .map(|tree| (tree.name, Some(tree.path.to_string_lossy().to_string())))
+                    .collect()
+            } else if use_auto {
+                // One workspace per detected package
+                let root = path.unwrap_or_else(|| config.default_index_path.clone());
+                let packages = index::discover_workspaces(
+                    std::path::Path::new(&root),
+                    &config.auto_workspace_markers,
+                    config.auto_workspace_depth,
+                );
+                if packages.is_empty() {
+                    anyhow::bail!(
+                        "No packages found under {} (looked for {})",
+                        root,
+                        config.auto_workspace_markers.join(", ")
+                    );
+                }
+                for (name, dir) in &packages {
+                    println!("Workspace '{}': {}", name, dir.display());
+                }
+                packages
+                    .into_iter()
+                    .map(|(name, dir)| (name, Some(dir.to_string_lossy().to_string())))
+                    .collect()
             } else if !config.workspaces.is_empty() {
                 // No workspace specified, but we have some in config - DO ALL
                 config
                     .workspaces
                     .iter()
-                    .map(|(name, p)| (name.clone(), Some(p.clone())))
+                    .map(|(name, w)| (name.clone(), Some(w.source().to_string())))
                     .collect()
             } else {
                 // No workspace specified and none in config - use default
@@ -226,19 +454,37 @@ async fn main() -> anyhow::Result<()> {
             };
 
             for (ws_name, ws_path) in targets {
-                index::index_codebase(
+                let summary = index::index_codebase(
                     index::IndexOptions {
                         path: ws_path,
                         db_path: None,
                         update,
                         force,
-                        workspace: ws_name,
+                        workspace: ws_name.clone(),
                         batch_size: Some(config.batch_size),
                         threads: config.threads,
+                        allow_mass_delete,
+                        git: git_selection.clone(),
+                        quiet,
                     },
                     &config,
                 )
                 .await?;
+
+                if metrics_json {
+                    eprintln!("{}", serde_json::to_string(&summary.metrics)?);
+                }
+
+                if !summary.removed_files.is_empty() {
+                    println!(
+                        "Removed {} stale files from workspace '{}':",
+                        summary.removed_files.len(),
+                        ws_name
+                    );
+                    for file in &summary.removed_files {
+                        println!("  - {}", file);
+                    }
+                }
             }
         }
         Commands::Search {
@@ -248,11 +494,26 @@ async fn main() -> anyhow::Result<()> {
             html,
             ext,
             dir,
+            in_file,
+            min_lines,
+            perm,
+            kind,
             no_rerank,
             workspace,
             max_tokens,
             device,
             expand,
+            aggregate,
+            aggregate_score,
+            max_per_file,
+            as_of,
+            show_callers,
+            format,
+            exclude_tests,
+            only_tests,
+            include_vectors,
+            prompt,
+            compare_rerank,
         } => {
             let mut config = config.clone();
             if let Some(d) = device {
@@ -265,26 +526,90 @@ async fn main() -> anyhow::Result<()> {
                 json,
                 ext,
                 dir,
+                in_file,
+                min_lines,
+                required_mode: perm,
+                item_kind: kind,
                 no_rerank,
                 workspace: Some(workspace),
 
                 max_tokens,
                 expand,
+                aggregate: aggregate
+                    .map(|_| aggregate_score.parse())
+                    .transpose()
+                    .map_err(anyhow::Error::msg)?,
+                max_per_file,
+                as_of: as_of
+                    .map(|s| s.parse::<AsOf>())
+                    .transpose()
+                    .map_err(anyhow::Error::msg)?,
+                show_callers,
+                numbered: format.is_some(),
+                test_filter: if exclude_tests {
+                    TestFilter::Exclude
+                } else if only_tests {
+                    TestFilter::Only
+                } else {
+                    TestFilter::All
+                },
+                include_vectors,
+                prompt,
+                compare_rerank,
             };
             search::search_codebase(query, options, &config).await?;
         }
-        Commands::Grep { pattern, json } => {
-            search::grep_codebase(pattern, json, &config)?;
+        Commands::Grep {
+            pattern,
+            json,
+            fixed_strings,
+            ignore_case,
+            indexed,
+            workspace,
+        } => {
+            let options = GrepOptions {
+                fixed_strings,
+                ignore_case,
+            };
+            search::grep_codebase(pattern, json, options, indexed, workspace, &config).await?;
         }
-        Commands::Serve { port, host } => {
+        Commands::Callers {
+            name,
+            json,
+            workspace,
+        } => {
+            search::callers_codebase(name, json, workspace, &config).await?;
+        }
+        Commands::Serve {
+            port,
+            host,
+            unix_socket,
+        } => {
+            let mut config = config.clone();
+            if unix_socket.is_some() {
+                config.server_socket_path = unix_socket;
+            }
             serve::serve_api(port, host, None, &config).await?;
         }
-        Commands::Watch { path, workspace } => {
+        Commands::Watch {
+            path,
+            workspace,
+            subpaths,
+        } => {
+            let mut config = config.clone();
+            if !subpaths.is_empty() {
+                config.watch_subpaths = subpaths;
+            }
             watch::watch_codebase(path, None, workspace, &config).await?;
         }
         Commands::Mcp => {
             code_rag::commands::mcp::run(&config).await?;
         }
+        Commands::Config {
+            command: ConfigCommand::Sources { json },
+        } => {
+            code_rag::commands::config::show_sources(args.config, json)?;
+        }
         Commands::Start => {
             code_rag::commands::start::run(&config).await?;
         }
@@ -293,6 +618,13 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Rejects empty and whitespace-only search queries before any model is loaded.
+fn parse_query(query: &str) -> Result<String, String> {
+    code_rag::search::validate_query(query)
+        .map(|_| query.to_string())
+        .map_err(|e| e.to_string())
+}
+
 fn apply_process_priority(priority: &str) {
     let p_lower = priority.to_lowercase();
     match p_lower.as_str() {
@@ -1,6 +1,6 @@
 use crate::bm25::BM25Index;
 use crate::embedding::Embedder;
-use crate::indexer::CodeChunker;
+use crate::indexer::{source_extension, CodeChunker, FilePermissions};
 use crate::storage::Storage;
 use std::fs;
 use std::path::Path;
@@ -12,6 +12,7 @@ pub struct CodeIndexer<'a> {
     bm25: &'a mut BM25Index,
     chunker: &'a CodeChunker,
     workspace: String,
+    record_permissions: bool,
 }
 
 impl<'a> CodeIndexer<'a> {
@@ -28,9 +29,16 @@ impl<'a> CodeIndexer<'a> {
             bm25,
             chunker,
             workspace,
+            record_permissions: false,
         }
     }
 
+    /// Records each file's permission bits and owner on its chunks (default: off).
+    pub fn with_file_permissions(mut self, record: bool) -> Self {
+        self.record_permissions = record;
+        self
+    }
+
     /// Indexes a single file.
     /// 1. Checks if it's a supported code file.
     /// 2. Checks modification time (deltas) if needed.
@@ -41,8 +49,8 @@ impl<'a> CodeIndexer<'a> {
         let path_lossy = path.to_string_lossy();
         let fname_str = path_lossy.to_string();
 
-        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-        if CodeChunker::get_language(ext).is_none() {
+        let ext = source_extension(path);
+        if !CodeChunker::is_supported(ext) {
             return Ok(()); // Skip unsupported files silently
         }
 
@@ -67,7 +75,7 @@ impl<'a> CodeIndexer<'a> {
         };
         let mut reader = std::io::BufReader::new(file);
 
-        let chunks = match self.chunker.chunk_file(&fname_str, &mut reader, mtime) {
+        let mut chunks = match self.chunker.chunk_file(&fname_str, &mut reader, mtime) {
             Ok(c) => c,
             Err(e) => {
                 warn!("Failed to chunk file {}: {}", fname_str, e);
@@ -78,8 +86,19 @@ impl<'a> CodeIndexer<'a> {
         if chunks.is_empty() {
             return Ok(());
         }
+        if self.record_permissions {
+            let permissions = fs::metadata(path)
+                .ok()
+                .and_then(|m| FilePermissions::from_metadata(&m));
+            for chunk in &mut chunks {
+                chunk.permissions = permissions;
+            }
+        }
 
-        let texts: Vec<String> = chunks.iter().map(|c| c.code.clone()).collect();
+        let texts: Vec<String> = chunks
+            .iter()
+            .map(|c| self.chunker.embedding_text(c))
+            .collect();
         let embeddings = match self.embedder.embed(texts, Some(256)) {
             Ok(e) => e,
             Err(e) => {
@@ -98,10 +117,11 @@ impl<'a> CodeIndexer<'a> {
         let ends: Vec<i32> = chunks.iter().map(|c| c.line_end as i32).collect();
         let mtimes: Vec<i64> = chunks.iter().map(|c| c.last_modified).collect();
         let calls: Vec<Vec<String>> = chunks.iter().map(|c| c.calls.clone()).collect();
+        let metadata = chunks.iter().map(|c| c.metadata()).collect();
 
         if let Err(e) = self
             .storage
-            .add_chunks(
+            .add_chunks_with_metadata(
                 &self.workspace,
                 ids,
                 filenames,
@@ -111,6 +131,7 @@ impl<'a> CodeIndexer<'a> {
                 mtimes,
                 calls,
                 embeddings,
+                metadata,
             )
             .await
         {
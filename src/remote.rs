@@ -0,0 +1,264 @@
+use crate::config::AppConfig;
+use crate::core::CodeRagError;
+
+/// A directory on a remote host, given as `ssh://user@host[:port]/path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteSource {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+    /// Absolute path of the directory on the remote host
+    pub path: String,
+}
+
+/// Whether `source` names a remote directory rather than a local path.
+pub fn is_remote(source: &str) -> bool {
+    source.starts_with("ssh://")
+}
+
+impl RemoteSource {
+    /// Parses `ssh://user@host[:port]/path`; the port defaults to 22.
+    pub fn parse(source: &str) -> Result<Self, CodeRagError> {
+        let invalid = |reason: &str| {
+            CodeRagError::Generic(format!(
+                "Invalid remote source '{}': {} (expected ssh://user@host[:port]/path)",
+                source, reason
+            ))
+        };
+        let rest = source
+            .strip_prefix("ssh://")
+            .ok_or_else(|| invalid("not an ssh:// URL"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => return Err(invalid("missing path")),
+        };
+        let (user, host_port) = authority
+            .split_once('@')
+            .ok_or_else(|| invalid("missing user"))?;
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().map_err(|_| invalid("bad port"))?),
+            None => (host_port, 22),
+        };
+        if user.is_empty() || host.is_empty() {
+            return Err(invalid("missing user or host"));
+        }
+
+        // Trailing slashes would make qualified names differ between runs
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            trimmed => trimmed,
+        };
+        Ok(Self {
+            user: user.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Name stored for a file of this source: `host:/absolute/path`.
+    pub fn qualify(&self, remote_path: &str) -> String {
+        format!("{}:{}", self.host, remote_path)
+    }
+}
+
+/// A regular file found under a [`RemoteSource`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteFile {
+    /// Absolute path on the remote host
+    pub path: String,
+    pub size: u64,
+    /// Modification time in seconds since the epoch
+    pub mtime: i64,
+}
+
+/// How to authenticate to remote sources.
+///
+/// A key file is used when configured, then the `CODE_RAG_SSH_PASSWORD` environment
+/// variable, then the SSH agent. Host keys must be listed in `known_hosts`.
+#[derive(Debug, Clone, Default)]
+pub struct SshAuth {
+    pub identity_file: Option<String>,
+    /// Defaults to `~/.ssh/known_hosts`
+    pub known_hosts: Option<String>,
+}
+
+impl From<&AppConfig> for SshAuth {
+    fn from(config: &AppConfig) -> Self {
+        Self {
+            identity_file: config.ssh_identity_file.clone(),
+            known_hosts: config.ssh_known_hosts.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "sftp")]
+mod sftp {
+    use super::{RemoteFile, RemoteSource, SshAuth};
+    use crate::core::CodeRagError;
+    use ssh2::{CheckResult, KnownHostFileKind, Session, Sftp};
+    use std::io::Read;
+    use std::net::TcpStream;
+    use std::path::{Path, PathBuf};
+
+    fn ssh_error(e: ssh2::Error) -> CodeRagError {
+        CodeRagError::Generic(format!("SSH error: {}", e.message()))
+    }
+
+    /// An open SFTP session to a [`RemoteSource`].
+    pub struct SftpSource {
+        source: RemoteSource,
+        // Kept alive for as long as the SFTP channel is used
+        _session: Session,
+        sftp: Sftp,
+    }
+
+    impl SftpSource {
+        /// Connects, checks the host key against `known_hosts` and authenticates.
+        pub fn connect(source: &RemoteSource, auth: &SshAuth) -> Result<Self, CodeRagError> {
+            let tcp = TcpStream::connect((source.host.as_str(), source.port))?;
+            let mut session = Session::new().map_err(ssh_error)?;
+            session.set_tcp_stream(tcp);
+            session.handshake().map_err(ssh_error)?;
+            verify_host_key(&session, source, auth)?;
+
+            if let Some(key) = &auth.identity_file {
+                session
+                    .userauth_pubkey_file(&source.user, None, Path::new(key), None)
+                    .map_err(ssh_error)?;
+            } else if let Ok(password) = std::env::var("CODE_RAG_SSH_PASSWORD") {
+                session
+                    .userauth_password(&source.user, &password)
+                    .map_err(ssh_error)?;
+            } else {
+                session.userauth_agent(&source.user).map_err(ssh_error)?;
+            }
+            if !session.authenticated() {
+                return Err(CodeRagError::Generic(format!(
+                    "SSH authentication as '{}' to {} failed",
+                    source.user, source.host
+                )));
+            }
+
+            let sftp = session.sftp().map_err(ssh_error)?;
+            Ok(Self {
+                source: source.clone(),
+                _session: session,
+                sftp,
+            })
+        }
+
+        pub fn source(&self) -> &RemoteSource {
+            &self.source
+        }
+
+        /// Lists regular files under the source directory, sorted by path.
+        ///
+        /// Hidden entries are skipped like the local walker does; symlinks are not
+        /// followed. `.gitignore` files are not read.
+        pub fn list_files(&self) -> Result<Vec<RemoteFile>, CodeRagError> {
+            let mut files = Vec::new();
+            let mut dirs = vec![PathBuf::from(&self.source.path)];
+            while let Some(dir) = dirs.pop() {
+                for (path, stat) in self.sftp.readdir(&dir).map_err(ssh_error)? {
+                    let hidden = path
+                        .file_name()
+                        .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+                    if hidden {
+                        continue;
+                    }
+                    if stat.is_dir() {
+                        dirs.push(path);
+                    } else if stat.is_file() {
+                        files.push(RemoteFile {
+                            path: path.to_string_lossy().to_string(),
+                            size: stat.size.unwrap_or(0),
+                            mtime: stat.mtime.unwrap_or(0) as i64,
+                        });
+                    }
+                }
+            }
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            Ok(files)
+        }
+
+        /// Reads a whole remote file.
+        pub fn read(&self, path: &str) -> Result<Vec<u8>, CodeRagError> {
+            let mut file = self.sftp.open(Path::new(path)).map_err(ssh_error)?;
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            Ok(content)
+        }
+    }
+
+    fn verify_host_key(
+        session: &Session,
+        source: &RemoteSource,
+        auth: &SshAuth,
+    ) -> Result<(), CodeRagError> {
+        let known_hosts_path = match &auth.known_hosts {
+            Some(path) => PathBuf::from(path),
+            None => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".ssh").join("known_hosts"))
+                .ok_or_else(|| {
+                    CodeRagError::Generic("Cannot locate known_hosts; set ssh_known_hosts".into())
+                })?,
+        };
+        let mut known_hosts = session.known_hosts().map_err(ssh_error)?;
+        // A missing file simply knows no hosts
+        let _ = known_hosts.read_file(&known_hosts_path, KnownHostFileKind::OpenSSH);
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| CodeRagError::Generic("Remote host sent no host key".into()))?;
+
+        match known_hosts.check_port(&source.host, source.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::NotFound => Err(CodeRagError::Generic(format!(
+                "Host key of {} is not in {}; add it with `ssh-keyscan -p {} {} >> {}`",
+                source.host,
+                known_hosts_path.display(),
+                source.port,
+                source.host,
+                known_hosts_path.display()
+            ))),
+            CheckResult::Mismatch => Err(CodeRagError::Generic(format!(
+                "Host key of {} does not match {}",
+                source.host,
+                known_hosts_path.display()
+            ))),
+            CheckResult::Failure => Err(CodeRagError::Generic(format!(
+                "Could not check the host key of {}",
+                source.host
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "sftp")]
+pub use sftp::SftpSource;
+
+/// Stand-in when built without the `sftp` feature; it can never be constructed.
+#[cfg(not(feature = "sftp"))]
+pub enum SftpSource {}
+
+#[cfg(not(feature = "sftp"))]
+impl SftpSource {
+    pub fn connect(source: &RemoteSource, _auth: &SshAuth) -> Result<Self, CodeRagError> {
+        Err(CodeRagError::Generic(format!(
+            "Cannot index {}: code-rag was built without the `sftp` feature",
+            source.host
+        )))
+    }
+
+    pub fn source(&self) -> &RemoteSource {
+        match *self {}
+    }
+
+    pub fn list_files(&self) -> Result<Vec<RemoteFile>, CodeRagError> {
+        match *self {}
+    }
+
+    pub fn read(&self, _path: &str) -> Result<Vec<u8>, CodeRagError> {
+        match *self {}
+    }
+}
@@ -1,9 +1,129 @@
-use crate::search::SearchResult;
+use crate::search::{RerankComparison, SearchResult};
 use anyhow::{Context, Result};
-use minijinja::{context, Environment};
+use minijinja::{context, AutoEscape, Environment, HtmlEscape, Value};
+use std::ops::Range;
 
-pub fn generate_html_report(query: &str, results: &[SearchResult]) -> Result<String> {
+/// Default per-result cap on the number of code bytes embedded in a report.
+pub const DEFAULT_REPORT_MAX_CODE_BYTES: usize = 64 * 1024;
+
+/// Default preamble of `search --prompt`; `{{ query }}` is replaced by the search query.
+pub const DEFAULT_PROMPT_PREAMBLE: &str =
+    "Answer the question using the code below from the repository. \
+Each snippet is labeled with its file and line range.\n\nQuestion: {{ query }}";
+
+/// Shortens `code` to at most `max_bytes` (on a char boundary) and appends a marker.
+/// A limit of 0 disables truncation.
+fn cap_code(code: &str, max_bytes: usize) -> String {
+    if max_bytes == 0 || code.len() <= max_bytes {
+        return code.to_string();
+    }
+    let mut end = max_bytes;
+    while !code.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}\n... [truncated {} bytes]",
+        &code[..end],
+        code.len() - end
+    )
+}
+
+/// Byte ranges in `code` where one of `calls` occurs as a whole identifier.
+///
+/// Matching is by substring with word boundaries, so `load` does not match inside
+/// `load_toml`. Ranges are sorted and do not overlap; the longer call wins a tie.
+fn call_sites(code: &str, calls: &[String]) -> Vec<Range<usize>> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut sites = Vec::new();
+    for call in calls.iter().filter(|c| !c.is_empty()) {
+        for (start, _) in code.match_indices(call.as_str()) {
+            let end = start + call.len();
+            let before = code[..start].chars().next_back();
+            let after = code[end..].chars().next();
+            if !before.is_some_and(is_ident) && !after.is_some_and(is_ident) {
+                sites.push(start..end);
+            }
+        }
+    }
+    sites.sort_by_key(|site| (site.start, std::cmp::Reverse(site.end)));
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for site in sites {
+        if merged.last().is_none_or(|last| site.start >= last.end) {
+            merged.push(site);
+        }
+    }
+    merged
+}
+
+/// HTML-escapes `code`, wrapping each call site in `<mark class="call-site">`.
+fn highlight_call_sites(code: &str, calls: &[String]) -> String {
+    let mut out = String::with_capacity(code.len());
+    let mut pos = 0;
+    for site in call_sites(code, calls) {
+        out.push_str(&HtmlEscape(&code[pos..site.start]).to_string());
+        out.push_str(&format!(
+            "<mark class=\"call-site\">{}</mark>",
+            HtmlEscape(&code[site.clone()])
+        ));
+        pos = site.end;
+    }
+    out.push_str(&HtmlEscape(&code[pos..]).to_string());
+    out
+}
+
+/// Serializes `results` for embedding in a `<script>` element, with code capped like
+/// the rendered cards.
+///
+/// `<` is written as `\u003c`, which JSON parsers decode back to `<`, so code
+/// containing `</script>` cannot close the element early.
+fn embedded_results_json(results: &[SearchResult], max_code_bytes: usize) -> Result<String> {
+    let capped: Vec<SearchResult> = results
+        .iter()
+        .map(|r| SearchResult {
+            code: cap_code(&r.code, max_code_bytes),
+            vector: None,
+            ..r.clone()
+        })
+        .collect();
+    let json = serde_json::to_string(&capped).context("Failed to serialize results")?;
+    Ok(json.replace('<', "\\u003c"))
+}
+
+/// Renders search results as a standalone HTML page.
+///
+/// All interpolated values are HTML-escaped, and each result's code is capped at
+/// `max_code_bytes` (0 means unlimited). With `highlight_calls`, occurrences of the
+/// result's `calls` in its code are highlighted. With `interactive`, the results are
+/// also embedded as JSON together with a small script that filters the result cards
+/// in the browser; the page still loads nothing external.
+pub fn generate_html_report(
+    query: &str,
+    results: &[SearchResult],
+    max_code_bytes: usize,
+    highlight_calls: bool,
+    interactive: bool,
+) -> Result<String> {
+    let results_json = if interactive {
+        Some(embedded_results_json(results, max_code_bytes)?)
+    } else {
+        None
+    };
     let mut env = Environment::new();
+    env.set_auto_escape_callback(|_| AutoEscape::Html);
+    env.add_filter("cap_code", move |code: String| {
+        cap_code(&code, max_code_bytes)
+    });
+    env.add_filter(
+        "highlight_calls",
+        move |code: String, calls: Vec<String>| -> Value {
+            if highlight_calls {
+                Value::from_safe_string(highlight_call_sites(&code, &calls))
+            } else {
+                Value::from(code)
+            }
+        },
+    );
 
     const TEMPLATE: &str = r#"
 <!DOCTYPE html>
@@ -18,9 +138,14 @@ pub fn generate_html_report(query: &str, results: &[SearchResult]) -> Result<Str
         .meta { display: flex; justify-content: space-between; color: #666; font-size: 0.9em; margin-bottom: 10px; }
         .score { font-weight: bold; color: #2ecc71; }
         .filename { color: #3498db; font-weight: bold; }
+        .symbol { font-family: monospace; color: #8e44ad; }
         .calls { font-size: 0.85em; color: #d35400; margin-top: 10px; border-top: 1px solid #eee; padding-top: 5px; }
         .call-tag { background: #fae5d3; padding: 2px 6px; border-radius: 4px; margin-right: 5px; display: inline-block; }
+        mark.call-site { background: #fae5d3; color: inherit; border-radius: 2px; }
         pre { background: #f8f8f8; padding: 15px; border-radius: 4px; overflow-x: auto; font-size: 0.9em; border: 1px solid #eee; }
+        .filter { display: flex; gap: 10px; align-items: center; margin-bottom: 20px; }
+        .filter input { flex: 1; padding: 8px; font-size: 1em; border: 1px solid #ddd; border-radius: 4px; }
+        .filter-count { color: #666; font-size: 0.9em; }
     </style>
 </head>
 <body>
@@ -29,14 +154,24 @@ pub fn generate_html_report(query: &str, results: &[SearchResult]) -> Result<Str
         <p>Query: <strong>{{ query }}</strong></p>
     </div>
 
+    {% if results_json %}
+    <div class="filter">
+        <input id="filter" type="search" placeholder="Filter by file, code or calls" autocomplete="off">
+        <span id="filter-count" class="filter-count"></span>
+    </div>
+    {% endif %}
+
     {% for result in results %}
-    <div class="result-card">
+    <div class="result-card" data-index="{{ loop.index0 }}">
         <div class="meta">
             <span class="rank">#{{ result.rank }}</span>
             <span class="filename">{{ result.filename }}:{{ result.line_start }}-{{ result.line_end }}</span>
+            {% if result.symbol %}
+            <span class="symbol">{% for name in result.scope %}{{ name }}::{% endfor %}{{ result.symbol }}</span>
+            {% endif %}
             <span class="score">Score: {{ "%.4f"|format(result.score) }}</span>
         </div>
-        <pre><code>{{ result.code }}</code></pre>
+        <pre><code>{{ result.code|cap_code|highlight_calls(result.calls) }}</code></pre>
         {% if result.calls %}
         <div class="calls">
             <strong>Calls:</strong> 
@@ -47,6 +182,35 @@ pub fn generate_html_report(query: &str, results: &[SearchResult]) -> Result<Str
         {% endif %}
     </div>
     {% endfor %}
+    {% if results_json %}
+    <script type="application/json" id="results-data">{{ results_json|safe }}</script>
+    <script>
+    (function () {
+        var results = JSON.parse(document.getElementById("results-data").textContent);
+        var cards = document.querySelectorAll(".result-card");
+        var input = document.getElementById("filter");
+        var count = document.getElementById("filter-count");
+        var haystacks = results.map(function (r) {
+            return [r.filename, r.code, (r.calls || []).join(" ")].join("\n").toLowerCase();
+        });
+
+        function applyFilter() {
+            var terms = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+            var shown = 0;
+            cards.forEach(function (card) {
+                var text = haystacks[Number(card.dataset.index)] || "";
+                var match = terms.every(function (t) { return text.indexOf(t) !== -1; });
+                card.style.display = match ? "" : "none";
+                if (match) { shown++; }
+            });
+            count.textContent = shown + " of " + cards.length + " results";
+        }
+
+        input.addEventListener("input", applyFilter);
+        applyFilter();
+    })();
+    </script>
+    {% endif %}
 </body>
 </html>
     "#;
@@ -61,6 +225,423 @@ pub fn generate_html_report(query: &str, results: &[SearchResult]) -> Result<Str
         .render(context! {
             query => query,
             results => results,
+            results_json => results_json,
         })
         .context("Failed to render HTML report")
 }
+
+/// Renders search results as Markdown with absolute line numbers (`search --format numbered`).
+///
+/// Each result gets a heading with its location, the function it defines and the calls
+/// it makes, then its code with every line prefixed by its line number in the file, so
+/// an LLM can cite or edit exact lines. With `highlight_calls`, each call is followed by
+/// the lines it occurs on.
+pub fn format_numbered(results: &[SearchResult], highlight_calls: bool) -> String {
+    let mut out = String::new();
+    for result in results {
+        out.push_str(&format!(
+            "## {}. {}:{}-{}\n",
+            result.rank, result.filename, result.line_start, result.line_end
+        ));
+        if let Some(symbol) = crate::indexer::defined_symbol(&result.filename, &result.code) {
+            out.push_str(&format!("symbol: {}\n", symbol));
+        }
+        let first = result.line_start.max(1) as usize;
+        if !result.calls.is_empty() {
+            let calls: Vec<String> = if highlight_calls {
+                result
+                    .calls
+                    .iter()
+                    .map(|call| {
+                        let lines: Vec<String> =
+                            call_sites(&result.code, std::slice::from_ref(call))
+                                .iter()
+                                .map(|site| {
+                                    let line =
+                                        first + result.code[..site.start].matches('\n').count();
+                                    line.to_string()
+                                })
+                                .collect();
+                        if lines.is_empty() {
+                            call.clone()
+                        } else {
+                            format!("{} ({})", call, lines.join(", "))
+                        }
+                    })
+                    .collect()
+            } else {
+                result.calls.clone()
+            };
+            out.push_str(&format!("calls: {}\n", calls.join(", ")));
+        }
+
+        let lang = fence_language(&result.filename);
+        let width = (first + result.code.lines().count()).to_string().len();
+        out.push_str(&format!("```{}\n", lang));
+        for (i, line) in result.code.lines().enumerate() {
+            out.push_str(&format!(
+                "{:>width$} | {}\n",
+                first + i,
+                line,
+                width = width
+            ));
+        }
+        out.push_str("```\n\n");
+    }
+    out
+}
+
+/// Renders search results as a ready-to-paste LLM prompt (`search --prompt`).
+///
+/// `preamble` is a template with `query` available. Each result follows as a fenced code
+/// block labeled with its file and lines. Results are expected to fit the token budget
+/// already (see `ContextOptimizer`).
+pub fn format_prompt(query: &str, results: &[SearchResult], preamble: &str) -> Result<String> {
+    let env = Environment::new();
+    let mut out = env
+        .render_str(preamble, context! { query => query })
+        .context("Failed to render prompt preamble")?
+        .trim_end()
+        .to_string();
+
+    for result in results {
+        let fence = code_fence(&result.code);
+        out.push_str(&format!(
+            "\n\nFile: {} (lines {}-{})\n{}{}\n{}\n{}",
+            result.filename,
+            result.line_start,
+            result.line_end,
+            fence,
+            fence_language(&result.filename),
+            result.code.trim_end_matches('\n'),
+            fence
+        ));
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+/// Renders both orderings of `search --compare-rerank`, marking how far reranking
+/// moved each result (`up 2`, `down 1`, `unchanged`, or `new` if it was not a
+/// candidate without reranking).
+pub fn format_rerank_comparison(comparison: &RerankComparison) -> String {
+    let line = |result: &SearchResult| {
+        format!(
+            "{:>3}. {}:{}-{} (score {:.4})",
+            result.rank, result.filename, result.line_start, result.line_end, result.score
+        )
+    };
+    let mut out = String::from("Without reranking:\n");
+    for result in &comparison.without_rerank {
+        out.push_str(&line(result));
+        out.push('\n');
+    }
+    out.push_str("\nWith reranking:\n");
+    for (result, change) in comparison.with_rerank.iter().zip(&comparison.changes) {
+        let movement = match change.shift() {
+            None => "new".to_string(),
+            Some(0) => "unchanged".to_string(),
+            Some(up) if up > 0 => format!("up {}", up),
+            Some(down) => format!("down {}", -down),
+        };
+        out.push_str(&format!("{}  {}\n", line(result), movement));
+    }
+    out
+}
+
+/// Language tag for a fenced code block, taken from the file extension.
+fn fence_language(filename: &str) -> &str {
+    std::path::Path::new(filename)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+}
+
+/// A backtick fence longer than any backtick run inside `code`.
+fn code_fence(code: &str) -> String {
+    let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    "`".repeat(longest_run.max(2) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_with_code(code: &str) -> SearchResult {
+        SearchResult {
+            rank: 1,
+            score: 0.5,
+            filename: "<b>evil</b>.rs".to_string(),
+            code: code.to_string(),
+            line_start: 1,
+            line_end: 2,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
+        }
+    }
+
+    #[test]
+    fn test_report_escapes_code_and_filename() {
+        let results = vec![result_with_code("</code><script>alert(1)</script>")];
+        let html = generate_html_report("q", &results, DEFAULT_REPORT_MAX_CODE_BYTES, false, false)
+            .unwrap();
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"));
+        assert!(!html.contains("<b>evil</b>"));
+    }
+
+    #[test]
+    fn test_report_qualifies_symbol_with_scope() {
+        let results = vec![SearchResult {
+            symbol: Some("add".to_string()),
+            scope: vec!["Calculator".to_string()],
+            item_kind: None,
+            ..result_with_code("def add(self, a, b): ...")
+        }];
+        let html = generate_html_report("q", &results, DEFAULT_REPORT_MAX_CODE_BYTES, false, false)
+            .unwrap();
+        assert!(html.contains("Calculator::add"));
+
+        let json = serde_json::to_value(&results[0]).unwrap();
+        assert_eq!(json["scope"], serde_json::json!(["Calculator"]));
+        assert_eq!(json["symbol"], "add");
+    }
+
+    #[test]
+    fn test_report_truncates_large_code() {
+        let results = vec![result_with_code(&"a".repeat(1000))];
+        let html = generate_html_report("q", &results, 100, false, false).unwrap();
+
+        assert!(html.contains(&format!("{}\n... [truncated 900 bytes]", "a".repeat(100))));
+        assert!(!html.contains(&"a".repeat(101)));
+    }
+
+    #[test]
+    fn test_interactive_report_embeds_results_and_filter() {
+        let mut results = vec![result_with_code(
+            "fn close() { \"</script><script>alert(1)\" }",
+        )];
+        results.push(SearchResult {
+            rank: 2,
+            filename: "src/config.rs".to_string(),
+            calls: vec!["load_toml".to_string()],
+            ..result_with_code("fn parse_config() {}")
+        });
+        let html = generate_html_report("q", &results, DEFAULT_REPORT_MAX_CODE_BYTES, false, true)
+            .unwrap();
+
+        // A standalone page: one document that loads nothing external
+        let trimmed = html.trim();
+        assert!(trimmed.starts_with("<!DOCTYPE html>"));
+        assert!(trimmed.ends_with("</html>"));
+        assert_eq!(html.matches("<body>").count(), 1);
+        assert!(!html.contains(" src=") && !html.contains(" href="));
+
+        // The embedded data is the results, and code cannot end its script element
+        let open = r#"<script type="application/json" id="results-data">"#;
+        let start = html.find(open).unwrap() + open.len();
+        let end = start + html[start..].find("</script>").unwrap();
+        let embedded: Vec<SearchResult> = serde_json::from_str(&html[start..end]).unwrap();
+        assert_eq!(embedded.len(), 2);
+        assert_eq!(embedded[0].code, results[0].code);
+        assert_eq!(embedded[1].filename, "src/config.rs");
+        assert_eq!(embedded[1].calls, vec!["load_toml".to_string()]);
+        assert_eq!(html.matches("</script>").count(), 2);
+
+        assert!(html.contains(r#"<input id="filter""#));
+        assert!(html.contains("function applyFilter()"));
+        assert!(html.contains(r#"data-index="1""#));
+
+        // The static report stays script-free
+        let plain =
+            generate_html_report("q", &results, DEFAULT_REPORT_MAX_CODE_BYTES, false, false)
+                .unwrap();
+        assert!(!plain.contains("<script"));
+        assert!(!plain.contains(r#"id="filter""#));
+    }
+
+    #[test]
+    fn test_numbered_output_uses_absolute_lines() {
+        let result = SearchResult {
+            filename: "src/config.rs".to_string(),
+            code: "fn parse_config() {\n    load_toml()\n}".to_string(),
+            line_start: 98,
+            line_end: 100,
+            calls: vec!["load_toml".to_string()],
+            ..result_with_code("")
+        };
+        let out = format_numbered(&[result], false);
+
+        assert!(out.starts_with("## 1. src/config.rs:98-100\n"));
+        assert!(out.contains("symbol: parse_config\n"));
+        assert!(out.contains("calls: load_toml\n"));
+        assert!(
+            out.contains("```rs\n 98 | fn parse_config() {\n 99 |     load_toml()\n100 | }\n```")
+        );
+    }
+
+    #[test]
+    fn test_report_highlights_call_sites() {
+        let result = SearchResult {
+            code: "fn parse_config() {\n    let raw = load_toml(\"<a>\");\n    load(raw)\n}"
+                .to_string(),
+            calls: vec!["load_toml".to_string(), "load".to_string()],
+            ..result_with_code("")
+        };
+        let results = vec![result];
+
+        let html = generate_html_report("q", &results, DEFAULT_REPORT_MAX_CODE_BYTES, true, false)
+            .unwrap();
+        assert!(html.contains(
+            r#"let raw = <mark class="call-site">load_toml</mark>(&quot;&lt;a&gt;&quot;);"#
+        ));
+        assert!(html.contains(r#"<mark class="call-site">load</mark>(raw)"#));
+        assert_eq!(html.matches("<mark class=\"call-site\">").count(), 2);
+
+        let plain =
+            generate_html_report("q", &results, DEFAULT_REPORT_MAX_CODE_BYTES, false, false)
+                .unwrap();
+        assert!(!plain.contains("<mark"));
+    }
+
+    #[test]
+    fn test_numbered_output_lists_call_site_lines() {
+        let result = SearchResult {
+            filename: "src/config.rs".to_string(),
+            code: "fn parse_config() {\n    load_toml()\n}".to_string(),
+            line_start: 98,
+            calls: vec!["load_toml".to_string(), "missing".to_string()],
+            ..result_with_code("")
+        };
+        let out = format_numbered(&[result], true);
+        assert!(out.contains("calls: load_toml (99), missing\n"));
+    }
+
+    #[test]
+    fn test_call_sites_match_whole_identifiers() {
+        let calls = vec!["load".to_string(), "load_toml".to_string()];
+        let code = "reload(); load_toml(); load();";
+        let sites: Vec<&str> = call_sites(code, &calls)
+            .into_iter()
+            .map(|site| &code[site])
+            .collect();
+        assert_eq!(sites, vec!["load_toml", "load"]);
+    }
+
+    #[test]
+    fn test_prompt_includes_selected_results_within_budget() {
+        use crate::context::ContextOptimizer;
+
+        let small = |name: &str, score: f32, code: &str| SearchResult {
+            filename: name.to_string(),
+            score,
+            code: code.to_string(),
+            ..result_with_code("")
+        };
+        let results = vec![
+            small(
+                "src/auth.rs",
+                0.9,
+                "fn login(user: &str) -> bool {\n    check(user)\n}",
+            ),
+            small("docs/usage.md", 0.8, "Run ```login``` first."),
+            small("src/huge.rs", 0.1, &"let x = 1;\n".repeat(200)),
+        ];
+
+        let budget = 60;
+        let selected: Vec<SearchResult> = ContextOptimizer::new(budget)
+            .optimize_with_stats(results)
+            .unwrap()
+            .chunks
+            .into_iter()
+            .map(|chunk| SearchResult {
+                filename: chunk.filename,
+                code: chunk.code,
+                line_start: chunk.start_line,
+                line_end: chunk.end_line,
+                ..result_with_code("")
+            })
+            .collect();
+        assert_eq!(selected.len(), 2);
+
+        let prompt =
+            format_prompt("how does login work?", &selected, DEFAULT_PROMPT_PREAMBLE).unwrap();
+        assert!(prompt.starts_with("Answer the question"));
+        assert!(prompt.contains("Question: how does login work?\n\n"));
+        for result in &selected {
+            assert!(prompt.contains(&format!("File: {} (lines 1-2)\n", result.filename)));
+            assert!(prompt.contains(&result.code));
+        }
+        assert!(!prompt.contains("huge.rs"));
+        // Code containing backticks gets a longer fence, so every block is closed
+        assert!(prompt.contains("````md\nRun ```login``` first.\n````"));
+        assert!(prompt.contains("```rs\nfn login"));
+        assert!(prompt.ends_with("```\n"));
+
+        let bpe = tiktoken_rs::cl100k_base().unwrap();
+        let code_tokens: usize = selected
+            .iter()
+            .map(|r| bpe.encode_with_special_tokens(&r.code).len())
+            .sum();
+        assert!(code_tokens <= budget);
+
+        let custom = format_prompt("q", &selected, "Context for: {{ query }}").unwrap();
+        assert!(custom.starts_with("Context for: q\n\nFile: "));
+    }
+
+    #[test]
+    fn test_rerank_comparison_marks_moved_results() {
+        let result = |name: &str, score: f32| SearchResult {
+            filename: format!("{}.rs", name),
+            score,
+            ..result_with_code("")
+        };
+        let fused = vec![
+            result("a", 0.04),
+            result("b", 0.03),
+            result("c", 0.02),
+            result("d", 0.01),
+        ];
+        let reranked = vec![
+            result("c", 5.0),
+            result("a", 4.0),
+            result("b", 3.0),
+            result("d", 2.0),
+        ];
+        let comparison = RerankComparison::new(fused, reranked, 3);
+        let shifts: Vec<_> = comparison.changes.iter().map(|c| c.shift()).collect();
+        assert_eq!(shifts, vec![Some(2), Some(-1), Some(-1)]);
+
+        let out = format_rerank_comparison(&comparison);
+        let (without, with) = out.split_once("\nWith reranking:\n").unwrap();
+        assert_eq!(
+            without,
+            "Without reranking:\n  1. a.rs:1-2 (score 0.0400)\n  2. b.rs:1-2 (score 0.0300)\n  3. c.rs:1-2 (score 0.0200)\n"
+        );
+        assert_eq!(
+            with,
+            "  1. c.rs:1-2 (score 5.0000)  up 2\n  2. a.rs:1-2 (score 4.0000)  down 1\n  3. b.rs:1-2 (score 3.0000)  down 1\n"
+        );
+        assert!(!out.contains("d.rs"));
+    }
+
+    #[test]
+    fn test_cap_code_respects_char_boundaries() {
+        assert_eq!(cap_code("héllo", 2), "h\n... [truncated 5 bytes]");
+        assert_eq!(cap_code("short", 0), "short");
+    }
+}
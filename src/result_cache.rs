@@ -0,0 +1,78 @@
+//! On-disk cache of search outcomes, so repeated CLI searches (e.g. across CI jobs)
+//! skip loading the models and running the search.
+
+use crate::search::SearchOutcome;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory under a database where `search` stores cached outcomes.
+pub const RESULT_CACHE_DIR: &str = "result_cache";
+
+/// One cache file; the full key guards against hash collisions.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    outcome: SearchOutcome,
+}
+
+/// Search outcomes stored as one JSON file per query, grouped by index version.
+///
+/// Entries are only read for the index version they were written for; storing an
+/// entry for a newer version removes those of all other versions.
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    /// Uses `<db_path>/result_cache`; it is removed together with the database.
+    pub fn for_database(db_path: &Path) -> Self {
+        Self {
+            dir: db_path.join(RESULT_CACHE_DIR),
+        }
+    }
+
+    fn entry_path(&self, version: u64, key: &str) -> PathBuf {
+        // SHA-256 keeps file names stable across Rust versions and builds
+        let digest = Sha256::digest(key.as_bytes());
+        let name: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        self.dir
+            .join(format!("v{}", version))
+            .join(format!("{}.json", name))
+    }
+
+    /// Returns the outcome cached for `key` at index `version`, if any.
+    ///
+    /// Unreadable entries count as misses.
+    pub fn get(&self, version: u64, key: &str) -> Option<SearchOutcome> {
+        let data = fs::read(self.entry_path(version, key)).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+        (entry.key == key).then_some(entry.outcome)
+    }
+
+    /// Stores `outcome` for `key` at index `version` and drops entries of other versions.
+    pub fn put(&self, version: u64, key: &str, outcome: &SearchOutcome) -> Result<()> {
+        let path = self.entry_path(version, key);
+        let version_dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(version_dir)?;
+
+        let current = format!("v{}", version);
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            if entry.file_name() != current.as_str() {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+
+        let entry = CacheEntry {
+            key: key.to_string(),
+            outcome: outcome.clone(),
+        };
+        // Write then rename so a concurrent reader never sees a partial file
+        let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_vec(&entry)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
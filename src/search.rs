@@ -1,33 +1,727 @@
 use crate::bm25::BM25Index;
-use crate::embedding::Embedder;
+use crate::context::ContextStats;
+use crate::embedding::{EmbedLimiter, Embedder};
+use crate::indexer::{call_target, is_test_path, ChunkKind, ChunkPart, FilePermissions, ItemKind};
 use crate::llm::QueryExpander;
-use crate::storage::Storage;
+use crate::storage::{
+    row_item_kind, row_kind, row_permissions, row_scope, row_symbol, row_vector, Storage,
+};
 use anyhow::{anyhow, Context, Result};
-use arrow_array::{Array, Int32Array, Int64Array, ListArray, StringArray};
-use grep_regex::RegexMatcher;
+use arrow_array::{
+    Array, BooleanArray, Int32Array, Int64Array, ListArray, RecordBatch, StringArray,
+};
+use grep_regex::RegexMatcherBuilder;
 use grep_searcher::sinks::UTF8;
 use grep_searcher::Searcher;
 use ignore::WalkBuilder;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::sync::Arc;
+use tracing::{info_span, Instrument};
 
 /// A single search result from code search.
 ///
 /// Contains the matched code chunk with metadata and relevance score.
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SearchResult {
     pub rank: usize,
+    /// Raw score of the path that ranked the result (reranker logit or fused RRF sum)
     pub score: f32,
+    /// `score` calibrated to [0, 1] within the result set, see [`ScoreCalibration`]
+    pub normalized_score: f32,
     pub filename: String,
     pub code: String,
     pub line_start: i32,
     pub line_end: i32,
     pub last_modified: i64,
     pub calls: Vec<String>,
+    /// Name of the function, class or other item the chunk defines
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// Enclosing classes, impls and namespaces of the chunk, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scope: Vec<String>,
+    /// Whether the chunk is a function, class, script statement or other item, when
+    /// recorded at index time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_kind: Option<ItemKind>,
+    /// Set when the chunk is one part of a larger unit that was split
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub part: Option<ChunkPart>,
+    /// Whether the chunk comes from a test file
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_test: bool,
+    /// The chunk's stored embedding, only filled when vectors are requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+    /// `kind: code`, or `kind: note` with the `anchor` the note is about
+    #[serde(flatten)]
+    pub kind: ChunkKind,
+    /// Canonical handle of the chunk, see [`ChunkUri`]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub uri: String,
+    /// Bucket of `normalized_score`, set when confidence buckets are configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<Confidence>,
+    /// Permissions of the source file, when recorded at index time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<FilePermissions>,
+    /// Why the result matches the query, written by the LLM when explanations are on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    /// The query-relevant windows of `code`, when snippet windows are configured
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub snippets: Vec<Snippet>,
+}
+
+/// Canonical handle of an indexed chunk: `code-rag://{workspace}/{filename}#L{start}-L{end}`.
+///
+/// It names the same chunk as the storage id (`filename-start-end`) within a workspace.
+/// `%` and `#` in the filename, and also `/` in the workspace, are percent-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkUri {
+    pub workspace: String,
+    pub filename: String,
+    pub line_start: i32,
+    pub line_end: i32,
+}
+
+impl ChunkUri {
+    pub const SCHEME: &'static str = "code-rag://";
+
+    pub fn parse(uri: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid chunk URI '{}'", uri);
+        let rest = uri.strip_prefix(Self::SCHEME).ok_or_else(invalid)?;
+        let (workspace, rest) = rest.split_once('/').ok_or_else(invalid)?;
+        let (filename, lines) = rest.rsplit_once("#L").ok_or_else(invalid)?;
+        let (start, end) = lines.split_once("-L").ok_or_else(invalid)?;
+        if workspace.is_empty() || filename.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            workspace: percent_decode(workspace).ok_or_else(invalid)?,
+            filename: percent_decode(filename).ok_or_else(invalid)?,
+            line_start: start.parse().map_err(|_| invalid())?,
+            line_end: end.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl std::fmt::Display for ChunkUri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}/{}#L{}-L{}",
+            Self::SCHEME,
+            percent_encode(&self.workspace, &['%', '#', '/']),
+            percent_encode(&self.filename, &['%', '#']),
+            self.line_start,
+            self.line_end
+        )
+    }
+}
+
+fn percent_encode(s: &str, reserved: &[char]) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for c in s.chars() {
+        if reserved.contains(&c) {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Rejects queries that are empty or only whitespace.
+///
+/// Such a query would still be embedded and keyword-parsed, returning arbitrary results.
+pub fn validate_query(query: &str) -> Result<()> {
+    if query.trim().is_empty() {
+        return Err(anyhow!("Search query is empty"));
+    }
+    Ok(())
+}
+
+/// Which results to keep based on whether they come from test files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum TestFilter {
+    #[default]
+    All,
+    /// Drop results from test files (`--exclude-tests`)
+    Exclude,
+    /// Keep only results from test files (`--only-tests`)
+    Only,
+}
+
+impl TestFilter {
+    fn keeps(self, is_test: bool) -> bool {
+        match self {
+            Self::All => true,
+            Self::Exclude => !is_test,
+            Self::Only => is_test,
+        }
+    }
+}
+
+impl SearchResult {
+    /// The result's chunk in `workspace`.
+    pub fn chunk_uri(&self, workspace: &str) -> ChunkUri {
+        ChunkUri {
+            workspace: workspace.to_string(),
+            filename: self.filename.clone(),
+            line_start: self.line_start,
+            line_end: self.line_end,
+        }
+    }
+}
+
+/// How [`SearchResult::normalized_score`] is derived from the raw scores.
+///
+/// Reranked scores are logits and fused scores are small RRF sums, so raw scores
+/// cannot share a threshold; both calibrations map a result set into [0, 1] and
+/// keep the order of `score`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreCalibration {
+    /// `(score - min) / (max - min)`: the best result gets 1.0, the worst 0.0
+    #[default]
+    MinMax,
+    /// `exp(score) / sum(exp(scores))`: the results share a total of 1.0
+    Softmax,
+}
+
+impl ScoreCalibration {
+    /// Sets `normalized_score` on every result from its `score`.
+    pub fn apply(self, results: &mut [SearchResult]) {
+        match self {
+            Self::MinMax => {
+                let scores: Vec<f32> = results.iter().map(|r| r.score).collect();
+                // A single result or a tie counts as a full match
+                for (result, normalized) in results
+                    .iter_mut()
+                    .zip(CodeSearcher::min_max_normalize(&scores))
+                {
+                    result.normalized_score = normalized;
+                }
+            }
+            Self::Softmax => {
+                // Shift by the maximum so exp() cannot overflow
+                let max = results.iter().map(|r| r.score).fold(f32::MIN, f32::max);
+                let total: f32 = results.iter().map(|r| (r.score - max).exp()).sum();
+                for result in results.iter_mut() {
+                    result.normalized_score = (result.score - max).exp() / total;
+                }
+            }
+        }
+    }
+}
+
+/// How far a result can be trusted, for consumers that should not interpret scores.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    High,
+    Medium,
+    Low,
+}
+
+/// Lowest `normalized_score` of the `high` and `medium` [`Confidence`] buckets.
+///
+/// The buckets follow the calibrated score, so the thresholds mean the same with
+/// and without reranking; everything below `medium` is `low`. Since that score is
+/// calibrated within the result set, the labels are relative too: with `minmax` the
+/// best result is always `high`, even when every match is weak.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ConfidenceThresholds {
+    pub high: f32,
+    pub medium: f32,
+}
+
+impl ConfidenceThresholds {
+    pub fn bucket(&self, normalized_score: f32) -> Confidence {
+        if normalized_score >= self.high {
+            Confidence::High
+        } else if normalized_score >= self.medium {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        }
+    }
+
+    /// Sets `confidence` on every result from its `normalized_score`.
+    pub fn apply(&self, results: &mut [SearchResult]) {
+        for result in results {
+            result.confidence = Some(self.bucket(result.normalized_score));
+        }
+    }
+}
+
+/// A window of a result's code around lines that match the query.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Snippet {
+    pub line_start: i32,
+    pub line_end: i32,
+    pub code: String,
+}
+
+/// Selects up to `count` non-overlapping windows of at most `lines` lines from each
+/// result, so a long chunk shows every region the query touches.
+///
+/// A line is as relevant as the number of distinct query terms it contains. Windows
+/// are centered on the most relevant lines not yet shown, in turn; a window squeezed
+/// between two earlier ones is cut short rather than overlapping them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SnippetWindows {
+    pub count: usize,
+    pub lines: usize,
+}
+
+impl SnippetWindows {
+    /// Windows of `code`, which starts at line `line_start`, in file order. Empty when
+    /// no line contains a term.
+    pub fn select(&self, code: &str, line_start: i32, terms: &[String]) -> Vec<Snippet> {
+        let lines: Vec<&str> = code.lines().collect();
+        let width = self.lines.max(1);
+        let relevance: Vec<usize> = lines
+            .iter()
+            .map(|line| {
+                let line = line.to_lowercase();
+                terms.iter().filter(|t| line.contains(t.as_str())).count()
+            })
+            .collect();
+        let mut anchors: Vec<usize> = (0..lines.len()).filter(|&i| relevance[i] > 0).collect();
+        // Most relevant first; earlier lines break ties
+        anchors.sort_by_key(|&i| (std::cmp::Reverse(relevance[i]), i));
+
+        let mut windows: Vec<(usize, usize)> = Vec::new();
+        for anchor in anchors {
+            if windows.len() >= self.count {
+                break;
+            }
+            if windows.iter().any(|&(s, e)| (s..e).contains(&anchor)) {
+                continue;
+            }
+            // The gap between the chosen windows around the anchor
+            let lo = windows
+                .iter()
+                .map(|&(_, e)| e)
+                .filter(|&e| e <= anchor)
+                .max()
+                .unwrap_or(0);
+            let hi = windows
+                .iter()
+                .map(|&(s, _)| s)
+                .filter(|&s| s > anchor)
+                .min()
+                .unwrap_or(lines.len());
+            let start = anchor
+                .saturating_sub(width / 2)
+                .min(hi.saturating_sub(width))
+                .max(lo);
+            windows.push((start, (start + width).min(hi)));
+        }
+
+        windows.sort();
+        windows
+            .into_iter()
+            .map(|(start, end)| Snippet {
+                line_start: line_start + start as i32,
+                line_end: line_start + end as i32 - 1,
+                code: lines[start..end].join("\n"),
+            })
+            .collect()
+    }
+
+    /// Sets `snippets` on every result from the words of `queries`.
+    pub fn apply(&self, results: &mut [SearchResult], queries: &[String]) {
+        let mut terms: Vec<String> = queries
+            .iter()
+            .flat_map(|q| q.split(|c: char| !c.is_alphanumeric()))
+            .filter(|word| word.chars().count() >= 3)
+            .map(str::to_lowercase)
+            .collect();
+        terms.sort();
+        terms.dedup();
+        for result in results {
+            result.snippets = self.select(&result.code, result.line_start, &terms);
+        }
+    }
+}
+
+/// Reaction to a reranker that strongly reorders the retrieved candidates.
+///
+/// Agreement is the Spearman rank correlation between the fused and the reranked
+/// order, from 1.0 (same order) to -1.0 (reversed). Below `threshold` a warning is
+/// logged, and with `refetch` the search is retried once on a pool of twice as many
+/// candidates, on the theory that retrieval missed the best hits.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RerankDisagreement {
+    pub threshold: f32,
+    pub refetch: bool,
+}
+
+/// How the reranker scores of one candidate against several queries are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RerankCombine {
+    /// The best score against any query
+    Max,
+    /// The average score over the queries
+    Mean,
+}
+
+impl RerankCombine {
+    /// Combines one candidate's scores; `None` when no query scored it.
+    fn combine(self, scores: impl Iterator<Item = f32>) -> Option<f32> {
+        let (count, total, max) = scores.fold((0usize, 0.0f32, f32::NEG_INFINITY), |acc, s| {
+            (acc.0 + 1, acc.1 + s, acc.2.max(s))
+        });
+        (count > 0).then(|| match self {
+            Self::Max => max,
+            Self::Mean => total / count as f32,
+        })
+    }
+}
+
+/// Reranking against every query of an expanded search, not only the original one.
+///
+/// Each candidate is scored against the original query and the expansion queries and
+/// the scores are combined, so results that match an expansion term closely can rise.
+/// Costs one reranker pass per query; expansion queries are dropped (last first) once
+/// queries × candidates would exceed `max_pairs`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MultiQueryRerank {
+    pub combine: RerankCombine,
+    pub max_pairs: usize,
+}
+
+/// How a query reads, as judged by [`QueryRouting::classify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryStyle {
+    /// A symbol or pattern, e.g. `parse_config` or `Storage::search`
+    Identifier,
+    /// A natural-language description
+    Prose,
+    /// Anything in between; weights are left unchanged
+    Mixed,
+}
+
+/// Per-query adjustment of the vector/BM25 weights based on the query's style.
+///
+/// Identifier-like queries favour keyword search, prose favours vector search.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct QueryRouting {
+    /// Factor applied to the favoured leg's weight
+    pub boost: f32,
+    /// Minimum number of words for a query without code-like tokens to count as prose
+    pub prose_min_words: usize,
+}
+
+impl QueryRouting {
+    pub fn classify(&self, query: &str) -> QueryStyle {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        let code_like = |word: &str| {
+            // Sentence punctuation does not make a word code
+            let word = word.trim_end_matches(['.', ',', '?', '!', ':', ';']);
+            word.contains(|c: char| "_:.()[]{}<>*\\$^|=".contains(c))
+                || (word.chars().skip(1).any(|c| c.is_uppercase())
+                    && word.chars().any(|c| c.is_lowercase()))
+        };
+
+        match words.len() {
+            0 => QueryStyle::Mixed,
+            1 => QueryStyle::Identifier,
+            _ if words.iter().all(|w| code_like(w)) => QueryStyle::Identifier,
+            n if n >= self.prose_min_words && !words.iter().any(|w| code_like(w)) => {
+                QueryStyle::Prose
+            }
+            _ => QueryStyle::Mixed,
+        }
+    }
+
+    /// Returns `(vector_weight, bm25_weight)` adjusted for `query`.
+    pub fn weights(&self, query: &str, vector_weight: f32, bm25_weight: f32) -> (f32, f32) {
+        match self.classify(query) {
+            QueryStyle::Identifier => (vector_weight, bm25_weight * self.boost),
+            QueryStyle::Prose => (vector_weight * self.boost, bm25_weight),
+            QueryStyle::Mixed => (vector_weight, bm25_weight),
+        }
+    }
+}
+
+/// A part of hybrid search that could not be used for a request.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Subsystem {
+    /// The embedding model: no vector search and no reranking
+    Embedder,
+    /// The keyword index: vector results only
+    Bm25,
+    /// Reranking was requested but failed or had no model
+    Reranker,
+    /// Query expansion was requested but the LLM was unavailable or failed
+    Expander,
+}
+
+impl std::fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Embedder => "embedder",
+            Self::Bm25 => "bm25",
+            Self::Reranker => "reranker",
+            Self::Expander => "expander",
+        })
+    }
+}
+
+/// Results of a search plus optional metadata about how they were produced.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SearchOutcome {
+    pub results: Vec<SearchResult>,
+    /// Token budget usage, present when `max_tokens` was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextStats>,
+    /// Set when no embedder was available and only keyword search was used
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+    /// Parts of hybrid search that were unavailable for this search, so the results
+    /// may be worse than usual
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unavailable: Vec<Subsystem>,
+    /// Terms the query was expanded with, besides the query itself
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expansion_terms: Vec<String>,
+    /// Candidates that matched before truncating to the limit.
+    ///
+    /// An estimate: each retriever only fetches a bounded pool (see `fetch_limit`),
+    /// so larger result sets are undercounted.
+    #[serde(default)]
+    pub total_candidates: usize,
+    /// Whether candidates beyond the returned results matched
+    #[serde(default)]
+    pub has_more: bool,
+}
+
+/// One query ranked with and without reranking (`search --compare-rerank`).
+#[derive(Serialize, Clone, Debug)]
+pub struct RerankComparison {
+    /// Top results by fused retrieval score
+    pub without_rerank: Vec<SearchResult>,
+    /// Top results after reranking the same candidates
+    pub with_rerank: Vec<SearchResult>,
+    /// Rank of each reranked result before and after reranking
+    pub changes: Vec<RankChange>,
 }
 
-impl SearchResult {}
+impl RerankComparison {
+    /// Compares two orderings of the same candidates and keeps the top `limit` of each.
+    ///
+    /// Ranks before reranking are positions in all of `fused`, so a result reranked
+    /// into the top `limit` from further down still gets its earlier rank.
+    pub fn new(
+        mut fused: Vec<SearchResult>,
+        mut reranked: Vec<SearchResult>,
+        limit: usize,
+    ) -> Self {
+        let chunk_id = |r: &SearchResult| (r.filename.clone(), r.line_start, r.line_end);
+        let fused_ranks: HashMap<_, usize> = fused
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (chunk_id(r), i + 1))
+            .collect();
+        fused.truncate(limit);
+        reranked.truncate(limit);
+        for results in [&mut fused, &mut reranked] {
+            for (i, result) in results.iter_mut().enumerate() {
+                result.rank = i + 1;
+            }
+        }
+        let changes = reranked
+            .iter()
+            .map(|r| RankChange {
+                filename: r.filename.clone(),
+                line_start: r.line_start,
+                line_end: r.line_end,
+                before: fused_ranks.get(&chunk_id(r)).copied(),
+                after: r.rank,
+            })
+            .collect();
+        Self {
+            without_rerank: fused,
+            with_rerank: reranked,
+            changes,
+        }
+    }
+}
+
+/// Where reranking moved one result.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RankChange {
+    pub filename: String,
+    pub line_start: i32,
+    pub line_end: i32,
+    /// Rank by fused score; `None` if the result was not a candidate without reranking
+    pub before: Option<usize>,
+    /// Rank after reranking
+    pub after: usize,
+}
+
+impl RankChange {
+    /// Ranks gained by reranking: positive moved up, negative moved down.
+    pub fn shift(&self) -> Option<i64> {
+        self.before.map(|before| before as i64 - self.after as i64)
+    }
+}
+
+/// A chunk that calls a result's function (`search --show-callers`).
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CallerRef {
+    pub filename: String,
+    pub line_start: i32,
+    pub line_end: i32,
+}
+
+/// Pattern handling for [`CodeSearcher::grep_search_with`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrepOptions {
+    /// Match the pattern literally instead of as a regex (`grep -F`)
+    pub fixed_strings: bool,
+    /// Match case-insensitively (`grep -i`)
+    pub ignore_case: bool,
+}
+
+/// How chunk scores are combined into a file score by [`aggregate_by_file`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreAggregation {
+    /// Score of the file's best chunk
+    Max,
+    /// Sum of the file's chunk scores; favours files with many matches
+    Sum,
+}
+
+impl std::str::FromStr for ScoreAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "max" => Ok(Self::Max),
+            "sum" => Ok(Self::Sum),
+            other => Err(format!(
+                "Unknown score aggregation '{}' (expected max or sum)",
+                other
+            )),
+        }
+    }
+}
+
+/// A file ranked by the combined score of its matching chunks.
+#[derive(Serialize, Clone, Debug)]
+pub struct FileResult {
+    pub rank: usize,
+    pub score: f32,
+    pub filename: String,
+    /// Number of result chunks from this file
+    pub chunk_count: usize,
+    /// Line range of the file's best chunk
+    pub line_start: i32,
+    pub line_end: i32,
+}
+
+/// Groups chunk results by filename, one entry per file sorted by aggregate score.
+pub fn aggregate_by_file(results: &[SearchResult], method: ScoreAggregation) -> Vec<FileResult> {
+    let mut files: Vec<FileResult> = Vec::new();
+    // Best chunk score per file, used to pick the reported line range
+    let mut best: Vec<f32> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+
+    for res in results {
+        match index.get(res.filename.as_str()) {
+            Some(&i) => {
+                let file = &mut files[i];
+                file.chunk_count += 1;
+                file.score = match method {
+                    ScoreAggregation::Max => file.score.max(res.score),
+                    ScoreAggregation::Sum => file.score + res.score,
+                };
+                if res.score > best[i] {
+                    best[i] = res.score;
+                    file.line_start = res.line_start;
+                    file.line_end = res.line_end;
+                }
+            }
+            None => {
+                index.insert(&res.filename, files.len());
+                best.push(res.score);
+                files.push(FileResult {
+                    rank: 0,
+                    score: res.score,
+                    filename: res.filename.clone(),
+                    chunk_count: 1,
+                    line_start: res.line_start,
+                    line_end: res.line_end,
+                });
+            }
+        }
+    }
+
+    files.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    for (i, file) in files.iter_mut().enumerate() {
+        file.rank = i + 1;
+    }
+    files
+}
+
+/// Scores texts for a query like [`Embedder::rerank`]: `(index, score)` pairs.
+type Reranker = Arc<dyn Fn(&str, Vec<String>, usize) -> Result<Vec<(usize, f32)>> + Send + Sync>;
+
+/// Candidates of one retrieval, in ranked order.
+struct SearchPass {
+    candidates: Vec<SearchResult>,
+    /// Keyword-only hits, whose note kinds are looked up later
+    keyword_only: std::collections::HashSet<String>,
+    /// Agreement of the reranker with retrieval, when checked
+    correlation: Option<f32>,
+    /// Whether this is the wider retry after a disagreement
+    refetched: bool,
+    /// Retrievers or the reranker that could not be used
+    unavailable: Vec<Subsystem>,
+}
+
+/// Fused candidates of one retrieval, ordered by fused score and ready to rerank.
+#[derive(Clone)]
+struct FusedPool {
+    candidates: Vec<SearchResult>,
+    keyword_only: std::collections::HashSet<String>,
+    /// Call-graph importance by chunk id, when the importance boost is on
+    importance: HashMap<String, f32>,
+    /// Fused scores by chunk id before the importance and path boosts; reranking
+    /// blends these
+    unboosted: HashMap<String, f32>,
+    /// Retrievers or the reranker that could not be used
+    unavailable: Vec<Subsystem>,
+}
 
 /// Hybrid code search engine combining BM25 and vector search.
 ///
@@ -58,6 +752,27 @@ pub struct CodeSearcher {
     vector_weight: f32,
     bm25_weight: f32,
     rrf_k: f64,
+    rerank_blend: f32,
+    rerank_skip_extensions: Vec<String>,
+    max_per_file: Option<usize>,
+    rerank_batch_size: Option<usize>,
+    rerank_cascade_k: Option<usize>,
+    test_filter: TestFilter,
+    include_vectors: bool,
+    query_routing: Option<QueryRouting>,
+    in_file: Option<String>,
+    min_lines: Option<usize>,
+    required_mode: Option<u32>,
+    item_kind: Option<ItemKind>,
+    score_calibration: ScoreCalibration,
+    confidence: Option<ConfidenceThresholds>,
+    snippets: Option<SnippetWindows>,
+    rerank_disagreement: Option<RerankDisagreement>,
+    multi_query_rerank: Option<MultiQueryRerank>,
+    importance_boost: f32,
+    path_boost: f32,
+    embed_limiter: Option<Arc<EmbedLimiter>>,
+    keyword_fallback: bool,
 }
 
 impl CodeSearcher {
@@ -78,9 +793,257 @@ impl CodeSearcher {
             vector_weight,
             bm25_weight,
             rrf_k,
+            rerank_blend: 1.0,
+            rerank_skip_extensions: Vec::new(),
+            max_per_file: None,
+            rerank_batch_size: None,
+            rerank_cascade_k: None,
+            test_filter: TestFilter::All,
+            include_vectors: false,
+            query_routing: None,
+            in_file: None,
+            min_lines: None,
+            required_mode: None,
+            item_kind: None,
+            score_calibration: ScoreCalibration::MinMax,
+            confidence: None,
+            snippets: None,
+            rerank_disagreement: None,
+            multi_query_rerank: None,
+            importance_boost: 0.0,
+            path_boost: 0.0,
+            embed_limiter: None,
+            keyword_fallback: false,
+        }
+    }
+
+    /// Sets how much the reranker score counts versus the fused retrieval score.
+    ///
+    /// `1.0` (default) uses the reranker score alone, `0.0` keeps the fused ordering.
+    pub fn with_rerank_blend(mut self, blend: f32) -> Self {
+        self.rerank_blend = blend.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets file extensions whose results are never reranked.
+    ///
+    /// Matching results keep their fused score and their position in the fused ordering;
+    /// only the remaining results are reranked among themselves.
+    pub fn with_rerank_skip_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.rerank_skip_extensions = extensions
+            .into_iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .collect();
+        self
+    }
+
+    /// Caps how many chunks from any one file appear in the results (default unlimited).
+    ///
+    /// Applied after ranking, so each file keeps its best chunks and the freed slots
+    /// go to the next results from other files. `Some(0)` is treated as unlimited.
+    pub fn with_max_per_file(mut self, max_per_file: Option<usize>) -> Self {
+        self.max_per_file = max_per_file.filter(|&n| n > 0);
+        self
+    }
+
+    /// Sets how many candidates are sent to the reranker per call (default: all at once).
+    pub fn with_rerank_batch_size(mut self, batch_size: Option<usize>) -> Self {
+        self.rerank_batch_size = batch_size.filter(|&n| n > 0);
+        self
+    }
+
+    /// Limits reranking to the `k` best candidates by fused score (default: all).
+    ///
+    /// The fused ranking acts as a cheap first stage; only its top `k` are sent to the
+    /// reranker and the rest follow in fused order. `Some(0)` is treated as unset.
+    pub fn with_rerank_cascade_k(mut self, k: Option<usize>) -> Self {
+        self.rerank_cascade_k = k.filter(|&n| n > 0);
+        self
+    }
+
+    /// Checks reranked orders against retrieval and reacts to disagreement (default: off).
+    pub fn with_rerank_disagreement(mut self, check: Option<RerankDisagreement>) -> Self {
+        self.rerank_disagreement = check;
+        self
+    }
+
+    /// Reranks expanded searches against every expansion query too (default: off).
+    pub fn with_multi_query_rerank(mut self, multi: Option<MultiQueryRerank>) -> Self {
+        self.multi_query_rerank = multi;
+        self
+    }
+
+    /// Keeps only non-test or only test results (default: all).
+    pub fn with_test_filter(mut self, filter: TestFilter) -> Self {
+        self.test_filter = filter;
+        self
+    }
+
+    /// Sets how `normalized_score` is computed (default: min-max).
+    pub fn with_score_calibration(mut self, calibration: ScoreCalibration) -> Self {
+        self.score_calibration = calibration;
+        self
+    }
+
+    /// Labels each result with a [`Confidence`] bucket (default: off).
+    pub fn with_confidence(mut self, thresholds: Option<ConfidenceThresholds>) -> Self {
+        self.confidence = thresholds;
+        self
+    }
+
+    /// Adds the query-relevant [`Snippet`] windows of each result (default: off).
+    pub fn with_snippets(mut self, windows: Option<SnippetWindows>) -> Self {
+        self.snippets = windows.filter(|w| w.count > 0);
+        self
+    }
+
+    /// Raises scores of chunks whose function is called from many places (default: off).
+    ///
+    /// A score grows by `boost * importance` of its magnitude, where importance is the
+    /// chunk's stored call-graph importance in `[0, 1]`. Indexes without importance
+    /// values are unaffected.
+    pub fn with_importance_boost(mut self, boost: f32) -> Self {
+        self.importance_boost = boost.max(0.0);
+        self
+    }
+
+    /// Raises scores of chunks whose file path mentions the query's words (default: off).
+    ///
+    /// A score grows by `boost` times the fraction of query words found among the
+    /// path's components, of its magnitude, so `auth middleware` favours
+    /// `src/auth/middleware.rs`.
+    pub fn with_path_boost(mut self, boost: f32) -> Self {
+        self.path_boost = boost.max(0.0);
+        self
+    }
+
+    /// Shares a cap on concurrent embedding and reranking calls (default: unlimited).
+    pub fn with_embed_limiter(mut self, limiter: Option<Arc<EmbedLimiter>>) -> Self {
+        self.embed_limiter = limiter;
+        self
+    }
+
+    /// Answers with keyword (BM25) results, flagged as `degraded`, when embedding the
+    /// query fails (default: off, the search fails).
+    ///
+    /// A searcher without an embedder always searches by keyword only.
+    pub fn with_keyword_fallback(mut self, enabled: bool) -> Self {
+        self.keyword_fallback = enabled;
+        self
+    }
+
+    /// Restricts the search to a single file (default: all files).
+    ///
+    /// `path` matches a stored filename exactly or as a trailing path, so `src/a.rs`
+    /// and `a.rs` both select `/repo/src/a.rs`, but `a.rs` does not select `data.rs`.
+    pub fn with_in_file(mut self, path: Option<String>) -> Self {
+        self.in_file = path
+            .map(|p| p.replace('\\', "/").trim_start_matches("./").to_string())
+            .filter(|p| !p.is_empty());
+        self
+    }
+
+    /// Drops chunks spanning fewer than `lines` lines (default: keep all).
+    pub fn with_min_lines(mut self, lines: Option<usize>) -> Self {
+        self.min_lines = lines.filter(|&n| n > 1);
+        self
+    }
+
+    /// Keeps only chunks of files whose recorded permissions include every bit of
+    /// `mask`, e.g. `0o002` for world-writable files (default: keep all).
+    ///
+    /// Files indexed without `index_file_permissions` never match.
+    pub fn with_required_mode(mut self, mask: Option<u32>) -> Self {
+        self.required_mode = mask;
+        self
+    }
+
+    /// Keeps only chunks of one item kind, e.g. functions (default: keep all).
+    ///
+    /// Chunks indexed before item kinds were recorded never match.
+    pub fn with_item_kind(mut self, kind: Option<ItemKind>) -> Self {
+        self.item_kind = kind;
+        self
+    }
+
+    /// Attaches each result's stored embedding to `SearchResult::vector` (default: off).
+    ///
+    /// Vectors add several KB per result, so only request them when needed.
+    pub fn with_include_vectors(mut self, include: bool) -> Self {
+        self.include_vectors = include;
+        self
+    }
+
+    /// Whether `filename` passes the [`with_in_file`](Self::with_in_file) filter.
+    fn keeps_file(&self, filename: &str) -> bool {
+        let Some(path) = &self.in_file else {
+            return true;
+        };
+        let filename = filename.replace('\\', "/");
+        filename == *path
+            || filename
+                .strip_suffix(path.as_str())
+                .is_some_and(|prefix| prefix.ends_with('/'))
+    }
+
+    /// Adjusts the vector/BM25 weights per query by its style (default: off).
+    pub fn with_query_routing(mut self, routing: Option<QueryRouting>) -> Self {
+        self.query_routing = routing;
+        self
+    }
+
+    /// The `(vector_weight, bm25_weight)` used for `query`, after query routing.
+    pub fn query_weights(&self, query: &str) -> (f32, f32) {
+        match &self.query_routing {
+            Some(routing) => routing.weights(query, self.vector_weight, self.bm25_weight),
+            None => (self.vector_weight, self.bm25_weight),
         }
     }
 
+    /// Queries the candidates are reranked against: the original one first, then as
+    /// many expansions as [`MultiQueryRerank::max_pairs`] allows for `candidates`.
+    fn rerank_queries(&self, query: &str, expansions: &[String], candidates: usize) -> Vec<String> {
+        let Some(multi) = &self.multi_query_rerank else {
+            return vec![query.to_string()];
+        };
+        let extra = (multi.max_pairs / candidates.max(1))
+            .saturating_sub(1)
+            .min(expansions.len());
+        if extra < expansions.len() {
+            tracing::debug!(
+                "Reranking against {} of {} expansion queries to stay within {} pairs",
+                extra,
+                expansions.len(),
+                multi.max_pairs
+            );
+        }
+        std::iter::once(query.to_string())
+            .chain(expansions[..extra].iter().cloned())
+            .collect()
+    }
+
+    /// Narrows the rerank slots (in fused order) to the cascade's final stage.
+    fn cascade_slots(&self, mut slots: Vec<usize>) -> Vec<usize> {
+        if let Some(k) = self.rerank_cascade_k {
+            slots.truncate(k);
+        }
+        slots
+    }
+
+    fn skips_rerank(&self, filename: &str) -> bool {
+        if self.rerank_skip_extensions.is_empty() {
+            return false;
+        }
+        std::path::Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| {
+                self.rerank_skip_extensions
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(ext))
+            })
+    }
+
     /// Performs semantic search using a hybrid approach (Vector + BM25).
     ///
     /// This method executes both vector search (using embeddings) and keyword search
@@ -100,6 +1063,9 @@ impl CodeSearcher {
     /// # Returns
     ///
     /// Returns a list of `SearchResult`s, ranked by their combined RRF score.
+    ///
+    /// If the searcher has no embedder, only BM25 is used and reranking is skipped;
+    /// [`search_with_stats`](Self::search_with_stats) reports this as `degraded`.
     #[allow(clippy::too_many_arguments)]
     pub async fn semantic_search(
         &self,
@@ -112,47 +1078,468 @@ impl CodeSearcher {
         max_tokens: Option<usize>,
         enable_expansion: bool,
     ) -> Result<Vec<SearchResult>> {
-        let storage = self.storage.as_ref().context("Storage not initialized")?;
-        let embedder = self.embedder.as_ref().context("Embedder not initialized")?;
+        Ok(self
+            .search_with_stats(
+                query,
+                limit,
+                ext,
+                dir,
+                no_rerank,
+                workspace,
+                max_tokens,
+                enable_expansion,
+            )
+            .await?
+            .results)
+    }
+
+    /// Same as [`semantic_search`](Self::semantic_search), but also returns
+    /// context budget statistics when `max_tokens` is set.
+    ///
+    /// Runs in a `search` span with a child span per stage (`search.expand`,
+    /// `search.embed`, `search.vector`, `search.bm25`, `search.rerank`), so traces
+    /// show where the time of each request goes.
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(
+        name = "search",
+        skip_all,
+        fields(limit = limit, workspace = workspace.as_deref().unwrap_or("default"))
+    )]
+    pub async fn search_with_stats(
+        &self,
+        query: &str,
+        limit: usize,
+        ext: Option<String>,
+        dir: Option<String>,
+        no_rerank: bool,
+        workspace: Option<String>,
+        max_tokens: Option<usize>,
+        enable_expansion: bool,
+    ) -> Result<SearchOutcome> {
+        validate_query(query)?;
+
+        // Without an embedder, fall back to keyword search (no vector leg, no reranking)
+        let mut embedder = self.embedder.as_ref();
+        if embedder.is_none() && self.bm25.is_none() {
+            return Err(anyhow!("Embedder not initialized"));
+        }
+        let mut unavailable = Vec::new();
 
         // 1. Expand Query if enabled
         let mut search_queries = vec![query.to_string()];
+        let mut expansion_terms = Vec::new();
         if enable_expansion {
+            if self.expander.is_none() {
+                unavailable.push(Subsystem::Expander);
+            }
             if let Some(expander) = &self.expander {
-                match expander.expand(query).await {
-                    Ok(expanded) => {
-                        // expander returns original query too, so we can just use that
-                        search_queries = expanded;
-                        tracing::info!("Expanded query '{}' to: {:?}", query, search_queries);
+                // An unreachable LLM is detected once, then expansion is skipped;
+                // one that keeps failing is skipped while its circuit breaker is open
+                let expanded = async {
+                    if expander.check_health().await && expander.available() {
+                        Some(expander.expand(query).await)
+                    } else {
+                        None
                     }
-                    Err(e) => {
-                        tracing::warn!("Query expansion failed: {}. Using original query.", e);
+                }
+                .instrument(info_span!("search.expand"))
+                .await;
+                if expanded.is_none() {
+                    unavailable.push(Subsystem::Expander);
+                }
+                if let Some(expanded) = expanded {
+                    match expanded {
+                        Ok(expanded) => {
+                            // expander returns original query too, so we can just use that
+                            search_queries = expanded;
+                            expansion_terms = search_queries
+                                .iter()
+                                .filter(|q| q.as_str() != query)
+                                .cloned()
+                                .collect();
+                            tracing::info!("Expanded query '{}' to: {:?}", query, search_queries);
+                        }
+                        Err(e) => {
+                            tracing::warn!("Query expansion failed: {}. Using original query.", e);
+                            unavailable.push(Subsystem::Expander);
+                        }
                     }
                 }
             }
         }
 
+        // Batched Embedding Generation
+        let all_query_vectors = match embedder {
+            Some(embedder_ref) => {
+                let embedder_handle = embedder_ref.clone();
+                let query_batch = search_queries.clone();
+                let embedded = self
+                    .run_model(move || {
+                        embedder_handle
+                            .embed(query_batch, None)
+                            .map_err(|e| anyhow!(e.to_string()))
+                    })
+                    .instrument(info_span!("search.embed", queries = search_queries.len()))
+                    .await?;
+                match embedded {
+                    Ok(vectors) => vectors,
+                    Err(e) if self.keyword_fallback && self.bm25.is_some() => {
+                        tracing::warn!(
+                            "Query embedding failed: {}. Falling back to keyword search.",
+                            e
+                        );
+                        embedder = None;
+                        Vec::new()
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            None => Vec::new(),
+        };
+        let degraded = embedder.is_none();
+        if degraded {
+            // Listed ahead of the expander, which was tried first
+            let mut missing = vec![Subsystem::Embedder];
+            if !no_rerank {
+                missing.push(Subsystem::Reranker);
+            }
+            unavailable.splice(0..0, missing);
+        }
+        let no_rerank = no_rerank || degraded;
+
+        let reranker: Option<Reranker> = embedder.filter(|_| !no_rerank).map(|embedder| {
+            let embedder = embedder.clone();
+            Arc::new(move |query: &str, texts: Vec<String>, count: usize| {
+                embedder.rerank(query, texts, count)
+            }) as Reranker
+        });
+        let SearchPass {
+            mut candidates,
+            keyword_only,
+            unavailable: pass_unavailable,
+            ..
+        } = self
+            .retrieve_candidates(
+                query,
+                &expansion_terms,
+                &all_query_vectors,
+                self.fetch_limit(limit, no_rerank),
+                &ext,
+                &dir,
+                workspace.as_deref(),
+                reranker,
+            )
+            .await?;
+        for subsystem in pass_unavailable {
+            if !unavailable.contains(&subsystem) {
+                unavailable.push(subsystem);
+            }
+        }
+
+        if let Some(max) = self.max_per_file {
+            candidates = Self::limit_per_file(candidates, max);
+        }
+        let total_candidates = candidates.len();
+        let has_more = total_candidates > limit;
+
+        // Truncate and assign ranks
+        let mut final_results = candidates.into_iter().take(limit).collect::<Vec<_>>();
+        for (i, res) in final_results.iter_mut().enumerate() {
+            res.rank = i + 1;
+        }
+        if self.include_vectors {
+            self.fill_missing_vectors(&mut final_results, workspace.as_deref())
+                .await?;
+        }
+        if !keyword_only.is_empty() {
+            self.fill_note_kinds(&mut final_results, &keyword_only, workspace.as_deref())
+                .await?;
+        }
+
+        if let Some(tokens) = max_tokens {
+            use crate::context::ContextOptimizer;
+            let optimizer = ContextOptimizer::new(tokens);
+            // Merged chunks come from a single file, so they share its test flag
+            let test_files: std::collections::HashSet<String> = final_results
+                .iter()
+                .filter(|r| r.is_test)
+                .map(|r| r.filename.clone())
+                .collect();
+            let optimized = optimizer.optimize_with_stats(final_results)?;
+
+            // Map back to SearchResult
+            let mut mapped_results = Vec::new();
+            for (i, chunk) in optimized.chunks.into_iter().enumerate() {
+                let is_test = test_files.contains(&chunk.filename);
+                mapped_results.push(SearchResult {
+                    rank: i + 1,
+                    score: chunk.max_score, // Use max score of the group
+                    filename: chunk.filename,
+                    code: chunk.code,
+                    line_start: chunk.start_line,
+                    line_end: chunk.end_line,
+                    last_modified: chunk.last_modified,
+                    calls: chunk.calls,
+                    part: None,
+                    is_test,
+                    vector: None,
+                    normalized_score: 0.0,
+                    kind: Default::default(),
+                    uri: String::new(),
+                    confidence: None,
+                    permissions: None,
+                    explanation: None,
+                    snippets: Vec::new(),
+                    symbol: None,
+                    scope: Vec::new(),
+                    item_kind: None,
+                });
+            }
+            self.score_calibration.apply(&mut mapped_results);
+            self.apply_confidence(&mut mapped_results);
+            self.apply_snippets(&mut mapped_results, &search_queries);
+            Self::assign_uris(&mut mapped_results, workspace.as_deref());
+            Ok(SearchOutcome {
+                results: mapped_results,
+                context: Some(optimized.stats),
+                degraded,
+                unavailable,
+                expansion_terms,
+                total_candidates,
+                has_more,
+            })
+        } else {
+            self.score_calibration.apply(&mut final_results);
+            self.apply_confidence(&mut final_results);
+            self.apply_snippets(&mut final_results, &search_queries);
+            Self::assign_uris(&mut final_results, workspace.as_deref());
+            Ok(SearchOutcome {
+                results: final_results,
+                context: None,
+                degraded,
+                unavailable,
+                expansion_terms,
+                total_candidates,
+                has_more,
+            })
+        }
+    }
+
+    /// Ranks the top `limit` results for `query` with and without reranking.
+    ///
+    /// Candidates are retrieved and fused once, then reranked as in
+    /// [`semantic_search`](Self::semantic_search), so both orderings cover the same pool.
+    /// Query expansion and the token budget do not apply.
+    pub async fn compare_rerank(
+        &self,
+        query: &str,
+        limit: usize,
+        ext: Option<String>,
+        dir: Option<String>,
+        workspace: Option<String>,
+    ) -> Result<RerankComparison> {
+        validate_query(query)?;
+        let embedder = self
+            .embedder
+            .as_ref()
+            .context("Comparing reranking needs the embedding model")?;
+
+        let embedder_handle = embedder.clone();
+        let query_batch = vec![query.to_string()];
+        let query_vectors = self
+            .run_model(move || {
+                embedder_handle
+                    .embed(query_batch, None)
+                    .map_err(|e| anyhow!(e.to_string()))
+            })
+            .await??;
+
+        let fused = self
+            .fuse_candidates(
+                query,
+                &query_vectors,
+                self.fetch_limit(limit, false),
+                &ext,
+                &dir,
+                workspace.as_deref(),
+            )
+            .await?;
+        let mut reranked = fused.clone();
+        let embedder = embedder.clone();
+        let reranker: Reranker = Arc::new(move |query: &str, texts: Vec<String>, count: usize| {
+            embedder.rerank(query, texts, count)
+        });
+        self.rerank_pool(query, &[], &mut reranked, &reranker)
+            .await?;
+
+        let (mut fused, mut reranked) = (fused.candidates, reranked.candidates);
+        if let Some(max) = self.max_per_file {
+            fused = Self::limit_per_file(fused, max);
+            reranked = Self::limit_per_file(reranked, max);
+        }
+        let mut comparison = RerankComparison::new(fused, reranked, limit);
+        for results in [&mut comparison.without_rerank, &mut comparison.with_rerank] {
+            self.score_calibration.apply(results);
+            self.apply_confidence(results);
+            Self::assign_uris(results, workspace.as_deref());
+        }
+        Ok(comparison)
+    }
+
+    /// Retrieves, fuses and reranks candidates for `query`.
+    ///
+    /// With [`RerankDisagreement`] set, a reranking that contradicts the retrieval order
+    /// is logged and, if enabled, retried once on a pool of twice the size.
+    #[allow(clippy::too_many_arguments)]
+    async fn retrieve_candidates(
+        &self,
+        query: &str,
+        expansions: &[String],
+        query_vectors: &[Vec<f32>],
+        fetch_limit: usize,
+        ext: &Option<String>,
+        dir: &Option<String>,
+        workspace: Option<&str>,
+        reranker: Option<Reranker>,
+    ) -> Result<SearchPass> {
+        let pass = self
+            .search_pass(
+                query,
+                expansions,
+                query_vectors,
+                fetch_limit,
+                ext,
+                dir,
+                workspace,
+                reranker.as_ref(),
+            )
+            .await?;
+        let (Some(check), Some(correlation)) = (&self.rerank_disagreement, pass.correlation) else {
+            return Ok(pass);
+        };
+        if correlation >= check.threshold {
+            return Ok(pass);
+        }
+        if !check.refetch {
+            tracing::warn!(
+                "Reranker disagrees with retrieval for '{}' (rank correlation {:.2} < {:.2})",
+                query,
+                correlation,
+                check.threshold
+            );
+            return Ok(pass);
+        }
+
+        let wider = fetch_limit * 2;
+        tracing::warn!(
+            "Reranker disagrees with retrieval for '{}' (rank correlation {:.2} < {:.2}); \
+             reranking {} candidates instead of {}",
+            query,
+            correlation,
+            check.threshold,
+            wider,
+            fetch_limit
+        );
+        let mut pass = self
+            .search_pass(
+                query,
+                expansions,
+                query_vectors,
+                wider,
+                ext,
+                dir,
+                workspace,
+                reranker.as_ref(),
+            )
+            .await?;
+        pass.refetched = true;
+        Ok(pass)
+    }
+
+    /// One retrieval of `fetch_limit` candidates per retriever, fused and reranked.
+    #[allow(clippy::too_many_arguments)]
+    async fn search_pass(
+        &self,
+        query: &str,
+        expansions: &[String],
+        query_vectors: &[Vec<f32>],
+        fetch_limit: usize,
+        ext: &Option<String>,
+        dir: &Option<String>,
+        workspace: Option<&str>,
+        reranker: Option<&Reranker>,
+    ) -> Result<SearchPass> {
+        let mut pool = self
+            .fuse_candidates(query, query_vectors, fetch_limit, ext, dir, workspace)
+            .await?;
+        let correlation = match reranker {
+            Some(reranker) => {
+                self.rerank_pool(query, expansions, &mut pool, reranker)
+                    .await?
+            }
+            None => None,
+        };
+        Ok(SearchPass {
+            candidates: pool.candidates,
+            keyword_only: pool.keyword_only,
+            correlation,
+            refetched: false,
+            unavailable: pool.unavailable,
+        })
+    }
+
+    /// Retrieves `fetch_limit` candidates per retriever and fuses them, best first.
+    async fn fuse_candidates(
+        &self,
+        query: &str,
+        query_vectors: &[Vec<f32>],
+        fetch_limit: usize,
+        ext: &Option<String>,
+        dir: &Option<String>,
+        workspace: Option<&str>,
+    ) -> Result<FusedPool> {
+        let (vector_weight, bm25_weight) = self.query_weights(query);
+
         // 2. Vector Search for all queries (Standard + Expanded)
         // We accumulate RRF scores from all vector searches
         let mut vector_rrf_scores: std::collections::HashMap<String, f64> =
             std::collections::HashMap::new();
         // Also map ID to SearchResult to reconstruct later.
         let mut all_vector_results: std::collections::HashMap<String, SearchResult> =
-            std::collections::HashMap::with_capacity(std::cmp::max(50, limit * 2));
+            std::collections::HashMap::with_capacity(std::cmp::max(50, fetch_limit * 2));
 
-        // Batched Embedding Generation
-        let embedder_handle = embedder.clone();
-        let query_batch = search_queries.clone();
-        let all_query_vectors = tokio::task::spawn_blocking(move || {
-            embedder_handle
-                .embed(query_batch, None)
-                .map_err(|e| anyhow!(e.to_string()))
-        })
-        .await??;
+        // Filter test files in the vector query when the table records them
+        let test_condition = match self.test_filter {
+            TestFilter::All => None,
+            TestFilter::Exclude => Some("is_test = false"),
+            TestFilter::Only => Some("is_test = true"),
+        };
+        let test_condition = match (test_condition, &self.storage) {
+            (Some(condition), Some(storage)) if !query_vectors.is_empty() => storage
+                .has_column("is_test")
+                .await
+                .unwrap_or(false)
+                .then_some(condition),
+            _ => None,
+        };
+        let kind_condition = match (self.item_kind, &self.storage) {
+            (Some(kind), Some(storage)) if !query_vectors.is_empty() => storage
+                .has_column("item_kind")
+                .await
+                .unwrap_or(false)
+                .then(|| format!("item_kind = '{}'", kind.as_str())),
+            _ => None,
+        };
 
-        for vector in all_query_vectors {
+        for vector in query_vectors {
             // Construct Filters
-            let mut filters = Vec::with_capacity(2);
+            let mut filters = Vec::with_capacity(4);
+            if let Some(condition) = test_condition {
+                filters.push(condition.to_string());
+            }
+            if let Some(condition) = &kind_condition {
+                filters.push(condition.clone());
+            }
             if let Some(ext_val) = &ext {
                 let clean_ext = if let Some(stripped) = ext_val.strip_prefix('.') {
                     stripped
@@ -165,20 +1552,31 @@ impl CodeSearcher {
                 let clean_dir = dir_val.replace("\\", "/");
                 filters.push(format!("filename LIKE '%{}%'", clean_dir));
             }
+            if let Some(path) = &self.in_file {
+                let path = path.replace('\'', "''");
+                filters.push(format!(
+                    "(filename = '{}' OR filename LIKE '%/{}')",
+                    path, path
+                ));
+            }
             let filter_str = if filters.is_empty() {
                 None
             } else {
                 Some(filters.join(" AND "))
             };
 
-            let fetch_limit = if no_rerank {
-                limit
-            } else {
-                std::cmp::max(50, limit * 5)
-            };
-
-            let results = storage
-                .search(vector, fetch_limit, filter_str, workspace.as_deref())
+            let results = self
+                .storage
+                .as_ref()
+                .context("Storage not initialized")?
+                .search(
+                    vector.clone(),
+                    fetch_limit,
+                    filter_str,
+                    workspace,
+                    self.include_vectors,
+                )
+                .instrument(info_span!("search.vector", fetch_limit = fetch_limit))
                 .await
                 .map_err(|e| anyhow!(e.to_string()))?;
 
@@ -190,41 +1588,7 @@ impl CodeSearcher {
                     .as_any()
                     .downcast_ref()
                     .ok_or_else(|| anyhow!("id wrong type"))?;
-                let filenames: &StringArray = batch
-                    .column_by_name("filename")
-                    .ok_or_else(|| anyhow!("filename missing"))?
-                    .as_any()
-                    .downcast_ref()
-                    .ok_or_else(|| anyhow!("filename wrong type"))?;
-                let codes: &StringArray = batch
-                    .column_by_name("code")
-                    .ok_or_else(|| anyhow!("code missing"))?
-                    .as_any()
-                    .downcast_ref()
-                    .ok_or_else(|| anyhow!("code wrong type"))?;
-                let line_starts: &Int32Array = batch
-                    .column_by_name("line_start")
-                    .ok_or_else(|| anyhow!("line_start missing"))?
-                    .as_any()
-                    .downcast_ref()
-                    .ok_or_else(|| anyhow!("line_start wrong type"))?;
-                let line_ends: &Int32Array = batch
-                    .column_by_name("line_end")
-                    .ok_or_else(|| anyhow!("line_end missing"))?
-                    .as_any()
-                    .downcast_ref()
-                    .ok_or_else(|| anyhow!("line_end wrong type"))?;
-                let last_modifieds: &Int64Array = batch
-                    .column_by_name("last_modified")
-                    .ok_or_else(|| anyhow!("last_modified missing"))?
-                    .as_any()
-                    .downcast_ref()
-                    .ok_or_else(|| anyhow!("last_modified wrong type"))?;
-                let calls_col: Option<&ListArray> = batch
-                    .column_by_name("calls")
-                    .and_then(|c| c.as_any().downcast_ref());
-
-                for i in 0..batch.num_rows() {
+                for (i, result) in self.batch_results(&batch)?.into_iter().enumerate() {
                     let id = ids.value(i).to_string();
                     let rank = i + 1; // Rank in this specific query result list
 
@@ -233,30 +1597,7 @@ impl CodeSearcher {
                         Self::compute_rrf_component(rank, self.rrf_k);
 
                     // Store Result Data if not present
-                    all_vector_results.entry(id.clone()).or_insert_with(|| {
-                        let mut calls_vec = Vec::new();
-                        if let Some(calls_arr) = calls_col {
-                            if !calls_arr.is_null(i) {
-                                if let Some(str_arr) =
-                                    calls_arr.value(i).as_any().downcast_ref::<StringArray>()
-                                {
-                                    for s in str_arr.iter().flatten() {
-                                        calls_vec.push(s.to_string());
-                                    }
-                                }
-                            }
-                        }
-                        SearchResult {
-                            rank: 0,
-                            score: 0.0,
-                            filename: filenames.value(i).to_string(),
-                            code: codes.value(i).to_string(),
-                            line_start: line_starts.value(i),
-                            line_end: line_ends.value(i),
-                            last_modified: last_modifieds.value(i),
-                            calls: calls_vec,
-                        }
-                    });
+                    all_vector_results.entry(id).or_insert(result);
                 }
             }
         } // End of vector search loop
@@ -265,13 +1606,15 @@ impl CodeSearcher {
         let mut candidates: Vec<SearchResult> = all_vector_results.into_values().collect();
 
         // --- 2. Process BM25 Results ---
+        // Keyword hits carry no metadata columns; notes among them are looked up later
+        let mut keyword_only: std::collections::HashSet<String> = std::collections::HashSet::new();
+        let mut unavailable = Vec::new();
         if let Some(bm25) = &self.bm25 {
-            let fetch_limit = if no_rerank {
-                limit
-            } else {
-                std::cmp::max(50, limit * 5)
-            };
-            match bm25.search(query, fetch_limit, workspace.as_deref()) {
+            let bm25_results =
+                info_span!("search.bm25", fetch_limit = fetch_limit).in_scope(|| {
+                    bm25.search_in_file(query, fetch_limit, workspace, self.in_file.as_deref())
+                });
+            match bm25_results {
                 Ok(bm25_results) => {
                     let bm25_ranks: std::collections::HashMap<String, usize> = bm25_results
                         .iter()
@@ -319,8 +1662,22 @@ impl CodeSearcher {
                             line_end: res.line_end as i32,
                             last_modified: 0, // BM25 doesn't track this currently, might need update
                             calls: Vec::new(),
+                            part: None,
+                            is_test: res.is_test.unwrap_or_else(|| is_test_path(&res.filename)),
+                            vector: None,
+                            normalized_score: 0.0,
+                            kind: Default::default(),
+                            uri: String::new(),
+                            confidence: None,
+                            permissions: None,
+                            explanation: None,
+                            snippets: Vec::new(),
+                            symbol: res.symbol.clone(),
+                            scope: res.scope.clone(),
+                            item_kind: res.item_kind,
                         });
                         existing_ids.insert(res.id.clone());
+                        keyword_only.insert(res.id.clone());
                     }
 
                     for candidate in candidates.iter_mut() {
@@ -334,19 +1691,23 @@ impl CodeSearcher {
 
                         let bm25_rank = bm25_ranks.get(&id).copied();
 
-                        let vec_score = vec_rrf_sum as f32 * self.vector_weight;
+                        let vec_score = vec_rrf_sum as f32 * vector_weight;
 
                         let bm25_score = bm25_rank
                             .map(|r| Self::compute_rrf_component(r, self.rrf_k))
                             .unwrap_or(0.0) as f32
-                            * self.bm25_weight;
+                            * bm25_weight;
 
                         candidate.score = vec_score + bm25_score;
                     }
                 }
-                Err(e) => tracing::error!("BM25 search failed: {}", e),
+                Err(e) => {
+                    tracing::error!("BM25 search failed: {}", e);
+                    unavailable.push(Subsystem::Bm25);
+                }
             }
         } else {
+            unavailable.push(Subsystem::Bm25);
             // No BM25, just set score from vectors
             for candidate in candidates.iter_mut() {
                 let id = format!(
@@ -354,72 +1715,441 @@ impl CodeSearcher {
                     candidate.filename, candidate.line_start, candidate.line_end
                 );
                 let vec_rrf_sum = vector_rrf_scores.get(&id).copied().unwrap_or(0.0);
-                candidate.score = vec_rrf_sum as f32 * self.vector_weight;
+                candidate.score = vec_rrf_sum as f32 * vector_weight;
             }
         }
 
-        if !no_rerank && !candidates.is_empty() {
+        // Also covers keyword hits and tables without the is_test column
+        candidates.retain(|c| self.test_filter.keeps(c.is_test));
+        if let Some(kind) = self.item_kind {
+            candidates.retain(|c| c.item_kind == Some(kind));
+        }
+        // LIKE treats `_` as a wildcard, so check --in-file matches exactly here
+        candidates.retain(|c| self.keeps_file(&c.filename));
+        if let Some(min) = self.min_lines {
+            candidates.retain(|c| (c.line_end - c.line_start + 1) as i64 >= min as i64);
+        }
+        if let Some(mask) = self.required_mode {
+            self.fill_permissions(&mut candidates, workspace).await?;
+            candidates.retain(|c| c.permissions.is_some_and(|p| p.has_bits(mask)));
+        }
+
+        // Looked up once; boosts the fused or, after reranking, the reranked scores
+        let importance = self.load_importance(&candidates, workspace).await?;
+        let unboosted = self.boost_fused(query, &mut candidates, &importance);
+
+        // Order by fused score so weights take effect even when reranking is skipped
+        candidates.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        Ok(FusedPool {
+            candidates,
+            keyword_only,
+            importance,
+            unboosted,
+            unavailable,
+        })
+    }
+
+    /// Boosts the fused scores of `candidates`; returns their scores before boosting
+    /// by chunk id.
+    fn boost_fused(
+        &self,
+        query: &str,
+        candidates: &mut [SearchResult],
+        importance: &HashMap<String, f32>,
+    ) -> HashMap<String, f32> {
+        let path_terms = self.path_terms(query);
+        let mut unboosted = HashMap::with_capacity(candidates.len());
+        for candidate in candidates.iter_mut() {
+            let id = format!(
+                "{}-{}-{}",
+                candidate.filename, candidate.line_start, candidate.line_end
+            );
+            unboosted.insert(id, candidate.score);
+            candidate.score = self.boosted(importance, &path_terms, candidate, candidate.score);
+        }
+        unboosted
+    }
+
+    /// `score` of `candidate` raised by its call-graph importance and path match.
+    fn boosted(
+        &self,
+        importance: &HashMap<String, f32>,
+        path_terms: &[String],
+        candidate: &SearchResult,
+        score: f32,
+    ) -> f32 {
+        let id = format!(
+            "{}-{}-{}",
+            candidate.filename, candidate.line_start, candidate.line_end
+        );
+        let importance = importance.get(&id).copied().unwrap_or(0.0);
+        let score = Self::boost_score(score, self.importance_boost, importance);
+        Self::boost_score(
+            score,
+            self.path_boost,
+            Self::path_match(path_terms, &candidate.filename),
+        )
+    }
+
+    /// Reranks `pool` in place; returns the rank correlation with the fused order when
+    /// [`RerankDisagreement`] is checked.
+    ///
+    /// With [`MultiQueryRerank`] set, candidates are also scored against `expansions`.
+    async fn rerank_pool(
+        &self,
+        query: &str,
+        expansions: &[String],
+        pool: &mut FusedPool,
+        reranker: &Reranker,
+    ) -> Result<Option<f32>> {
+        let candidates = &mut pool.candidates;
+        let importance = &pool.importance;
+        // Blending with the boosted fused score would boost a candidate twice
+        let unboosted = &pool.unboosted;
+        let unboosted_of = |c: &SearchResult| {
+            let id = format!("{}-{}-{}", c.filename, c.line_start, c.line_end);
+            unboosted.get(&id).copied().unwrap_or(c.score)
+        };
+
+        // Candidates from skipped extensions keep their fused score
+        let rerank_slots: Vec<usize> = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !self.skips_rerank(&c.filename))
+            .map(|(i, _)| i)
+            .collect();
+        let eligible = rerank_slots.len();
+        let rerank_slots = self.cascade_slots(rerank_slots);
+        // Candidates after the cascade's last reranked slot lost its first stage
+        let head_len = match rerank_slots.last() {
+            Some(&last) if rerank_slots.len() < eligible => last + 1,
+            _ => candidates.len(),
+        };
+
+        // Reranking fewer than two candidates cannot change the order and would only
+        // swap the fused score for one on a different scale, so skip the model call.
+        let mut correlation = None;
+        if rerank_slots.len() >= 2 {
+            let fused_scores: Vec<f32> =
+                rerank_slots.iter().map(|&i| candidates[i].score).collect();
+            let unboosted_scores: Vec<f32> = rerank_slots
+                .iter()
+                .map(|&i| unboosted_of(&candidates[i]))
+                .collect();
+
             // Re-rank
-            let texts: Vec<String> = candidates.iter().map(|c| c.code.clone()).collect();
-            let embedder_handle = embedder.clone();
-            let query_str = query.to_string();
-            let rerank_texts = texts.clone();
-            let rerank_count = texts.len();
-
-            match tokio::task::spawn_blocking(move || {
-                embedder_handle.rerank(&query_str, rerank_texts, rerank_count)
-            })
-            .await?
+            let texts: Vec<String> = rerank_slots
+                .iter()
+                .map(|&i| candidates[i].code.clone())
+                .collect();
+            let reranker = reranker.clone();
+            let queries = self.rerank_queries(query, expansions, rerank_slots.len());
+            let query_count = queries.len();
+            let combine = self.multi_query_rerank.map(|m| m.combine);
+            let batch_size = self.rerank_batch_size;
+
+            match self
+                .run_model(move || {
+                    let per_query = queries
+                        .iter()
+                        .map(|query| {
+                            Self::rerank_batched(texts.clone(), batch_size, |batch| {
+                                let count = batch.len();
+                                reranker(query, batch, count)
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Ok::<_, anyhow::Error>(Self::combine_rerank_scores(per_query, combine))
+                })
+                .instrument(info_span!(
+                    "search.rerank",
+                    candidates = rerank_slots.len(),
+                    queries = query_count
+                ))
+                .await?
             {
-                Ok(rerank_results) => {
-                    // Update scores
-                    for (original_idx, new_score) in rerank_results {
-                        if let Some(candidate) = candidates.get_mut(original_idx) {
-                            candidate.score = new_score;
-                        }
+                Ok(rerank_scores) => {
+                    if self.rerank_disagreement.is_some() {
+                        correlation = Some(Self::rank_correlation(&fused_scores, &rerank_scores));
+                    }
+                    // Texts the reranker dropped rank with its lowest score
+                    let floor = rerank_scores
+                        .iter()
+                        .flatten()
+                        .copied()
+                        .fold(f32::INFINITY, f32::min);
+                    let rerank_scores: Vec<f32> =
+                        rerank_scores.iter().map(|s| s.unwrap_or(floor)).collect();
+                    let new_scores = if self.rerank_blend >= 1.0 {
+                        rerank_scores
+                    } else {
+                        Self::blend_scores(&unboosted_scores, &rerank_scores, self.rerank_blend)
+                    };
+                    Self::apply_rerank(candidates, &rerank_slots, &new_scores);
+
+                    // When skipped candidates rank among the reranked ones, map the
+                    // reranked scores onto the fused scale so the two compare
+                    let mut reranked: Vec<f32> =
+                        rerank_slots.iter().map(|&i| candidates[i].score).collect();
+                    if rerank_slots.len() < head_len {
+                        reranked = Self::to_fused_scale(&unboosted_scores, &reranked);
+                    }
+                    let path_terms = self.path_terms(query);
+                    for (&i, score) in rerank_slots.iter().zip(reranked) {
+                        candidates[i].score =
+                            self.boosted(importance, &path_terms, &candidates[i], score);
                     }
-                    // Sort by new score (descending)
-                    candidates.sort_by(|a, b| {
+                    // Stable, so reranked candidates with equal scores keep the reranker's order
+                    let (head, tail) = candidates.split_at_mut(head_len);
+                    head.sort_by(|a, b| {
                         b.score
                             .partial_cmp(&a.score)
                             .unwrap_or(std::cmp::Ordering::Equal)
                     });
+                    // The cascade's tail stays below its head, in fused order
+                    let floor = head.iter().map(|c| c.score).fold(f32::INFINITY, f32::min);
+                    for candidate in tail {
+                        candidate.score = candidate.score.min(floor);
+                    }
                 }
                 Err(e) => {
                     tracing::warn!("Reranking failed/skipped: {}. Using vector scores.", e);
+                    pool.unavailable.push(Subsystem::Reranker);
                 }
             }
         }
+        Ok(correlation)
+    }
+
+    fn apply_confidence(&self, results: &mut [SearchResult]) {
+        if let Some(thresholds) = &self.confidence {
+            thresholds.apply(results);
+        }
+    }
+
+    fn apply_snippets(&self, results: &mut [SearchResult], queries: &[String]) {
+        if let Some(windows) = &self.snippets {
+            windows.apply(results, queries);
+        }
+    }
+
+    fn assign_uris(results: &mut [SearchResult], workspace: Option<&str>) {
+        let workspace = workspace.unwrap_or("default");
+        for result in results {
+            result.uri = result.chunk_uri(workspace).to_string();
+        }
+    }
+
+    /// Runs a model call on the blocking pool, within the embed limiter if one is set.
+    async fn run_model<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match &self.embed_limiter {
+            Some(limiter) => limiter.run_blocking(f).await,
+            None => Ok(tokio::task::spawn_blocking(f).await?),
+        }
+    }
+
+    /// Loads vectors for results that came from keyword search only.
+    async fn fill_missing_vectors(
+        &self,
+        results: &mut [SearchResult],
+        workspace: Option<&str>,
+    ) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        let chunk_id = |r: &SearchResult| format!("{}-{}-{}", r.filename, r.line_start, r.line_end);
+        let missing: Vec<String> = results
+            .iter()
+            .filter(|r| r.vector.is_none())
+            .map(chunk_id)
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let mut vectors = storage.vectors_by_id(&missing, workspace).await?;
+        for result in results.iter_mut().filter(|r| r.vector.is_none()) {
+            result.vector = vectors.remove(&chunk_id(result));
+        }
+        Ok(())
+    }
+
+    /// Loads permissions for results that came from keyword search only.
+    async fn fill_permissions(
+        &self,
+        results: &mut [SearchResult],
+        workspace: Option<&str>,
+    ) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        let mut missing: Vec<String> = results
+            .iter()
+            .filter(|r| r.permissions.is_none())
+            .map(|r| r.filename.clone())
+            .collect();
+        missing.sort();
+        missing.dedup();
+        if missing.is_empty() {
+            return Ok(());
+        }
 
-        // Truncate and assign ranks
-        let mut final_results = candidates.into_iter().take(limit).collect::<Vec<_>>();
-        for (i, res) in final_results.iter_mut().enumerate() {
-            res.rank = i + 1;
+        let permissions = storage.permissions_by_file(&missing, workspace).await?;
+        for result in results.iter_mut().filter(|r| r.permissions.is_none()) {
+            result.permissions = permissions.get(&result.filename).copied();
         }
+        Ok(())
+    }
 
-        if let Some(tokens) = max_tokens {
-            use crate::context::ContextOptimizer;
-            let optimizer = ContextOptimizer::new(tokens);
-            let merged_chunks = optimizer.optimize(final_results)?;
+    /// Marks notes among results that came from keyword search only.
+    async fn fill_note_kinds(
+        &self,
+        results: &mut [SearchResult],
+        keyword_only: &std::collections::HashSet<String>,
+        workspace: Option<&str>,
+    ) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        let chunk_id = |r: &SearchResult| format!("{}-{}-{}", r.filename, r.line_start, r.line_end);
+        let ids: Vec<String> = results
+            .iter()
+            .map(chunk_id)
+            .filter(|id| keyword_only.contains(id))
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
 
-            // Map back to SearchResult
-            let mut mapped_results = Vec::new();
-            for (i, chunk) in merged_chunks.into_iter().enumerate() {
-                mapped_results.push(SearchResult {
-                    rank: i + 1,
-                    score: chunk.max_score, // Use max score of the group
-                    filename: chunk.filename,
-                    code: chunk.code,
-                    line_start: chunk.start_line,
-                    line_end: chunk.end_line,
-                    last_modified: chunk.last_modified,
-                    calls: chunk.calls,
-                });
+        let mut kinds = storage.note_kinds_by_id(&ids, workspace).await?;
+        for result in results.iter_mut() {
+            if let Some(kind) = kinds.remove(&chunk_id(result)) {
+                result.kind = kind;
             }
-            Ok(mapped_results)
-        } else {
-            Ok(final_results)
         }
+        Ok(())
+    }
+
+    /// Loads the call-graph importance of `candidates` when the importance boost is on.
+    async fn load_importance(
+        &self,
+        candidates: &[SearchResult],
+        workspace: Option<&str>,
+    ) -> Result<HashMap<String, f32>> {
+        let Some(storage) = self
+            .storage
+            .as_ref()
+            .filter(|_| self.importance_boost > 0.0)
+        else {
+            return Ok(HashMap::new());
+        };
+        let ids: Vec<String> = candidates
+            .iter()
+            .map(|c| format!("{}-{}-{}", c.filename, c.line_start, c.line_end))
+            .collect();
+        storage.importance_by_id(&ids, workspace).await
+    }
+
+    /// Finds up to `limit` chunks that call the function defined in `result`.
+    ///
+    /// Returns an empty list when no function name can be extracted from the result.
+    /// The result's own chunk (recursion) is not reported as a caller.
+    pub async fn find_callers(
+        &self,
+        result: &SearchResult,
+        limit: usize,
+        workspace: Option<&str>,
+    ) -> Result<Vec<CallerRef>> {
+        let storage = self.storage.as_ref().context("Storage not initialized")?;
+        let Some(symbol) = crate::indexer::defined_symbol(&result.filename, &result.code) else {
+            return Ok(Vec::new());
+        };
+
+        // One extra row in case the result itself shows up
+        let batches = storage
+            .find_callers(&symbol, limit + 1, workspace)
+            .await
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut callers = Vec::new();
+        for batch in batches {
+            let filenames: &StringArray = batch
+                .column_by_name("filename")
+                .ok_or_else(|| anyhow!("filename missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("filename wrong type"))?;
+            let line_starts: &Int32Array = batch
+                .column_by_name("line_start")
+                .ok_or_else(|| anyhow!("line_start missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("line_start wrong type"))?;
+            let line_ends: &Int32Array = batch
+                .column_by_name("line_end")
+                .ok_or_else(|| anyhow!("line_end missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("line_end wrong type"))?;
+
+            for i in 0..batch.num_rows() {
+                let caller = CallerRef {
+                    filename: filenames.value(i).to_string(),
+                    line_start: line_starts.value(i),
+                    line_end: line_ends.value(i),
+                };
+                let is_self = caller.filename == result.filename
+                    && caller.line_start == result.line_start
+                    && caller.line_end == result.line_end;
+                if !is_self {
+                    callers.push(caller);
+                }
+            }
+        }
+        callers.truncate(limit);
+        Ok(callers)
+    }
+
+    /// Finds every chunk that calls `name`, sorted by filename and line.
+    ///
+    /// A call matches when `name` is its last path segment, so `query` matches
+    /// `self.db.query` but not `query_all`. Results are unscored and ranked in order.
+    pub async fn find_callers_of(
+        &self,
+        name: &str,
+        workspace: Option<&str>,
+    ) -> Result<Vec<SearchResult>> {
+        let storage = self.storage.as_ref().context("Storage not initialized")?;
+        let batches = storage
+            .chunks_calling(name, workspace)
+            .await
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut callers = Vec::new();
+        for batch in &batches {
+            callers.extend(
+                self.batch_results(batch)?
+                    .into_iter()
+                    .filter(|r| r.calls.iter().any(|call| call_target(call) == name)),
+            );
+        }
+        callers.sort_by(|a, b| {
+            (&a.filename, a.line_start, a.line_end).cmp(&(&b.filename, b.line_start, b.line_end))
+        });
+        for (i, caller) in callers.iter_mut().enumerate() {
+            caller.rank = i + 1;
+        }
+        Self::assign_uris(&mut callers, workspace);
+        Ok(callers)
     }
 
     pub fn grep_search(
@@ -427,7 +2157,20 @@ impl CodeSearcher {
         pattern: &str,
         base_path: &str,
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let matcher = RegexMatcher::new(pattern)?;
+        self.grep_search_with(pattern, base_path, GrepOptions::default())
+    }
+
+    /// Same as [`grep_search`](Self::grep_search), with literal and case-insensitive modes.
+    pub fn grep_search_with(
+        &self,
+        pattern: &str,
+        base_path: &str,
+        options: GrepOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let matcher = RegexMatcherBuilder::new()
+            .fixed_strings(options.fixed_strings)
+            .case_insensitive(options.ignore_case)
+            .build(pattern)?;
         let mut matches = Vec::new();
         let walker = WalkBuilder::new(base_path).build(); // Respects .gitignore by default
 
@@ -460,6 +2203,403 @@ impl CodeSearcher {
         Ok(matches)
     }
 
+    /// Search results for the stored chunks in `batch`, unscored and unranked.
+    ///
+    /// Columns added after the table was created may be missing; the fields they fill
+    /// then get their defaults.
+    fn batch_results(&self, batch: &RecordBatch) -> Result<Vec<SearchResult>> {
+        let filenames: &StringArray = batch
+            .column_by_name("filename")
+            .ok_or_else(|| anyhow!("filename missing"))?
+            .as_any()
+            .downcast_ref()
+            .ok_or_else(|| anyhow!("filename wrong type"))?;
+        let codes: &StringArray = batch
+            .column_by_name("code")
+            .ok_or_else(|| anyhow!("code missing"))?
+            .as_any()
+            .downcast_ref()
+            .ok_or_else(|| anyhow!("code wrong type"))?;
+        let line_starts: &Int32Array = batch
+            .column_by_name("line_start")
+            .ok_or_else(|| anyhow!("line_start missing"))?
+            .as_any()
+            .downcast_ref()
+            .ok_or_else(|| anyhow!("line_start wrong type"))?;
+        let line_ends: &Int32Array = batch
+            .column_by_name("line_end")
+            .ok_or_else(|| anyhow!("line_end missing"))?
+            .as_any()
+            .downcast_ref()
+            .ok_or_else(|| anyhow!("line_end wrong type"))?;
+        let last_modifieds: &Int64Array = batch
+            .column_by_name("last_modified")
+            .ok_or_else(|| anyhow!("last_modified missing"))?
+            .as_any()
+            .downcast_ref()
+            .ok_or_else(|| anyhow!("last_modified wrong type"))?;
+        let calls_col: Option<&ListArray> = batch
+            .column_by_name("calls")
+            .and_then(|c| c.as_any().downcast_ref());
+        // Absent in tables created before split parts were recorded
+        let part_col = |name: &str| -> Option<&Int32Array> {
+            batch
+                .column_by_name(name)
+                .and_then(|c| c.as_any().downcast_ref())
+        };
+        let part_cols = (
+            part_col("part_index"),
+            part_col("part_count"),
+            part_col("parent_line_start"),
+            part_col("parent_line_end"),
+        );
+        let is_test_col: Option<&BooleanArray> = batch
+            .column_by_name("is_test")
+            .and_then(|c| c.as_any().downcast_ref());
+
+        let mut results = Vec::with_capacity(batch.num_rows());
+        for i in 0..batch.num_rows() {
+            let mut calls_vec = Vec::new();
+            if let Some(calls_arr) = calls_col {
+                if !calls_arr.is_null(i) {
+                    if let Some(str_arr) = calls_arr.value(i).as_any().downcast_ref::<StringArray>()
+                    {
+                        for s in str_arr.iter().flatten() {
+                            calls_vec.push(s.to_string());
+                        }
+                    }
+                }
+            }
+            results.push(SearchResult {
+                rank: 0,
+                score: 0.0,
+                filename: filenames.value(i).to_string(),
+                code: codes.value(i).to_string(),
+                line_start: line_starts.value(i),
+                line_end: line_ends.value(i),
+                last_modified: last_modifieds.value(i),
+                calls: calls_vec,
+                part: match part_cols {
+                    (Some(index), Some(count), Some(start), Some(end)) if !index.is_null(i) => {
+                        Some(ChunkPart {
+                            index: index.value(i) as usize,
+                            count: count.value(i) as usize,
+                            parent_line_start: start.value(i) as usize,
+                            parent_line_end: end.value(i) as usize,
+                        })
+                    }
+                    _ => None,
+                },
+                is_test: match is_test_col {
+                    Some(col) if !col.is_null(i) => col.value(i),
+                    _ => is_test_path(filenames.value(i)),
+                },
+                vector: if self.include_vectors {
+                    row_vector(batch, i)
+                } else {
+                    None
+                },
+                normalized_score: 0.0,
+                kind: row_kind(batch, i),
+                uri: String::new(),
+                confidence: None,
+                permissions: row_permissions(batch, i),
+                explanation: None,
+                snippets: Vec::new(),
+                symbol: row_symbol(batch, i),
+                scope: row_scope(batch, i),
+                item_kind: row_item_kind(batch, i),
+            });
+        }
+        Ok(results)
+    }
+
+    /// Same as [`grep_search_with`](Self::grep_search_with), over the indexed code instead
+    /// of the files on disk.
+    ///
+    /// Nothing is walked, and files that were moved or deleted since indexing are still
+    /// matched. Lines repeated by overlapping chunks are reported once.
+    pub async fn indexed_grep(
+        &self,
+        pattern: &str,
+        options: GrepOptions,
+        workspace: Option<&str>,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let storage = self
+            .storage
+            .as_ref()
+            .ok_or("Indexed grep needs an index; run `code-rag index` first")?;
+        let matcher = RegexMatcherBuilder::new()
+            .fixed_strings(options.fixed_strings)
+            .case_insensitive(options.ignore_case)
+            .build(pattern)?;
+
+        // Keyed by (filename, line) for sorted, de-duplicated output
+        let mut lines: BTreeMap<(String, u64), String> = BTreeMap::new();
+        storage
+            .scan_code(workspace, |filename, line_start, code| {
+                let _ = Searcher::new().search_slice(
+                    &matcher,
+                    code.as_bytes(),
+                    UTF8(|ln, line| {
+                        let line_number = line_start as u64 + ln - 1;
+                        lines
+                            .entry((filename.to_string(), line_number))
+                            .or_insert_with(|| line.trim_end_matches(['\r', '\n']).to_string());
+                        Ok(true)
+                    }),
+                );
+            })
+            .await?;
+
+        Ok(lines
+            .into_iter()
+            .map(|((filename, ln), line)| format!("{}:{}: {}", filename, ln, line))
+            .collect())
+    }
+
+    /// Number of candidates fetched from each retriever for `limit` results.
+    ///
+    /// Reranking, the per-file cap and the test, file, length, permission and kind filters need
+    /// a deeper pool
+    /// than `limit`. Otherwise one extra candidate tells whether there are more.
+    fn fetch_limit(&self, limit: usize, no_rerank: bool) -> usize {
+        if no_rerank
+            && self.max_per_file.is_none()
+            && self.test_filter == TestFilter::All
+            && self.in_file.is_none()
+            && self.min_lines.is_none()
+            && self.required_mode.is_none()
+            && self.item_kind.is_none()
+        {
+            limit + 1
+        } else {
+            std::cmp::max(50, limit * 5)
+        }
+    }
+
+    /// Keeps at most `max` results per file, preserving the order of `candidates`.
+    fn limit_per_file(candidates: Vec<SearchResult>, max: usize) -> Vec<SearchResult> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        candidates
+            .into_iter()
+            .filter(|c| {
+                let count = counts.entry(c.filename.clone()).or_insert(0);
+                *count += 1;
+                *count <= max
+            })
+            .collect()
+    }
+
+    /// Reranks `texts` in batches of `batch_size` (one batch if `None`).
+    ///
+    /// The reranker reports indices into the batch it was given; these are offset back to
+    /// positions in `texts`, so the returned scores line up with `texts`. Texts the reranker
+    /// did not score are `None`.
+    fn rerank_batched<F>(
+        texts: Vec<String>,
+        batch_size: Option<usize>,
+        mut rerank: F,
+    ) -> Result<Vec<Option<f32>>>
+    where
+        F: FnMut(Vec<String>) -> Result<Vec<(usize, f32)>>,
+    {
+        let batch_size = batch_size.unwrap_or(texts.len()).max(1);
+        let mut scores = vec![None; texts.len()];
+        let mut texts = texts.into_iter();
+        let mut offset = 0;
+        loop {
+            let batch: Vec<String> = texts.by_ref().take(batch_size).collect();
+            let len = batch.len();
+            if len == 0 {
+                break;
+            }
+            for (local_idx, score) in rerank(batch)? {
+                // An out-of-range index must not spill into the next batch
+                if local_idx < len {
+                    scores[offset + local_idx] = Some(score);
+                }
+            }
+            offset += len;
+        }
+        Ok(scores)
+    }
+
+    /// Merges the scores of each query's rerank pass into one score per text.
+    ///
+    /// A single pass is returned unchanged.
+    fn combine_rerank_scores(
+        mut per_query: Vec<Vec<Option<f32>>>,
+        combine: Option<RerankCombine>,
+    ) -> Vec<Option<f32>> {
+        let combine = match combine {
+            Some(combine) if per_query.len() > 1 => combine,
+            _ => return per_query.swap_remove(0),
+        };
+        (0..per_query[0].len())
+            .map(|i| combine.combine(per_query.iter().filter_map(|scores| scores[i])))
+            .collect()
+    }
+
+    /// Writes reranked scores back and reorders the reranked candidates among `slots`.
+    ///
+    /// Candidates outside `slots` keep both their score and their position.
+    fn apply_rerank(candidates: &mut [SearchResult], slots: &[usize], scores: &[f32]) {
+        let mut reranked: Vec<SearchResult> = slots
+            .iter()
+            .zip(scores)
+            .map(|(&i, &score)| SearchResult {
+                score,
+                ..candidates[i].clone()
+            })
+            .collect();
+        // Sort by new score (descending)
+        reranked.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for (&slot, candidate) in slots.iter().zip(reranked) {
+            candidates[slot] = candidate;
+        }
+    }
+
+    /// Raises `score` by `boost * importance` of its magnitude.
+    ///
+    /// Using the magnitude keeps the boost upward for negative reranker logits too.
+    fn boost_score(score: f32, boost: f32, importance: f32) -> f32 {
+        score + score.abs() * boost * importance
+    }
+
+    /// Lowercased words of `query` matched against paths; none when the path boost is off.
+    ///
+    /// Words shorter than 3 characters are skipped, as they match too many paths.
+    fn path_terms(&self, query: &str) -> Vec<String> {
+        if self.path_boost <= 0.0 {
+            return Vec::new();
+        }
+        let mut terms: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.chars().count() >= 3)
+            .map(str::to_lowercase)
+            .collect();
+        terms.sort();
+        terms.dedup();
+        terms
+    }
+
+    /// Fraction of `terms` found among the components of `filename`, in `[0, 1]`.
+    ///
+    /// Components are split at separators, `_`, `-`, `.` and camelCase humps; a term
+    /// matches a component it equals or starts, so `auth` matches `authentication.rs`.
+    fn path_match(terms: &[String], filename: &str) -> f32 {
+        if terms.is_empty() {
+            return 0.0;
+        }
+        let mut words = Vec::new();
+        for part in filename.split(|c: char| !c.is_alphanumeric()) {
+            let mut word = String::new();
+            let mut prev_lower = false;
+            for c in part.chars() {
+                if c.is_uppercase() && prev_lower {
+                    words.push(std::mem::take(&mut word));
+                }
+                prev_lower = c.is_lowercase() || c.is_ascii_digit();
+                word.extend(c.to_lowercase());
+            }
+            if !word.is_empty() {
+                words.push(word);
+            }
+        }
+        let matched = terms
+            .iter()
+            .filter(|term| words.iter().any(|word| word.starts_with(term.as_str())))
+            .count();
+        matched as f32 / terms.len() as f32
+    }
+
+    /// Spearman rank correlation between the order of `fused` and of `reranked`.
+    ///
+    /// Texts the reranker dropped (`None`) rank last.
+    fn rank_correlation(fused: &[f32], reranked: &[Option<f32>]) -> f32 {
+        let n = fused.len().min(reranked.len());
+        if n < 2 {
+            return 1.0;
+        }
+        let ranks = |scores: Vec<f32>| {
+            let mut order: Vec<usize> = (0..n).collect();
+            order.sort_by(|&a, &b| {
+                scores[b]
+                    .partial_cmp(&scores[a])
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            let mut ranks = vec![0usize; n];
+            for (rank, i) in order.into_iter().enumerate() {
+                ranks[i] = rank;
+            }
+            ranks
+        };
+        let fused_ranks = ranks(fused[..n].to_vec());
+        let reranked_ranks = ranks(
+            reranked[..n]
+                .iter()
+                .map(|s| s.unwrap_or(f32::NEG_INFINITY))
+                .collect(),
+        );
+        let squared_diffs: f64 = fused_ranks
+            .iter()
+            .zip(&reranked_ranks)
+            .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+            .sum();
+        let n = n as f64;
+        (1.0 - 6.0 * squared_diffs / (n * (n * n - 1.0))) as f32
+    }
+
+    /// Blends reranker and fused retrieval scores after min-max normalizing each.
+    ///
+    /// Formula: `blend * rerank_norm + (1 - blend) * fused_norm`
+    fn blend_scores(fused: &[f32], rerank: &[f32], blend: f32) -> Vec<f32> {
+        let fused_norm = Self::min_max_normalize(fused);
+        let rerank_norm = Self::min_max_normalize(rerank);
+        fused_norm
+            .iter()
+            .zip(rerank_norm.iter())
+            .map(|(f, r)| blend * r + (1.0 - blend) * f)
+            .collect()
+    }
+
+    /// Maps `scores` linearly onto the range of `fused`, the best to its maximum and the
+    /// worst to its minimum.
+    ///
+    /// Reranker logits and blended scores are on other scales than fused RRF sums; mapped
+    /// onto the range the reranked candidates had before, they compare with the fused
+    /// scores of candidates that were not reranked.
+    fn to_fused_scale(fused: &[f32], scores: &[f32]) -> Vec<f32> {
+        let min = fused.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = fused.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        Self::min_max_normalize(scores)
+            .into_iter()
+            .map(|n| min + n * (max - min))
+            .collect()
+    }
+
+    /// Scales scores into `[0, 1]`; a constant list maps to all `1.0`.
+    fn min_max_normalize(scores: &[f32]) -> Vec<f32> {
+        let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let range = max - min;
+        scores
+            .iter()
+            .map(|s| {
+                if range > f32::EPSILON {
+                    (s - min) / range
+                } else {
+                    1.0
+                }
+            })
+            .collect()
+    }
+
     /// Helper to compute RRF score component.
     ///
     /// Formula: `1.0 / (k + rank)`
@@ -472,6 +2612,37 @@ impl CodeSearcher {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_chunk_uri_round_trip() {
+        let uri = ChunkUri {
+            workspace: "team/api".to_string(),
+            filename: "./src/100%#done/main.rs".to_string(),
+            line_start: 12,
+            line_end: 40,
+        };
+        let text = uri.to_string();
+        assert_eq!(
+            text,
+            "code-rag://team%2Fapi/./src/100%25%23done/main.rs#L12-L40"
+        );
+        assert_eq!(ChunkUri::parse(&text).unwrap(), uri);
+
+        let plain = "code-rag://default//home/dev/app.py#L1-L9";
+        let parsed = ChunkUri::parse(plain).unwrap();
+        assert_eq!(parsed.filename, "/home/dev/app.py");
+        assert_eq!(parsed.to_string(), plain);
+
+        for bad in [
+            "file:///src/main.rs#L1-L2",
+            "code-rag://default/src/main.rs",
+            "code-rag://default/src/main.rs#L1",
+            "code-rag:///src/main.rs#L1-L2",
+            "code-rag://default/src/%zz.rs#L1-L2",
+        ] {
+            assert!(ChunkUri::parse(bad).is_err(), "{} should not parse", bad);
+        }
+    }
+
     #[test]
     fn test_rrf_scoring_formula() {
         let k = 60.0;
@@ -484,6 +2655,702 @@ mod tests {
         assert!((score_10 - (1.0 / 70.0)).abs() < f64::EPSILON);
     }
 
+    fn order_by(scores: &[f32]) -> Vec<usize> {
+        let mut idx: Vec<usize> = (0..scores.len()).collect();
+        idx.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        idx
+    }
+
+    #[test]
+    fn test_rerank_blend_extremes() {
+        // Fused RRF scores and cross-encoder logits disagree on ordering
+        let fused = [0.032, 0.030, 0.016, 0.010];
+        let rerank = [-4.0, 7.5, 2.0, 9.1];
+
+        let pure_fused = CodeSearcher::blend_scores(&fused, &rerank, 0.0);
+        assert_eq!(order_by(&pure_fused), order_by(&fused));
+
+        let pure_rerank = CodeSearcher::blend_scores(&fused, &rerank, 1.0);
+        assert_eq!(order_by(&pure_rerank), order_by(&rerank));
+
+        let mixed = CodeSearcher::blend_scores(&fused, &rerank, 0.5);
+        assert!(mixed.iter().all(|s| (0.0..=1.0).contains(s)));
+    }
+
+    fn result(filename: &str, score: f32) -> SearchResult {
+        SearchResult {
+            rank: 0,
+            score,
+            filename: filename.into(),
+            code: "".into(),
+            line_start: 0,
+            line_end: 0,
+            last_modified: 0,
+            calls: Vec::new(),
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
+        }
+    }
+
+    #[test]
+    fn test_rerank_skip_keeps_slot_and_score() {
+        let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+            .with_rerank_skip_extensions(vec![".JSON".into()]);
+        let mut candidates = vec![
+            result("a.rs", 0.03),
+            result("config.json", 0.02),
+            result("b.rs", 0.01),
+        ];
+        assert!(searcher.skips_rerank("config.json"));
+        assert!(!searcher.skips_rerank("a.rs"));
+
+        // Reranker prefers b.rs; only the Rust slots (0 and 2) are reordered
+        CodeSearcher::apply_rerank(&mut candidates, &[0, 2], &[-3.0, 5.0]);
+
+        let order: Vec<&str> = candidates.iter().map(|c| c.filename.as_str()).collect();
+        assert_eq!(order, ["b.rs", "config.json", "a.rs"]);
+        assert_eq!(candidates[0].score, 5.0);
+        assert_eq!(candidates[1].score, 0.02);
+        assert_eq!(candidates[2].score, -3.0);
+    }
+
+    #[test]
+    fn test_confidence_is_relative_to_the_result_set() {
+        let thresholds = ConfidenceThresholds {
+            high: 0.8,
+            medium: 0.5,
+        };
+        let labels = |scores: &[f32]| {
+            let mut results: Vec<SearchResult> =
+                scores.iter().map(|&s| result("a.rs", s)).collect();
+            ScoreCalibration::MinMax.apply(&mut results);
+            thresholds.apply(&mut results);
+            results
+                .into_iter()
+                .map(|r| r.confidence.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // Uniformly weak reranker logits are labelled like strong ones
+        let expected = [Confidence::High, Confidence::Medium, Confidence::Low];
+        assert_eq!(labels(&[-9.0, -9.5, -10.0]), expected);
+        assert_eq!(labels(&[9.0, 5.0, 1.0]), expected);
+    }
+
+    #[tokio::test]
+    async fn test_reranked_scores_share_the_fused_scale() {
+        // Fused order; the reranker prefers c.rs and scores in logits
+        let rerank = |skip: Vec<String>| async move {
+            let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+                .with_rerank_skip_extensions(skip);
+            let mut pool = FusedPool {
+                candidates: [
+                    ("a.rs", 0.5),
+                    ("config.json", 0.375),
+                    ("b.rs", 0.25),
+                    ("c.rs", 0.125),
+                ]
+                .iter()
+                .map(|&(name, score)| SearchResult {
+                    code: name.into(),
+                    ..result(name, score)
+                })
+                .collect(),
+                keyword_only: Default::default(),
+                importance: HashMap::new(),
+                unboosted: HashMap::new(),
+                unavailable: Vec::new(),
+            };
+            let reranker: Reranker = Arc::new(|_: &str, texts: Vec<String>, _: usize| {
+                Ok(texts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| (i, if name == "c.rs" { 9.0 } else { -4.0 }))
+                    .collect())
+            });
+            searcher
+                .rerank_pool("query", &[], &mut pool, &reranker)
+                .await
+                .unwrap();
+            pool.candidates
+                .into_iter()
+                .map(|c| (c.filename, c.score))
+                .collect::<Vec<_>>()
+        };
+
+        // The reranked Rust files span the fused range they had, 0.125 to 0.5, so the
+        // JSON file's fused 0.375 sits below c.rs but above a.rs and b.rs
+        let ranked = rerank(vec!["json".into()]).await;
+        assert_eq!(ranked[0], ("c.rs".to_string(), 0.5));
+        assert_eq!(ranked[1], ("config.json".to_string(), 0.375));
+        assert!(ranked[2..].iter().all(|(_, score)| *score == 0.125));
+        assert!(ranked.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        // With every candidate reranked, the reranker's scores are kept as they are
+        let ranked = rerank(Vec::new()).await;
+        assert_eq!(ranked[0], ("c.rs".to_string(), 9.0));
+        assert!(ranked[1..].iter().all(|(_, score)| *score == -4.0));
+    }
+
+    #[test]
+    fn test_rerank_batched_maps_batch_indices() {
+        let texts: Vec<String> = (0..5).map(|i| format!("doc{}", i)).collect();
+        let mut batches = Vec::new();
+
+        // The stub scores "docN" as N and, like the reranker, returns results sorted by
+        // score with indices relative to the batch it was given
+        let scores = CodeSearcher::rerank_batched(texts, Some(3), |batch| {
+            batches.push(batch.len());
+            let mut results: Vec<(usize, f32)> = batch
+                .iter()
+                .enumerate()
+                .map(|(i, t)| (i, t.trim_start_matches("doc").parse::<f32>().unwrap()))
+                .collect();
+            results.sort_by(|a, b| b.1.total_cmp(&a.1));
+            Ok(results)
+        })
+        .unwrap();
+
+        assert_eq!(batches, [3, 2]);
+        assert_eq!(
+            scores,
+            [Some(0.0), Some(1.0), Some(2.0), Some(3.0), Some(4.0)]
+        );
+
+        // Unscored and out-of-range indices leave gaps instead of shifting scores
+        let scores = CodeSearcher::rerank_batched(vec!["a".into(), "b".into()], None, |_| {
+            Ok(vec![(1, 9.0), (7, 1.0)])
+        })
+        .unwrap();
+        assert_eq!(scores, [None, Some(9.0)]);
+    }
+
+    #[test]
+    fn test_query_routing_adjusts_weights_by_query_style() {
+        let routing = QueryRouting {
+            boost: 2.0,
+            prose_min_words: 4,
+        };
+        let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+            .with_query_routing(Some(routing));
+
+        for query in ["parseConfig", "parse_config", "Storage::search", "fn.*new("] {
+            assert_eq!(routing.classify(query), QueryStyle::Identifier, "{}", query);
+            let (vector, bm25) = searcher.query_weights(query);
+            assert!(bm25 > vector, "{} should lean BM25", query);
+        }
+        for query in [
+            "how do we load the configuration file?",
+            "retry failed requests with backoff",
+        ] {
+            assert_eq!(routing.classify(query), QueryStyle::Prose, "{}", query);
+            let (vector, bm25) = searcher.query_weights(query);
+            assert!(vector > bm25, "{} should lean vector", query);
+        }
+        for query in ["where is parse_config called", "load config"] {
+            assert_eq!(routing.classify(query), QueryStyle::Mixed, "{}", query);
+            assert_eq!(searcher.query_weights(query), (1.0, 1.0));
+        }
+
+        // Off by default
+        let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0);
+        assert_eq!(searcher.query_weights("parseConfig"), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_rank_correlation() {
+        let fused = [0.9, 0.7, 0.5, 0.3];
+        let same = [Some(4.0), Some(3.0), Some(2.0), Some(1.0)];
+        let reversed = [Some(1.0), Some(2.0), Some(3.0), Some(4.0)];
+        assert!((CodeSearcher::rank_correlation(&fused, &same) - 1.0).abs() < 1e-6);
+        assert!((CodeSearcher::rank_correlation(&fused, &reversed) + 1.0).abs() < 1e-6);
+        // A dropped text ranks last
+        let dropped = [None, Some(3.0), Some(2.0), Some(1.0)];
+        assert!(CodeSearcher::rank_correlation(&fused, &dropped) < 0.0);
+    }
+
+    #[test]
+    fn test_path_match() {
+        let searcher =
+            CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0).with_path_boost(0.5);
+        let terms = searcher.path_terms("Auth middleware of the API");
+        assert_eq!(terms, vec!["api", "auth", "middleware", "the"]);
+        assert_eq!(
+            CodeSearcher::path_match(&terms, "src/authentication/middleware.rs"),
+            0.5
+        );
+        assert_eq!(
+            CodeSearcher::path_match(&terms, "src/ApiAuthMiddleware.ts"),
+            0.75
+        );
+        assert_eq!(CodeSearcher::path_match(&terms, "src/billing.rs"), 0.0);
+        // Off by default
+        let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0);
+        assert!(searcher.path_terms("auth middleware").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_path_boost_ranks_matching_path_higher() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap(), "code_chunks")
+            .await
+            .unwrap();
+        storage.init(2).await.unwrap();
+        // The billing chunk lies closer to the query vector
+        let names = vec![
+            "src/billing/handlers.rs".to_string(),
+            "src/auth/middleware.rs".to_string(),
+        ];
+        storage
+            .add_chunks(
+                "default",
+                names.iter().map(|n| format!("{}-1-1", n)).collect(),
+                names.clone(),
+                vec!["fn handle() {}".to_string(), "fn check() {}".to_string()],
+                vec![1; 2],
+                vec![1; 2],
+                vec![0; 2],
+                vec![Vec::new(); 2],
+                vec![vec![1.0, 0.0], vec![1.0, 0.5]],
+            )
+            .await
+            .unwrap();
+        let storage = Arc::new(storage);
+
+        let top = |boost: f32| {
+            let searcher =
+                CodeSearcher::new(Some(storage.clone()), None, None, None, 1.0, 1.0, 60.0)
+                    .with_path_boost(boost);
+            async move {
+                let pool = searcher
+                    .fuse_candidates("auth middleware", &[vec![1.0, 0.0]], 10, &None, &None, None)
+                    .await
+                    .unwrap();
+                pool.candidates[0].filename.clone()
+            }
+        };
+        assert_eq!(top(0.0).await, "src/billing/handlers.rs");
+        assert_eq!(top(1.0).await, "src/auth/middleware.rs");
+    }
+
+    /// Fuses `scores` as `fuse_candidates` would, reranks `query` with a reranker that
+    /// agrees with the fused order, and returns the final score of each file.
+    async fn boosted_rerank(
+        searcher: CodeSearcher,
+        query: &str,
+        scores: &[(&str, f32)],
+    ) -> HashMap<String, f32> {
+        let importance = HashMap::from([("b.rs-0-0".to_string(), 1.0)]);
+        let mut candidates: Vec<SearchResult> = scores
+            .iter()
+            .map(|&(name, score)| SearchResult {
+                code: name.into(),
+                ..result(name, score)
+            })
+            .collect();
+        let unboosted = searcher.boost_fused(query, &mut candidates, &importance);
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut pool = FusedPool {
+            candidates,
+            keyword_only: Default::default(),
+            importance,
+            unboosted,
+            unavailable: Vec::new(),
+        };
+        let fused: HashMap<String, f32> = scores
+            .iter()
+            .map(|&(name, score)| (name.to_string(), score))
+            .collect();
+        let reranker: Reranker = Arc::new(move |_: &str, texts: Vec<String>, _: usize| {
+            Ok(texts
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (i, fused[name]))
+                .collect())
+        });
+        searcher
+            .rerank_pool(query, &[], &mut pool, &reranker)
+            .await
+            .unwrap();
+        pool.candidates
+            .into_iter()
+            .map(|c| (c.filename, c.score))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_importance_boost_applies_once_after_rerank() {
+        let scores = [("a.rs", 3.0), ("b.rs", 2.0), ("c.rs", 1.0)];
+        let searcher = |boost: f32| {
+            CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+                .with_rerank_blend(0.5)
+                .with_importance_boost(boost)
+        };
+
+        // Without a boost b.rs blends to 0.5; a boost of 1.0 at importance 1.0 doubles
+        // that once, rather than also lifting the fused score it is blended from
+        let control = boosted_rerank(searcher(0.0), "query", &scores).await;
+        let boosted = boosted_rerank(searcher(1.0), "query", &scores).await;
+        assert_eq!(control["b.rs"], 0.5);
+        assert_eq!(boosted["b.rs"], 2.0 * control["b.rs"]);
+        assert_eq!(boosted["a.rs"], control["a.rs"]);
+        assert_eq!(boosted["c.rs"], control["c.rs"]);
+    }
+
+    #[tokio::test]
+    async fn test_path_boost_applies_once_after_rerank() {
+        let scores = [("a.rs", 3.0), ("src/auth.rs", 2.0), ("c.rs", 1.0)];
+        let searcher = |boost: f32| {
+            CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+                .with_rerank_blend(0.5)
+                .with_path_boost(boost)
+        };
+
+        let control = boosted_rerank(searcher(0.0), "auth", &scores).await;
+        let boosted = boosted_rerank(searcher(1.0), "auth", &scores).await;
+        assert_eq!(control["src/auth.rs"], 0.5);
+        assert_eq!(boosted["src/auth.rs"], 2.0 * control["src/auth.rs"]);
+        assert_eq!(boosted["a.rs"], control["a.rs"]);
+    }
+
+    #[tokio::test]
+    async fn test_rerank_disagreement_refetches_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap(), "code_chunks")
+            .await
+            .unwrap();
+        storage.init(2).await.unwrap();
+        // Chunk i lies further from the query the larger i is
+        let count = 120;
+        let names: Vec<String> = (0..count).map(|i| format!("src/f{}.rs", i)).collect();
+        storage
+            .add_chunks(
+                "default",
+                names.iter().map(|n| format!("{}-1-1", n)).collect(),
+                names.clone(),
+                (0..count).map(|i| format!("fn f{}() {{}}", i)).collect(),
+                vec![1; count],
+                vec![1; count],
+                vec![0; count],
+                vec![Vec::new(); count],
+                (0..count).map(|i| vec![1.0, i as f32 * 0.01]).collect(),
+            )
+            .await
+            .unwrap();
+        let storage = Arc::new(storage);
+
+        // The mock reranker inverts the retrieval order
+        let reranker: Reranker = Arc::new(|_: &str, texts: Vec<String>, _: usize| {
+            Ok((0..texts.len()).map(|i| (i, i as f32)).collect())
+        });
+        let search = |check: RerankDisagreement| {
+            let searcher =
+                CodeSearcher::new(Some(storage.clone()), None, None, None, 1.0, 1.0, 60.0)
+                    .with_rerank_disagreement(Some(check));
+            let reranker = reranker.clone();
+            async move {
+                searcher
+                    .retrieve_candidates(
+                        "f",
+                        &[],
+                        &[vec![1.0, 0.0]],
+                        50,
+                        &None,
+                        &None,
+                        None,
+                        Some(reranker),
+                    )
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let pass = search(RerankDisagreement {
+            threshold: 0.0,
+            refetch: true,
+        })
+        .await;
+        assert!(pass.refetched);
+        assert!(pass.correlation.unwrap() < 0.0);
+        assert_eq!(pass.candidates.len(), 100);
+        assert_eq!(pass.candidates[0].filename, "src/f99.rs");
+
+        // Logging only, or an order that passes the threshold, keeps the first pool
+        for check in [
+            RerankDisagreement {
+                threshold: 0.0,
+                refetch: false,
+            },
+            RerankDisagreement {
+                threshold: -1.0,
+                refetch: true,
+            },
+        ] {
+            let pass = search(check).await;
+            assert!(!pass.refetched);
+            assert_eq!(pass.candidates.len(), 50);
+            assert_eq!(pass.candidates[0].filename, "src/f49.rs");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multi_query_rerank_surfaces_expansion_matches() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let storage = Storage::new(temp_dir.path().to_str().unwrap(), "code_chunks")
+            .await
+            .unwrap();
+        storage.init(2).await.unwrap();
+        // Retrieval order: login.rs, other.rs, then session.rs
+        let names = ["src/login.rs", "src/other.rs", "src/session.rs"];
+        let codes = ["fn login() {}", "fn other() {}", "fn authenticate() {}"];
+        storage
+            .add_chunks(
+                "default",
+                names.iter().map(|n| format!("{}-1-1", n)).collect(),
+                names.iter().map(|n| n.to_string()).collect(),
+                codes.iter().map(|c| c.to_string()).collect(),
+                vec![1; 3],
+                vec![1; 3],
+                vec![0; 3],
+                vec![Vec::new(); 3],
+                (0..3).map(|i| vec![1.0, i as f32 * 0.1]).collect(),
+            )
+            .await
+            .unwrap();
+        let storage = Arc::new(storage);
+
+        // The mock reranker only rewards code containing the query
+        let reranker: Reranker = Arc::new(|query: &str, texts: Vec<String>, _: usize| {
+            Ok(texts
+                .iter()
+                .enumerate()
+                .map(|(i, t)| (i, if t.contains(query) { 5.0 } else { 0.0 }))
+                .collect())
+        });
+        let order = |multi: Option<MultiQueryRerank>| {
+            let searcher =
+                CodeSearcher::new(Some(storage.clone()), None, None, None, 1.0, 1.0, 60.0)
+                    .with_multi_query_rerank(multi);
+            let reranker = reranker.clone();
+            async move {
+                let pass = searcher
+                    .retrieve_candidates(
+                        "login",
+                        &["authenticate".to_string()],
+                        &[vec![1.0, 0.0]],
+                        10,
+                        &None,
+                        &None,
+                        None,
+                        Some(reranker),
+                    )
+                    .await
+                    .unwrap();
+                pass.candidates
+                    .into_iter()
+                    .map(|c| c.filename)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let single = order(None).await;
+        assert_eq!(single, ["src/login.rs", "src/other.rs", "src/session.rs"]);
+        for combine in [RerankCombine::Max, RerankCombine::Mean] {
+            let multi = order(Some(MultiQueryRerank {
+                combine,
+                max_pairs: 200,
+            }))
+            .await;
+            assert_eq!(multi, ["src/login.rs", "src/session.rs", "src/other.rs"]);
+        }
+
+        // A cap too small for a second query falls back to the original one
+        let capped = order(Some(MultiQueryRerank {
+            combine: RerankCombine::Max,
+            max_pairs: 5,
+        }))
+        .await;
+        assert_eq!(capped, single);
+    }
+
+    #[test]
+    fn test_combine_rerank_scores() {
+        let per_query = vec![
+            vec![Some(1.0), Some(-2.0), None],
+            vec![Some(3.0), None, None],
+        ];
+        assert_eq!(
+            CodeSearcher::combine_rerank_scores(per_query.clone(), Some(RerankCombine::Max)),
+            [Some(3.0), Some(-2.0), None]
+        );
+        assert_eq!(
+            CodeSearcher::combine_rerank_scores(per_query, Some(RerankCombine::Mean)),
+            [Some(2.0), Some(-2.0), None]
+        );
+        assert_eq!(
+            CodeSearcher::combine_rerank_scores(vec![vec![Some(1.0)]], None),
+            [Some(1.0)]
+        );
+    }
+
+    #[test]
+    fn test_rerank_cascade_reranks_top_k_only() {
+        let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+            .with_rerank_cascade_k(Some(3));
+        // Sorted by fused score; the reranker prefers c.rs, then b.rs
+        let mut candidates = vec![
+            result("a.rs", 0.05),
+            result("b.rs", 0.04),
+            result("c.rs", 0.03),
+            result("d.rs", 0.02),
+            result("e.rs", 0.01),
+        ];
+        let rerank_score = |name: &str| match name {
+            "c.rs" => 8.0,
+            "b.rs" => 4.0,
+            _ => -1.0,
+        };
+
+        let slots = searcher.cascade_slots((0..candidates.len()).collect());
+        let texts: Vec<String> = slots
+            .iter()
+            .map(|&i| candidates[i].filename.clone())
+            .collect();
+        let mut reranked = 0;
+        let scores = CodeSearcher::rerank_batched(texts, None, |batch| {
+            reranked += batch.len();
+            Ok(batch
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (i, rerank_score(name)))
+                .collect())
+        })
+        .unwrap();
+        assert_eq!(reranked, 3);
+
+        let scores: Vec<f32> = scores.into_iter().map(Option::unwrap).collect();
+        CodeSearcher::apply_rerank(&mut candidates, &slots, &scores);
+        let order: Vec<&str> = candidates.iter().map(|c| c.filename.as_str()).collect();
+        assert_eq!(order, ["c.rs", "b.rs", "a.rs", "d.rs", "e.rs"]);
+
+        // Unset (or 0) keeps the single full pass
+        let full = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+            .with_rerank_cascade_k(Some(0));
+        assert_eq!(full.cascade_slots(vec![0, 1, 2, 3, 4]).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_rerank_cascade_tail_stays_below_head() {
+        let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0)
+            .with_rerank_cascade_k(Some(2))
+            .with_path_boost(1.0);
+        // src/auth.rs reaches the head through the path boost (0.3125 doubled)
+        let mut candidates: Vec<SearchResult> = [
+            ("src/x.rs", 0.5),
+            ("src/auth.rs", 0.3125),
+            ("src/y.rs", 0.4375),
+        ]
+        .iter()
+        .map(|&(name, score)| SearchResult {
+            code: name.into(),
+            ..result(name, score)
+        })
+        .collect();
+        let unboosted = searcher.boost_fused("auth", &mut candidates, &HashMap::new());
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut pool = FusedPool {
+            candidates,
+            keyword_only: Default::default(),
+            importance: HashMap::new(),
+            unboosted,
+            unavailable: Vec::new(),
+        };
+        let reranker: Reranker = Arc::new(|_: &str, texts: Vec<String>, _: usize| {
+            Ok(texts
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (i, if name == "src/auth.rs" { 9.0 } else { -4.0 }))
+                .collect())
+        });
+        searcher
+            .rerank_pool("auth", &[], &mut pool, &reranker)
+            .await
+            .unwrap();
+
+        // x.rs drops to the bottom of the head's fused range, 0.3125, below y.rs's
+        // fused 0.4375; y.rs was never reranked and still follows the head
+        let ranked: Vec<(&str, f32)> = pool
+            .candidates
+            .iter()
+            .map(|c| (c.filename.as_str(), c.score))
+            .collect();
+        assert_eq!(
+            ranked,
+            [
+                ("src/auth.rs", 1.0),
+                ("src/x.rs", 0.3125),
+                ("src/y.rs", 0.3125)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_limit_per_file() {
+        let candidates = vec![
+            result("big.rs", 0.9),
+            result("big.rs", 0.8),
+            result("big.rs", 0.7),
+            result("small.rs", 0.5),
+            result("big.rs", 0.4),
+        ];
+
+        let limited = CodeSearcher::limit_per_file(candidates.clone(), 1);
+        let order: Vec<&str> = limited.iter().map(|c| c.filename.as_str()).collect();
+        assert_eq!(order, ["big.rs", "small.rs"]);
+        assert_eq!(limited[0].score, 0.9);
+
+        let limited = CodeSearcher::limit_per_file(candidates, 2);
+        let scores: Vec<f32> = limited.iter().map(|c| c.score).collect();
+        assert_eq!(scores, [0.9, 0.8, 0.5]);
+    }
+
+    #[test]
+    fn test_aggregate_by_file() {
+        let results = vec![
+            result("busy.rs", 0.8),
+            result("busy.rs", 0.7),
+            result("weak.rs", 0.3),
+            result("busy.rs", 0.6),
+        ];
+
+        for method in [ScoreAggregation::Max, ScoreAggregation::Sum] {
+            let files = aggregate_by_file(&results, method);
+            assert_eq!(files.len(), 2);
+            assert_eq!(files[0].filename, "busy.rs");
+            assert_eq!(files[0].chunk_count, 3);
+            assert_eq!(files[1].filename, "weak.rs");
+            assert_eq!(files[1].rank, 2);
+        }
+
+        let summed = aggregate_by_file(&results, ScoreAggregation::Sum);
+        assert!((summed[0].score - 2.1).abs() < 1e-6);
+        assert_eq!(
+            aggregate_by_file(&results, ScoreAggregation::Max)[0].score,
+            0.8
+        );
+    }
+
     #[test]
     fn test_sorting_logic() {
         let mut results = [
@@ -496,6 +3363,19 @@ mod tests {
                 line_end: 0,
                 last_modified: 0,
                 calls: Vec::new(),
+                part: None,
+                is_test: false,
+                vector: None,
+                normalized_score: 0.0,
+                kind: Default::default(),
+                uri: String::new(),
+                confidence: None,
+                permissions: None,
+                explanation: None,
+                snippets: Vec::new(),
+                symbol: None,
+                scope: Vec::new(),
+                item_kind: None,
             },
             SearchResult {
                 rank: 0,
@@ -506,6 +3386,19 @@ mod tests {
                 line_end: 0,
                 last_modified: 0,
                 calls: Vec::new(),
+                part: None,
+                is_test: false,
+                vector: None,
+                normalized_score: 0.0,
+                kind: Default::default(),
+                uri: String::new(),
+                confidence: None,
+                permissions: None,
+                explanation: None,
+                snippets: Vec::new(),
+                symbol: None,
+                scope: Vec::new(),
+                item_kind: None,
             },
             SearchResult {
                 rank: 0,
@@ -516,6 +3409,19 @@ mod tests {
                 line_end: 0,
                 last_modified: 0,
                 calls: Vec::new(),
+                part: None,
+                is_test: false,
+                vector: None,
+                normalized_score: 0.0,
+                kind: Default::default(),
+                uri: String::new(),
+                confidence: None,
+                permissions: None,
+                explanation: None,
+                snippets: Vec::new(),
+                symbol: None,
+                scope: Vec::new(),
+                item_kind: None,
             },
         ];
 
@@ -525,4 +3431,34 @@ mod tests {
         assert_eq!(results[1].filename, "C"); // 0.5
         assert_eq!(results[2].filename, "A"); // 0.1
     }
+
+    #[test]
+    fn test_snippet_windows_cover_separate_relevant_regions() {
+        let mut lines: Vec<String> = (0..40)
+            .map(|i| format!("let filler_{} = {};", i, i))
+            .collect();
+        lines[5] = "let token = session.refresh_token();".to_string();
+        lines[30] = "if token.expired() { session.refresh_token(); }".to_string();
+        let code = lines.join("\n");
+
+        let windows = SnippetWindows { count: 2, lines: 5 };
+        let terms = vec!["refresh".to_string(), "token".to_string()];
+        let snippets = windows.select(&code, 100, &terms);
+
+        assert_eq!(snippets.len(), 2);
+        // In file order, each centered on its region and at most 5 lines long
+        assert_eq!((snippets[0].line_start, snippets[0].line_end), (103, 107));
+        assert_eq!((snippets[1].line_start, snippets[1].line_end), (128, 132));
+        for snippet in &snippets {
+            assert_eq!(snippet.code.lines().count(), 5);
+            assert!(snippet.code.contains("refresh_token"));
+        }
+
+        // Only the best region fits in one window; none without a matching line
+        let one = SnippetWindows { count: 1, lines: 5 }.select(&code, 100, &terms);
+        assert_eq!(one, snippets[..1]);
+        assert!(windows
+            .select(&code, 100, &["unrelated".to_string()])
+            .is_empty());
+    }
 }
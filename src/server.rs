@@ -1,14 +1,29 @@
-use crate::embedding::Embedder;
+use crate::context::ContextStats;
+use crate::embedding::{Embedder, ModelCacheOptions};
+use crate::index_meta::{IndexMeta, IndexVersionCheck};
+use crate::indexer::{FilePermissions, ItemKind};
 use crate::llm::client::OllamaClient;
 use crate::llm::expander::QueryExpander;
-use crate::search::{CodeSearcher, SearchResult};
+use crate::llm::CircuitBreakerPolicy;
+use crate::llm::ResultExplainer;
+use crate::reporting::format_prompt;
+use crate::search::{
+    validate_query, CodeSearcher, ConfidenceThresholds, MultiQueryRerank, QueryRouting,
+    RerankDisagreement, ScoreCalibration, SearchResult, SnippetWindows, Subsystem,
+};
+use crate::storage::{FileChunk, ShardKey};
+pub mod config_reload;
+pub mod cursors;
+pub mod msgpack;
 pub mod workspace_manager;
+use crate::server::cursors::{request_fingerprint, Cursor, CursorError};
 use crate::server::workspace_manager::WorkspaceManager;
 use anyhow::Result;
 use axum::{
-    extract::{Json, Path, State},
-    http::StatusCode,
-    response::IntoResponse,
+    error_handling::HandleErrorLayer,
+    extract::{Json, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     routing::{get, post},
     Router,
 };
@@ -18,9 +33,12 @@ use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
-use tower_http::cors::CorsLayer;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::load_shed::LoadShedLayer;
+use tower::{BoxError, ServiceBuilder};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tower_http::trace::TraceLayer;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 // Shared state holding the workspace manager
 #[derive(Clone)]
@@ -32,40 +50,253 @@ pub struct AppState {
 #[derive(Debug, Deserialize)]
 pub struct SearchRequest {
     pub query: String,
-    #[serde(default = "default_limit")]
-    pub limit: usize,
+    /// Defaults to the configured `default_limit`
+    pub limit: Option<usize>,
     pub ext: Option<String>,
     pub dir: Option<String>,
+    /// Only search chunks of this file
+    pub in_file: Option<String>,
+    /// Only return chunks spanning at least this many lines
+    pub min_lines: Option<usize>,
+    /// Only return chunks of files with all these permission bits (octal, e.g. "002")
+    pub perm: Option<String>,
+    /// Only return chunks of this kind: "function", "class", "statement" or "other"
+    pub kind: Option<String>,
     #[serde(default)]
     pub no_rerank: bool,
 
     pub max_tokens: Option<usize>,
     #[serde(default)]
     pub expand: bool,
+
+    /// Overrides the workspace's vector weight for this request only
+    pub vector_weight: Option<f32>,
+    /// Overrides the workspace's BM25 weight for this request only
+    pub bm25_weight: Option<f32>,
+    /// Overrides the workspace's RRF constant for this request only
+    pub rrf_k: Option<f64>,
+    /// Attach each result's embedding vector
+    #[serde(default)]
+    pub include_vectors: bool,
+    /// Also return the results formatted as an LLM prompt, within `max_tokens`
+    /// (or the configured `prompt_max_tokens`)
+    #[serde(default)]
+    pub prompt: bool,
+    /// Return the first page of the results with a `cursor` for the next one
+    #[serde(default)]
+    pub paginate: bool,
+    /// Cursor from the previous page; the query and options must be unchanged
+    pub cursor: Option<String>,
+    /// Have the LLM explain why each top result matches (needs `explain_results`)
+    #[serde(default)]
+    pub explain: bool,
 }
 
-fn default_limit() -> usize {
-    5
+impl SearchRequest {
+    /// Checks the per-request weight overrides: weights must be finite and not
+    /// negative, and `rrf_k` positive, or fused scores become NaN or flip sign.
+    fn validate_weights(&self) -> Result<(), String> {
+        for (name, weight) in [
+            ("vector_weight", self.vector_weight),
+            ("bm25_weight", self.bm25_weight),
+        ] {
+            if let Some(weight) = weight {
+                if !weight.is_finite() || weight < 0.0 {
+                    return Err(format!(
+                        "{} must be a finite, non-negative number, got {}",
+                        name, weight
+                    ));
+                }
+            }
+        }
+        if let Some(rrf_k) = self.rrf_k {
+            if !rrf_k.is_finite() || rrf_k <= 0.0 {
+                return Err(format!("rrf_k must be a positive number, got {}", rrf_k));
+            }
+        }
+        Ok(())
+    }
 }
 
 // Response payload
 #[derive(Serialize)]
 pub struct SearchResponse {
     pub results: Vec<SearchResult>,
+    /// Token budget usage, present when `max_tokens` was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextStats>,
+    /// The results as an LLM prompt, present when `prompt` was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Terms the query was expanded with, present when `expand` ran
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub expansion_terms: Vec<String>,
+    /// Subsystems (`bm25`, `reranker`, `expander`, `embedder`) that were unavailable,
+    /// present when the results were produced without them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub degraded: Vec<Subsystem>,
+    /// Cursor of the next page, present while a paginated search has more results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// Candidates that matched, an estimate bounded by the retrieval pool size
+    pub total_candidates: usize,
+    /// Whether more results exist beyond this response
+    pub has_more: bool,
+}
+
+/// Body encoding of a search response, negotiated from the request's `Accept` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Json,
+    MessagePack,
+}
+
+impl ResponseFormat {
+    /// MessagePack when `Accept` lists it (`application/msgpack` or
+    /// `application/x-msgpack`) at least as preferred as JSON; JSON otherwise.
+    pub fn negotiate(headers: &HeaderMap) -> Self {
+        let (mut msgpack, mut json) = (0.0_f32, 0.0_f32);
+        let accept = headers.get_all(header::ACCEPT).iter();
+        for range in accept
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+        {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = params
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            match media_type.as_str() {
+                "application/msgpack" | "application/x-msgpack" => msgpack = msgpack.max(quality),
+                "application/json" => json = json.max(quality),
+                _ => {}
+            }
+        }
+        if msgpack > 0.0 && msgpack >= json {
+            Self::MessagePack
+        } else {
+            Self::Json
+        }
+    }
+
+    fn respond<T: Serialize>(self, body: &T) -> Response {
+        match self {
+            Self::Json => (StatusCode::OK, Json(body)).into_response(),
+            Self::MessagePack => match msgpack::to_vec(body) {
+                Ok(bytes) => (
+                    StatusCode::OK,
+                    [(header::CONTENT_TYPE, msgpack::MSGPACK_CONTENT_TYPE)],
+                    bytes,
+                )
+                    .into_response(),
+                Err(e) => {
+                    error!("Failed to encode MessagePack response: {}", e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+                }
+            },
+        }
+    }
+}
+
+/// Query string of the file endpoints
+#[derive(Debug, Deserialize)]
+pub struct FileRequest {
+    /// Indexed path of the file, as returned in search results
+    pub path: String,
+}
+
+#[derive(Serialize)]
+pub struct FileResponse {
+    pub path: String,
+    /// The file rebuilt from its chunks; lines no chunk covers are left empty
+    pub content: String,
+    /// The stored chunks, ordered by line
+    pub chunks: Vec<FileChunk>,
 }
 
 pub struct ServerStartConfig {
     pub host: String,
     pub port: u16,
+    /// When set, listen on this Unix domain socket instead of `host:port` (Unix only)
+    pub socket_path: Option<String>,
+    /// Search requests served at once before returning 503 (unlimited when `None`)
+    pub max_concurrent_requests: Option<usize>,
+    /// Web origins allowed to make cross-origin requests (any origin when `None`)
+    pub cors_allowed_origins: Option<Vec<String>>,
+    /// Embedding and reranking calls run at once across all searches
+    pub embed_max_in_flight: usize,
     pub db_path: String,
+    /// Workspaces whose index lives outside `db_path`, by name
+    pub workspace_db_paths: std::collections::HashMap<String, String>,
     pub embedding_model: String,
     pub reranker_model: String,
     pub embedding_model_path: Option<String>,
     pub reranker_model_path: Option<String>,
+    pub model_cache_dir: Option<String>,
+    pub normalize_embeddings: bool,
+    pub offline: bool,
     pub device: String,
+    pub vector_weight: f32,
+    pub bm25_weight: f32,
+    pub rrf_k: f64,
+    pub rerank_blend: f32,
+    pub rerank_skip_extensions: Vec<String>,
+    pub rerank_batch_size: Option<usize>,
+    pub rerank_cascade_k: Option<usize>,
+    pub rerank_disagreement: Option<RerankDisagreement>,
+    /// Reranking against each expansion query too (unset = original query only)
+    pub multi_query_rerank: Option<MultiQueryRerank>,
+    pub query_routing: Option<QueryRouting>,
+    /// How `normalized_score` is computed
+    pub score_calibration: ScoreCalibration,
+    /// Thresholds of the `confidence` labels (unset = no labels)
+    pub confidence: Option<ConfidenceThresholds>,
+    /// Query-relevant windows added to each result (unset = none)
+    pub snippets: Option<SnippetWindows>,
+    /// Ranking boost from call-graph importance (0 = off)
+    pub importance_boost: f32,
+    /// Ranking boost from query words in the file path (0 = off)
+    pub path_boost: f32,
+    pub prompt_preamble: String,
+    pub prompt_max_tokens: usize,
+    pub normalize_identifiers: bool,
+    pub default_limit: usize,
+    /// Token budget of searches that give no `max_tokens` and do not paginate
+    pub default_max_tokens: Option<usize>,
+    /// Answer with keyword results when embedding a query fails
+    pub keyword_fallback: bool,
+    /// Answer searches in MessagePack when the `Accept` header asks for it
+    pub msgpack: bool,
+    /// Configuration whose files are watched for hot-reloadable settings (disabled
+    /// when `None`)
+    pub reload_config: Option<crate::config::AppConfig>,
+    /// Run an internal search on the default workspace before accepting requests
+    pub warmup: bool,
+    /// Workspaces loaded before accepting requests, besides "default"
+    pub preload_workspaces: Vec<String>,
+    /// Workspaces loaded at once by the preload
+    pub preload_concurrency: usize,
+    /// Seconds an unused search cursor stays valid
+    pub cursor_ttl_secs: u64,
+    /// Results captured for a paginated search, across all of its pages
+    pub cursor_max_results: usize,
+    /// Tables each workspace index is spread over (1 = unsharded)
+    pub shard_count: usize,
+    pub shard_key: ShardKey,
+    /// Settings each workspace's index is compared with when it loads (`None` = no check)
+    pub index_meta: Option<IndexMeta>,
+    pub index_version_check: IndexVersionCheck,
     pub llm_enabled: bool,
     pub llm_host: String,
     pub llm_model: String,
+    pub llm_timeout_ms: u64,
+    /// Pauses query expansion while the LLM keeps failing (unset = never)
+    pub llm_circuit_breaker: Option<CircuitBreakerPolicy>,
+    /// Allow requests to ask the LLM to explain their top results
+    pub explain_results: bool,
+    /// Results explained per request
+    pub explain_top_k: usize,
 }
 
 pub async fn start_server(config: ServerStartConfig) -> Result<()> {
@@ -74,46 +305,91 @@ pub async fn start_server(config: ServerStartConfig) -> Result<()> {
     // Extract connection info before moving config
     let host = config.host.clone();
     let port = config.port;
+    let socket_path = config.socket_path.clone();
 
     // 1. Init Embedder (with re-ranker) - Shared across workspaces
-    let embedder = Embedder::new(
+    let embedder = Embedder::new_with_cache(
+        false,
         config.embedding_model.clone(),
         config.reranker_model.clone(),
         config.embedding_model_path.clone(),
         config.reranker_model_path.clone(),
         config.device.clone(),
-    )?;
+        ModelCacheOptions {
+            cache_dir: config.model_cache_dir.clone(),
+            offline: config.offline,
+        },
+    )?
+    .with_normalization(config.normalize_embeddings);
     embedder.init_reranker()?; // Pre-load re-ranker
     let embedder = Arc::new(embedder);
 
     // 2. Init LLM Client (Optional) - Shared
     let expander = if config.llm_enabled {
         let client = OllamaClient::new(&config.llm_host, &config.llm_model);
-        Some(Arc::new(QueryExpander::new(
+        let expander = QueryExpander::new(
             Arc::new(client) as Arc<dyn crate::llm::client::LlmClient + Send + Sync>
-        )))
+        )
+        .with_probe_timeout(std::time::Duration::from_millis(config.llm_timeout_ms))
+        .with_circuit_breaker(config.llm_circuit_breaker);
+        // Probe up front so the first search does not pay for an unreachable host
+        if expander.check_health().await {
+            info!("LLM query expansion available at {}", config.llm_host);
+        }
+        Some(Arc::new(expander))
     } else {
         None
     };
 
+    let explainer = config.explain_results.then(|| {
+        let client = OllamaClient::new(&config.llm_host, &config.llm_model);
+        Arc::new(ResultExplainer::new(Arc::new(client)).with_top_k(config.explain_top_k))
+    });
+
     // 3. Init WorkspaceManager
-    let manager = WorkspaceManager::new(config, embedder, expander);
+    let reload_config = config.reload_config.clone();
+    let warmup = config.warmup;
+    let manager =
+        Arc::new(WorkspaceManager::new(config, embedder, expander).with_explainer(explainer));
+
+    if let Some(startup) = reload_config {
+        config_reload::spawn_config_reloader(startup, Some(manager.clone()))?;
+    }
 
     // Pre-load default workspace if exists
-    if let Err(e) = manager.get_searcher("default").await {
+    let default_loaded = manager.get_searcher("default").await;
+    manager.preload_workspaces().await;
+    if let Err(e) = default_loaded {
         info!("Note: Default workspace could not be pre-loaded: {}", e);
     } else {
         info!("Default workspace pre-loaded successfully.");
+        if warmup {
+            match manager.warm_up("default").await {
+                Ok(took) => info!("Search pipeline warmed up in {:?}", took),
+                Err(e) => warn!("Warm-up search failed: {}", e),
+            }
+        }
     }
 
     let state = AppState {
-        workspace_manager: Arc::new(manager),
+        workspace_manager: manager,
     };
 
     // 4. Build Router
     let router = create_router(state);
 
     // 5. Bind & Serve
+    if let Some(path) = socket_path {
+        #[cfg(unix)]
+        return serve_unix(router, &path).await;
+
+        #[cfg(not(unix))]
+        anyhow::bail!(
+            "Cannot listen on '{}': Unix domain sockets are only supported on Unix platforms",
+            path
+        );
+    }
+
     let addr = SocketAddr::new(host.parse()?, port);
     info!("Server listening on {}", addr);
 
@@ -125,24 +401,90 @@ pub async fn start_server(config: ServerStartConfig) -> Result<()> {
     Ok(())
 }
 
+/// Serves the router on a Unix domain socket, replacing a stale socket file if present.
+#[cfg(unix)]
+pub async fn serve_unix(router: Router, path: &str) -> Result<()> {
+    let socket_path = std::path::Path::new(path);
+    if socket_path.exists() {
+        std::fs::remove_file(socket_path)?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(socket_path)?;
+    info!("✓ HTTP Server started successfully on unix socket {}", path);
+
+    axum::serve(listener, router).await?;
+
+    Ok(())
+}
+
 /// Create router with routes and middleware
 pub fn create_router(state: AppState) -> Router {
+    let mut search_routes = Router::new()
+        .route("/search", post(search_handler_default))
+        .route("/v1/{workspace}/search", post(search_handler_workspace));
+
+    // Shed load instead of queuing; one semaphore is shared by all search routes
+    if let Some(max) = state.workspace_manager.max_concurrent_requests() {
+        search_routes = search_routes.layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|_: BoxError| async {
+                    (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        "Server is busy, try again later",
+                    )
+                }))
+                .layer(LoadShedLayer::new())
+                .layer(GlobalConcurrencyLimitLayer::new(max)),
+        );
+    }
+
     Router::new()
         .route("/health", get(health_check))
         .route("/status", get(status_handler))
         .route("/metrics", get(metrics_handler))
-        .route("/search", post(search_handler_default))
-        .route("/v1/{workspace}/search", post(search_handler_workspace))
+        .route("/file", get(file_handler_default))
+        .route("/v1/{workspace}/file", get(file_handler_workspace))
+        .merge(search_routes)
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &axum::http::Request<_>| {
                     tracing::info_span!("http_request", method = ?request.method(), uri = ?request.uri())
                 })
         )
-        .layer(CorsLayer::permissive())
+        .layer(cors_layer(state.workspace_manager.cors_allowed_origins()))
         .with_state(state)
 }
 
+/// CORS policy for the configured origins.
+///
+/// `None` or a `"*"` entry allows any origin. Otherwise only the listed origins get
+/// CORS headers, so browsers block responses to every other site; an empty list
+/// leaves the API to same-origin pages and non-browser clients.
+fn cors_layer(allowed_origins: Option<&[String]>) -> CorsLayer {
+    let Some(origins) = allowed_origins else {
+        return CorsLayer::permissive();
+    };
+    if origins.iter().any(|o| o == "*") {
+        return CorsLayer::permissive();
+    }
+
+    let origins: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| {
+            let value = HeaderValue::from_str(origin.trim_end_matches('/'));
+            if value.is_err() {
+                warn!("Ignoring invalid CORS origin '{}'", origin);
+            }
+            value.ok()
+        })
+        .collect();
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers(Any)
+}
+
 /// Health check handler
 async fn health_check() -> impl IntoResponse {
     StatusCode::OK
@@ -183,9 +525,11 @@ async fn status_handler(State(state): State<AppState>) -> impl IntoResponse {
 #[tracing::instrument(skip(state, payload))]
 async fn search_handler_default(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<SearchRequest>,
 ) -> impl IntoResponse {
-    process_search(state, "default".to_string(), payload).await
+    let format = response_format(&state, &headers);
+    process_search(state, "default".to_string(), payload, format).await
 }
 
 /// Handler for specific workspace (POST /v1/:workspace/search)
@@ -193,9 +537,94 @@ async fn search_handler_default(
 async fn search_handler_workspace(
     State(state): State<AppState>,
     Path(workspace): Path<String>,
+    headers: HeaderMap,
     Json(payload): Json<SearchRequest>,
 ) -> impl IntoResponse {
-    process_search(state, workspace, payload).await
+    let format = response_format(&state, &headers);
+    process_search(state, workspace, payload, format).await
+}
+
+/// The encoding a client asked for, or JSON when `server_msgpack` is off.
+fn response_format(state: &AppState, headers: &HeaderMap) -> ResponseFormat {
+    if state.workspace_manager.search_settings().msgpack {
+        ResponseFormat::negotiate(headers)
+    } else {
+        ResponseFormat::Json
+    }
+}
+
+/// Handler for default workspace (GET /file?path=...)
+async fn file_handler_default(
+    State(state): State<AppState>,
+    Query(request): Query<FileRequest>,
+) -> impl IntoResponse {
+    process_file(state, "default".to_string(), request.path).await
+}
+
+/// Handler for specific workspace (GET /v1/:workspace/file?path=...)
+async fn file_handler_workspace(
+    State(state): State<AppState>,
+    Path(workspace): Path<String>,
+    Query(request): Query<FileRequest>,
+) -> impl IntoResponse {
+    process_file(state, workspace, request.path).await
+}
+
+/// Returns the indexed content of one file, or 404 if it has no chunks.
+async fn process_file(state: AppState, workspace: String, path: String) -> impl IntoResponse {
+    let context = match state.workspace_manager.get_search_context(&workspace).await {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            let error_msg = format!("Failed to access workspace '{}': {}", workspace, e);
+            return (StatusCode::NOT_FOUND, error_msg).into_response();
+        }
+    };
+
+    match context
+        .storage
+        .get_file_chunks(&path, Some(&workspace))
+        .await
+    {
+        Ok(chunks) if chunks.is_empty() => (
+            StatusCode::NOT_FOUND,
+            format!(
+                "File '{}' is not indexed in workspace '{}'",
+                path, workspace
+            ),
+        )
+            .into_response(),
+        Ok(chunks) => (
+            StatusCode::OK,
+            Json(FileResponse {
+                content: reconstruct_file(&chunks),
+                path,
+                chunks,
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("File lookup error in workspace '{}': {}", workspace, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
+
+/// Rebuilds a file from its chunks so that line N of the result is line N of the file.
+///
+/// Lines covered by several chunks are taken from the first one.
+fn reconstruct_file(chunks: &[FileChunk]) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for chunk in chunks {
+        for (offset, line) in chunk.code.lines().enumerate() {
+            let index = (chunk.line_start + offset).saturating_sub(1);
+            if index < lines.len() {
+                continue;
+            }
+            lines.resize(index, "");
+            lines.push(line);
+        }
+    }
+    lines.join("\n")
 }
 
 /// Core search logic shared by handlers
@@ -203,6 +632,7 @@ async fn process_search(
     state: AppState,
     workspace: String,
     payload: SearchRequest,
+    format: ResponseFormat,
 ) -> impl IntoResponse {
     let start_time = Instant::now();
     let meter = global::meter("code-rag-system");
@@ -211,6 +641,79 @@ async fn process_search(
     let search_counter = meter.u64_counter("search_requests_total").init();
     search_counter.add(1, &[KeyValue::new("workspace", workspace.clone())]);
 
+    if let Err(e) = validate_query(&payload.query) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    if let Err(e) = payload.validate_weights() {
+        return (StatusCode::BAD_REQUEST, e).into_response();
+    }
+
+    let required_mode = match payload.perm.as_deref().map(FilePermissions::parse_mode) {
+        Some(Ok(mask)) => Some(mask),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        None => None,
+    };
+    let item_kind = match payload.kind.as_deref().map(ItemKind::parse) {
+        Some(Ok(kind)) => Some(kind),
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        None => None,
+    };
+
+    let explainer = state.workspace_manager.explainer();
+    if payload.explain && explainer.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Result explanations are disabled on this server",
+        )
+            .into_response();
+    }
+
+    let paginate = payload.paginate || payload.cursor.is_some();
+    if paginate && (payload.max_tokens.is_some() || payload.prompt) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Pagination cannot be combined with max_tokens or prompt",
+        )
+            .into_response();
+    }
+    let fingerprint = request_fingerprint(&workspace, &payload);
+    let page_size = payload
+        .limit
+        .unwrap_or_else(|| state.workspace_manager.search_settings().default_limit);
+
+    // Later pages come from the snapshot taken by the first one
+    if let Some(token) = &payload.cursor {
+        let cursors = state.workspace_manager.cursors();
+        let page = Cursor::decode(token)
+            .ok_or(CursorError::Malformed)
+            .and_then(|cursor| cursors.resume(cursor, fingerprint, page_size));
+        return match page {
+            Ok(page) => format.respond(&SearchResponse {
+                results: page.results,
+                context: None,
+                prompt: None,
+                expansion_terms: Vec::new(),
+                degraded: Vec::new(),
+                cursor: page.next.map(|c| c.encode()),
+                total_candidates: page.total,
+                has_more: page.next.is_some(),
+            }),
+            Err(CursorError::Malformed) => {
+                (StatusCode::BAD_REQUEST, "Invalid cursor").into_response()
+            }
+            Err(CursorError::Mismatch) => (
+                StatusCode::BAD_REQUEST,
+                "Cursor belongs to a different query or options",
+            )
+                .into_response(),
+            Err(CursorError::Expired) => (
+                StatusCode::GONE,
+                "Cursor expired; repeat the search without a cursor",
+            )
+                .into_response(),
+        };
+    }
+
     // 1. Get Search Context for Workspace (no lock!)
     let context = match state.workspace_manager.get_search_context(&workspace).await {
         Ok(ctx) => ctx,
@@ -221,26 +724,77 @@ async fn process_search(
     };
 
     // 2. Create per-request searcher from context (cheap - just Arc clones)
+    // Request-level weights override the current settings for this call only.
+    let settings = state.workspace_manager.search_settings();
     let searcher = CodeSearcher::new(
         Some(context.storage.clone()),
         Some(context.embedder.clone()),
         context.bm25.clone(),
         context.expander.clone(),
-        context.vector_weight,
-        context.bm25_weight,
-        context.rrf_k,
-    );
+        payload.vector_weight.unwrap_or(settings.vector_weight),
+        payload.bm25_weight.unwrap_or(settings.bm25_weight),
+        payload.rrf_k.unwrap_or(settings.rrf_k),
+    )
+    .with_rerank_blend(settings.rerank_blend)
+    .with_rerank_skip_extensions(settings.rerank_skip_extensions.clone())
+    .with_rerank_batch_size(settings.rerank_batch_size)
+    .with_rerank_cascade_k(settings.rerank_cascade_k)
+    .with_rerank_disagreement(settings.rerank_disagreement)
+    .with_multi_query_rerank(settings.multi_query_rerank)
+    .with_query_routing(settings.query_routing)
+    .with_score_calibration(settings.score_calibration)
+    .with_confidence(settings.confidence)
+    .with_snippets(settings.snippets)
+    .with_importance_boost(settings.importance_boost)
+    .with_path_boost(settings.path_boost)
+    .with_embed_limiter(Some(state.workspace_manager.embed_limiter()))
+    .with_include_vectors(payload.include_vectors)
+    .with_in_file(payload.in_file.clone())
+    .with_min_lines(payload.min_lines)
+    .with_required_mode(required_mode)
+    .with_item_kind(item_kind)
+    .with_keyword_fallback(settings.keyword_fallback);
+
+    // A prompt always fits a token budget
+    // Pages are cut by count, so paginated searches get no default budget
+    let max_tokens = if paginate {
+        None
+    } else {
+        payload.max_tokens.or(settings.default_max_tokens)
+    };
+    let max_tokens = if payload.prompt {
+        Some(max_tokens.unwrap_or(settings.prompt_max_tokens))
+    } else {
+        max_tokens
+    };
+
+    // The version is read first so the snapshot holds nothing older than it
+    let version = if paginate {
+        match context.storage.version().await {
+            Ok(v) => v,
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        }
+    } else {
+        0
+    };
+    let limit = if paginate {
+        state.workspace_manager.cursors().max_results()
+    } else {
+        page_size
+    };
 
     // 3. Execute Search (concurrent-safe, no Mutex needed)
-    let results = match searcher
-        .semantic_search(
+    let outcome = match searcher
+        .search_with_stats(
             &payload.query,
-            payload.limit,
+            limit,
             payload.ext,
             payload.dir,
             payload.no_rerank,
             Some(workspace.clone()),
-            payload.max_tokens,
+            max_tokens,
             payload.expand,
         )
         .await
@@ -260,5 +814,52 @@ async fn process_search(
         &[KeyValue::new("workspace", workspace.clone())],
     );
 
-    (StatusCode::OK, Json(SearchResponse { results })).into_response()
+    let prompt = if payload.prompt {
+        match format_prompt(&payload.query, &outcome.results, &settings.prompt_preamble) {
+            Ok(prompt) => Some(prompt),
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response();
+            }
+        }
+    } else {
+        None
+    };
+
+    let (mut results, cursor, total_candidates, has_more) = if paginate {
+        let page = state.workspace_manager.cursors().start(
+            fingerprint,
+            version,
+            outcome.results,
+            page_size,
+        );
+        let has_more = page.next.is_some();
+        (
+            page.results,
+            page.next.map(|c| c.encode()),
+            page.total,
+            has_more,
+        )
+    } else {
+        (
+            outcome.results,
+            None,
+            outcome.total_candidates,
+            outcome.has_more,
+        )
+    };
+
+    if let Some(explainer) = explainer.filter(|_| payload.explain) {
+        explainer.explain(&payload.query, &mut results).await;
+    }
+
+    format.respond(&SearchResponse {
+        results,
+        context: outcome.context,
+        prompt,
+        expansion_terms: outcome.expansion_terms,
+        degraded: outcome.unavailable,
+        cursor,
+        total_candidates,
+        has_more,
+    })
 }
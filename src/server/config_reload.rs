@@ -0,0 +1,133 @@
+use crate::config::AppConfig;
+use crate::server::workspace_manager::{SearchSettings, WorkspaceManager};
+use crate::watcher;
+use anyhow::Result;
+use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Builds the hot-reloadable settings from a loaded config.
+pub fn settings_from_config(config: &AppConfig) -> SearchSettings {
+    SearchSettings {
+        vector_weight: config.vector_weight,
+        bm25_weight: config.bm25_weight,
+        rrf_k: config.rrf_k as f64,
+        rerank_blend: config.rerank_blend,
+        rerank_skip_extensions: config.rerank_skip_extensions.clone(),
+        rerank_batch_size: config.rerank_batch_size,
+        rerank_cascade_k: config.rerank_cascade_k,
+        rerank_disagreement: config.rerank_disagreement(),
+        multi_query_rerank: config.multi_query_rerank(),
+        query_routing: config.query_routing(),
+        score_calibration: config.score_calibration,
+        confidence: config.confidence(),
+        snippets: config.snippets(),
+        importance_boost: config.importance_boost,
+        path_boost: config.path_boost,
+        prompt_preamble: config.prompt_preamble.clone(),
+        prompt_max_tokens: config.prompt_max_tokens,
+        default_limit: config.default_limit,
+        default_max_tokens: config.default_max_tokens,
+        keyword_fallback: config.keyword_fallback,
+        msgpack: config.server_msgpack,
+    }
+}
+
+/// Applies the reloadable subset of `new` to the manager, if any, and the exclusions
+/// to the running watchers.
+///
+/// Settings that need a restart (models, paths, bind address, ...) are only reported.
+pub fn apply_config(manager: Option<&WorkspaceManager>, current: &AppConfig, new: &AppConfig) {
+    let restart_only = current.restart_required_changes(new);
+    if !restart_only.is_empty() {
+        warn!(
+            "Config changes to [{}] require a restart and were not applied",
+            restart_only.join(", ")
+        );
+    }
+
+    if new.exclusions != watcher::exclusions() {
+        info!("Reloaded exclusions: {:?}", new.exclusions);
+        watcher::set_exclusions(new.exclusions.clone());
+    }
+
+    let Some(manager) = manager else {
+        return;
+    };
+    let settings = settings_from_config(new);
+    if settings != manager.search_settings() {
+        info!("Reloaded search settings: {:?}", settings);
+        manager.update_search_settings(settings);
+    }
+}
+
+/// Watches the files `startup` was loaded from and applies reloadable settings to
+/// `manager` (when a server runs) and the watchers whenever one of them changes.
+///
+/// Without `--config` that is both the user-level file and `code-rag.toml` in the
+/// current directory, and a reload reads both again, as at startup. Parent directories
+/// are watched so editors that save by replacing the file are handled; a directory that
+/// does not exist yet is skipped.
+pub fn spawn_config_reloader(
+    startup: AppConfig,
+    manager: Option<Arc<WorkspaceManager>>,
+) -> Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut debouncer = new_debouncer(Duration::from_millis(500), tx)?;
+
+    let files = startup.config_files();
+    for file in &files {
+        let watch_dir = file
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        if !watch_dir.is_dir() {
+            continue;
+        }
+        debouncer
+            .watcher()
+            .watch(watch_dir, RecursiveMode::NonRecursive)?;
+        info!("Watching {} for configuration changes", file.display());
+    }
+
+    let file_names: Vec<OsString> = files
+        .iter()
+        .filter_map(|f| f.file_name().map(|n| n.to_os_string()))
+        .collect();
+
+    tokio::spawn(async move {
+        // Keep the debouncer alive for as long as the task runs
+        let _debouncer = debouncer;
+
+        loop {
+            let mut changed = false;
+            while let Ok(result) = rx.try_recv() {
+                match result {
+                    Ok(events) => {
+                        changed |= events.iter().any(|e| {
+                            e.path
+                                .file_name()
+                                .is_some_and(|n| file_names.iter().any(|f| f == n))
+                        });
+                    }
+                    Err(e) => error!("Config watch error: {:?}", e),
+                }
+            }
+
+            if changed {
+                // Restart-only settings are always compared against the startup config
+                match AppConfig::from_path(startup.config_arg.clone()) {
+                    Ok(new_config) => apply_config(manager.as_deref(), &startup, &new_config),
+                    Err(e) => warn!("Ignoring invalid config change: {}", e),
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+
+    Ok(())
+}
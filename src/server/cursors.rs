@@ -0,0 +1,252 @@
+use crate::search::SearchResult;
+use crate::server::SearchRequest;
+use dashmap::DashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Position in a paginated search, handed to clients as an opaque token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    snapshot: u64,
+    /// Index of the first result of the next page
+    pub offset: usize,
+    /// Table version the results were captured at
+    pub version: u64,
+    fingerprint: u64,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!(
+            "{:016x}{:016x}{:016x}{:016x}",
+            self.snapshot, self.offset as u64, self.version, self.fingerprint
+        )
+    }
+
+    /// Parses a token from [`Cursor::encode`]; `None` when it is malformed.
+    pub fn decode(token: &str) -> Option<Self> {
+        if token.len() != 64 || !token.is_ascii() {
+            return None;
+        }
+        let field = |i: usize| u64::from_str_radix(&token[i * 16..(i + 1) * 16], 16).ok();
+        Some(Self {
+            snapshot: field(0)?,
+            offset: usize::try_from(field(1)?).ok()?,
+            version: field(2)?,
+            fingerprint: field(3)?,
+        })
+    }
+}
+
+/// Why a cursor could not be resumed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CursorError {
+    Malformed,
+    /// The cursor belongs to a different query or search options
+    Mismatch,
+    /// The snapshot timed out or the server restarted
+    Expired,
+}
+
+/// One page of a snapshot.
+pub struct Page {
+    pub results: Vec<SearchResult>,
+    /// Cursor of the following page, if any
+    pub next: Option<Cursor>,
+    /// Results in the whole snapshot
+    pub total: usize,
+}
+
+/// The ranked results of one search, captured so every page comes from the same ordering.
+struct Snapshot {
+    fingerprint: u64,
+    version: u64,
+    results: Vec<SearchResult>,
+    last_used: Instant,
+}
+
+/// Result snapshots of paginated searches, dropped after `ttl` without use.
+///
+/// Pages are served from the snapshot rather than by searching again, so results
+/// neither repeat nor go missing when the index or the search settings change
+/// between pages.
+pub struct CursorStore {
+    snapshots: DashMap<u64, Snapshot>,
+    next_id: AtomicU64,
+    ttl: Duration,
+    max_results: usize,
+}
+
+impl CursorStore {
+    pub fn new(ttl: Duration, max_results: usize) -> Self {
+        // Start ids from the clock so tokens from before a restart are not reused
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self {
+            snapshots: DashMap::new(),
+            next_id: AtomicU64::new(seed),
+            ttl,
+            max_results: max_results.max(1),
+        }
+    }
+
+    /// Results to fetch for a paginated search; later pages can go no further.
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+
+    /// Stores `results` and returns the first page with the cursor of the next one.
+    pub fn start(
+        &self,
+        fingerprint: u64,
+        version: u64,
+        results: Vec<SearchResult>,
+        page_size: usize,
+    ) -> Page {
+        self.evict_expired();
+        let snapshot = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cursor = Cursor {
+            snapshot,
+            offset: 0,
+            version,
+            fingerprint,
+        };
+        let mut entry = Snapshot {
+            fingerprint,
+            version,
+            results,
+            last_used: Instant::now(),
+        };
+        let page = next_page(&mut entry, cursor, page_size);
+        if page.next.is_some() {
+            self.snapshots.insert(snapshot, entry);
+        }
+        page
+    }
+
+    /// Returns the page at `cursor` and the cursor of the page after it, if any.
+    pub fn resume(
+        &self,
+        cursor: Cursor,
+        fingerprint: u64,
+        page_size: usize,
+    ) -> Result<Page, CursorError> {
+        if cursor.fingerprint != fingerprint {
+            return Err(CursorError::Mismatch);
+        }
+        let mut entry = self
+            .snapshots
+            .get_mut(&cursor.snapshot)
+            .filter(|e| e.last_used.elapsed() < self.ttl)
+            .ok_or(CursorError::Expired)?;
+        if entry.fingerprint != fingerprint || entry.version != cursor.version {
+            return Err(CursorError::Malformed);
+        }
+        let page = next_page(&mut entry, cursor, page_size);
+        drop(entry);
+        if page.next.is_none() {
+            self.snapshots.remove(&cursor.snapshot);
+        }
+        Ok(page)
+    }
+
+    fn evict_expired(&self) {
+        self.snapshots
+            .retain(|_, snapshot| snapshot.last_used.elapsed() < self.ttl);
+    }
+}
+
+fn next_page(snapshot: &mut Snapshot, cursor: Cursor, page_size: usize) -> Page {
+    snapshot.last_used = Instant::now();
+    let total = snapshot.results.len();
+    let start = cursor.offset.min(total);
+    let end = start.saturating_add(page_size).min(total);
+    Page {
+        results: snapshot.results[start..end].to_vec(),
+        next: (end < total).then_some(Cursor {
+            offset: end,
+            ..cursor
+        }),
+        total,
+    }
+}
+
+/// Identifies a request's query and options, so a cursor is only resumed by the same search.
+///
+/// `limit` is left out: the page size may change from page to page.
+pub fn request_fingerprint(workspace: &str, request: &SearchRequest) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    workspace.hash(&mut hasher);
+    request.query.hash(&mut hasher);
+    request.ext.hash(&mut hasher);
+    request.dir.hash(&mut hasher);
+    request.in_file.hash(&mut hasher);
+    request.min_lines.hash(&mut hasher);
+    request.perm.hash(&mut hasher);
+    request.kind.hash(&mut hasher);
+    request.no_rerank.hash(&mut hasher);
+    request.max_tokens.hash(&mut hasher);
+    request.expand.hash(&mut hasher);
+    request.vector_weight.map(f32::to_bits).hash(&mut hasher);
+    request.bm25_weight.map(f32::to_bits).hash(&mut hasher);
+    request.rrf_k.map(f64::to_bits).hash(&mut hasher);
+    request.include_vectors.hash(&mut hasher);
+    request.prompt.hash(&mut hasher);
+    request.explain.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_token_round_trip() {
+        let cursor = Cursor {
+            snapshot: u64::MAX,
+            offset: 42,
+            version: 7,
+            fingerprint: 0xdead_beef,
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("not-a-cursor"), None);
+        assert_eq!(Cursor::decode(&"g".repeat(64)), None);
+    }
+
+    #[test]
+    fn test_fingerprint_covers_result_options() {
+        let request = |extra: serde_json::Value| {
+            let mut body = serde_json::json!({ "query": "parse config", "limit": 5 });
+            body.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            serde_json::from_value::<SearchRequest>(body).unwrap()
+        };
+        let base = request_fingerprint("default", &request(serde_json::json!({})));
+        // The page size may change between pages
+        assert_eq!(
+            request_fingerprint("default", &request(serde_json::json!({ "limit": 20 }))),
+            base
+        );
+        for extra in [
+            serde_json::json!({ "kind": "function" }),
+            serde_json::json!({ "explain": true }),
+            serde_json::json!({ "rrf_k": 10.0 }),
+        ] {
+            assert_ne!(
+                request_fingerprint("default", &request(extra.clone())),
+                base,
+                "{}",
+                extra
+            );
+        }
+        assert_ne!(
+            request_fingerprint("other", &request(serde_json::json!({}))),
+            base
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! MessagePack encoding of API responses, for clients that prefer a compact binary body
+//! to JSON (`Accept: application/msgpack`).
+//!
+//! Structs are written as maps keyed by field name, so a response decodes to the same
+//! document as its JSON form. `f32` fields (scores, vectors) are written as 32-bit floats.
+
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Media type of MessagePack bodies.
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Encodes `value` as MessagePack.
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec_named(value)?)
+}
+
+/// Decodes a MessagePack document.
+pub fn from_slice<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    Ok(rmp_serde::from_slice(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Example {
+        compact: bool,
+        schema: u32,
+    }
+
+    #[test]
+    fn test_matches_reference_encoding() {
+        // The example on msgpack.org: {"compact":true,"schema":0} in 18 bytes
+        let reference = [
+            0x82, 0xa7, b'c', b'o', b'm', b'p', b'a', b'c', b't', 0xc3, 0xa6, b's', b'c', b'h',
+            b'e', b'm', b'a', 0x00,
+        ];
+        let example = Example {
+            compact: true,
+            schema: 0,
+        };
+        assert_eq!(to_vec(&example).unwrap(), reference);
+        assert_eq!(from_slice::<Example>(&reference).unwrap(), example);
+        assert_eq!(
+            from_slice::<serde_json::Value>(&reference).unwrap(),
+            serde_json::json!({ "compact": true, "schema": 0 })
+        );
+
+        // float 32 is 0xca and the big-endian IEEE 754 bits
+        assert_eq!(to_vec(&0.5_f32).unwrap(), [0xca, 0x3f, 0x00, 0x00, 0x00]);
+        assert!(from_slice::<Example>(&[0x82, 0xa7]).is_err());
+    }
+}
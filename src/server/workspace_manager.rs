@@ -1,13 +1,20 @@
 use crate::bm25::BM25Index;
-use crate::embedding::Embedder;
+use crate::embedding::{EmbedLimiter, Embedder};
+use crate::index_meta::verify_index;
 use crate::llm::expander::QueryExpander;
-use crate::search::CodeSearcher;
+use crate::llm::ResultExplainer;
+use crate::search::{
+    CodeSearcher, ConfidenceThresholds, MultiQueryRerank, QueryRouting, RerankDisagreement,
+    ScoreCalibration, SnippetWindows,
+};
+use crate::server::cursors::CursorStore;
 use crate::server::ServerStartConfig;
 use crate::storage::Storage;
 use anyhow::{anyhow, Result};
-use dashmap::DashMap;
-use std::path::PathBuf;
-use std::sync::Arc;
+use dashmap::{DashMap, DashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tracing::{info, warn};
 
 /// Thread-safe search context for a single workspace.
@@ -18,17 +25,75 @@ pub struct WorkspaceSearchContext {
     pub embedder: Arc<Embedder>,
     pub bm25: Option<Arc<BM25Index>>,
     pub expander: Option<Arc<QueryExpander>>,
+}
+
+/// Search tuning shared by all workspaces that can be changed while the server runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchSettings {
     pub vector_weight: f32,
     pub bm25_weight: f32,
     pub rrf_k: f64,
+    pub rerank_blend: f32,
+    pub rerank_skip_extensions: Vec<String>,
+    pub rerank_batch_size: Option<usize>,
+    pub rerank_cascade_k: Option<usize>,
+    pub rerank_disagreement: Option<RerankDisagreement>,
+    pub multi_query_rerank: Option<MultiQueryRerank>,
+    pub query_routing: Option<QueryRouting>,
+    pub score_calibration: ScoreCalibration,
+    pub confidence: Option<ConfidenceThresholds>,
+    pub snippets: Option<SnippetWindows>,
+    pub importance_boost: f32,
+    pub path_boost: f32,
+    pub prompt_preamble: String,
+    pub prompt_max_tokens: usize,
+    pub default_limit: usize,
+    pub default_max_tokens: Option<usize>,
+    pub keyword_fallback: bool,
+    pub msgpack: bool,
+}
+
+impl From<&ServerStartConfig> for SearchSettings {
+    fn from(config: &ServerStartConfig) -> Self {
+        Self {
+            vector_weight: config.vector_weight,
+            bm25_weight: config.bm25_weight,
+            rrf_k: config.rrf_k,
+            rerank_blend: config.rerank_blend,
+            rerank_skip_extensions: config.rerank_skip_extensions.clone(),
+            rerank_batch_size: config.rerank_batch_size,
+            rerank_cascade_k: config.rerank_cascade_k,
+            rerank_disagreement: config.rerank_disagreement,
+            multi_query_rerank: config.multi_query_rerank,
+            query_routing: config.query_routing,
+            score_calibration: config.score_calibration,
+            confidence: config.confidence,
+            snippets: config.snippets,
+            importance_boost: config.importance_boost,
+            path_boost: config.path_boost,
+            prompt_preamble: config.prompt_preamble.clone(),
+            prompt_max_tokens: config.prompt_max_tokens,
+            default_limit: config.default_limit,
+            default_max_tokens: config.default_max_tokens,
+            keyword_fallback: config.keyword_fallback,
+            msgpack: config.msgpack,
+        }
+    }
 }
 
 pub struct WorkspaceManager {
     workspaces: DashMap<String, Arc<WorkspaceSearchContext>>,
     loading_locks: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
     config: Arc<ServerStartConfig>,
+    settings: RwLock<SearchSettings>,
     embedder: Arc<Embedder>,
     expander: Option<Arc<QueryExpander>>,
+    explainer: Option<Arc<ResultExplainer>>,
+    /// Shared by every searcher so the cap holds across workspaces
+    embed_limiter: Arc<EmbedLimiter>,
+    cursors: CursorStore,
+    /// Workspaces whose search pipeline `warm_up` has run
+    warmed: DashSet<String>,
 }
 
 impl WorkspaceManager {
@@ -40,19 +105,71 @@ impl WorkspaceManager {
         Self {
             workspaces: DashMap::new(),
             loading_locks: DashMap::new(),
+            settings: RwLock::new(SearchSettings::from(&config)),
+            embed_limiter: Arc::new(EmbedLimiter::new(config.embed_max_in_flight)),
+            cursors: CursorStore::new(
+                Duration::from_secs(config.cursor_ttl_secs),
+                config.cursor_max_results,
+            ),
             config: Arc::new(config),
             embedder,
             expander,
+            explainer: None,
+            warmed: DashSet::new(),
         }
     }
 
+    /// Sets the explainer used by searches that ask for explanations.
+    pub fn with_explainer(mut self, explainer: Option<Arc<ResultExplainer>>) -> Self {
+        self.explainer = explainer;
+        self
+    }
+
+    /// The explainer, when result explanations are enabled.
+    pub fn explainer(&self) -> Option<Arc<ResultExplainer>> {
+        self.explainer.clone()
+    }
+
+    /// The limiter every search holds while embedding or reranking.
+    pub fn embed_limiter(&self) -> Arc<EmbedLimiter> {
+        self.embed_limiter.clone()
+    }
+
+    /// Result snapshots of paginated searches.
+    pub fn cursors(&self) -> &CursorStore {
+        &self.cursors
+    }
+
+    /// Returns the current search settings.
+    pub fn search_settings(&self) -> SearchSettings {
+        self.settings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Replaces the search settings; subsequent requests use the new values.
+    pub fn update_search_settings(&self, settings: SearchSettings) {
+        *self.settings.write().unwrap_or_else(|e| e.into_inner()) = settings;
+    }
+
+    /// Configured limit on concurrent search requests, if any.
+    pub fn max_concurrent_requests(&self) -> Option<usize> {
+        self.config.max_concurrent_requests.filter(|&n| n > 0)
+    }
+
+    /// Configured CORS origins; `None` allows any origin.
+    pub fn cors_allowed_origins(&self) -> Option<&[String]> {
+        self.config.cors_allowed_origins.as_deref()
+    }
+
     /// Retrieves search context for the given workspace ID.
     ///
     /// Returns Arc<WorkspaceSearchContext> which can be shared across
     /// multiple concurrent requests without blocking.
     ///
-    /// If the context is not in the cache, it attempts to load from:
-    /// `config.db_path / workspace_id`.
+    /// If the context is not in the cache, it attempts to load from the workspace's
+    /// entry in `config.workspace_db_paths`, else `config.db_path / workspace_id`.
     ///
     /// The "default" workspace works on `config.db_path` directly to maintain backward compatibility.
     pub async fn get_search_context(
@@ -102,20 +219,91 @@ impl WorkspaceManager {
     ) -> Result<Arc<tokio::sync::Mutex<CodeSearcher>>> {
         // For backward compatibility with existing code
         let context = self.get_search_context(workspace_id).await?;
+        let settings = self.search_settings();
 
         let searcher = CodeSearcher::new(
             Some(context.storage.clone()),
             Some(context.embedder.clone()),
             context.bm25.clone(),
             context.expander.clone(),
-            context.vector_weight,
-            context.bm25_weight,
-            context.rrf_k,
-        );
+            settings.vector_weight,
+            settings.bm25_weight,
+            settings.rrf_k,
+        )
+        .with_rerank_blend(settings.rerank_blend)
+        .with_rerank_skip_extensions(settings.rerank_skip_extensions)
+        .with_rerank_batch_size(settings.rerank_batch_size)
+        .with_rerank_cascade_k(settings.rerank_cascade_k)
+        .with_rerank_disagreement(settings.rerank_disagreement)
+        .with_multi_query_rerank(settings.multi_query_rerank)
+        .with_query_routing(settings.query_routing)
+        .with_score_calibration(settings.score_calibration)
+        .with_confidence(settings.confidence)
+        .with_snippets(settings.snippets)
+        .with_importance_boost(settings.importance_boost)
+        .with_path_boost(settings.path_boost)
+        .with_embed_limiter(Some(self.embed_limiter()))
+        .with_keyword_fallback(settings.keyword_fallback);
 
         Ok(Arc::new(tokio::sync::Mutex::new(searcher)))
     }
 
+    /// Runs one internal search on `workspace_id` so the first client search does not pay
+    /// for loading the workspace, the reranker and first inference. Returns how long it took.
+    pub async fn warm_up(&self, workspace_id: &str) -> Result<std::time::Duration> {
+        let started = std::time::Instant::now();
+        let searcher = self.get_searcher(workspace_id).await?;
+        searcher
+            .lock()
+            .await
+            .search_with_stats(
+                "warm up search pipeline",
+                2,
+                None,
+                None,
+                false,
+                Some(workspace_id.to_string()),
+                None,
+                false,
+            )
+            .await?;
+        self.warmed.insert(workspace_id.to_string());
+        Ok(started.elapsed())
+    }
+
+    /// Loads the configured `preload_workspaces`, at most `preload_concurrency` at once,
+    /// so their first requests do not wait for the indexes to open.
+    ///
+    /// Workspaces that fail to load are logged and left to load on demand. Returns how
+    /// many were loaded.
+    pub async fn preload_workspaces(&self) -> usize {
+        let ids = &self.config.preload_workspaces;
+        if ids.is_empty() {
+            return 0;
+        }
+        let started = std::time::Instant::now();
+        let permits = tokio::sync::Semaphore::new(self.config.preload_concurrency.max(1));
+        let mut loaded = 0;
+        let permits = &permits;
+        let loads = ids.iter().map(|id| async move {
+            let _permit = permits.acquire().await;
+            (id, self.get_search_context(id).await)
+        });
+        for (id, result) in futures_util::future::join_all(loads).await {
+            match result {
+                Ok(_) => loaded += 1,
+                Err(e) => warn!("Workspace '{}' could not be pre-loaded: {}", id, e),
+            }
+        }
+        info!(
+            "Pre-loaded {} of {} workspaces in {:?}",
+            loaded,
+            ids.len(),
+            started.elapsed()
+        );
+        loaded
+    }
+
     pub fn get_stats(&self) -> WorkspaceStats {
         WorkspaceStats {
             loaded_workspaces: self.workspaces.len(),
@@ -125,13 +313,18 @@ impl WorkspaceManager {
                 .map(|entry| entry.key().clone())
                 .collect(),
             active_locks: self.loading_locks.len(),
+            warmed_ids: self.warmed.iter().map(|id| id.clone()).collect(),
+            llm_healthy: self.expander.as_ref().and_then(|e| e.health()),
         }
     }
 
     async fn load_search_context(&self, workspace_id: &str) -> Result<WorkspaceSearchContext> {
         // Logical Isolation: All workspaces share the same physical DB path.
         // Isolation is handled by "workspace" column in LanceDB and field in BM25.
-        let db_path = PathBuf::from(&self.config.db_path);
+        let db_path = match self.config.workspace_db_paths.get(workspace_id) {
+            Some(custom) => PathBuf::from(custom),
+            None => PathBuf::from(&self.config.db_path),
+        };
 
         if !db_path.exists() {
             return Err(anyhow!("Database root not found at {:?}", db_path));
@@ -142,12 +335,23 @@ impl WorkspaceManager {
             workspace_id, db_path
         );
 
-        let storage_path = if workspace_id == "default" {
+        let storage_path = if workspace_id == "default"
+            || self.config.workspace_db_paths.contains_key(workspace_id)
+        {
             db_path.to_string_lossy().to_string()
         } else {
             db_path.join(workspace_id).to_string_lossy().to_string()
         };
-        let storage = Storage::new(&storage_path, "code_chunks").await?;
+        if let Some(meta) = &self.config.index_meta {
+            verify_index(
+                Path::new(&storage_path),
+                meta,
+                self.config.index_version_check,
+            )?;
+        }
+        let storage = Storage::new(&storage_path, "code_chunks")
+            .await?
+            .with_shards(self.config.shard_count, self.config.shard_key);
 
         // Ensure valid index (and check if we have data for this workspace?)
         if storage.get_indexed_metadata(workspace_id).await.is_err() {
@@ -159,7 +363,9 @@ impl WorkspaceManager {
 
         // Resilient BM25 Loading
         let bm25_index = match BM25Index::new(&storage_path, true, "log") {
-            Ok(idx) => Some(Arc::new(idx)),
+            Ok(idx) => Some(Arc::new(
+                idx.with_normalized_identifiers(self.config.normalize_identifiers),
+            )),
             Err(e) => {
                 warn!(
                     "BM25 index load failed for '{}': {}. Proceeding with Vector-only search.",
@@ -174,9 +380,6 @@ impl WorkspaceManager {
             embedder: self.embedder.clone(),
             bm25: bm25_index,
             expander: self.expander.clone(),
-            vector_weight: 1.0,
-            bm25_weight: 1.0,
-            rrf_k: 60.0,
         })
     }
 }
@@ -186,4 +389,10 @@ pub struct WorkspaceStats {
     pub loaded_workspaces: usize,
     pub active_ids: Vec<String>,
     pub active_locks: usize,
+    /// Workspaces warmed up by `server_warmup`
+    #[serde(default)]
+    pub warmed_ids: Vec<String>,
+    /// Result of the LLM reachability probe; `None` when expansion is disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub llm_healthy: Option<bool>,
 }
@@ -1,8 +1,12 @@
+use crate::indexer::{
+    defined_symbol, CallGraphNode, ChunkKind, ChunkMetadata, ChunkPart, FilePermissions, ItemKind,
+    NoteAnchor,
+};
 use anyhow::{anyhow, Result};
 use arrow_array::builder::{ListBuilder, StringBuilder};
 use arrow_array::{
-    Array, FixedSizeListArray, Float32Array, Int32Array, Int64Array, RecordBatch,
-    RecordBatchIterator, StringArray,
+    Array, BooleanArray, FixedSizeListArray, Float32Array, Int32Array, Int64Array, ListArray,
+    RecordBatch, RecordBatchIterator, StringArray, UInt32Array,
 };
 use arrow_schema::{DataType, Field, Schema};
 use futures_util::stream::TryStreamExt;
@@ -11,9 +15,129 @@ use lancedb::connection::Connection;
 use lancedb::index::scalar::BTreeIndexBuilder;
 use lancedb::query::{ExecutableQuery, QueryBase};
 use lancedb::table::Table;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::OnceCell;
 
+/// One stored chunk of a file, as returned by `Storage::get_file_chunks`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct FileChunk {
+    pub line_start: usize,
+    pub line_end: usize,
+    pub code: String,
+}
+
+/// Reads the embedding in row `i` of a batch that includes the `vector` column.
+pub fn row_vector(batch: &RecordBatch, i: usize) -> Option<Vec<f32>> {
+    let vectors: &FixedSizeListArray = batch.column_by_name("vector")?.as_any().downcast_ref()?;
+    if vectors.is_null(i) {
+        return None;
+    }
+    let values = vectors.value(i);
+    let floats: &Float32Array = values.as_any().downcast_ref()?;
+    Some(floats.values().to_vec())
+}
+
+/// Reads the kind of row `i`; rows without the `kind` columns are code.
+pub fn row_kind(batch: &RecordBatch, i: usize) -> ChunkKind {
+    let text = |name: &str| -> Option<&StringArray> {
+        batch.column_by_name(name)?.as_any().downcast_ref()
+    };
+    let line = |name: &str| -> Option<usize> {
+        let column: &Int32Array = batch.column_by_name(name)?.as_any().downcast_ref()?;
+        (!column.is_null(i)).then(|| column.value(i) as usize)
+    };
+    match text("kind") {
+        Some(kind) if !kind.is_null(i) && kind.value(i) == "note" => {
+            let anchor = text("anchor_filename")
+                .filter(|f| !f.is_null(i))
+                .zip(line("anchor_line_start"))
+                .zip(line("anchor_line_end"))
+                .map(|((filename, line_start), line_end)| NoteAnchor {
+                    filename: filename.value(i).to_string(),
+                    line_start,
+                    line_end,
+                });
+            match anchor {
+                Some(anchor) => ChunkKind::Note { anchor },
+                None => ChunkKind::Code,
+            }
+        }
+        _ => ChunkKind::Code,
+    }
+}
+
+/// The source file permissions stored for row `i`, if recorded.
+pub fn row_permissions(batch: &RecordBatch, i: usize) -> Option<FilePermissions> {
+    let column = |name: &str| -> Option<u32> {
+        let column: &UInt32Array = batch.column_by_name(name)?.as_any().downcast_ref()?;
+        (!column.is_null(i)).then(|| column.value(i))
+    };
+    Some(FilePermissions {
+        mode: column("file_mode")?,
+        owner: column("file_owner")?,
+    })
+}
+
+/// The symbol name stored for row `i`, if the chunk defines a named item.
+pub fn row_symbol(batch: &RecordBatch, i: usize) -> Option<String> {
+    let column: &StringArray = batch.column_by_name("symbol")?.as_any().downcast_ref()?;
+    (!column.is_null(i)).then(|| column.value(i).to_string())
+}
+
+/// The item kind stored for row `i`; `None` in tables without the column.
+pub fn row_item_kind(batch: &RecordBatch, i: usize) -> Option<ItemKind> {
+    let column: &StringArray = batch.column_by_name("item_kind")?.as_any().downcast_ref()?;
+    (!column.is_null(i))
+        .then(|| ItemKind::parse(column.value(i)).ok())
+        .flatten()
+}
+
+/// The enclosing scope stored for row `i`; empty for rows without one.
+pub fn row_scope(batch: &RecordBatch, i: usize) -> Vec<String> {
+    let Some(column) = batch
+        .column_by_name("scope")
+        .and_then(|c| c.as_any().downcast_ref::<ListArray>())
+        .filter(|c| !c.is_null(i))
+    else {
+        return Vec::new();
+    };
+    let names = column.value(i);
+    names
+        .as_any()
+        .downcast_ref::<StringArray>()
+        .map(|names| names.iter().flatten().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// A point in the table's history to read from (`search --as-of`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOf {
+    /// An exact LanceDB table version
+    Version(u64),
+    /// The latest version written at or before this Unix timestamp (seconds)
+    Timestamp(i64),
+}
+
+impl std::str::FromStr for AsOf {
+    type Err = String;
+
+    /// Parses a version number (`3`) or an RFC 3339 timestamp (`2024-05-01T12:00:00Z`).
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(version) = s.parse::<u64>() {
+            return Ok(Self::Version(version));
+        }
+        time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339)
+            .map(|t| Self::Timestamp(t.unix_timestamp()))
+            .map_err(|_| {
+                format!(
+                    "Invalid --as-of '{}': expected a version number or an RFC 3339 timestamp",
+                    s
+                )
+            })
+    }
+}
+
 /// Vector storage backend using LanceDB.
 ///
 /// Provides persistent storage for code embeddings with workspace isolation.
@@ -21,6 +145,115 @@ pub struct Storage {
     conn: Connection,
     table_name: String,
     table: OnceCell<Table>,
+    /// Tables of a sharded index; empty when the index is a single table
+    shards: Vec<Storage>,
+    shard_key: ShardKey,
+}
+
+/// Which shard of a sharded index a file's chunks are stored in (`shard_key`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShardKey {
+    /// Files in the same directory share a shard
+    #[default]
+    Directory,
+    /// Each file is placed on its own
+    File,
+}
+
+impl ShardKey {
+    /// Shard of `filename` among `count`; the same on every run and platform.
+    pub fn shard_of(self, filename: &str, count: usize) -> usize {
+        let normalized = filename.replace('\\', "/");
+        let key = match self {
+            Self::Directory => normalized.rsplit_once('/').map_or("", |(dir, _)| dir),
+            Self::File => normalized.as_str(),
+        };
+        // FNV-1a, since std's hasher may change between Rust releases
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in key.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        (hash % count.max(1) as u64) as usize
+    }
+}
+
+/// Filter for chunks that call `symbol`: a `calls` entry that is `symbol` itself or a
+/// path ending in `.symbol` or `::symbol`.
+fn calls_condition(symbol: &str) -> String {
+    let quoted = symbol.replace("'", "''");
+    // `^` escapes LIKE wildcards; identifiers never contain it
+    let pattern = quoted
+        .replace('^', "^^")
+        .replace('%', "^%")
+        .replace('_', "^_");
+    format!(
+        "(array_has_any(calls, ['{}']) \
+         OR concat(' ', array_to_string(calls, ' '), ' ') LIKE '%.{} %' ESCAPE '^' \
+         OR concat(' ', array_to_string(calls, ' '), ' ') LIKE '%:{} %' ESCAPE '^')",
+        quoted, pattern, pattern
+    )
+}
+
+/// Merges nearest-neighbour results of several shards into the `limit` closest rows.
+fn merge_nearest(batches: Vec<RecordBatch>, limit: usize) -> Result<Vec<RecordBatch>> {
+    let Some(first) = batches.first() else {
+        return Ok(batches);
+    };
+    let merged = arrow_select::concat::concat_batches(&first.schema(), &batches)?;
+    let distances: &Float32Array = merged
+        .column_by_name("_distance")
+        .ok_or_else(|| anyhow!("_distance missing"))?
+        .as_any()
+        .downcast_ref()
+        .ok_or_else(|| anyhow!("_distance wrong type"))?;
+    let mut order: Vec<u32> = (0..merged.num_rows() as u32).collect();
+    order.sort_by(|&a, &b| {
+        distances
+            .value(a as usize)
+            .total_cmp(&distances.value(b as usize))
+    });
+    order.truncate(limit);
+    Ok(vec![arrow_select::take::take_record_batch(
+        &merged,
+        &UInt32Array::from(order),
+    )?])
+}
+
+/// Rejects a batch whose vectors do not line up with its chunks or the table dimension.
+fn validate_vectors(ids: &[String], vectors: &[Vec<f32>], dim: i32) -> Result<()> {
+    // A ragged batch would otherwise be flattened into misaligned rows
+    if vectors.len() != ids.len() {
+        return Err(anyhow!(
+            "Dimension mismatch: got {} vectors for {} chunks",
+            vectors.len(),
+            ids.len()
+        ));
+    }
+    if let Some((i, vector)) = vectors
+        .iter()
+        .enumerate()
+        .find(|(_, v)| v.len() != dim as usize)
+    {
+        return Err(anyhow!(
+            "Dimension mismatch: vector {} (chunk '{}') has {} values, expected {}",
+            i,
+            ids[i],
+            vector.len(),
+            dim
+        ));
+    }
+    Ok(())
+}
+
+/// Splits parallel per-chunk values by the shard each chunk goes to.
+fn split_by_shard<T>(values: Vec<T>, shard_of: &[usize], count: usize) -> Vec<Vec<T>> {
+    let mut split: Vec<Vec<T>> = (0..count).map(|_| Vec::new()).collect();
+    for (value, &shard) in values.into_iter().zip(shard_of) {
+        split[shard].push(value);
+    }
+    split
 }
 
 impl Storage {
@@ -30,9 +263,50 @@ impl Storage {
             conn,
             table_name: table_name.to_string(),
             table: OnceCell::new(),
+            shards: Vec::new(),
+            shard_key: ShardKey::default(),
         })
     }
 
+    /// Spreads the index over `count` tables named `{table}_shard{i}`, assigning each
+    /// file by `key`; `count` of 1 or less keeps the single table.
+    ///
+    /// Writes go to the shard of each chunk's file, reads fan out to every shard and
+    /// are merged. Changing the shard count or key needs a full re-index.
+    pub fn with_shards(mut self, count: usize, key: ShardKey) -> Self {
+        self.shard_key = key;
+        self.shards = if count > 1 {
+            (0..count)
+                .map(|i| Storage {
+                    conn: self.conn.clone(),
+                    table_name: format!("{}_shard{}", self.table_name, i),
+                    table: OnceCell::new(),
+                    shards: Vec::new(),
+                    shard_key: key,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// Whether `db_dir` holds table `table_name`, sharded or not.
+    pub fn table_exists_on_disk(db_dir: &Path, table_name: &str) -> bool {
+        db_dir.join(format!("{}.lance", table_name)).exists()
+            || db_dir.join(format!("{}_shard0.lance", table_name)).exists()
+    }
+
+    /// Number of tables the index is spread over (1 when unsharded).
+    pub fn shard_count(&self) -> usize {
+        self.shards.len().max(1)
+    }
+
+    /// The shard holding `filename`'s chunks; only valid on a sharded index.
+    fn shard_for(&self, filename: &str) -> &Storage {
+        &self.shards[self.shard_key.shard_of(filename, self.shards.len())]
+    }
+
     async fn get_table(&self) -> Result<Table> {
         self.table
             .get_or_try_init(|| async {
@@ -46,7 +320,73 @@ impl Storage {
             .cloned()
     }
 
+    /// Current version of the table; every write creates a new one.
+    ///
+    /// A sharded index reports the sum over its shards, which also grows with every write.
+    pub async fn version(&self) -> Result<u64> {
+        if !self.shards.is_empty() {
+            let mut total = 0;
+            for shard in &self.shards {
+                total += Box::pin(shard.version()).await?;
+            }
+            return Ok(total);
+        }
+        Ok(self.get_table().await?.version().await?)
+    }
+
+    /// Whether the table has `name`; tables from older versions lack newer metadata columns.
+    pub async fn has_column(&self, name: &str) -> Result<bool> {
+        if let Some(shard) = self.shards.first() {
+            return Box::pin(shard.has_column(name)).await;
+        }
+        let schema = self.get_table().await?.schema().await?;
+        Ok(schema.field_with_name(name).is_ok())
+    }
+
+    /// Pins all reads to a previous version of the table and returns that version.
+    ///
+    /// The table becomes read-only for this `Storage`.
+    pub async fn checkout(&self, as_of: AsOf) -> Result<u64> {
+        if !self.shards.is_empty() {
+            return Err(anyhow!(
+                "Reading a past version is not supported for a sharded index"
+            ));
+        }
+        let table = self.get_table().await?;
+        let versions = table.list_versions().await?;
+        let version = match as_of {
+            AsOf::Version(v) => versions.iter().find(|ver| ver.version == v),
+            AsOf::Timestamp(ts) => versions
+                .iter()
+                .filter(|ver| ver.timestamp.timestamp() <= ts)
+                .max_by_key(|ver| ver.version),
+        }
+        .map(|ver| ver.version)
+        .ok_or_else(|| {
+            let oldest = versions.iter().map(|ver| ver.version).min();
+            let latest = versions.iter().map(|ver| ver.version).max();
+            let available = match (oldest, latest) {
+                (Some(first), Some(last)) => format!("{}..={}", first, last),
+                _ => "none".to_string(),
+            };
+            anyhow!(
+                "No version of table '{}' matches {:?} (available versions: {})",
+                self.table_name,
+                as_of,
+                available
+            )
+        })?;
+        table.checkout(version).await?;
+        Ok(version)
+    }
+
     pub async fn init(&self, dim: usize) -> Result<()> {
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                Box::pin(shard.init(dim)).await?;
+            }
+            return Ok(());
+        }
         let schema = Arc::new(Schema::new(vec![
             Field::new("id", DataType::Utf8, false),
             Field::new("workspace", DataType::Utf8, false),
@@ -68,6 +408,32 @@ impl Storage {
                 ),
                 false,
             ),
+            // Set for parts of a unit split because it was too large; see `ChunkPart`
+            Field::new("part_index", DataType::Int32, true),
+            Field::new("part_count", DataType::Int32, true),
+            Field::new("parent_line_start", DataType::Int32, true),
+            Field::new("parent_line_end", DataType::Int32, true),
+            Field::new("is_test", DataType::Boolean, true),
+            // "code" or "note"; notes also record the code range they are anchored to
+            Field::new("kind", DataType::Utf8, true),
+            Field::new("anchor_filename", DataType::Utf8, true),
+            Field::new("anchor_line_start", DataType::Int32, true),
+            Field::new("anchor_line_end", DataType::Int32, true),
+            // Call-graph importance, written by the post-index pass when enabled
+            Field::new("importance", DataType::Float32, true),
+            // Source file permissions, recorded with `index_file_permissions`
+            Field::new("file_mode", DataType::UInt32, true),
+            Field::new("file_owner", DataType::UInt32, true),
+            // Name of the function, class, ... the chunk defines
+            Field::new("symbol", DataType::Utf8, true),
+            // Enclosing classes, impls and namespaces, outermost first
+            Field::new(
+                "scope",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+            // "function", "class", "statement" or "other"
+            Field::new("item_kind", DataType::Utf8, true),
         ]));
 
         if self
@@ -101,6 +467,102 @@ impl Storage {
         calls: Vec<Vec<String>>,
         vectors: Vec<Vec<f32>>,
     ) -> Result<()> {
+        let metadata = vec![ChunkMetadata::default(); ids.len()];
+        self.add_chunks_with_metadata(
+            workspace,
+            ids,
+            filenames,
+            code,
+            line_starts,
+            line_ends,
+            last_modified,
+            calls,
+            vectors,
+            metadata,
+        )
+        .await
+    }
+
+    /// Like `add_chunks`, also storing per-chunk metadata (split part, test file flag).
+    ///
+    /// Metadata columns missing from tables created by older versions are skipped.
+    /// Nothing is written if any vector's length differs from the table's dimension.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_chunks_with_metadata(
+        &self,
+        workspace: &str,
+        ids: Vec<String>,
+        filenames: Vec<String>,
+        code: Vec<String>,
+        line_starts: Vec<i32>,
+        line_ends: Vec<i32>,
+        last_modified: Vec<i64>,
+        calls: Vec<Vec<String>>,
+        vectors: Vec<Vec<f32>>,
+        metadata: Vec<ChunkMetadata>,
+    ) -> Result<()> {
+        if !self.shards.is_empty() {
+            // Validate the whole batch first so no shard is written if any chunk is bad
+            let schema = self.shards[0].get_table().await?.schema().await?;
+            let dim = match schema.field_with_name("vector").map(|f| f.data_type()) {
+                Ok(DataType::FixedSizeList(_, d)) => *d,
+                Ok(_) => 768,
+                Err(_) => {
+                    return Err(anyhow!(
+                        "Validation error: 'vector' field missing in table schema"
+                    ))
+                }
+            };
+            validate_vectors(&ids, &vectors, dim)?;
+
+            let count = self.shards.len();
+            let shard_of: Vec<usize> = filenames
+                .iter()
+                .map(|f| self.shard_key.shard_of(f, count))
+                .collect();
+            let mut ids = split_by_shard(ids, &shard_of, count).into_iter();
+            let mut filenames = split_by_shard(filenames, &shard_of, count).into_iter();
+            let mut code = split_by_shard(code, &shard_of, count).into_iter();
+            let mut line_starts = split_by_shard(line_starts, &shard_of, count).into_iter();
+            let mut line_ends = split_by_shard(line_ends, &shard_of, count).into_iter();
+            let mut last_modified = split_by_shard(last_modified, &shard_of, count).into_iter();
+            let mut calls = split_by_shard(calls, &shard_of, count).into_iter();
+            let mut vectors = split_by_shard(vectors, &shard_of, count).into_iter();
+            let mut metadata = split_by_shard(metadata, &shard_of, count).into_iter();
+            for shard in &self.shards {
+                let (ids, filenames, code, line_starts, line_ends, last_modified) = (
+                    ids.next().unwrap_or_default(),
+                    filenames.next().unwrap_or_default(),
+                    code.next().unwrap_or_default(),
+                    line_starts.next().unwrap_or_default(),
+                    line_ends.next().unwrap_or_default(),
+                    last_modified.next().unwrap_or_default(),
+                );
+                let (calls, vectors, metadata) = (
+                    calls.next().unwrap_or_default(),
+                    vectors.next().unwrap_or_default(),
+                    metadata.next().unwrap_or_default(),
+                );
+                if ids.is_empty() {
+                    continue;
+                }
+                Box::pin(shard.add_chunks_with_metadata(
+                    workspace,
+                    ids,
+                    filenames,
+                    code,
+                    line_starts,
+                    line_ends,
+                    last_modified,
+                    calls,
+                    vectors,
+                    metadata,
+                ))
+                .await?;
+            }
+            return Ok(());
+        }
+
         let table = self.get_table().await?;
         let table_schema = table.schema().await?;
         let vector_field = table_schema
@@ -112,6 +574,8 @@ impl Storage {
             768
         };
 
+        validate_vectors(&ids, &vectors, dim_val)?;
+
         // Reuse table_schema for insertion batch if possible, or construct matching one
         let schema = table_schema;
 
@@ -139,20 +603,81 @@ impl Storage {
         let field = Arc::new(Field::new("item", DataType::Float32, true));
         let vector_array = FixedSizeListArray::try_new(field, dim_val, Arc::new(values), None)?;
 
-        let batch = RecordBatch::try_new(
-            schema.clone(),
-            vec![
-                Arc::new(id_array),
-                Arc::new(workspace_array),
-                Arc::new(filename_array),
-                Arc::new(code_array),
-                Arc::new(line_starts_array),
-                Arc::new(line_ends_array),
-                Arc::new(last_modified_array),
-                Arc::new(calls_array),
-                Arc::new(vector_array),
-            ],
-        )?;
+        let mut columns: Vec<Arc<dyn Array>> = vec![
+            Arc::new(id_array),
+            Arc::new(workspace_array),
+            Arc::new(filename_array),
+            Arc::new(code_array),
+            Arc::new(line_starts_array),
+            Arc::new(line_ends_array),
+            Arc::new(last_modified_array),
+            Arc::new(calls_array),
+            Arc::new(vector_array),
+        ];
+        let part_column = |field: fn(&ChunkPart) -> usize| -> Arc<dyn Array> {
+            Arc::new(Int32Array::from_iter(
+                metadata
+                    .iter()
+                    .map(|m| m.part.as_ref().map(|p| field(p) as i32)),
+            ))
+        };
+        let anchor_column = |field: fn(&NoteAnchor) -> usize| -> Arc<dyn Array> {
+            Arc::new(Int32Array::from_iter(
+                metadata.iter().map(|m| m.anchor().map(|a| field(a) as i32)),
+            ))
+        };
+        // Metadata columns, in whatever subset and order this table has them
+        for field in schema.fields().iter().skip(columns.len()) {
+            let column = match field.name().as_str() {
+                "part_index" => part_column(|p| p.index),
+                "part_count" => part_column(|p| p.count),
+                "parent_line_start" => part_column(|p| p.parent_line_start),
+                "parent_line_end" => part_column(|p| p.parent_line_end),
+                "is_test" => Arc::new(BooleanArray::from_iter(
+                    metadata.iter().map(|m| Some(m.is_test)),
+                )),
+                "kind" => Arc::new(StringArray::from_iter(metadata.iter().map(|m| {
+                    Some(match m.kind {
+                        ChunkKind::Code => "code",
+                        ChunkKind::Note { .. } => "note",
+                    })
+                }))),
+                "anchor_filename" => Arc::new(StringArray::from_iter(
+                    metadata
+                        .iter()
+                        .map(|m| m.anchor().map(|a| a.filename.as_str())),
+                )),
+                "anchor_line_start" => anchor_column(|a| a.line_start),
+                "anchor_line_end" => anchor_column(|a| a.line_end),
+                "importance" => Arc::new(Float32Array::from(vec![None; metadata.len()])),
+                "file_mode" => Arc::new(UInt32Array::from_iter(
+                    metadata.iter().map(|m| m.permissions.map(|p| p.mode)),
+                )),
+                "file_owner" => Arc::new(UInt32Array::from_iter(
+                    metadata.iter().map(|m| m.permissions.map(|p| p.owner)),
+                )),
+                "symbol" => Arc::new(StringArray::from_iter(
+                    metadata.iter().map(|m| m.symbol.as_deref()),
+                )),
+                "scope" => {
+                    let mut builder = ListBuilder::new(StringBuilder::new());
+                    for m in &metadata {
+                        for name in &m.scope {
+                            builder.values().append_value(name);
+                        }
+                        builder.append(true);
+                    }
+                    Arc::new(builder.finish())
+                }
+                "item_kind" => Arc::new(StringArray::from_iter(
+                    metadata.iter().map(|m| Some(m.item_kind.as_str())),
+                )),
+                other => return Err(anyhow!("Unexpected column '{}' in table schema", other)),
+            };
+            columns.push(column);
+        }
+
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
 
         let reader = Box::new(RecordBatchIterator::new(vec![Ok(batch)], schema));
         table.add(reader).execute().await?;
@@ -160,15 +685,46 @@ impl Storage {
         Ok(())
     }
 
+    /// Nearest-neighbour search. The `vector` column is only returned with `include_vectors`.
     pub async fn search(
         &self,
         query_vector: Vec<f32>,
         limit: usize,
         filter: Option<String>,
         workspace: Option<&str>,
+        include_vectors: bool,
     ) -> Result<Vec<RecordBatch>> {
+        if !self.shards.is_empty() {
+            // Shards are queried concurrently
+            let searches = self.shards.iter().map(|shard| {
+                Box::pin(shard.search(
+                    query_vector.clone(),
+                    limit,
+                    filter.clone(),
+                    workspace,
+                    include_vectors,
+                ))
+            });
+            let mut batches = Vec::new();
+            for result in futures_util::future::join_all(searches).await {
+                batches.extend(result?);
+            }
+            return merge_nearest(batches, limit);
+        }
+
         let table = self.get_table().await?;
         let mut query = table.query().nearest_to(query_vector)?;
+        if !include_vectors {
+            let columns = table
+                .schema()
+                .await?
+                .fields()
+                .iter()
+                .map(|f| f.name().clone())
+                .filter(|name| name != "vector")
+                .collect();
+            query = query.select(lancedb::query::Select::Columns(columns));
+        }
 
         let mut conditions: Vec<String> = Vec::new();
         if let Some(f) = filter {
@@ -192,10 +748,619 @@ impl Storage {
         Ok(results)
     }
 
+    /// Looks up the stored embeddings of the chunks with the given ids.
+    pub async fn vectors_by_id(
+        &self,
+        ids: &[String],
+        workspace: Option<&str>,
+    ) -> Result<std::collections::HashMap<String, Vec<f32>>> {
+        let mut vectors = std::collections::HashMap::new();
+        if ids.is_empty() {
+            return Ok(vectors);
+        }
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                vectors.extend(Box::pin(shard.vectors_by_id(ids, workspace)).await?);
+            }
+            return Ok(vectors);
+        }
+        let table = self.get_table().await?;
+
+        let id_list = ids
+            .iter()
+            .map(|id| format!("'{}'", id.replace("'", "''")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut conditions = vec![format!("id IN ({})", id_list)];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let batches = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .select(lancedb::query::Select::Columns(vec![
+                "id".to_string(),
+                "vector".to_string(),
+            ]))
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for batch in batches {
+            let batch_ids: &StringArray = batch
+                .column_by_name("id")
+                .ok_or_else(|| anyhow!("id missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("id wrong type"))?;
+            for i in 0..batch.num_rows() {
+                if let Some(vector) = row_vector(&batch, i) {
+                    vectors.insert(batch_ids.value(i).to_string(), vector);
+                }
+            }
+        }
+        Ok(vectors)
+    }
+
+    /// Looks up which of the given chunk ids are notes, with their anchors.
+    ///
+    /// Tables created before notes were supported have none.
+    pub async fn note_kinds_by_id(
+        &self,
+        ids: &[String],
+        workspace: Option<&str>,
+    ) -> Result<std::collections::HashMap<String, ChunkKind>> {
+        let mut kinds = std::collections::HashMap::new();
+        if ids.is_empty() || !self.has_column("kind").await? {
+            return Ok(kinds);
+        }
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                kinds.extend(Box::pin(shard.note_kinds_by_id(ids, workspace)).await?);
+            }
+            return Ok(kinds);
+        }
+        let table = self.get_table().await?;
+
+        let id_list = ids
+            .iter()
+            .map(|id| format!("'{}'", id.replace("'", "''")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut conditions = vec![format!("id IN ({})", id_list), "kind = 'note'".to_string()];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let batches = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .select(lancedb::query::Select::Columns(vec![
+                "id".to_string(),
+                "kind".to_string(),
+                "anchor_filename".to_string(),
+                "anchor_line_start".to_string(),
+                "anchor_line_end".to_string(),
+            ]))
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for batch in batches {
+            let batch_ids: &StringArray = batch
+                .column_by_name("id")
+                .ok_or_else(|| anyhow!("id missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("id wrong type"))?;
+            for i in 0..batch.num_rows() {
+                kinds.insert(batch_ids.value(i).to_string(), row_kind(&batch, i));
+            }
+        }
+        Ok(kinds)
+    }
+
+    /// The recorded permissions of every file in `workspace`, for `--update` to notice
+    /// permission changes, which leave the modification time alone.
+    ///
+    /// Files indexed without `index_file_permissions` are left out.
+    pub async fn get_indexed_permissions(
+        &self,
+        workspace: &str,
+    ) -> Result<std::collections::HashMap<String, FilePermissions>> {
+        let mut permissions = std::collections::HashMap::new();
+        if !self.has_column("file_mode").await? {
+            return Ok(permissions);
+        }
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                permissions.extend(Box::pin(shard.get_indexed_permissions(workspace)).await?);
+            }
+            return Ok(permissions);
+        }
+        let table = self.get_table().await?;
+
+        let mut stream = table
+            .query()
+            .only_if(format!(
+                "workspace = '{}' AND file_mode IS NOT NULL",
+                workspace.replace("'", "''")
+            ))
+            .select(lancedb::query::Select::Columns(vec![
+                "filename".to_string(),
+                "file_mode".to_string(),
+                "file_owner".to_string(),
+            ]))
+            .execute()
+            .await?;
+        while let Some(batch) = stream.try_next().await? {
+            let names: &StringArray = batch
+                .column_by_name("filename")
+                .ok_or_else(|| anyhow!("filename missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("filename wrong type"))?;
+            for i in 0..batch.num_rows() {
+                if let Some(found) = row_permissions(&batch, i) {
+                    permissions.insert(names.value(i).to_string(), found);
+                }
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// Looks up the recorded permissions of the given files.
+    ///
+    /// Files indexed without `index_file_permissions` are left out.
+    pub async fn permissions_by_file(
+        &self,
+        filenames: &[String],
+        workspace: Option<&str>,
+    ) -> Result<std::collections::HashMap<String, FilePermissions>> {
+        let mut permissions = std::collections::HashMap::new();
+        if filenames.is_empty() || !self.has_column("file_mode").await? {
+            return Ok(permissions);
+        }
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                permissions
+                    .extend(Box::pin(shard.permissions_by_file(filenames, workspace)).await?);
+            }
+            return Ok(permissions);
+        }
+        let table = self.get_table().await?;
+
+        let name_list = filenames
+            .iter()
+            .map(|f| format!("'{}'", f.replace("'", "''")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut conditions = vec![
+            format!("filename IN ({})", name_list),
+            "file_mode IS NOT NULL".to_string(),
+        ];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let batches = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .select(lancedb::query::Select::Columns(vec![
+                "filename".to_string(),
+                "file_mode".to_string(),
+                "file_owner".to_string(),
+            ]))
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for batch in batches {
+            let names: &StringArray = batch
+                .column_by_name("filename")
+                .ok_or_else(|| anyhow!("filename missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("filename wrong type"))?;
+            for i in 0..batch.num_rows() {
+                if let Some(found) = row_permissions(&batch, i) {
+                    permissions.insert(names.value(i).to_string(), found);
+                }
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// Returns all chunks stored for `filename`, ordered by line.
+    pub async fn get_file_chunks(
+        &self,
+        filename: &str,
+        workspace: Option<&str>,
+    ) -> Result<Vec<FileChunk>> {
+        if !self.shards.is_empty() {
+            return Box::pin(
+                self.shard_for(filename)
+                    .get_file_chunks(filename, workspace),
+            )
+            .await;
+        }
+        let table = self.get_table().await?;
+
+        let mut conditions = vec![format!("filename = '{}'", filename.replace("'", "''"))];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let batches = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .select(lancedb::query::Select::Columns(vec![
+                "code".to_string(),
+                "line_start".to_string(),
+                "line_end".to_string(),
+            ]))
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut chunks = Vec::new();
+        for batch in batches {
+            let column = |name: &str| {
+                batch
+                    .column_by_name(name)
+                    .ok_or_else(|| anyhow!("{} missing", name))
+            };
+            let codes: &StringArray = column("code")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("code wrong type"))?;
+            let starts: &Int32Array = column("line_start")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("line_start wrong type"))?;
+            let ends: &Int32Array = column("line_end")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("line_end wrong type"))?;
+            for i in 0..batch.num_rows() {
+                chunks.push(FileChunk {
+                    line_start: starts.value(i) as usize,
+                    line_end: ends.value(i) as usize,
+                    code: codes.value(i).to_string(),
+                });
+            }
+        }
+        chunks.sort_by_key(|c| (c.line_start, c.line_end));
+        Ok(chunks)
+    }
+
+    /// Streams the filename, first line and code of every stored code chunk to `visit`.
+    ///
+    /// Batches are read one at a time, so the whole table is never held in memory.
+    /// Notes are skipped: their text is not file content.
+    pub async fn scan_code<F>(&self, workspace: Option<&str>, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&str, usize, &str),
+    {
+        self.scan_code_dyn(workspace, &mut visit).await
+    }
+
+    async fn scan_code_dyn(
+        &self,
+        workspace: Option<&str>,
+        visit: &mut dyn FnMut(&str, usize, &str),
+    ) -> Result<()> {
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                Box::pin(shard.scan_code_dyn(workspace, &mut *visit)).await?;
+            }
+            return Ok(());
+        }
+        let mut conditions = Vec::new();
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+        if self.has_column("kind").await? {
+            conditions.push("(kind IS NULL OR kind != 'note')".to_string());
+        }
+        let table = self.get_table().await?;
+
+        let mut query = table.query().select(lancedb::query::Select::Columns(vec![
+            "filename".to_string(),
+            "line_start".to_string(),
+            "code".to_string(),
+        ]));
+        if !conditions.is_empty() {
+            query = query.only_if(conditions.join(" AND "));
+        }
+        let mut stream = query.execute().await?;
+
+        while let Some(batch) = stream.try_next().await? {
+            let column = |name: &str| {
+                batch
+                    .column_by_name(name)
+                    .ok_or_else(|| anyhow!("{} missing", name))
+            };
+            let filenames: &StringArray = column("filename")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("filename wrong type"))?;
+            let starts: &Int32Array = column("line_start")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("line_start wrong type"))?;
+            let codes: &StringArray = column("code")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("code wrong type"))?;
+            for i in 0..batch.num_rows() {
+                visit(filenames.value(i), starts.value(i) as usize, codes.value(i));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the call graph of a workspace: each chunk's id, defined function and calls.
+    ///
+    /// Notes are skipped.
+    pub async fn call_graph(&self, workspace: &str) -> Result<Vec<CallGraphNode>> {
+        if !self.shards.is_empty() {
+            let mut nodes = Vec::new();
+            for shard in &self.shards {
+                nodes.extend(Box::pin(shard.call_graph(workspace)).await?);
+            }
+            return Ok(nodes);
+        }
+        let mut condition = format!("workspace = '{}'", workspace.replace("'", "''"));
+        if self.has_column("kind").await? {
+            condition.push_str(" AND (kind IS NULL OR kind != 'note')");
+        }
+        let table = self.get_table().await?;
+
+        let mut stream = table
+            .query()
+            .only_if(condition)
+            .select(lancedb::query::Select::Columns(vec![
+                "id".to_string(),
+                "filename".to_string(),
+                "code".to_string(),
+                "calls".to_string(),
+            ]))
+            .execute()
+            .await?;
+
+        let mut nodes = Vec::new();
+        while let Some(batch) = stream.try_next().await? {
+            let column = |name: &str| {
+                batch
+                    .column_by_name(name)
+                    .ok_or_else(|| anyhow!("{} missing", name))
+            };
+            let ids: &StringArray = column("id")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("id wrong type"))?;
+            let filenames: &StringArray = column("filename")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("filename wrong type"))?;
+            let codes: &StringArray = column("code")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("code wrong type"))?;
+            let calls: &ListArray = column("calls")?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("calls wrong type"))?;
+            for i in 0..batch.num_rows() {
+                let mut chunk_calls = Vec::new();
+                if !calls.is_null(i) {
+                    if let Some(values) = calls.value(i).as_any().downcast_ref::<StringArray>() {
+                        chunk_calls.extend(values.iter().flatten().map(str::to_string));
+                    }
+                }
+                nodes.push(CallGraphNode {
+                    id: ids.value(i).to_string(),
+                    symbol: defined_symbol(filenames.value(i), codes.value(i)),
+                    calls: chunk_calls,
+                });
+            }
+        }
+        Ok(nodes)
+    }
+
+    /// Replaces the stored importance of a workspace's chunks with `importance`.
+    ///
+    /// Chunks not in the map are reset to zero. Rows are updated in groups sharing a
+    /// value, so the number of writes follows the number of distinct values.
+    pub async fn set_importance(
+        &self,
+        workspace: &str,
+        importance: &std::collections::HashMap<String, f32>,
+    ) -> Result<()> {
+        if !self.shards.is_empty() {
+            // Ids do not name their shard; updates of ids a shard lacks match no rows
+            for shard in &self.shards {
+                Box::pin(shard.set_importance(workspace, importance)).await?;
+            }
+            return Ok(());
+        }
+        let table = self.get_table().await?;
+        let ws_condition = format!("workspace = '{}'", workspace.replace("'", "''"));
+
+        table
+            .update()
+            .only_if(format!("{} AND importance > 0", ws_condition))
+            .column("importance", "0.0")
+            .execute()
+            .await?;
+
+        let mut groups: std::collections::BTreeMap<u32, Vec<&str>> =
+            std::collections::BTreeMap::new();
+        for (id, value) in importance {
+            groups.entry(value.to_bits()).or_default().push(id);
+        }
+        for (bits, ids) in groups {
+            for batch in ids.chunks(500) {
+                let id_list = batch
+                    .iter()
+                    .map(|id| format!("'{}'", id.replace("'", "''")))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                table
+                    .update()
+                    .only_if(format!("{} AND id IN ({})", ws_condition, id_list))
+                    .column("importance", format!("{:?}", f32::from_bits(bits)))
+                    .execute()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up the call-graph importance of the given chunk ids; unscored chunks are absent.
+    ///
+    /// Tables created before importance was supported have none.
+    pub async fn importance_by_id(
+        &self,
+        ids: &[String],
+        workspace: Option<&str>,
+    ) -> Result<std::collections::HashMap<String, f32>> {
+        let mut importance = std::collections::HashMap::new();
+        if ids.is_empty() || !self.has_column("importance").await? {
+            return Ok(importance);
+        }
+        if !self.shards.is_empty() {
+            for shard in &self.shards {
+                importance.extend(Box::pin(shard.importance_by_id(ids, workspace)).await?);
+            }
+            return Ok(importance);
+        }
+        let table = self.get_table().await?;
+
+        let id_list = ids
+            .iter()
+            .map(|id| format!("'{}'", id.replace("'", "''")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut conditions = vec![format!("id IN ({})", id_list), "importance > 0".to_string()];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let batches = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .select(lancedb::query::Select::Columns(vec![
+                "id".to_string(),
+                "importance".to_string(),
+            ]))
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        for batch in batches {
+            let batch_ids: &StringArray = batch
+                .column_by_name("id")
+                .ok_or_else(|| anyhow!("id missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("id wrong type"))?;
+            let values: &Float32Array = batch
+                .column_by_name("importance")
+                .ok_or_else(|| anyhow!("importance missing"))?
+                .as_any()
+                .downcast_ref()
+                .ok_or_else(|| anyhow!("importance wrong type"))?;
+            for i in 0..batch.num_rows() {
+                importance.insert(batch_ids.value(i).to_string(), values.value(i));
+            }
+        }
+        Ok(importance)
+    }
+
+    /// Returns chunks whose `calls` list contains `symbol`, bare or as the last segment of a
+    /// path like `self.db.symbol` or `module::symbol` (filename and line columns only).
+    pub async fn find_callers(
+        &self,
+        symbol: &str,
+        limit: usize,
+        workspace: Option<&str>,
+    ) -> Result<Vec<RecordBatch>> {
+        if !self.shards.is_empty() {
+            let mut batches = Vec::new();
+            for shard in &self.shards {
+                batches.extend(Box::pin(shard.find_callers(symbol, limit, workspace)).await?);
+            }
+            return Ok(batches);
+        }
+        let table = self.get_table().await?;
+
+        let mut conditions = vec![calls_condition(symbol)];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let results = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .select(lancedb::query::Select::Columns(vec![
+                "filename".to_string(),
+                "line_start".to_string(),
+                "line_end".to_string(),
+            ]))
+            .limit(limit)
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(results)
+    }
+
+    /// Returns every column of the chunks whose `calls` list contains `symbol`, matched
+    /// like [`find_callers`](Self::find_callers).
+    pub async fn chunks_calling(
+        &self,
+        symbol: &str,
+        workspace: Option<&str>,
+    ) -> Result<Vec<RecordBatch>> {
+        if !self.shards.is_empty() {
+            let mut batches = Vec::new();
+            for shard in &self.shards {
+                batches.extend(Box::pin(shard.chunks_calling(symbol, workspace)).await?);
+            }
+            return Ok(batches);
+        }
+        let table = self.get_table().await?;
+
+        let mut conditions = vec![calls_condition(symbol)];
+        if let Some(ws) = workspace {
+            conditions.push(format!("workspace = '{}'", ws.replace("'", "''")));
+        }
+
+        let results = table
+            .query()
+            .only_if(conditions.join(" AND "))
+            .execute()
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(results)
+    }
+
     pub async fn get_indexed_metadata(
         &self,
         workspace: &str,
     ) -> Result<std::collections::HashMap<String, i64>> {
+        if !self.shards.is_empty() {
+            let mut metadata = std::collections::HashMap::new();
+            for shard in &self.shards {
+                metadata.extend(Box::pin(shard.get_indexed_metadata(workspace)).await?);
+            }
+            return Ok(metadata);
+        }
         let table = match self.get_table().await {
             Ok(t) => t,
             Err(_) => return Ok(std::collections::HashMap::new()),
@@ -239,6 +1404,13 @@ impl Storage {
     }
 
     pub async fn delete_file_chunks(&self, filename: &str, workspace: &str) -> Result<()> {
+        if !self.shards.is_empty() {
+            return Box::pin(
+                self.shard_for(filename)
+                    .delete_file_chunks(filename, workspace),
+            )
+            .await;
+        }
         if let Ok(table) = self.get_table().await {
             let safe_filename = filename.replace("'", "''");
             table
@@ -255,6 +1427,18 @@ impl Storage {
         if filenames.is_empty() {
             return Ok(());
         }
+        if !self.shards.is_empty() {
+            let count = self.shards.len();
+            let shard_of: Vec<usize> = filenames
+                .iter()
+                .map(|f| self.shard_key.shard_of(f, count))
+                .collect();
+            let split = split_by_shard(filenames.to_vec(), &shard_of, count);
+            for (shard, filenames) in self.shards.iter().zip(split) {
+                Box::pin(shard.batch_delete_files(&filenames, workspace)).await?;
+            }
+            return Ok(());
+        }
         if let Ok(table) = self.get_table().await {
             // Chunk deletions to avoid hitting SQL/AST limits if filenames list is huge
             // A safer chunk size for IN clause might be around 50-100 depending on path lengths.
@@ -285,6 +1469,9 @@ impl Storage {
     }
 
     pub async fn create_filename_index(&self) -> Result<()> {
+        for shard in &self.shards {
+            Box::pin(shard.create_filename_index()).await?;
+        }
         if let Ok(table) = self.get_table().await {
             let _ = table
                 .create_index(
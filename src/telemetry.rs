@@ -2,6 +2,7 @@ use anyhow::Result;
 use opentelemetry::{global, KeyValue};
 use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::{metrics::SdkMeterProvider, trace as sdktrace, Resource};
+use std::io::IsTerminal;
 use std::sync::{Arc, Mutex};
 use sysinfo::{Pid, System};
 use tracing::info;
@@ -42,15 +43,17 @@ pub fn init_telemetry(mode: AppMode, config: &AppConfig) -> Result<TelemetryGuar
         // For MCP, we MUST NOT print logs to stdout as it corrupts the JSON-RPC stream
         if config.enable_mcp {
             // Redirect logs to stderr only
-            let subscriber = Registry::default()
-                .with(env_filter)
-                .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr));
+            let subscriber = Registry::default().with(env_filter).with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .with_ansi(std::io::stderr().is_terminal()),
+            );
             let _ = subscriber.try_init();
         } else {
             // Normal CLI/Server mode - stdout is fine
             let subscriber = Registry::default()
                 .with(env_filter)
-                .with(tracing_subscriber::fmt::layer());
+                .with(tracing_subscriber::fmt::layer().with_ansi(std::io::stdout().is_terminal()));
             let _ = subscriber.try_init();
         }
 
@@ -86,14 +89,18 @@ fn init_cli_telemetry(config: &AppConfig) -> Result<TelemetryGuard> {
         let registry = Registry::default()
             .with(filter_layer)
             .with(chrome_layer)
-            .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr));
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(std::io::stderr)
+                    .with_ansi(std::io::stderr().is_terminal()),
+            );
         let _ = registry.try_init();
     } else {
         // Normal Mode: Chrome Layer + Default (Stdout) Logging + Filter
         let registry = Registry::default()
             .with(filter_layer)
             .with(chrome_layer)
-            .with(tracing_subscriber::fmt::layer());
+            .with(tracing_subscriber::fmt::layer().with_ansi(std::io::stdout().is_terminal()));
         let _ = registry.try_init();
     }
 
@@ -184,7 +191,7 @@ fn init_server_telemetry(endpoint: &str, _config: &AppConfig) -> Result<Telemetr
     let subscriber = Registry::default()
         .with(filter_layer)
         .with(telemetry)
-        .with(tracing_subscriber::fmt::layer());
+        .with(tracing_subscriber::fmt::layer().with_ansi(std::io::stdout().is_terminal()));
 
     // Ignore error if already set
     let _ = subscriber.try_init();
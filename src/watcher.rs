@@ -4,28 +4,94 @@ use crate::indexer::CodeChunker;
 use crate::ops::indexer::CodeIndexer;
 use crate::storage::Storage;
 use notify_debouncer_mini::{new_debouncer, notify::RecursiveMode};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
 use std::time::Duration;
 use tracing::{error, info};
 
+/// `exclusions` applied to file events by every running watcher.
+fn exclusion_registry() -> &'static RwLock<Vec<String>> {
+    static EXCLUSIONS: OnceLock<RwLock<Vec<String>>> = OnceLock::new();
+    EXCLUSIONS.get_or_init(Default::default)
+}
+
+/// The exclusion patterns the watchers currently apply.
+pub fn exclusions() -> Vec<String> {
+    exclusion_registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Replaces the exclusion patterns, at startup and when the config is reloaded.
+pub fn set_exclusions(exclusions: Vec<String>) {
+    *exclusion_registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = exclusions;
+}
+
+/// Whether `path` contains one of the current exclusion patterns.
+pub fn is_excluded(path: &str) -> bool {
+    exclusion_registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .any(|ex| path.contains(ex.as_str()))
+}
+
+/// Directories to watch: each of `subpaths` under `root`, or `root` itself when none.
+///
+/// Subpaths may be relative to `root` or absolute. Ones inside another subpath are
+/// dropped, since the watch is recursive.
+pub fn watch_roots(root: &str, subpaths: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    if subpaths.is_empty() {
+        return Ok(vec![PathBuf::from(root)]);
+    }
+    let mut dirs = Vec::with_capacity(subpaths.len());
+    for subpath in subpaths {
+        let dir = Path::new(root).join(subpath);
+        if !dir.is_dir() {
+            anyhow::bail!("Watch subpath {} is not a directory", dir.display());
+        }
+        dirs.push(dir);
+    }
+    dirs.sort();
+    dirs.dedup();
+    Ok(dirs
+        .iter()
+        .filter(|dir| {
+            !dirs
+                .iter()
+                .any(|other| other != *dir && dir.starts_with(other))
+        })
+        .cloned()
+        .collect())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn start_watcher(
     path: &str,
+    subpaths: &[String],
     storage: Storage,
     mut embedder: Embedder,
     mut bm25: BM25Index,
     chunker: CodeChunker,
     workspace: String,
+    record_permissions: bool,
 ) -> anyhow::Result<()> {
-    info!("Starting watcher on: {}", path);
+    let roots = watch_roots(path, subpaths)?;
+    for root in &roots {
+        info!("Starting watcher on: {}", root.display());
+    }
 
     let (tx, rx) = std::sync::mpsc::channel();
 
     // Create a debouncer with 2 seconds timeout
     let mut debouncer = new_debouncer(Duration::from_secs(2), tx)?;
 
-    debouncer
-        .watcher()
-        .watch(Path::new(path), RecursiveMode::Recursive)?;
+    for root in &roots {
+        debouncer.watcher().watch(root, RecursiveMode::Recursive)?;
+    }
 
     // We need to keep the components alive and mutable.
     // Since notify runs in a separate thread (or system event loop) but communicates via channel,
@@ -38,7 +104,8 @@ pub async fn start_watcher(
     // Since we need to call async methods on storage/indexer, we can't easily be in a blocking loop unless we block_on.
     // Let's use a standard loop checking the channel.
 
-    let mut indexer = CodeIndexer::new(&storage, &mut embedder, &mut bm25, &chunker, workspace);
+    let mut indexer = CodeIndexer::new(&storage, &mut embedder, &mut bm25, &chunker, workspace)
+        .with_file_permissions(record_permissions);
 
     // Process events in a non-blocking way to allow graceful shutdown
     loop {
@@ -50,11 +117,12 @@ pub async fn start_watcher(
                         let path = event.path;
                         let path_lossy = path.to_string_lossy();
 
-                        // Simple exclusion for .git and target/lancedb
+                        // Simple exclusion for .git and target/lancedb, plus `exclusions`
                         if path_lossy.contains(".git")
                             || path_lossy.contains("node_modules")
                             || path_lossy.contains("target")
                             || path_lossy.contains(".lancedb")
+                            || is_excluded(&path_lossy)
                         {
                             continue;
                         }
@@ -20,6 +20,13 @@ fn test_bm25_batch_delete() -> Result<()> {
             line_end: 10,
             last_modified: 100,
             calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
         },
         CodeChunk {
             filename: "file2.rs".to_string(),
@@ -28,6 +35,13 @@ fn test_bm25_batch_delete() -> Result<()> {
             line_end: 10,
             last_modified: 100,
             calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
         },
         CodeChunk {
             filename: "file3.rs".to_string(),
@@ -36,6 +50,13 @@ fn test_bm25_batch_delete() -> Result<()> {
             line_end: 10,
             last_modified: 100,
             calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
         },
     ];
 
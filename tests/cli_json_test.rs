@@ -90,3 +90,189 @@ telemetry_enabled = false
 
     Ok(())
 }
+
+#[test]
+fn test_index_metrics_json() -> Result<()> {
+    let dir = tempdir()?;
+    let db_path = dir.path().join("db");
+    let src_dir = dir.path().join("src");
+    fs::create_dir_all(&src_dir)?;
+    fs::write(src_dir.join("a.rs"), "fn alpha() {}\n")?;
+    fs::write(src_dir.join("b.py"), "def beta():\n    pass\n")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("RUST_LOG", "off")
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&src_dir)
+        .arg("--metrics-json")
+        .output()?;
+    assert!(output.status.success());
+
+    // Metrics go to stderr; pick the JSON line out of any other diagnostics
+    let stderr = String::from_utf8(output.stderr)?;
+    let metrics: Value = stderr
+        .lines()
+        .find_map(|line| serde_json::from_str::<Value>(line).ok())
+        .ok_or_else(|| anyhow::anyhow!("No metrics JSON on stderr: {}", stderr))?;
+
+    assert_eq!(metrics["workspace"], "default");
+    assert_eq!(metrics["files_indexed"], 2);
+    assert_eq!(metrics["failures"], 0);
+    assert!(metrics["chunks"].as_u64().unwrap() >= 2);
+    assert!(metrics["bytes"].as_u64().unwrap() > 0);
+    for field in [
+        "files_unchanged",
+        "files_removed",
+        "duration_ms",
+        "throttled_ms",
+    ] {
+        assert!(metrics[field].is_u64(), "missing field {}", field);
+    }
+
+    // stdout stays free of the metrics
+    assert!(!String::from_utf8(output.stdout)?.contains("files_indexed"));
+
+    Ok(())
+}
+
+/// Indexes `src_dir` one file per batch and returns the metrics line.
+fn index_metrics_with_priority(
+    src_dir: &std::path::Path,
+    db_path: &std::path::Path,
+    priority: &str,
+) -> Result<Value> {
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("RUST_LOG", "off")
+        .env("CODE_RAG__DB_PATH", db_path)
+        .env("CODE_RAG__LOW_PRIORITY_PAUSE_RATIO", "1.0")
+        .arg("index")
+        .arg("--path")
+        .arg(src_dir)
+        .arg("--batch-size")
+        .arg("1")
+        .arg("--priority")
+        .arg(priority)
+        .arg("--metrics-json")
+        .output()?;
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    stderr
+        .lines()
+        .find_map(|line| serde_json::from_str::<Value>(line).ok())
+        .ok_or_else(|| anyhow::anyhow!("No metrics JSON on stderr: {}", stderr))
+}
+
+#[test]
+fn test_quiet_index_prints_no_terminal_control_sequences() -> Result<()> {
+    let dir = tempdir()?;
+    let src_dir = dir.path().join("src");
+    fs::create_dir_all(&src_dir)?;
+    fs::write(src_dir.join("lib.rs"), "fn quiet_index() {}\n")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", dir.path().join("db"))
+        .arg("index")
+        .arg("--path")
+        .arg(&src_dir)
+        .arg("--quiet")
+        .output()?;
+    assert!(output.status.success());
+
+    for stream in [&output.stdout, &output.stderr] {
+        let text = String::from_utf8_lossy(stream);
+        assert!(
+            !text.contains('\x1b') && !text.contains('\r'),
+            "control sequences in output: {:?}",
+            text
+        );
+        assert!(!text.contains("files processed"), "spinner drawn: {}", text);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_low_priority_index_throttles_between_batches() -> Result<()> {
+    let dir = tempdir()?;
+    let src_dir = dir.path().join("src");
+    fs::create_dir_all(&src_dir)?;
+    for i in 0..4 {
+        fs::write(
+            src_dir.join(format!("f{}.rs", i)),
+            format!("fn func_{}() {{}}\n", i),
+        )?;
+    }
+
+    let low = index_metrics_with_priority(&src_dir, &dir.path().join("low_db"), "low")?;
+    let high = index_metrics_with_priority(&src_dir, &dir.path().join("high_db"), "high")?;
+
+    assert_eq!(low["files_indexed"], 4);
+    assert!(low["throttled_ms"].as_u64().unwrap() > 0);
+    assert_eq!(high["throttled_ms"], 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_reindex_reads_embeddings_from_cache_dir() -> Result<()> {
+    let dir = tempdir()?;
+    let db_path = dir.path().join("db");
+    let cache_dir = dir.path().join("embeddings");
+    let src_dir = dir.path().join("src");
+    fs::create_dir_all(&src_dir)?;
+    fs::write(src_dir.join("a.rs"), "fn alpha() -> u32 {\n    1\n}\n")?;
+    fs::write(src_dir.join("b.py"), "def beta():\n    return 2\n")?;
+
+    let index = || -> Result<Value> {
+        let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+            .env("RUST_LOG", "off")
+            .env("CODE_RAG__DB_PATH", &db_path)
+            .env("CODE_RAG__EMBEDDING_CACHE_DIR", &cache_dir)
+            .arg("index")
+            .arg("--path")
+            .arg(&src_dir)
+            .arg("--force")
+            .arg("--metrics-json")
+            .output()?;
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        stderr
+            .lines()
+            .find_map(|line| serde_json::from_str::<Value>(line).ok())
+            .ok_or_else(|| anyhow::anyhow!("No metrics JSON on stderr: {}", stderr))
+    };
+
+    let first = index()?;
+    let chunks = first["chunks"].as_u64().unwrap();
+    assert!(chunks >= 2);
+    assert_eq!(first["chunks_embedded"], chunks);
+    assert_eq!(first["embedding_cache_hits"], 0);
+
+    // Rebuilding the index from unchanged files runs no chunk through the model
+    let second = index()?;
+    assert_eq!(second["chunks"], chunks);
+    assert_eq!(second["chunks_embedded"], 0);
+    assert_eq!(second["embedding_cache_hits"], chunks);
+
+    Ok(())
+}
+
+#[test]
+fn test_search_rejects_empty_query() -> Result<()> {
+    let dir = tempdir()?;
+    for query in ["", "   "] {
+        let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+            .env("RUST_LOG", "off")
+            .env("CODE_RAG__DB_PATH", dir.path().join("db"))
+            .arg("search")
+            .arg(query)
+            .arg("--json")
+            .output()?;
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr)?.contains("Search query is empty"));
+    }
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use code_rag::config::AppConfig;
+use code_rag::config::{AppConfig, ConfigSource};
 use std::env;
 
 #[test]
@@ -30,3 +30,77 @@ fn test_default_values() {
     assert_eq!(config.chunk_size, 1024);
     assert_eq!(config.log_level, "info");
 }
+
+#[test]
+fn test_workspace_forms() {
+    let dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+    let path = dir.path().join("code-rag.toml");
+    std::fs::write(
+        &path,
+        "db_path = 'root_db'\n\n[workspaces]\nsmall = '/src/small'\nlarge = { source = '/src/large', db = '/mnt/large_db' }\n",
+    )
+    .expect("Failed to write config");
+
+    let config = AppConfig::from_path(Some(path.to_string_lossy().to_string()))
+        .expect("Failed to load config");
+
+    assert_eq!(config.workspaces["small"].source(), "/src/small");
+    assert_eq!(config.workspaces["large"].source(), "/src/large");
+    assert_eq!(config.workspace_db_path("large"), "/mnt/large_db");
+    // db_path itself may be overridden by other tests through the environment
+    assert_eq!(
+        config.workspace_db_path("small"),
+        std::path::Path::new(&config.db_path)
+            .join("small")
+            .to_string_lossy()
+    );
+    assert_eq!(config.workspace_db_path("default"), config.db_path);
+}
+
+#[test]
+fn test_include_extensions() {
+    let dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+    let path = dir.path().join("code-rag.toml");
+    std::fs::write(&path, "include_extensions = ['rs', '.PY']\n").unwrap();
+
+    let config = AppConfig::from_path(Some(path.to_string_lossy().to_string()))
+        .expect("Failed to load config");
+    assert!(config.includes_extension("rs"));
+    assert!(config.includes_extension("py"));
+    assert!(!config.includes_extension("js"));
+    assert!(!config.includes_extension(""));
+
+    let default = AppConfig::new().expect("Failed to load config");
+    assert!(default.include_extensions.is_empty());
+    assert!(default.includes_extension("js"));
+}
+
+#[test]
+fn test_sources_attribute_env_overrides() {
+    // A key no other test sets
+    let key = "CODE_RAG__EXPLAIN_TOP_K";
+    env::set_var(key, "7");
+
+    let dir = tempfile::TempDir::new().expect("Failed to create temp dir");
+    let path = dir.path().join("code-rag.toml");
+    std::fs::write(&path, "explain_top_k = 5\nchunk_balance_target = 300\n").unwrap();
+    let sources = AppConfig::sources(Some(path.to_string_lossy().to_string()))
+        .expect("Failed to read config sources");
+    env::remove_var(key);
+
+    let find = |name: &str| {
+        sources
+            .iter()
+            .find(|s| s.key == name)
+            .unwrap_or_else(|| panic!("{} not reported", name))
+    };
+    let explain = find("explain_top_k");
+    assert_eq!(explain.source, ConfigSource::Env);
+    assert_eq!(explain.value, serde_json::json!("7"));
+
+    let balance = find("chunk_balance_target");
+    assert_eq!(balance.source, ConfigSource::ConfigFile);
+    assert_eq!(balance.value, serde_json::json!(300));
+
+    assert_eq!(find("mcp_ordered_responses").source, ConfigSource::Default);
+}
@@ -13,6 +13,19 @@ fn test_context_optimizer_merging() {
             line_end: 11,
             last_modified: 0,
             calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         },
         // Lines 12-13
         SearchResult {
@@ -24,6 +37,19 @@ fn test_context_optimizer_merging() {
             line_end: 13,
             last_modified: 0,
             calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         },
         // Another file
         SearchResult {
@@ -35,6 +61,19 @@ fn test_context_optimizer_merging() {
             line_end: 101,
             last_modified: 0,
             calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         },
     ];
 
@@ -71,6 +110,19 @@ fn test_context_optimizer_budgeting() {
             line_end: 2,
             last_modified: 0,
             calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
         });
     }
 
@@ -83,3 +135,50 @@ fn test_context_optimizer_budgeting() {
     assert!(optimized.len() < 10);
     assert!(!optimized.is_empty());
 }
+
+#[test]
+fn test_context_optimizer_reports_budget_usage() {
+    let results: Vec<SearchResult> = (0..10)
+        .map(|i| SearchResult {
+            rank: i + 1,
+            score: 1.0 - (i as f32 * 0.01),
+            filename: format!("file{}.rs", i),
+            code: "fn budget_check() { let value = compute(); }".to_string(),
+            line_start: 1,
+            line_end: 2,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            vector: None,
+            normalized_score: 0.0,
+            kind: Default::default(),
+            uri: String::new(),
+            confidence: None,
+            permissions: None,
+            explanation: None,
+            snippets: Vec::new(),
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: None,
+        })
+        .collect();
+
+    let budget = 40;
+    let optimizer = ContextOptimizer::new(budget);
+    let optimized = optimizer
+        .optimize_with_stats(results)
+        .expect("Optimization failed");
+
+    let bpe = tiktoken_rs::cl100k_base().unwrap();
+    let selected_tokens: usize = optimized
+        .chunks
+        .iter()
+        .map(|c| bpe.encode_with_special_tokens(&c.code).len())
+        .sum();
+
+    assert!(!optimized.chunks.is_empty());
+    assert_eq!(optimized.stats.context_tokens_used, selected_tokens);
+    assert!(optimized.stats.context_tokens_used <= budget);
+    assert_eq!(optimized.stats.chunks_dropped, 10 - optimized.chunks.len());
+}
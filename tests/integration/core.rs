@@ -1,7 +1,7 @@
 use code_rag::bm25::BM25Index;
 
-use code_rag::indexer::CodeChunker;
-use code_rag::search::CodeSearcher;
+use code_rag::indexer::{CodeChunk, CodeChunker};
+use code_rag::search::{CodeSearcher, Subsystem};
 
 use std::fs;
 use std::path::Path;
@@ -147,6 +147,8 @@ async fn test_search_rust_function() {
         results[0].filename.contains("test.rs"),
         "Top result should be from test.rs"
     );
+    let uri = code_rag::search::ChunkUri::parse(&results[0].uri).expect("Result has no URI");
+    assert_eq!(uri, results[0].chunk_uri("default"));
     println!("✓ Found {} results for Rust function search", results.len());
 
     cleanup_test_db(&db_path);
@@ -497,6 +499,22 @@ fn test_language_detection() {
         CodeChunker::get_language("yaml").is_some(),
         "YAML not detected"
     );
+    for ext in ["kt", "kts"] {
+        assert!(
+            CodeChunker::get_language(ext).is_some(),
+            "Kotlin not detected"
+        );
+    }
+    assert!(
+        CodeChunker::get_language("swift").is_some(),
+        "Swift not detected"
+    );
+    for ext in ["scala", "sc"] {
+        assert!(
+            CodeChunker::get_language(ext).is_some(),
+            "Scala not detected"
+        );
+    }
     assert!(
         CodeChunker::get_language("unknown").is_none(),
         "Unknown extension should return None"
@@ -562,6 +580,129 @@ class Calculator:
     println!("✓ Python chunking produced {} chunks", chunks.len());
 }
 
+/// Start line and symbol of each chunk, in file order.
+fn chunk_symbols(filename: &str, code: &str) -> Vec<(usize, Option<String>)> {
+    let chunker = CodeChunker::default();
+    let mut reader = std::io::Cursor::new(code.as_bytes());
+    let chunks = chunker.chunk_file(filename, &mut reader, 0).unwrap();
+    chunks
+        .into_iter()
+        .map(|c| (c.line_start, c.symbol))
+        .collect()
+}
+
+#[test]
+fn test_chunking_kotlin_file() {
+    let kotlin_code = r#"
+class Greeter(val name: String) {
+    fun greet(): String {
+        return "Hello, $name"
+    }
+
+    companion object {
+        fun create(): Greeter = Greeter("world")
+    }
+}
+
+object Registry {
+    fun register(g: Greeter) {}
+}
+"#;
+
+    let symbols = chunk_symbols("Greeter.kt", kotlin_code);
+    for expected in [
+        (2, Some("Greeter")),
+        (3, Some("greet")),
+        (7, None),
+        (8, Some("create")),
+        (12, Some("Registry")),
+        (13, Some("register")),
+    ] {
+        assert!(
+            symbols
+                .iter()
+                .any(|(line, symbol)| (*line, symbol.as_deref()) == expected),
+            "Missing Kotlin chunk {:?} in {:?}",
+            expected,
+            symbols
+        );
+    }
+}
+
+#[test]
+fn test_chunking_swift_file() {
+    let swift_code = r#"
+protocol Shape {
+    func area() -> Double
+}
+
+struct Circle: Shape {
+    let radius: Double
+
+    init(radius: Double) {
+        self.radius = radius
+    }
+
+    func area() -> Double {
+        return 3.14 * radius * radius
+    }
+}
+"#;
+
+    let symbols = chunk_symbols("Shapes.swift", swift_code);
+    for expected in [
+        (2, Some("Shape")),
+        (6, Some("Circle")),
+        (9, Some("init")),
+        (13, Some("area")),
+    ] {
+        assert!(
+            symbols
+                .iter()
+                .any(|(line, symbol)| (*line, symbol.as_deref()) == expected),
+            "Missing Swift chunk {:?} in {:?}",
+            expected,
+            symbols
+        );
+    }
+}
+
+#[test]
+fn test_chunking_scala_file() {
+    let scala_code = r#"
+trait Shape {
+  def area: Double
+}
+
+class Circle(radius: Double) extends Shape {
+  def area: Double = math.Pi * radius * radius
+}
+
+object Shapes {
+  def describe(shape: Shape): String = s"area ${shape.area}"
+}
+"#;
+
+    let symbols = chunk_symbols("Shapes.scala", scala_code);
+    for expected in [
+        (2, Some("Shape")),
+        (3, Some("area")),
+        (6, Some("Circle")),
+        (7, Some("area")),
+        (10, Some("Shapes")),
+        (11, Some("describe")),
+    ] {
+        assert!(
+            symbols
+                .iter()
+                .any(|(line, symbol)| (*line, symbol.as_deref()) == expected),
+            "Missing Scala chunk {:?} in {:?}",
+            expected,
+            symbols
+        );
+    }
+}
+
 #[tokio::test]
 async fn test_lancedb_filename_index() {
     let (storage, embedder, chunker, db_path) = setup_test_env("index_verification").await;
@@ -635,3 +776,1242 @@ async fn test_lancedb_filename_index() {
 
     cleanup_test_db(&db_path);
 }
+
+#[tokio::test]
+async fn test_single_candidate_skips_rerank() {
+    let (storage, embedder, _, db_path) = setup_test_env("single_candidate").await;
+
+    let chunk = CodeChunk {
+        filename: "only.rs".to_string(),
+        code: "fn only_function() { println!(\"alone\"); }".to_string(),
+        line_start: 1,
+        line_end: 1,
+        last_modified: 0,
+        calls: vec![],
+        part: None,
+        is_test: false,
+        kind: Default::default(),
+        permissions: None,
+        symbol: None,
+        scope: Vec::new(),
+        item_kind: Default::default(),
+    };
+    let embeddings = embedder
+        .embed(vec![chunk.code.clone()], None)
+        .expect("Failed to embed");
+    let chunks = vec![chunk];
+    let (_, filenames, codes, line_starts, line_ends, last_modified, calls) =
+        prepare_chunks(&chunks);
+    let ids = vec![format!("{}-{}-{}", "only.rs", 1, 1)];
+    storage
+        .add_chunks(
+            "default",
+            ids,
+            filenames,
+            codes,
+            line_starts,
+            line_ends,
+            last_modified,
+            calls,
+            embeddings,
+        )
+        .await
+        .expect("Failed to add chunks");
+
+    let searcher = CodeSearcher::new(
+        Some(std::sync::Arc::new(storage)),
+        Some(std::sync::Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    );
+    // Reranking enabled, but with a single candidate the fused RRF score must survive
+    let results = searcher
+        .semantic_search("lonely function", 5, None, None, false, None, None, false)
+        .await
+        .expect("Search failed");
+
+    assert_eq!(results.len(), 1);
+    let expected = 1.0 / 61.0;
+    assert!(
+        (results[0].score - expected).abs() < 1e-6,
+        "Expected fused score {}, got {} (reranker was applied)",
+        expected,
+        results[0].score
+    );
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_rerank_skip_extensions() {
+    let (storage, embedder, _, db_path) = setup_test_env("rerank_skip").await;
+
+    let chunks = vec![
+        CodeChunk {
+            filename: "settings.json".to_string(),
+            code: "{ \"database\": { \"host\": \"localhost\", \"port\": 5432 } }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+        CodeChunk {
+            filename: "db.rs".to_string(),
+            code: "fn connect_database(host: &str, port: u16) -> Connection { todo!() }"
+                .to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+        CodeChunk {
+            filename: "pool.rs".to_string(),
+            code: "fn database_pool(size: usize) -> Pool { Pool::new(size) }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+    ];
+    let embeddings = embedder
+        .embed(chunks.iter().map(|c| c.code.clone()).collect(), None)
+        .expect("Failed to embed");
+    let (_, filenames, codes, line_starts, line_ends, last_modified, calls) =
+        prepare_chunks(&chunks);
+    let ids = chunks
+        .iter()
+        .map(|c| format!("{}-{}-{}", c.filename, c.line_start, c.line_end))
+        .collect();
+    storage
+        .add_chunks(
+            "default",
+            ids,
+            filenames,
+            codes,
+            line_starts,
+            line_ends,
+            last_modified,
+            calls,
+            embeddings,
+        )
+        .await
+        .expect("Failed to add chunks");
+
+    let searcher = CodeSearcher::new(
+        Some(std::sync::Arc::new(storage)),
+        Some(std::sync::Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    )
+    .with_rerank_skip_extensions(vec!["json".to_string()]);
+
+    let fused = searcher
+        .semantic_search(
+            "database connection",
+            5,
+            None,
+            None,
+            true,
+            None,
+            None,
+            false,
+        )
+        .await
+        .expect("Search failed");
+    let reranked = searcher
+        .semantic_search(
+            "database connection",
+            5,
+            None,
+            None,
+            false,
+            None,
+            None,
+            false,
+        )
+        .await
+        .expect("Search failed");
+
+    let score_of = |results: &[code_rag::search::SearchResult], name: &str| {
+        results
+            .iter()
+            .find(|r| r.filename == name)
+            .map(|r| r.score)
+            .expect("missing result")
+    };
+
+    assert_eq!(reranked.len(), 3);
+    assert_eq!(
+        score_of(&reranked, "settings.json"),
+        score_of(&fused, "settings.json"),
+        "JSON result must keep its fused score"
+    );
+    // Reranked results are mapped onto the range of their fused scores
+    let fused_rust = [score_of(&fused, "db.rs"), score_of(&fused, "pool.rs")];
+    let (low, high) = (
+        fused_rust[0].min(fused_rust[1]),
+        fused_rust[0].max(fused_rust[1]),
+    );
+    for name in ["db.rs", "pool.rs"] {
+        let score = score_of(&reranked, name);
+        assert!(
+            (low - 1e-6..=high + 1e-6).contains(&score),
+            "{} scored {} outside the fused range {}..={}",
+            name,
+            score,
+            low,
+            high
+        );
+    }
+    assert!(reranked.windows(2).all(|w| w[0].score >= w[1].score));
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_max_per_file_diversifies_results() {
+    let (storage, embedder, _, db_path) = setup_test_env("max_per_file").await;
+
+    // One file with many strong matches and one with a single match
+    let mut chunks: Vec<CodeChunk> = (0..6)
+        .map(|i| CodeChunk {
+            filename: "retry.rs".to_string(),
+            code: format!(
+                "fn retry_request_{}(attempts: u32) {{ for _ in 0..attempts {{ send_request(); }} }}",
+                i
+            ),
+            line_start: i * 10 + 1,
+            line_end: i * 10 + 5,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        })
+        .collect();
+    chunks.push(CodeChunk {
+        filename: "backoff.rs".to_string(),
+        code: "fn retry_with_backoff(delay_ms: u64) { sleep(delay_ms); send_request(); }"
+            .to_string(),
+        line_start: 1,
+        line_end: 1,
+        last_modified: 0,
+        calls: vec![],
+        part: None,
+        is_test: false,
+        kind: Default::default(),
+        permissions: None,
+        symbol: None,
+        scope: Vec::new(),
+        item_kind: Default::default(),
+    });
+
+    let embeddings = embedder
+        .embed(chunks.iter().map(|c| c.code.clone()).collect(), None)
+        .expect("Failed to embed");
+    let (_, filenames, codes, line_starts, line_ends, last_modified, calls) =
+        prepare_chunks(&chunks);
+    let ids = chunks
+        .iter()
+        .map(|c| format!("{}-{}-{}", c.filename, c.line_start, c.line_end))
+        .collect();
+    storage
+        .add_chunks(
+            "default",
+            ids,
+            filenames,
+            codes,
+            line_starts,
+            line_ends,
+            last_modified,
+            calls,
+            embeddings,
+        )
+        .await
+        .expect("Failed to add chunks");
+
+    let searcher = CodeSearcher::new(
+        Some(std::sync::Arc::new(storage)),
+        Some(std::sync::Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    )
+    .with_max_per_file(Some(1));
+
+    let results = searcher
+        .semantic_search("retry request", 2, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+
+    let files: Vec<&str> = results.iter().map(|r| r.filename.as_str()).collect();
+    assert_eq!(results.len(), 2);
+    assert!(files.contains(&"retry.rs"));
+    assert!(
+        files.contains(&"backoff.rs"),
+        "second file should surface with max_per_file 1: {:?}",
+        files
+    );
+
+    cleanup_test_db(&db_path);
+}
+
+async fn add_chunk(
+    storage: &code_rag::storage::Storage,
+    embedder: &code_rag::embedding::Embedder,
+    filename: &str,
+    code: &str,
+    calls: &[&str],
+) {
+    let chunks = vec![CodeChunk {
+        filename: filename.to_string(),
+        code: code.to_string(),
+        line_start: 1,
+        line_end: 1,
+        last_modified: 0,
+        calls: calls.iter().map(|c| c.to_string()).collect(),
+        part: None,
+        is_test: false,
+        kind: Default::default(),
+        permissions: None,
+        symbol: None,
+        scope: Vec::new(),
+        item_kind: Default::default(),
+    }];
+    let embeddings = embedder
+        .embed(vec![code.to_string()], None)
+        .expect("Failed to embed");
+    let (_, filenames, codes, line_starts, line_ends, last_modified, calls) =
+        prepare_chunks(&chunks);
+    storage
+        .add_chunks(
+            "default",
+            vec![format!("{}-1-1", filename)],
+            filenames,
+            codes,
+            line_starts,
+            line_ends,
+            last_modified,
+            calls,
+            embeddings,
+        )
+        .await
+        .expect("Failed to add chunks");
+}
+
+#[tokio::test]
+async fn test_search_as_of_previous_version() {
+    use code_rag::storage::{AsOf, Storage};
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("as_of").await;
+
+    // First generation of the index
+    add_chunk(
+        &storage,
+        &embedder,
+        "old_parser.rs",
+        "fn parse_config(path: &str) -> Config { load_toml(path) }",
+        &[],
+    )
+    .await;
+    let first_version = storage.version().await.expect("No version");
+
+    // Reindex with different content
+    storage
+        .batch_delete_files(&["old_parser.rs".to_string()], "default")
+        .await
+        .expect("Failed to delete");
+    add_chunk(
+        &storage,
+        &embedder,
+        "new_parser.rs",
+        "fn parse_settings(path: &str) -> Settings { load_yaml(path) }",
+        &[],
+    )
+    .await;
+    assert!(storage.version().await.unwrap() > first_version);
+
+    let embedder = Arc::new(embedder);
+    let search = |storage: Storage| {
+        let searcher = CodeSearcher::new(
+            Some(Arc::new(storage)),
+            Some(embedder.clone()),
+            None,
+            None,
+            1.0,
+            1.0,
+            60.0,
+        );
+        async move {
+            searcher
+                .semantic_search("parse config", 5, None, None, true, None, None, false)
+                .await
+                .expect("Search failed")
+                .into_iter()
+                .map(|r| r.filename)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    assert_eq!(search(storage).await, ["new_parser.rs"]);
+
+    let snapshot = Storage::new(&db_path, "code_chunks").await.unwrap();
+    assert_eq!(
+        snapshot
+            .checkout(AsOf::Version(first_version))
+            .await
+            .unwrap(),
+        first_version
+    );
+    assert_eq!(search(snapshot).await, ["old_parser.rs"]);
+
+    let missing = Storage::new(&db_path, "code_chunks").await.unwrap();
+    let err = missing
+        .checkout(AsOf::Version(first_version + 1000))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("available versions"));
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_show_callers() {
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("callers").await;
+    add_chunk(
+        &storage,
+        &embedder,
+        "config.rs",
+        "fn parse_config(path: &str) -> Config { load_toml(path) }",
+        &["load_toml"],
+    )
+    .await;
+    add_chunk(
+        &storage,
+        &embedder,
+        "main.rs",
+        "fn main() { let config = parse_config(\"app.toml\"); run(config); }",
+        &["parse_config", "run"],
+    )
+    .await;
+    add_chunk(
+        &storage,
+        &embedder,
+        "math.rs",
+        "fn add(a: i32, b: i32) -> i32 { a + b }",
+        &[],
+    )
+    .await;
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    );
+    let results = searcher
+        .semantic_search("parse config file", 3, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    let definition = results
+        .iter()
+        .find(|r| r.filename == "config.rs")
+        .expect("Definition not found");
+
+    let callers = searcher
+        .find_callers(definition, 5, None)
+        .await
+        .expect("Caller lookup failed");
+    assert_eq!(callers.len(), 1);
+    assert_eq!(callers[0].filename, "main.rs");
+    assert_eq!((callers[0].line_start, callers[0].line_end), (1, 1));
+
+    // Bounded by the limit
+    assert!(searcher
+        .find_callers(definition, 0, None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_find_callers_of_matches_last_path_segment() {
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("callers_of").await;
+    let chunks: [(&str, &str, &[&str]); 4] = [
+        (
+            "service.rs",
+            "fn list(&self) { self.db.query(\"users\") }",
+            &["self.db.query"],
+        ),
+        ("cli.rs", "fn main() { query(args) }", &["query"]),
+        ("report.rs", "fn totals() { query_all() }", &["query_all"]),
+        (
+            "app.rs",
+            "fn start() { Db::query(\"health\") }",
+            &["Db::query"],
+        ),
+    ];
+    for (filename, code, calls) in chunks {
+        add_chunk(&storage, &embedder, filename, code, calls).await;
+    }
+
+    let searcher = CodeSearcher::new(Some(Arc::new(storage)), None, None, None, 1.0, 1.0, 60.0);
+    let callers = searcher
+        .find_callers_of("query", None)
+        .await
+        .expect("Caller lookup failed");
+    let files: Vec<&str> = callers.iter().map(|c| c.filename.as_str()).collect();
+    assert_eq!(files, vec!["app.rs", "cli.rs", "service.rs"]);
+    assert_eq!(callers[2].calls, vec!["self.db.query".to_string()]);
+    assert_eq!(callers[0].rank, 1);
+
+    assert!(searcher
+        .find_callers_of("missing", None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_importance_boost_ranks_widely_called_function_higher() {
+    use code_rag::indexer::call_graph_importance;
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("importance").await;
+    // Equally relevant definitions; only their callers differ
+    add_chunk(
+        &storage,
+        &embedder,
+        "widget_alpha.rs",
+        "fn load_widget_alpha(path: &str) -> Widget { Widget::open(path) }",
+        &["open"],
+    )
+    .await;
+    add_chunk(
+        &storage,
+        &embedder,
+        "widget_bravo.rs",
+        "fn load_widget_bravo(path: &str) -> Widget { Widget::open(path) }",
+        &["open"],
+    )
+    .await;
+    for (i, callee) in ["alpha", "alpha", "alpha", "bravo"].iter().enumerate() {
+        add_chunk(
+            &storage,
+            &embedder,
+            &format!("caller_{}.rs", i),
+            &format!(
+                "fn render_{}() {{ draw(load_widget_{}(\"a.bin\")); }}",
+                i, callee
+            ),
+            &["draw", &format!("load_widget_{}", callee)],
+        )
+        .await;
+    }
+
+    // The post-index pass of `code-rag index`
+    let nodes = storage
+        .call_graph("default")
+        .await
+        .expect("Call graph failed");
+    let importance = call_graph_importance(&nodes);
+    assert_eq!(importance.get("widget_alpha.rs-1-1"), Some(&1.0));
+    storage
+        .set_importance("default", &importance)
+        .await
+        .expect("Storing importance failed");
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    )
+    .with_importance_boost(1.0);
+    let results = searcher
+        .semantic_search("load widget", 6, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    let position = |filename: &str| {
+        results
+            .iter()
+            .position(|r| r.filename == filename)
+            .unwrap_or_else(|| panic!("{} not found", filename))
+    };
+    assert!(position("widget_alpha.rs") < position("widget_bravo.rs"));
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_split_chunks_return_part_metadata() {
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("chunk_parts").await;
+    let body: String = (1..=20)
+        .map(|i| format!("    let retry_{} = backoff_delay({}, attempts);\n", i, i))
+        .collect();
+    let code = format!("fn schedule_retries(attempts: u32) {{\n{}}}\n", body);
+    let chunker = CodeChunker::new(300, 30);
+    let mut reader = std::io::Cursor::new(code.as_bytes());
+    let chunks = chunker.chunk_file("retry.rs", &mut reader, 0).unwrap();
+    assert!(chunks.len() > 1);
+
+    let texts: Vec<String> = chunks.iter().map(|c| c.code.clone()).collect();
+    let embeddings = embedder.embed(texts, None).expect("Embed failed");
+    let (_, filenames, codes, starts, ends, mtimes, calls) = prepare_chunks(&chunks);
+    let ids = chunks
+        .iter()
+        .map(|c| format!("{}-{}-{}", c.filename, c.line_start, c.line_end))
+        .collect();
+    let metadata = chunks.iter().map(|c| c.metadata()).collect();
+    storage
+        .add_chunks_with_metadata(
+            "default", ids, filenames, codes, starts, ends, mtimes, calls, embeddings, metadata,
+        )
+        .await
+        .expect("Add failed");
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    );
+    let results = searcher
+        .semantic_search("retry backoff", 10, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    assert_eq!(results.len(), chunks.len());
+
+    let mut indices = Vec::new();
+    for result in &results {
+        let part = result.part.expect("Split chunk without part metadata");
+        assert_eq!(part.count, chunks.len());
+        assert_eq!((part.parent_line_start, part.parent_line_end), (1, 22));
+        assert!(result.line_start as usize >= part.parent_line_start);
+        assert!(result.line_end as usize <= part.parent_line_end);
+        indices.push(part.index);
+    }
+    indices.sort();
+    assert_eq!(indices, (1..=chunks.len()).collect::<Vec<_>>());
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_keyword_search_without_embedder() {
+    let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let db_path = temp_dir.path().to_str().unwrap();
+
+    let writer = BM25Index::new(db_path, false, "log").expect("Failed to create BM25 index");
+    let chunks = vec![
+        CodeChunk {
+            filename: "auth.rs".to_string(),
+            code: "fn validate_token(token: &str) -> bool { !token.is_empty() }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+        CodeChunk {
+            filename: "math.rs".to_string(),
+            code: "fn add(a: i32, b: i32) -> i32 { a + b }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+    ];
+    writer
+        .add_chunks(&chunks, "default")
+        .expect("Failed to add chunks");
+    writer.commit().expect("Failed to commit");
+    drop(writer);
+
+    let bm25 = BM25Index::new(db_path, true, "log").expect("Failed to open BM25 index");
+    // Neither storage nor an embedder: the model failed to load
+    let searcher = CodeSearcher::new(
+        None,
+        None,
+        Some(std::sync::Arc::new(bm25)),
+        None,
+        1.0,
+        1.0,
+        60.0,
+    );
+
+    let outcome = searcher
+        .search_with_stats(
+            "validate_token",
+            5,
+            None,
+            None,
+            false,
+            Some("default".to_string()),
+            None,
+            false,
+        )
+        .await
+        .expect("Keyword fallback should not fail");
+
+    assert!(outcome.degraded);
+    assert_eq!(
+        outcome.unavailable,
+        [Subsystem::Embedder, Subsystem::Reranker]
+    );
+    assert_eq!(outcome.results.len(), 1);
+    assert_eq!(outcome.results[0].filename, "auth.rs");
+}
+
+#[tokio::test]
+async fn test_search_include_vectors() {
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("include_vectors").await;
+    add_chunk(
+        &storage,
+        &embedder,
+        "config.rs",
+        "fn parse_config(path: &str) -> Config { load_toml(path) }",
+        &[],
+    )
+    .await;
+    add_chunk(
+        &storage,
+        &embedder,
+        "math.rs",
+        "fn add(a: i32, b: i32) -> i32 { a + b }",
+        &[],
+    )
+    .await;
+    let dim = embedder.dim();
+    let storage = Arc::new(storage);
+    let embedder = Arc::new(embedder);
+
+    let searcher = |include: bool| {
+        CodeSearcher::new(
+            Some(storage.clone()),
+            Some(embedder.clone()),
+            None,
+            None,
+            1.0,
+            1.0,
+            60.0,
+        )
+        .with_include_vectors(include)
+    };
+
+    let results = searcher(true)
+        .semantic_search("parse config", 2, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    assert_eq!(results.len(), 2);
+    for result in &results {
+        let vector = result.vector.as_ref().expect("Vector missing");
+        assert_eq!(vector.len(), dim);
+    }
+
+    let results = searcher(false)
+        .semantic_search("parse config", 2, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    assert!(results.iter().all(|r| r.vector.is_none()));
+    let json = serde_json::to_string(&results).unwrap();
+    assert!(!json.contains("\"vector\""));
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_normalized_score_in_unit_range_with_and_without_rerank() {
+    use code_rag::search::ScoreCalibration;
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("normalized_score").await;
+    for (filename, code) in [
+        (
+            "retry.rs",
+            "fn retry_request(attempts: u32) { send_request(); }",
+        ),
+        (
+            "config.rs",
+            "fn parse_config(path: &str) -> Config { load(path) }",
+        ),
+        (
+            "log.rs",
+            "fn write_log(line: &str) { println!(\"{}\", line); }",
+        ),
+        ("math.rs", "fn add(a: i32, b: i32) -> i32 { a + b }"),
+    ] {
+        add_chunk(&storage, &embedder, filename, code, &[]).await;
+    }
+
+    let storage = Arc::new(storage);
+    let embedder = Arc::new(embedder);
+    for calibration in [ScoreCalibration::MinMax, ScoreCalibration::Softmax] {
+        let searcher = CodeSearcher::new(
+            Some(storage.clone()),
+            Some(embedder.clone()),
+            None,
+            None,
+            1.0,
+            1.0,
+            60.0,
+        )
+        .with_score_calibration(calibration);
+
+        for no_rerank in [true, false] {
+            let results = searcher
+                .semantic_search("retry request", 4, None, None, no_rerank, None, None, false)
+                .await
+                .expect("Search failed");
+            assert_eq!(results.len(), 4);
+
+            for result in &results {
+                assert!(
+                    (0.0..=1.0).contains(&result.normalized_score),
+                    "{:?} no_rerank={}: {} out of range",
+                    calibration,
+                    no_rerank,
+                    result.normalized_score
+                );
+            }
+            // Results are ordered by score, so normalized scores must not increase
+            for pair in results.windows(2) {
+                assert!(pair[0].score >= pair[1].score);
+                assert!(pair[0].normalized_score >= pair[1].normalized_score);
+            }
+            if calibration == ScoreCalibration::MinMax {
+                assert_eq!(results[0].normalized_score, 1.0);
+            }
+        }
+    }
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_confidence_buckets_follow_normalized_score() {
+    use code_rag::search::{Confidence, ConfidenceThresholds};
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("confidence").await;
+    for (filename, code) in [
+        (
+            "retry.rs",
+            "fn retry_request(attempts: u32) { send_request(); }",
+        ),
+        (
+            "config.rs",
+            "fn parse_config(path: &str) -> Config { load(path) }",
+        ),
+        (
+            "log.rs",
+            "fn write_log(line: &str) { println!(\"{}\", line); }",
+        ),
+        ("math.rs", "fn add(a: i32, b: i32) -> i32 { a + b }"),
+    ] {
+        add_chunk(&storage, &embedder, filename, code, &[]).await;
+    }
+
+    let thresholds = ConfidenceThresholds {
+        high: 0.8,
+        medium: 0.5,
+    };
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        None,
+        1.0,
+        1.0,
+        60.0,
+    )
+    .with_confidence(Some(thresholds));
+
+    let results = searcher
+        .semantic_search("retry request", 4, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    assert_eq!(results.len(), 4);
+    // Min-max calibration puts the best result at 1.0 and the worst at 0.0
+    assert_eq!(results[0].confidence, Some(Confidence::High));
+    assert_eq!(results[3].confidence, Some(Confidence::Low));
+    for result in &results {
+        let expected = if result.normalized_score >= 0.8 {
+            Confidence::High
+        } else if result.normalized_score >= 0.5 {
+            Confidence::Medium
+        } else {
+            Confidence::Low
+        };
+        assert_eq!(result.confidence, Some(expected));
+    }
+    let json = serde_json::to_value(&results[0]).unwrap();
+    assert_eq!(json["confidence"], "high");
+
+    // Without thresholds no label is added
+    let results = searcher
+        .with_confidence(None)
+        .semantic_search("retry request", 4, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    assert!(results.iter().all(|r| r.confidence.is_none()));
+    assert!(!serde_json::to_string(&results)
+        .unwrap()
+        .contains("confidence"));
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_embed_limiter_caps_concurrent_model_calls() {
+    use code_rag::embedding::EmbedLimiter;
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("embed_limiter").await;
+    for (filename, code) in [
+        (
+            "retry.rs",
+            "fn retry_request(attempts: u32) { send_request(); }",
+        ),
+        (
+            "config.rs",
+            "fn parse_config(path: &str) -> Config { load(path) }",
+        ),
+        (
+            "log.rs",
+            "fn write_log(line: &str) { println!(\"{}\", line); }",
+        ),
+    ] {
+        add_chunk(&storage, &embedder, filename, code, &[]).await;
+    }
+
+    let limiter = Arc::new(EmbedLimiter::new(2));
+    let searcher = Arc::new(
+        CodeSearcher::new(
+            Some(Arc::new(storage)),
+            Some(Arc::new(embedder)),
+            None,
+            None,
+            1.0,
+            1.0,
+            60.0,
+        )
+        .with_embed_limiter(Some(limiter.clone())),
+    );
+
+    let searches: Vec<_> = (0..16)
+        .map(|i| {
+            let searcher = searcher.clone();
+            tokio::spawn(async move {
+                searcher
+                    .semantic_search(
+                        &format!("retry request {}", i),
+                        3,
+                        None,
+                        None,
+                        false,
+                        None,
+                        None,
+                        false,
+                    )
+                    .await
+            })
+        })
+        .collect();
+    for search in searches {
+        search
+            .await
+            .expect("Search panicked")
+            .expect("Search failed");
+    }
+
+    // Each search embeds the query and reranks: 32 model calls compete for 2 slots
+    assert!(limiter.peak() >= 1);
+    assert!(
+        limiter.peak() <= 2,
+        "{} model calls ran at once",
+        limiter.peak()
+    );
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_sharded_storage_matches_single_table() {
+    use code_rag::storage::{ShardKey, Storage};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    let (storage, embedder, _, db_path) = setup_test_env("sharded").await;
+    let sharded = Storage::new(&db_path, "code_chunks")
+        .await
+        .unwrap()
+        .with_shards(3, ShardKey::File);
+    sharded.init(embedder.dim()).await.unwrap();
+    assert_eq!(sharded.shard_count(), 3);
+
+    let files = [
+        (
+            "net/retry.rs",
+            "fn retry_request(attempts: u32) { send_request(); }",
+        ),
+        (
+            "net/backoff.rs",
+            "fn backoff_delay(attempt: u32) -> u64 { 2u64.pow(attempt) }",
+        ),
+        (
+            "config/parse.rs",
+            "fn parse_config(path: &str) -> Config { load(path) }",
+        ),
+        (
+            "log/write.rs",
+            "fn write_log(line: &str) { println!(\"{}\", line); }",
+        ),
+        (
+            "db/pool.rs",
+            "fn open_pool(size: usize) -> Pool { Pool::new(size) }",
+        ),
+        (
+            "net/timeout.rs",
+            "fn request_timeout(ms: u64) -> Duration { Duration::from_millis(ms) }",
+        ),
+    ];
+    let shards: HashSet<usize> = files
+        .iter()
+        .map(|(filename, _)| ShardKey::File.shard_of(filename, 3))
+        .collect();
+    assert!(shards.len() > 1, "test files all map to one shard");
+    for (filename, code) in files {
+        add_chunk(&storage, &embedder, filename, code, &[]).await;
+        add_chunk(&sharded, &embedder, filename, code, &[]).await;
+    }
+
+    let embedder = Arc::new(embedder);
+    let search = |storage: Storage| {
+        let searcher = CodeSearcher::new(
+            Some(Arc::new(storage)),
+            Some(embedder.clone()),
+            None,
+            None,
+            1.0,
+            1.0,
+            60.0,
+        );
+        async move {
+            searcher
+                .semantic_search("retry request", 4, None, None, true, None, None, false)
+                .await
+                .expect("Search failed")
+                .into_iter()
+                .map(|r| r.filename)
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let chunks = sharded.get_file_chunks("net/retry.rs", None).await.unwrap();
+    assert_eq!(chunks.len(), 1);
+    let expected = search(storage).await;
+    assert_eq!(expected.len(), 4);
+    assert_eq!(search(sharded).await, expected);
+
+    // Deletes reach the shard holding the file
+    let sharded = Storage::new(&db_path, "code_chunks")
+        .await
+        .unwrap()
+        .with_shards(3, ShardKey::File);
+    sharded
+        .batch_delete_files(&["net/retry.rs".to_string()], "default")
+        .await
+        .unwrap();
+    assert!(sharded
+        .get_file_chunks("net/retry.rs", None)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(!search(sharded).await.contains(&"net/retry.rs".to_string()));
+    assert!(Storage::table_exists_on_disk(
+        Path::new(&db_path),
+        "code_chunks"
+    ));
+
+    cleanup_test_db(&db_path);
+}
+
+/// Span name and the name of its parent, if any.
+type SpanRecord = (String, Option<String>);
+
+/// Records every span opened.
+#[derive(Clone, Default)]
+struct SpanRecorder(std::sync::Arc<std::sync::Mutex<Vec<SpanRecord>>>);
+
+impl<S> tracing_subscriber::Layer<S> for SpanRecorder
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        _attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let span = ctx.span(id).expect("new span is registered");
+        let parent = span.parent().map(|p| p.name().to_string());
+        self.0
+            .lock()
+            .unwrap()
+            .push((span.name().to_string(), parent));
+    }
+}
+
+#[tokio::test]
+async fn test_search_emits_stage_spans() {
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let (storage, embedder, _, db_path) = setup_test_env("search_spans").await;
+    let chunks: Vec<CodeChunk> = [
+        (
+            "retry.rs",
+            "fn retry_request(attempts: u32) { send_request(); }",
+        ),
+        (
+            "config.rs",
+            "fn parse_config(path: &str) -> Config { load(path) }",
+        ),
+        ("math.rs", "fn add(a: i32, b: i32) -> i32 { a + b }"),
+    ]
+    .into_iter()
+    .map(|(filename, code)| CodeChunk {
+        filename: filename.to_string(),
+        code: code.to_string(),
+        line_start: 1,
+        line_end: 1,
+        last_modified: 0,
+        calls: vec![],
+        part: None,
+        is_test: false,
+        kind: Default::default(),
+        permissions: None,
+        symbol: None,
+        scope: Vec::new(),
+        item_kind: Default::default(),
+    })
+    .collect();
+    for chunk in &chunks {
+        add_chunk(&storage, &embedder, &chunk.filename, &chunk.code, &[]).await;
+    }
+    let writer = BM25Index::new(&db_path, false, "log").expect("Failed to create BM25 index");
+    writer
+        .add_chunks(&chunks, "default")
+        .expect("Failed to add chunks");
+    writer.commit().expect("Failed to commit");
+    drop(writer);
+    let bm25 = BM25Index::new(&db_path, true, "log").expect("Failed to open BM25 index");
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        Some(Arc::new(bm25)),
+        None,
+        1.0,
+        1.0,
+        60.0,
+    );
+
+    let recorder = SpanRecorder::default();
+    let _guard =
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+    let results = searcher
+        .semantic_search("retry request", 3, None, None, false, None, None, false)
+        .await
+        .expect("Search failed");
+    assert_eq!(results[0].filename, "retry.rs");
+
+    let spans = recorder.0.lock().unwrap().clone();
+    assert!(spans.iter().any(|(name, _)| name == "search"));
+    for stage in [
+        "search.embed",
+        "search.vector",
+        "search.bm25",
+        "search.rerank",
+    ] {
+        assert!(
+            spans
+                .iter()
+                .any(|(name, parent)| name == stage && parent.as_deref() == Some("search")),
+            "missing {} span under search in {:?}",
+            stage,
+            spans
+        );
+    }
+
+    cleanup_test_db(&db_path);
+}
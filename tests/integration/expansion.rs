@@ -1,7 +1,9 @@
 use crate::common::{cleanup_test_db, prepare_chunks, setup_test_env};
-use code_rag::llm::{LlmClient, QueryExpander};
-use code_rag::search::CodeSearcher;
+use code_rag::llm::{CircuitBreakerPolicy, LlmClient, QueryExpander};
+use code_rag::search::{CodeSearcher, Subsystem};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 struct MockLlmClient {
     response: String,
@@ -14,6 +16,38 @@ impl LlmClient for MockLlmClient {
     }
 }
 
+/// An LLM host that never answers.
+struct HangingLlmClient {
+    generate_calls: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl LlmClient for HangingLlmClient {
+    async fn generate(&self, _prompt: &str) -> anyhow::Result<String> {
+        self.generate_calls.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(String::new())
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        Ok(())
+    }
+}
+
+/// A reachable LLM host whose every generation fails.
+struct FailingLlmClient {
+    generate_calls: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl LlmClient for FailingLlmClient {
+    async fn generate(&self, _prompt: &str) -> anyhow::Result<String> {
+        self.generate_calls.fetch_add(1, Ordering::SeqCst);
+        Err(anyhow::anyhow!("model crashed"))
+    }
+}
+
 #[tokio::test]
 async fn test_search_with_expansion() {
     // 1. Setup
@@ -162,3 +196,118 @@ async fn test_search_without_expansion() {
 
     cleanup_test_db(&db_path);
 }
+
+#[tokio::test]
+async fn test_unreachable_llm_disables_expansion() {
+    let (storage, embedder, _, db_path) = setup_test_env("unreachable_llm_test").await;
+
+    let client = Arc::new(HangingLlmClient {
+        generate_calls: AtomicUsize::new(0),
+    });
+    let expander = Arc::new(
+        QueryExpander::new(client.clone()).with_probe_timeout(Duration::from_millis(200)),
+    );
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        Some(expander.clone()),
+        1.0,
+        1.0,
+        60.0,
+    );
+
+    for _ in 0..3 {
+        let start = Instant::now();
+        searcher
+            .semantic_search("query", 1, None, None, true, None, None, true)
+            .await
+            .expect("Search failed");
+        assert!(
+            start.elapsed() < Duration::from_secs(30),
+            "Search waited on the unreachable LLM"
+        );
+    }
+
+    assert_eq!(expander.health(), Some(false));
+    assert_eq!(client.generate_calls.load(Ordering::SeqCst), 0);
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_returns_expansion_terms() {
+    let (storage, embedder, _, db_path) = setup_test_env("expansion_terms_test").await;
+
+    let mock_client = MockLlmClient {
+        response: "login, sign in, authentication".to_string(),
+    };
+    let expander = QueryExpander::new(Arc::new(mock_client));
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        Some(Arc::new(expander)),
+        1.0,
+        1.0,
+        60.0,
+    );
+
+    let outcome = searcher
+        .search_with_stats("authentication", 5, None, None, true, None, None, true)
+        .await
+        .expect("Search failed");
+    // The query itself is not repeated as a term
+    assert_eq!(outcome.expansion_terms, vec!["login", "sign in"]);
+
+    let outcome = searcher
+        .search_with_stats("authentication", 5, None, None, true, None, None, false)
+        .await
+        .expect("Search failed");
+    assert!(outcome.expansion_terms.is_empty());
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_failing_llm_opens_circuit_breaker() {
+    let (storage, embedder, _, db_path) = setup_test_env("llm_breaker_test").await;
+
+    let client = Arc::new(FailingLlmClient {
+        generate_calls: AtomicUsize::new(0),
+    });
+    let expander = Arc::new(QueryExpander::new(client.clone()).with_circuit_breaker(Some(
+        CircuitBreakerPolicy {
+            failure_threshold: 3,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(600),
+        },
+    )));
+
+    let searcher = CodeSearcher::new(
+        Some(Arc::new(storage)),
+        Some(Arc::new(embedder)),
+        None,
+        Some(expander.clone()),
+        1.0,
+        1.0,
+        60.0,
+    );
+
+    for _ in 0..6 {
+        let outcome = searcher
+            .search_with_stats("query", 1, None, None, true, None, None, true)
+            .await
+            .expect("Search failed");
+        assert!(outcome.unavailable.contains(&Subsystem::Expander));
+        assert!(outcome.expansion_terms.is_empty());
+    }
+
+    // Only the searches before the breaker opened called the LLM
+    assert_eq!(client.generate_calls.load(Ordering::SeqCst), 3);
+    assert!(!expander.available());
+
+    cleanup_test_db(&db_path);
+}
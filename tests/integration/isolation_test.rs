@@ -3,6 +3,7 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
+use code_rag::search::ScoreCalibration;
 use code_rag::server::workspace_manager::WorkspaceManager;
 use code_rag::server::{create_router, AppState, ServerStartConfig};
 use code_rag::storage::Storage;
@@ -99,15 +100,59 @@ async fn test_workspace_isolation() {
     let config = ServerStartConfig {
         host: "127.0.0.1".to_string(),
         port: 0,
+        socket_path: None,
+        max_concurrent_requests: None,
+        cors_allowed_origins: None,
+        embed_max_in_flight: 4,
         db_path: root_db_path.clone(), // Root containing workspace_a and workspace_b
+        workspace_db_paths: Default::default(),
         embedding_model: "dummy".to_string(),
         reranker_model: "dummy".to_string(),
         embedding_model_path: None,
         reranker_model_path: None,
+        model_cache_dir: None,
+        normalize_embeddings: false,
+        offline: false,
         device: "cpu".to_string(),
+        vector_weight: 1.0,
+        bm25_weight: 1.0,
+        rrf_k: 60.0,
+        rerank_blend: 1.0,
+        rerank_skip_extensions: vec![],
+        rerank_batch_size: None,
+        rerank_cascade_k: None,
+        rerank_disagreement: None,
+        multi_query_rerank: None,
+        query_routing: None,
+        score_calibration: ScoreCalibration::MinMax,
+        confidence: None,
+        snippets: None,
+        importance_boost: 0.0,
+        path_boost: 0.0,
+        prompt_preamble: code_rag::reporting::DEFAULT_PROMPT_PREAMBLE.to_string(),
+        prompt_max_tokens: 4000,
+        normalize_identifiers: false,
+        default_limit: 5,
+        default_max_tokens: None,
+        keyword_fallback: true,
+        msgpack: true,
+        reload_config: None,
+        warmup: false,
+        preload_workspaces: Vec::new(),
+        preload_concurrency: 4,
+        cursor_ttl_secs: 300,
+        cursor_max_results: 500,
+        shard_count: 1,
+        shard_key: Default::default(),
+        index_meta: None,
+        index_version_check: Default::default(),
         llm_enabled: false,
         llm_host: "".to_string(),
         llm_model: "".to_string(),
+        llm_timeout_ms: 2000,
+        llm_circuit_breaker: None,
+        explain_results: false,
+        explain_top_k: 3,
     };
 
     let manager = WorkspaceManager::new(config, embedder.clone(), None);
@@ -9,3 +9,4 @@ mod resilience;
 mod server;
 mod streaming_test;
 mod verify_hardening;
+mod watch;
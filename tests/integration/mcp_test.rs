@@ -75,3 +75,80 @@ fn test_mcp_initialize() {
     child.kill().ok();
     child.wait().ok();
 }
+
+/// Sends `count` requests without waiting and returns the parsed response lines.
+fn burst_responses(ordered: bool, count: u64) -> Vec<serde_json::Value> {
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .arg("mcp")
+        .env("CODE_RAG__MCP_MAX_IN_FLIGHT", "4")
+        .env("CODE_RAG__MCP_ORDERED_RESPONSES", ordered.to_string())
+        .env("RUST_LOG", "off")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to spawn MCP process");
+
+    let mut stdin = child.stdin.take().expect("Failed to open stdin");
+    let mut batch = String::new();
+    for id in 1..=count {
+        let method = match id % 3 {
+            0 => "initialize",
+            1 => "tools/list",
+            _ => "unknown/method",
+        };
+        let req = json!({ "jsonrpc": "2.0", "method": method, "id": id });
+        batch.push_str(&serde_json::to_string(&req).unwrap());
+        batch.push('\n');
+        // Notifications get no response and must not hold up the ones after them
+        batch.push_str(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#);
+        batch.push('\n');
+    }
+    stdin.write_all(batch.as_bytes()).unwrap();
+    drop(stdin);
+
+    let stdout = child.stdout.take().expect("Failed to open stdout");
+    let responses = std::io::BufReader::new(stdout)
+        .lines()
+        .map(|line| {
+            let line = line.expect("Failed to read line");
+            serde_json::from_str(&line)
+                .unwrap_or_else(|e| panic!("Incomplete or invalid response {:?}: {}", line, e))
+        })
+        .collect();
+    child.wait().ok();
+    responses
+}
+
+fn check_response(response: &serde_json::Value) {
+    assert_eq!(response["jsonrpc"], "2.0");
+    let id = response["id"].as_u64().expect("Response id missing");
+    match id % 3 {
+        0 => assert_eq!(response["result"]["serverInfo"]["name"], "code-rag"),
+        1 => assert!(response["result"]["tools"].is_array()),
+        _ => assert_eq!(response["error"]["code"], -32601),
+    }
+}
+
+#[test]
+fn test_mcp_concurrent_requests_write_whole_lines() {
+    let count = 30;
+
+    let responses = burst_responses(false, count);
+    assert_eq!(responses.len(), count as usize);
+    responses.iter().for_each(check_response);
+    let mut ids: Vec<u64> = responses
+        .iter()
+        .map(|r| r["id"].as_u64().unwrap())
+        .collect();
+    ids.sort();
+    assert_eq!(ids, (1..=count).collect::<Vec<_>>());
+
+    let responses = burst_responses(true, count);
+    responses.iter().for_each(check_response);
+    let ids: Vec<u64> = responses
+        .iter()
+        .map(|r| r["id"].as_u64().unwrap())
+        .collect();
+    assert_eq!(ids, (1..=count).collect::<Vec<_>>());
+}
@@ -1,4 +1,4 @@
-use code_rag::embedding::Embedder;
+use code_rag::embedding::{Embedder, ModelCacheOptions};
 use code_rag::indexer::CodeChunker;
 use code_rag::search::CodeSearcher;
 use code_rag::storage::Storage;
@@ -111,3 +111,26 @@ async fn test_local_model_loading() {
     assert!(!results.is_empty(), "Should have found at least one result");
     assert!(results[0].code.contains("hello_world"));
 }
+
+#[test]
+fn test_offline_mode_requires_cached_model() {
+    let cache_dir = tempdir().unwrap();
+    let result = Embedder::new_with_cache(
+        true,
+        "nomic-embed-text-v1.5".to_string(),
+        "bge-reranker-base".to_string(),
+        None,
+        None,
+        "cpu".to_string(),
+        ModelCacheOptions {
+            cache_dir: Some(cache_dir.path().to_string_lossy().to_string()),
+            offline: true,
+        },
+    );
+
+    let err = match result {
+        Ok(_) => panic!("offline mode should refuse to download a missing model"),
+        Err(e) => e.to_string(),
+    };
+    assert!(err.contains("not cached"), "unexpected error: {}", err);
+}
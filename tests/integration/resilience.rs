@@ -20,6 +20,42 @@ async fn test_corrupt_database() {
     cleanup_test_db(&db_path);
 }
 
+#[tokio::test]
+async fn test_add_chunks_rejects_wrong_dimension_vector() {
+    let (storage, embedder, _, db_path) = setup_test_env("ragged_vectors").await;
+    let dim = embedder.dim();
+
+    let chunk = |i: usize| format!("a.rs-{}-{}", i, i);
+    let err = storage
+        .add_chunks(
+            "default",
+            vec![chunk(1), chunk(2), chunk(3)],
+            vec!["a.rs".to_string(); 3],
+            vec!["fn a() {}".to_string(); 3],
+            vec![1, 2, 3],
+            vec![1, 2, 3],
+            vec![0; 3],
+            vec![Vec::new(); 3],
+            vec![vec![0.0; dim], vec![0.0; dim - 1], vec![0.0; dim]],
+        )
+        .await
+        .expect_err("Ragged batch was written");
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Dimension mismatch: vector 1 (chunk 'a.rs-2-2') has {} values, expected {}",
+            dim - 1,
+            dim
+        )
+    );
+
+    // Nothing from the batch was stored
+    let stored = storage.get_indexed_metadata("default").await.unwrap();
+    assert!(stored.is_empty());
+
+    cleanup_test_db(&db_path);
+}
+
 #[tokio::test]
 async fn test_empty_file_indexing() {
     let (_, _, chunker, _) = setup_test_env("empty_file").await;
@@ -129,3 +165,34 @@ async fn test_invalid_regex() {
     // Should return Err, not panic
     assert!(result.is_err(), "Invalid regex should return Error");
 }
+
+#[test]
+fn test_fixed_string_grep() {
+    use code_rag::search::{CodeSearcher, GrepOptions};
+
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    std::fs::write(
+        dir.path().join("callbacks.rs"),
+        "let cb = fn(u32) -> u32;\nlet other = FN(x);\nfn helper() {}\n",
+    )
+    .unwrap();
+    let base = dir.path().to_str().unwrap();
+    let searcher = CodeSearcher::new(None, None, None, None, 1.0, 1.0, 60.0);
+
+    let literal = GrepOptions {
+        fixed_strings: true,
+        ..Default::default()
+    };
+    let matches = searcher
+        .grep_search_with("fn(", base, literal)
+        .expect("Literal pattern should not be parsed as a regex");
+    assert_eq!(matches.len(), 1);
+    assert!(matches[0].contains(":1: let cb = fn(u32) -> u32;"));
+
+    let ignore_case = GrepOptions {
+        fixed_strings: true,
+        ignore_case: true,
+    };
+    let matches = searcher.grep_search_with("fn(", base, ignore_case).unwrap();
+    assert_eq!(matches.len(), 2);
+}
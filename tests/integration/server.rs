@@ -3,6 +3,12 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
+use code_rag::bm25::BM25Index;
+use code_rag::config::AppConfig;
+use code_rag::index_meta::{IndexMeta, IndexVersionCheck};
+use code_rag::indexer::CodeChunk;
+use code_rag::llm::{LlmClient, ResultExplainer};
+use code_rag::search::ScoreCalibration;
 use code_rag::server::workspace_manager::WorkspaceManager;
 use code_rag::server::{create_router, AppState, ServerStartConfig};
 use common::{cleanup_test_db, prepare_chunks, setup_test_env, TEST_ASSETS_PATH};
@@ -16,15 +22,59 @@ fn create_test_config(db_path: &str) -> ServerStartConfig {
     ServerStartConfig {
         host: "127.0.0.1".to_string(),
         port: 0,
+        socket_path: None,
+        max_concurrent_requests: None,
+        cors_allowed_origins: None,
+        embed_max_in_flight: 4,
         db_path: db_path.to_string(),
+        workspace_db_paths: Default::default(),
         embedding_model: "dummy".to_string(),
         reranker_model: "dummy".to_string(),
         embedding_model_path: None,
         reranker_model_path: None,
+        model_cache_dir: None,
+        normalize_embeddings: false,
+        offline: false,
         device: "cpu".to_string(),
+        vector_weight: 1.0,
+        bm25_weight: 1.0,
+        rrf_k: 60.0,
+        rerank_blend: 1.0,
+        rerank_skip_extensions: vec![],
+        rerank_batch_size: None,
+        rerank_cascade_k: None,
+        rerank_disagreement: None,
+        multi_query_rerank: None,
+        query_routing: None,
+        score_calibration: ScoreCalibration::MinMax,
+        confidence: None,
+        snippets: None,
+        importance_boost: 0.0,
+        path_boost: 0.0,
+        prompt_preamble: code_rag::reporting::DEFAULT_PROMPT_PREAMBLE.to_string(),
+        prompt_max_tokens: 4000,
+        normalize_identifiers: false,
+        default_limit: 5,
+        default_max_tokens: None,
+        keyword_fallback: true,
+        msgpack: true,
+        reload_config: None,
+        warmup: false,
+        preload_workspaces: Vec::new(),
+        preload_concurrency: 4,
+        cursor_ttl_secs: 300,
+        cursor_max_results: 500,
+        shard_count: 1,
+        shard_key: Default::default(),
+        index_meta: None,
+        index_version_check: Default::default(),
         llm_enabled: false,
         llm_host: "".to_string(),
         llm_model: "".to_string(),
+        llm_timeout_ms: 2000,
+        llm_circuit_breaker: None,
+        explain_results: false,
+        explain_top_k: 3,
     }
 }
 
@@ -62,6 +112,89 @@ async fn test_health_check() {
     cleanup_test_db(&db_path);
 }
 
+#[tokio::test]
+async fn test_cors_only_allows_configured_origins() {
+    let (_storage, embedder, _, db_path) = setup_test_env("server_cors").await;
+    let embedder = Arc::new(embedder);
+    let router = |origins: Option<Vec<String>>| {
+        let mut config = create_test_config(&db_path);
+        config.cors_allowed_origins = origins;
+        create_router(AppState {
+            workspace_manager: Arc::new(WorkspaceManager::new(config, embedder.clone(), None)),
+        })
+    };
+    let preflight = |origin: &str| {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/search")
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let allowed_origin = |response: &axum::response::Response| {
+        response
+            .headers()
+            .get("access-control-allow-origin")
+            .map(|v| v.to_str().unwrap().to_string())
+    };
+
+    let app = router(Some(vec!["https://ide.example.com/".to_string()]));
+
+    let response = app
+        .clone()
+        .oneshot(preflight("https://ide.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(
+        allowed_origin(&response).as_deref(),
+        Some("https://ide.example.com")
+    );
+    assert!(response
+        .headers()
+        .contains_key("access-control-allow-methods"));
+
+    // Browsers refuse the request when the preflight carries no CORS headers
+    let response = app
+        .clone()
+        .oneshot(preflight("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(allowed_origin(&response), None);
+
+    // Simple requests are answered, but without headers the browser would honor
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/health")
+                .header("origin", "https://evil.example.com")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(allowed_origin(&response), None);
+
+    // No cross-origin caller at all
+    let response = router(Some(vec![]))
+        .oneshot(preflight("https://ide.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(allowed_origin(&response), None);
+
+    // Unset keeps the permissive default
+    let response = router(None)
+        .oneshot(preflight("https://evil.example.com"))
+        .await
+        .unwrap();
+    assert_eq!(allowed_origin(&response).as_deref(), Some("*"));
+
+    cleanup_test_db(&db_path);
+}
+
 #[tokio::test]
 async fn test_search_endpoint() {
     // Setup environment
@@ -199,3 +332,929 @@ async fn test_concurrent_searches() {
 
     cleanup_test_db(&db_path);
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrency_limit_returns_503() {
+    let (embedder, db_path) = setup_weighted_corpus("server_limit").await;
+
+    let mut config = create_test_config(&db_path);
+    config.max_concurrent_requests = Some(1);
+    let manager = WorkspaceManager::new(config, Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let search_request = |i: usize| {
+        let payload = serde_json::json!({ "query": format!("query {}", i), "limit": 1 });
+        Request::builder()
+            .method("POST")
+            .uri("/search")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+
+    let handles: Vec<_> = (0..20)
+        .map(|i| tokio::spawn(app.clone().oneshot(search_request(i))))
+        .collect();
+    let mut statuses = Vec::new();
+    for handle in handles {
+        statuses.push(handle.await.unwrap().unwrap().status());
+    }
+
+    assert!(statuses
+        .iter()
+        .all(|s| *s == StatusCode::OK || *s == StatusCode::SERVICE_UNAVAILABLE));
+    assert!(statuses.contains(&StatusCode::OK));
+    assert!(
+        statuses.contains(&StatusCode::SERVICE_UNAVAILABLE),
+        "no request was rejected: {:?}",
+        statuses
+    );
+
+    // Health checks are not limited, and searches succeed again once the burst is over
+    let health = Request::builder()
+        .uri("/health")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(
+        app.clone().oneshot(health).await.unwrap().status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        app.clone()
+            .oneshot(search_request(0))
+            .await
+            .unwrap()
+            .status(),
+        StatusCode::OK
+    );
+
+    cleanup_test_db(&db_path);
+}
+
+/// Answers every prompt with `reply`, or fails like an unreachable host when it is `None`.
+struct CannedLlm {
+    reply: Option<&'static str>,
+}
+
+#[async_trait::async_trait]
+impl LlmClient for CannedLlm {
+    async fn generate(&self, _prompt: &str) -> anyhow::Result<String> {
+        self.reply
+            .map(str::to_string)
+            .ok_or_else(|| anyhow::anyhow!("connection refused"))
+    }
+}
+
+#[tokio::test]
+async fn test_search_explains_top_results() {
+    let (embedder, db_path) = setup_weighted_corpus("server_explain").await;
+    let embedder = Arc::new(embedder);
+    // `None` disables explanations; `Some(reply)` explains the top result with `reply`
+    let app = |llm: Option<Option<&'static str>>| {
+        let explainer = llm.map(|reply| {
+            Arc::new(ResultExplainer::new(Arc::new(CannedLlm { reply })).with_top_k(1))
+        });
+        let manager = WorkspaceManager::new(create_test_config(&db_path), embedder.clone(), None)
+            .with_explainer(explainer);
+        create_router(AppState {
+            workspace_manager: Arc::new(manager),
+        })
+    };
+    let search = |app: axum::Router, explain: bool| async move {
+        let payload = serde_json::json!({
+            "query": "load configuration file",
+            "limit": 2,
+            "no_rerank": true,
+            "explain": explain,
+        });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/search")
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        let status = response.status();
+        let body_bytes = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        let body = serde_json::from_slice(&body_bytes).unwrap_or(serde_json::Value::Null);
+        (status, body)
+    };
+    let reason = "It reads the app.toml settings file.";
+
+    let (status, body) = search(app(Some(Some(reason))), true).await;
+    assert_eq!(status, StatusCode::OK);
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["explanation"], reason);
+    assert!(results[1].get("explanation").is_none());
+
+    // Only on request
+    let (_, body) = search(app(Some(Some(reason))), false).await;
+    assert!(body["results"][0].get("explanation").is_none());
+
+    // A failing LLM leaves the results as they are
+    let (status, body) = search(app(Some(None)), true).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["results"].as_array().unwrap().len(), 2);
+    assert!(body["results"][0].get("explanation").is_none());
+
+    let (status, _) = search(app(None), true).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    cleanup_test_db(&db_path);
+}
+
+/// Time taken by the first search a fresh server answers.
+#[tokio::test]
+async fn test_warm_up_loads_workspace_before_first_search() {
+    let (embedder, db_path) = setup_weighted_corpus("server_warm").await;
+    let manager = Arc::new(WorkspaceManager::new(
+        create_test_config(&db_path),
+        Arc::new(embedder),
+        None,
+    ));
+    let stats = manager.get_stats();
+    assert_eq!(stats.loaded_workspaces, 0);
+    assert!(stats.warmed_ids.is_empty());
+
+    manager.warm_up("default").await.expect("Warm-up failed");
+    let stats = manager.get_stats();
+    assert_eq!(stats.loaded_workspaces, 1);
+    assert_eq!(stats.warmed_ids, vec!["default".to_string()]);
+
+    let app = create_router(AppState {
+        workspace_manager: manager.clone(),
+    });
+    let payload = serde_json::json!({ "query": "load configuration file", "limit": 2 });
+    let req = Request::builder()
+        .method("POST")
+        .uri("/search")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    // The first search reuses the warmed workspace instead of loading it again
+    assert_eq!(manager.get_stats().loaded_workspaces, 1);
+
+    cleanup_test_db(&db_path);
+}
+
+/// Indexes two chunks into vector + BM25 storage: "semantic.rs" matches the
+/// weight query's meaning but shares no keywords with it, "lexical.rs" only
+/// matches the query's rare token.
+async fn setup_weighted_corpus(test_name: &str) -> (code_rag::embedding::Embedder, String) {
+    let (storage, embedder, _, db_path) = setup_test_env(test_name).await;
+
+    let chunks = vec![
+        CodeChunk {
+            filename: "semantic.rs".to_string(),
+            code: "fn load_prefs() { let c = load_file_contents(\"app.toml\"); }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+        CodeChunk {
+            filename: "lexical.rs".to_string(),
+            code: "fn xqzv() { let total = 1 + 2; }".to_string(),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        },
+    ];
+
+    let texts: Vec<String> = chunks.iter().map(|c| c.code.clone()).collect();
+    let embeddings = embedder.embed(texts, None).expect("Embed failed");
+    let (_, filenames, codes, starts, ends, mtimes, calls) = prepare_chunks(&chunks);
+    let ids = chunks
+        .iter()
+        .map(|c| format!("{}-{}-{}", c.filename, c.line_start, c.line_end))
+        .collect();
+    storage
+        .add_chunks(
+            "default", ids, filenames, codes, starts, ends, mtimes, calls, embeddings,
+        )
+        .await
+        .expect("Add failed");
+
+    let bm25 = BM25Index::new(&db_path, false, "log").expect("BM25 init failed");
+    bm25.add_chunks(&chunks, "default")
+        .expect("BM25 add failed");
+    bm25.commit().expect("BM25 commit failed");
+
+    (embedder, db_path)
+}
+
+/// Runs the weight query and returns the filename of the top result.
+async fn top_weighted_result(app: &axum::Router, payload: serde_json::Value) -> String {
+    let mut payload = payload;
+    payload["query"] = "read configuration settings from disk xqzv".into();
+    payload["limit"] = 2.into();
+    payload["no_rerank"] = true.into();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/search")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body_bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+    body["results"][0]["filename"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_search_weight_override_changes_order() {
+    let (embedder, db_path) = setup_weighted_corpus("server_weights").await;
+
+    let config = create_test_config(&db_path);
+    let manager = WorkspaceManager::new(config, Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let vector_top = top_weighted_result(
+        &app,
+        serde_json::json!({ "vector_weight": 1.0, "bm25_weight": 0.0 }),
+    )
+    .await;
+    let bm25_top = top_weighted_result(
+        &app,
+        serde_json::json!({ "vector_weight": 0.0, "bm25_weight": 1.0 }),
+    )
+    .await;
+
+    assert_eq!(vector_top, "semantic.rs");
+    assert_eq!(bm25_top, "lexical.rs");
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_config_reload_applies_new_weights() {
+    use code_rag::server::config_reload::spawn_config_reloader;
+
+    let (embedder, db_path) = setup_weighted_corpus("config_reload").await;
+
+    let config_dir = tempfile::tempdir().unwrap();
+    let config_file = config_dir.path().join("code-rag.toml");
+    fs::write(&config_file, "vector_weight = 1.0\nbm25_weight = 0.0\n").unwrap();
+
+    let mut config = create_test_config(&db_path);
+    config.bm25_weight = 0.0;
+    let manager = Arc::new(WorkspaceManager::new(config, Arc::new(embedder), None));
+    let startup = AppConfig::from_path(Some(config_file.to_string_lossy().to_string())).unwrap();
+    spawn_config_reloader(startup, Some(manager.clone())).expect("Reloader failed");
+    let app = create_router(AppState {
+        workspace_manager: manager.clone(),
+    });
+
+    assert_eq!(
+        top_weighted_result(&app, serde_json::json!({})).await,
+        "semantic.rs"
+    );
+
+    fs::write(&config_file, "vector_weight = 0.0\nbm25_weight = 1.0\n").unwrap();
+
+    // Wait for the debounced reload
+    for _ in 0..100 {
+        if manager.search_settings().bm25_weight == 1.0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(manager.search_settings().vector_weight, 0.0);
+
+    assert_eq!(
+        top_weighted_result(&app, serde_json::json!({})).await,
+        "lexical.rs"
+    );
+
+    cleanup_test_db(&db_path);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_health_check_over_unix_socket() {
+    use code_rag::server::serve_unix;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    let (_storage, embedder, _, db_path) = setup_test_env("unix_socket").await;
+    let config = create_test_config(&db_path);
+    let manager = WorkspaceManager::new(config, Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let socket_dir = tempfile::tempdir().unwrap();
+    let socket_path = socket_dir.path().join("code-rag.sock");
+    let socket_str = socket_path.to_string_lossy().to_string();
+    let server = tokio::spawn(async move { serve_unix(app, &socket_str).await });
+
+    // Wait for the listener to come up
+    let mut stream = None;
+    for _ in 0..50 {
+        if let Ok(s) = UnixStream::connect(&socket_path).await {
+            stream = Some(s);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let mut stream = stream.expect("Failed to connect to unix socket");
+
+    stream
+        .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    assert!(
+        response.starts_with("HTTP/1.1 200"),
+        "Unexpected response: {}",
+        response
+    );
+
+    server.abort();
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_file_endpoint_returns_chunks_in_line_order() {
+    let (storage, embedder, chunker, db_path) = setup_test_env("server_file").await;
+
+    let path = Path::new(TEST_ASSETS_PATH).join("test.rs");
+    let code = fs::read_to_string(&path).expect("Failed to read test.rs");
+    let mut reader = std::io::Cursor::new(code.as_bytes());
+    let mut chunks = chunker.chunk_file("test.rs", &mut reader, 0).unwrap();
+    assert!(chunks.len() > 1);
+    // Stored out of order; the endpoint sorts by line
+    chunks.reverse();
+
+    let texts: Vec<String> = chunks.iter().map(|c| c.code.clone()).collect();
+    let embeddings = embedder.embed(texts, None).expect("Embed failed");
+    let (ids, filenames, codes, starts, ends, mtimes, calls) = prepare_chunks(&chunks);
+    storage
+        .add_chunks(
+            "default", ids, filenames, codes, starts, ends, mtimes, calls, embeddings,
+        )
+        .await
+        .expect("Add failed");
+
+    let manager = WorkspaceManager::new(create_test_config(&db_path), Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let req = Request::builder()
+        .uri("/v1/default/file?path=test.rs")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let body: serde_json::Value = serde_json::from_slice(&body_bytes).unwrap();
+
+    assert_eq!(body["path"], "test.rs");
+    let ranges: Vec<(u64, u64)> = body["chunks"]
+        .as_array()
+        .expect("Chunks missing")
+        .iter()
+        .map(|c| {
+            (
+                c["line_start"].as_u64().unwrap(),
+                c["line_end"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    let mut expected: Vec<(u64, u64)> = chunks
+        .iter()
+        .map(|c| (c.line_start as u64, c.line_end as u64))
+        .collect();
+    expected.sort();
+    assert_eq!(ranges, expected);
+
+    // Each chunk sits at its own lines in the rebuilt content
+    let original: Vec<&str> = code.lines().collect();
+    let content: Vec<&str> = body["content"].as_str().unwrap().lines().collect();
+    for (start, end) in ranges {
+        let (start, end) = (start as usize - 1, end as usize);
+        assert_eq!(content[start..end], original[start..end]);
+    }
+
+    let req = Request::builder()
+        .uri("/v1/default/file?path=missing.rs")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_rejects_empty_query() {
+    let (_storage, embedder, _, db_path) = setup_test_env("server_empty_query").await;
+    let manager = WorkspaceManager::new(create_test_config(&db_path), Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    for query in ["", "   \t\n"] {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/search")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({ "query": query }).to_string(),
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = http_body_util::BodyExt::collect(response.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        assert_eq!(String::from_utf8_lossy(&body), "Search query is empty");
+    }
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_rejects_invalid_weight_overrides() {
+    let (_storage, embedder, _, db_path) = setup_test_env("server_invalid_weights").await;
+    let manager = WorkspaceManager::new(create_test_config(&db_path), Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    for overrides in [
+        serde_json::json!({ "vector_weight": -0.5 }),
+        serde_json::json!({ "bm25_weight": -1.0 }),
+        serde_json::json!({ "rrf_k": 0.0 }),
+        serde_json::json!({ "rrf_k": -60.0 }),
+    ] {
+        let mut payload = overrides.clone();
+        payload["query"] = "config".into();
+        let (status, _) = post_search(&app, payload).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "accepted {}", overrides);
+    }
+
+    // Zero weights are valid: they switch a leg off
+    let (status, _) = post_search(
+        &app,
+        serde_json::json!({ "query": "config", "bm25_weight": 0.0, "rrf_k": 1.0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_refuses_incompatible_index() {
+    let (_storage, embedder, _, db_path) = setup_test_env("server_index_meta").await;
+    let current = IndexMeta {
+        format_version: code_rag::index_meta::INDEX_FORMAT_VERSION,
+        code_rag_version: env!("CARGO_PKG_VERSION").to_string(),
+        embedding_model: "dummy".to_string(),
+        normalize_embeddings: false,
+        normalize_identifiers: false,
+        chunk_size: 1024,
+        chunk_overlap: 128,
+        shard_count: 1,
+        shard_key: Default::default(),
+        strip_comments: Default::default(),
+        chunk_balance_target: 0,
+    };
+    IndexMeta {
+        shard_count: 4,
+        ..current.clone()
+    }
+    .write(Path::new(&db_path))
+    .unwrap();
+
+    let config = ServerStartConfig {
+        index_meta: Some(current),
+        index_version_check: IndexVersionCheck::Error,
+        ..create_test_config(&db_path)
+    };
+    let manager = WorkspaceManager::new(config, Arc::new(embedder), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/search")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::json!({ "query": "config" }).to_string(),
+        ))
+        .unwrap();
+    let response = app.oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let body = String::from_utf8_lossy(&body);
+    assert!(body.contains("shard_count is 4 in the index"), "{}", body);
+
+    cleanup_test_db(&db_path);
+}
+
+async fn post_search(
+    app: &axum::Router,
+    payload: serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let req = Request::builder()
+        .method("POST")
+        .uri("/search")
+        .header("content-type", "application/json")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    let status = response.status();
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn add_chunks(
+    storage: &code_rag::storage::Storage,
+    embedder: &code_rag::embedding::Embedder,
+    chunks: Vec<CodeChunk>,
+) {
+    let texts: Vec<String> = chunks.iter().map(|c| c.code.clone()).collect();
+    let embeddings = embedder.embed(texts, None).expect("Embed failed");
+    let (_, filenames, codes, starts, ends, mtimes, calls) = prepare_chunks(&chunks);
+    let ids = chunks.iter().map(|c| c.filename.clone()).collect();
+    storage
+        .add_chunks(
+            "default", ids, filenames, codes, starts, ends, mtimes, calls, embeddings,
+        )
+        .await
+        .expect("Add failed");
+}
+
+#[tokio::test]
+async fn test_search_cursor_pages_through_snapshot() {
+    let (storage, embedder, _, db_path) = setup_test_env("server_cursor").await;
+
+    let chunk = |i: usize| CodeChunk {
+        filename: format!("handler_{}.rs", i),
+        code: format!(
+            "fn handle_request_{}(req: Request) -> Response {{ route(req) }}",
+            i
+        ),
+        line_start: 1,
+        line_end: 1,
+        last_modified: 0,
+        calls: vec!["route".to_string()],
+        part: None,
+        is_test: false,
+        kind: Default::default(),
+        permissions: None,
+        symbol: None,
+        scope: Vec::new(),
+        item_kind: Default::default(),
+    };
+    let embedder = Arc::new(embedder);
+    add_chunks(&storage, &embedder, (0..7).map(chunk).collect()).await;
+
+    let manager = WorkspaceManager::new(create_test_config(&db_path), embedder.clone(), None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+    let query = serde_json::json!({ "query": "handle request", "no_rerank": true });
+
+    let filenames = |body: &serde_json::Value| -> Vec<String> {
+        body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["filename"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let mut all = query.clone();
+    all["limit"] = 50.into();
+    let (status, body) = post_search(&app, all).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("cursor").is_none());
+    let expected = filenames(&body);
+    assert_eq!(expected.len(), 7);
+
+    let mut payload = query.clone();
+    payload["limit"] = 3.into();
+    payload["paginate"] = true.into();
+    let mut paged = Vec::new();
+    let mut pages = 0;
+    loop {
+        let (status, body) = post_search(&app, payload.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        paged.extend(filenames(&body));
+        pages += 1;
+        if pages == 1 {
+            // Indexing between pages must not shift the remaining ones
+            add_chunks(&storage, &embedder, vec![chunk(7)]).await;
+        }
+        match body["cursor"].as_str() {
+            Some(cursor) => payload["cursor"] = cursor.into(),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 3);
+    assert_eq!(paged, expected, "pages repeated or skipped results");
+
+    // A cursor only resumes the search it came from
+    let (status, body) = post_search(&app, {
+        let mut first = query.clone();
+        first["limit"] = 3.into();
+        first["paginate"] = true.into();
+        first
+    })
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut other = serde_json::json!({ "query": "parse tokens", "no_rerank": true });
+    other["cursor"] = body["cursor"].clone();
+    assert_eq!(post_search(&app, other).await.0, StatusCode::BAD_REQUEST);
+
+    let mut garbage = query.clone();
+    garbage["cursor"] = "0123".into();
+    assert_eq!(post_search(&app, garbage).await.0, StatusCode::BAD_REQUEST);
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_reports_total_and_has_more() {
+    let (storage, embedder, _, db_path) = setup_test_env("server_has_more").await;
+    let chunks = (0..5)
+        .map(|i| CodeChunk {
+            filename: format!("parser_{}.rs", i),
+            code: format!(
+                "fn parse_token_{}(input: &str) -> Token {{ lex(input) }}",
+                i
+            ),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec!["lex".to_string()],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        })
+        .collect();
+    let embedder = Arc::new(embedder);
+    add_chunks(&storage, &embedder, chunks).await;
+
+    let manager = WorkspaceManager::new(create_test_config(&db_path), embedder, None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    for no_rerank in [true, false] {
+        let search = |limit: usize| {
+            post_search(
+                &app,
+                serde_json::json!({ "query": "parse token", "limit": limit, "no_rerank": no_rerank }),
+            )
+        };
+
+        let (status, body) = search(2).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"].as_array().unwrap().len(), 2);
+        assert_eq!(body["has_more"], true, "no_rerank={}", no_rerank);
+        assert!(body["total_candidates"].as_u64().unwrap() > 2);
+
+        let (status, body) = search(10).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"].as_array().unwrap().len(), 5);
+        assert_eq!(body["has_more"], false, "no_rerank={}", no_rerank);
+        assert_eq!(body["total_candidates"], 5);
+    }
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_applies_default_max_tokens() {
+    let (storage, embedder, _, db_path) = setup_test_env("server_default_budget").await;
+    let chunks = (0..5)
+        .map(|i| CodeChunk {
+            filename: format!("parser_{}.rs", i),
+            code: format!(
+                "fn parse_token_{}(input: &str) -> Token {{ lex(input) }}",
+                i
+            ),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec!["lex".to_string()],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        })
+        .collect();
+    let embedder = Arc::new(embedder);
+    add_chunks(&storage, &embedder, chunks).await;
+
+    let config = ServerStartConfig {
+        default_max_tokens: Some(30),
+        ..create_test_config(&db_path)
+    };
+    let manager = WorkspaceManager::new(config, embedder, None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let (status, body) = post_search(
+        &app,
+        serde_json::json!({ "query": "parse token", "limit": 5, "no_rerank": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let used = body["context"]["context_tokens_used"].as_u64().unwrap();
+    assert!(used > 0 && used <= 30, "used {}", used);
+    assert!(body["results"].as_array().unwrap().len() < 5);
+
+    // A request's own budget wins, and pages are not cut by the default
+    let (_, body) = post_search(
+        &app,
+        serde_json::json!({ "query": "parse token", "limit": 5, "max_tokens": 4000, "no_rerank": true }),
+    )
+    .await;
+    assert_eq!(body["results"].as_array().unwrap().len(), 5);
+    let (status, body) = post_search(
+        &app,
+        serde_json::json!({ "query": "parse token", "limit": 5, "paginate": true, "no_rerank": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.get("context").is_none());
+    assert_eq!(body["results"].as_array().unwrap().len(), 5);
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_reports_unavailable_bm25() {
+    let (storage, embedder, _, db_path) = setup_test_env("server_degraded_bm25").await;
+    let chunks = vec![CodeChunk {
+        filename: "session.rs".to_string(),
+        code: "fn open_session(user: &User) -> Session { Session::new(user) }".to_string(),
+        line_start: 1,
+        line_end: 1,
+        last_modified: 0,
+        calls: vec![],
+        part: None,
+        is_test: false,
+        kind: Default::default(),
+        permissions: None,
+        symbol: None,
+        scope: Vec::new(),
+        item_kind: Default::default(),
+    }];
+    let embedder = Arc::new(embedder);
+    add_chunks(&storage, &embedder, chunks).await;
+    // A file where the keyword index directory belongs cannot be opened as one
+    fs::write(Path::new(&db_path).join("bm25_index"), "not an index").unwrap();
+
+    let manager = WorkspaceManager::new(create_test_config(&db_path), embedder, None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+
+    let (status, body) = post_search(
+        &app,
+        serde_json::json!({ "query": "open user session", "no_rerank": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["degraded"], serde_json::json!(["bm25"]));
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["filename"], "session.rs");
+
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_search_responds_in_msgpack_when_accepted() {
+    let (storage, embedder, _, db_path) = setup_test_env("server_msgpack").await;
+    let chunks = ["open_session", "close_session", "parse_config"]
+        .iter()
+        .map(|name| CodeChunk {
+            filename: format!("{}.rs", name),
+            code: format!(
+                "fn {}(user: &User) -> Session {{ Session::new(user) }}",
+                name
+            ),
+            line_start: 1,
+            line_end: 1,
+            last_modified: 0,
+            calls: vec![],
+            part: None,
+            is_test: false,
+            kind: Default::default(),
+            permissions: None,
+            symbol: None,
+            scope: Vec::new(),
+            item_kind: Default::default(),
+        })
+        .collect();
+    let embedder = Arc::new(embedder);
+    add_chunks(&storage, &embedder, chunks).await;
+
+    let manager = WorkspaceManager::new(create_test_config(&db_path), embedder, None);
+    let app = create_router(AppState {
+        workspace_manager: Arc::new(manager),
+    });
+    let payload = serde_json::json!({ "query": "user session", "no_rerank": true });
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/search")
+        .header("content-type", "application/json")
+        .header("accept", "application/msgpack, application/json;q=0.5")
+        .body(Body::from(payload.to_string()))
+        .unwrap();
+    let response = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        code_rag::server::msgpack::MSGPACK_CONTENT_TYPE
+    );
+    let body = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let decoded: serde_json::Value = code_rag::server::msgpack::from_slice(&body).unwrap();
+
+    // Same results as the JSON response, up to the precision JSON prints scores with
+    let (status, json) = post_search(&app, payload).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.len() < json.to_string().len());
+    assert_eq!(decoded["total_candidates"], json["total_candidates"]);
+    assert_eq!(decoded["has_more"], json["has_more"]);
+    let results = decoded["results"].as_array().unwrap();
+    let expected = json["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.len(), expected.len());
+    for (result, expected) in results.iter().zip(expected) {
+        for field in ["rank", "filename", "code", "line_start", "line_end", "kind"] {
+            assert_eq!(result[field], expected[field], "{}", field);
+        }
+        let score = result["score"].as_f64().unwrap();
+        assert!((score - expected["score"].as_f64().unwrap()).abs() < 1e-6);
+    }
+
+    cleanup_test_db(&db_path);
+}
@@ -3,6 +3,7 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
+use code_rag::search::ScoreCalibration;
 use code_rag::server::{
     create_router,
     workspace_manager::{WorkspaceManager, WorkspaceStats},
@@ -15,15 +16,59 @@ fn create_test_config(db_path: &str) -> ServerStartConfig {
     ServerStartConfig {
         host: "127.0.0.1".to_string(),
         port: 0,
+        socket_path: None,
+        max_concurrent_requests: None,
+        cors_allowed_origins: None,
+        embed_max_in_flight: 4,
         db_path: db_path.to_string(),
+        workspace_db_paths: Default::default(),
         embedding_model: "dummy".to_string(),
         reranker_model: "dummy".to_string(),
         embedding_model_path: None,
         reranker_model_path: None,
+        model_cache_dir: None,
+        normalize_embeddings: false,
+        offline: false,
         device: "cpu".to_string(),
+        vector_weight: 1.0,
+        bm25_weight: 1.0,
+        rrf_k: 60.0,
+        rerank_blend: 1.0,
+        rerank_skip_extensions: vec![],
+        rerank_batch_size: None,
+        rerank_cascade_k: None,
+        rerank_disagreement: None,
+        multi_query_rerank: None,
+        query_routing: None,
+        score_calibration: ScoreCalibration::MinMax,
+        confidence: None,
+        snippets: None,
+        importance_boost: 0.0,
+        path_boost: 0.0,
+        prompt_preamble: code_rag::reporting::DEFAULT_PROMPT_PREAMBLE.to_string(),
+        prompt_max_tokens: 4000,
+        normalize_identifiers: false,
+        default_limit: 5,
+        default_max_tokens: None,
+        keyword_fallback: true,
+        msgpack: true,
+        reload_config: None,
+        warmup: false,
+        preload_workspaces: Vec::new(),
+        preload_concurrency: 4,
+        cursor_ttl_secs: 300,
+        cursor_max_results: 500,
+        shard_count: 1,
+        shard_key: Default::default(),
+        index_meta: None,
+        index_version_check: Default::default(),
         llm_enabled: false,
         llm_host: "".to_string(),
         llm_model: "".to_string(),
+        llm_timeout_ms: 2000,
+        llm_circuit_breaker: None,
+        explain_results: false,
+        explain_top_k: 3,
     }
 }
 
@@ -69,3 +114,53 @@ async fn test_server_status_endpoint() {
 
     cleanup_test_db(&db_path);
 }
+
+#[tokio::test]
+async fn test_preload_workspaces_before_first_request() {
+    let (_storage, embedder, _chunker, db_path) = setup_test_env("hardening_preload").await;
+    for workspace in ["alpha", "beta"] {
+        let path = format!("{}/{}", db_path, workspace);
+        let storage = code_rag::storage::Storage::new(&path, "code_chunks")
+            .await
+            .unwrap();
+        storage.init(embedder.dim()).await.unwrap();
+    }
+
+    let mut config = create_test_config(&db_path);
+    config.preload_workspaces = vec!["alpha".into(), "beta".into(), "ghost".into()];
+    config.preload_concurrency = 2;
+    config
+        .workspace_db_paths
+        .insert("ghost".into(), format!("{}/missing-root", db_path));
+    let manager = Arc::new(WorkspaceManager::new(config, Arc::new(embedder), None));
+
+    // A workspace that cannot load is skipped, not fatal
+    assert_eq!(manager.preload_workspaces().await, 2);
+
+    let app = create_router(AppState {
+        workspace_manager: manager,
+    });
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/status")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body_bytes = http_body_util::BodyExt::collect(response.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let stats: WorkspaceStats =
+        serde_json::from_slice(&body_bytes).expect("Failed to parse status");
+
+    let mut active = stats.active_ids.clone();
+    active.sort();
+    assert_eq!(active, ["alpha", "beta"]);
+    assert_eq!(stats.loaded_workspaces, 2);
+
+    cleanup_test_db(&db_path);
+}
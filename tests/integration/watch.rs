@@ -0,0 +1,167 @@
+use code_rag::bm25::BM25Index;
+use code_rag::storage::Storage;
+use code_rag::watcher::{set_exclusions, start_watcher, watch_roots};
+use std::fs;
+use std::time::Duration;
+
+use crate::common::{cleanup_test_db, setup_test_env};
+
+#[test]
+fn test_watch_roots_from_subpaths() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let root = temp_dir.path();
+    fs::create_dir_all(root.join("api/handlers")).unwrap();
+    fs::create_dir_all(root.join("web")).unwrap();
+    let root_str = root.to_str().unwrap();
+
+    assert_eq!(watch_roots(root_str, &[]).unwrap(), [root.to_path_buf()]);
+    // Nested and repeated subpaths are covered by the recursive watch of their parent
+    let subpaths = ["web", "api", "api/handlers", "api"].map(String::from);
+    assert_eq!(
+        watch_roots(root_str, &subpaths).unwrap(),
+        [root.join("api"), root.join("web")]
+    );
+    let err = watch_roots(root_str, &["missing".to_string()]).unwrap_err();
+    assert!(err.to_string().contains("missing"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_watch_subpaths_ignore_other_directories() {
+    let (storage, embedder, chunker, db_path) = setup_test_env("watch_subpaths").await;
+    let bm25 = BM25Index::new(&db_path, false, "log").expect("BM25 init failed");
+
+    let source = tempfile::TempDir::new().unwrap();
+    let root = source.path().to_path_buf();
+    for dir in ["api", "web", "docs"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let subpaths = vec!["api".to_string(), "web".to_string()];
+    let watcher = {
+        let root = root.to_string_lossy().to_string();
+        tokio::spawn(async move {
+            start_watcher(
+                &root,
+                &subpaths,
+                storage,
+                embedder,
+                bm25,
+                chunker,
+                "default".to_string(),
+                false,
+            )
+            .await
+        })
+    };
+    // Let the watches register before changing files
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    fs::write(
+        root.join("api/routes.rs"),
+        "fn list_routes() -> Vec<Route> { vec![] }\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("web/app.py"),
+        "def render_page(name):\n    return name\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("docs/build.rs"),
+        "fn build_docs() { generate(); }\n",
+    )
+    .unwrap();
+
+    let reader = Storage::new(&db_path, "code_chunks").await.unwrap();
+    let indexed = |files: &std::collections::HashMap<String, i64>, name: &str| {
+        files.keys().any(|f| f.replace('\\', "/").ends_with(name))
+    };
+    let mut files = Default::default();
+    for _ in 0..60 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        files = reader
+            .get_indexed_metadata("default")
+            .await
+            .unwrap_or_default();
+        if indexed(&files, "api/routes.rs") && indexed(&files, "web/app.py") {
+            break;
+        }
+    }
+    assert!(indexed(&files, "api/routes.rs"), "{:?}", files.keys());
+    assert!(indexed(&files, "web/app.py"), "{:?}", files.keys());
+
+    // The unwatched directory's change would have been debounced with the others
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let files = reader.get_indexed_metadata("default").await.unwrap();
+    assert!(!indexed(&files, "docs/build.rs"), "{:?}", files.keys());
+
+    watcher.abort();
+    cleanup_test_db(&db_path);
+}
+
+#[tokio::test]
+async fn test_watch_applies_reloaded_exclusions() {
+    let (storage, embedder, chunker, db_path) = setup_test_env("watch_exclusions").await;
+    let bm25 = BM25Index::new(&db_path, false, "log").expect("BM25 init failed");
+
+    let source = tempfile::TempDir::new().unwrap();
+    let root = source.path().to_path_buf();
+    for dir in ["src", "generated"] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let watcher = {
+        let root = root.to_string_lossy().to_string();
+        tokio::spawn(async move {
+            start_watcher(
+                &root,
+                &[],
+                storage,
+                embedder,
+                bm25,
+                chunker,
+                "default".to_string(),
+                false,
+            )
+            .await
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // As a config reload does while the watcher runs
+    set_exclusions(vec!["generated".to_string()]);
+    fs::write(
+        root.join("src/parser.rs"),
+        "fn parse_tokens() -> Vec<Token> { vec![] }\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("generated/bindings.rs"),
+        "fn generated_binding() {}\n",
+    )
+    .unwrap();
+
+    let reader = Storage::new(&db_path, "code_chunks").await.unwrap();
+    let indexed = |files: &std::collections::HashMap<String, i64>, name: &str| {
+        files.keys().any(|f| f.replace('\\', "/").ends_with(name))
+    };
+    let mut files = Default::default();
+    for _ in 0..60 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        files = reader
+            .get_indexed_metadata("default")
+            .await
+            .unwrap_or_default();
+        if indexed(&files, "src/parser.rs") {
+            break;
+        }
+    }
+    set_exclusions(Vec::new());
+    assert!(indexed(&files, "src/parser.rs"), "{:?}", files.keys());
+    assert!(
+        !indexed(&files, "generated/bindings.rs"),
+        "{:?}",
+        files.keys()
+    );
+
+    watcher.abort();
+    cleanup_test_db(&db_path);
+}
@@ -69,3 +69,47 @@ fn test_search_performance() -> Result<()> {
     assert!(duration.as_secs() < 15);
     Ok(())
 }
+
+#[test]
+#[ignore]
+fn test_first_batch_after_warmup() -> Result<()> {
+    use code_rag::embedding::{warmup_texts, Embedder};
+
+    const BATCH: usize = 32;
+    let embedder = Embedder::new(
+        "nomic-embed-text-v1.5".to_string(),
+        "bge-reranker-base".to_string(),
+        None,
+        None,
+        "cpu".to_string(),
+    )?;
+    embedder.warmup(BATCH)?;
+
+    // Distinct from the warmup inputs, same shape as indexed chunks
+    let batch = |n: usize| -> Vec<String> {
+        warmup_texts(BATCH)
+            .into_iter()
+            .map(|t| format!("// batch {}\n{}", n, t))
+            .collect()
+    };
+
+    let mut timings = Vec::new();
+    for n in 0..4 {
+        let start = Instant::now();
+        embedder.embed(batch(n), None)?;
+        timings.push(start.elapsed());
+    }
+
+    let first = timings[0];
+    let mut rest = timings[1..].to_vec();
+    rest.sort();
+    let median = rest[rest.len() / 2];
+    println!("First batch: {:?}, steady state: {:?}", first, median);
+    assert!(
+        first < median * 3,
+        "first batch took {:?}, steady state {:?}",
+        first,
+        median
+    );
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+fn search_finds_logo(index_asset_names: bool) -> Result<bool> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project_dir = temp_dir.path().join("project");
+    let assets_dir = project_dir.join("assets");
+
+    fs::create_dir_all(&assets_dir)?;
+    fs::write(
+        project_dir.join("main.rs"),
+        "fn main() { println!(\"hi\"); }",
+    )?;
+    // PNG signature followed by binary data
+    fs::write(
+        assets_dir.join("logo.png"),
+        [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00],
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("CODE_RAG__INDEX_ASSET_NAMES", index_asset_names.to_string())
+        .arg("index")
+        .arg("--path")
+        .arg(&project_dir)
+        .assert()
+        .success();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("logo")
+        .arg("--json")
+        .output()?;
+    assert!(
+        output.status.success(),
+        "Search failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    Ok(String::from_utf8(output.stdout)?.contains("logo.png"))
+}
+
+#[test]
+fn test_asset_names_searchable_when_enabled() -> Result<()> {
+    assert!(search_finds_logo(true)?);
+    Ok(())
+}
+
+#[test]
+fn test_asset_names_hidden_when_disabled() -> Result<()> {
+    assert!(!search_finds_logo(false)?);
+    Ok(())
+}
+
+fn index(db_path: &std::path::Path, project_dir: &std::path::Path, update: bool) {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+    cmd.env("CODE_RAG__DB_PATH", db_path)
+        .env("CODE_RAG__INDEX_ASSET_NAMES", "true")
+        .arg("index")
+        .arg("--path")
+        .arg(project_dir);
+    if update {
+        cmd.arg("--update");
+    }
+    cmd.assert().success();
+}
+
+fn search(db_path: &std::path::Path, query: &str) -> Result<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg(query)
+        .arg("--json")
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_asset_names_follow_updates() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project_dir = temp_dir.path().join("project");
+    fs::create_dir_all(&project_dir)?;
+    fs::write(
+        project_dir.join("main.rs"),
+        "fn main() { println!(\"hi\"); }",
+    )?;
+    fs::write(project_dir.join("util.rs"), "fn helper_util() {}")?;
+    fs::write(project_dir.join("logo.png"), [0x89, 0x50, 0x4E, 0x47, 0x00])?;
+    // A source extension with binary content is named only, not chunked
+    fs::write(project_dir.join("blob.rs"), [0x00, 0x01, 0x02, 0xFF, 0x03])?;
+
+    index(&db_path, &project_dir, false);
+    assert!(search(&db_path, "logo")?.contains("logo.png"));
+    assert!(search(&db_path, "blob")?.contains("blob.rs"));
+    assert!(search(&db_path, "helper_util")?.contains("util.rs"));
+
+    // Stale chunks and stale asset names leave both stores together
+    fs::remove_file(project_dir.join("logo.png"))?;
+    fs::remove_file(project_dir.join("util.rs"))?;
+    index(&db_path, &project_dir, true);
+    assert!(!search(&db_path, "logo")?.contains("logo.png"));
+    assert!(!search(&db_path, "helper_util")?.contains("util.rs"));
+    assert!(search(&db_path, "blob")?.contains("blob.rs"));
+    Ok(())
+}
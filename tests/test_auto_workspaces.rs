@@ -0,0 +1,69 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write_package(dir: &Path, name: &str, code: &str) -> Result<()> {
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!("[package]\nname = \"{}\"\nversion = \"0.1.0\"\n", name),
+    )?;
+    fs::write(dir.join("src").join("lib.rs"), code)?;
+    Ok(())
+}
+
+fn search_workspace(db_path: &Path, workspace: &str) -> Result<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("fn")
+        .arg("--workspace")
+        .arg(workspace)
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_auto_workspaces_per_package() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let repo = temp_dir.path().join("monorepo");
+    write_package(
+        &repo.join("crates").join("alpha"),
+        "alpha",
+        "pub fn alpha_parse() -> u32 { 1 }",
+    )?;
+    write_package(
+        &repo.join("crates").join("beta"),
+        "beta",
+        "pub fn beta_render() -> u32 { 2 }",
+    )?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&repo)
+        .arg("--auto-workspaces")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Workspace 'crates-alpha'"));
+    assert!(stdout.contains("Workspace 'crates-beta'"));
+
+    let alpha = search_workspace(&db_path, "crates-alpha")?;
+    assert!(alpha.contains("alpha_parse"));
+    assert!(!alpha.contains("beta_render"));
+
+    let beta = search_workspace(&db_path, "crates-beta")?;
+    assert!(beta.contains("beta_render"));
+    assert!(!beta.contains("alpha_parse"));
+
+    Ok(())
+}
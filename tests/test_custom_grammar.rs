@@ -0,0 +1,92 @@
+#![cfg(unix)]
+
+use anyhow::{bail, Result};
+use code_rag::grammars::load_custom_grammars;
+use code_rag::indexer::CodeChunker;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Generated sources of the JSON grammar, from the cargo registry this crate was built from.
+fn json_grammar_sources() -> Result<PathBuf> {
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+        .unwrap_or_default();
+    for registry in std::fs::read_dir(cargo_home.join("registry").join("src"))? {
+        for package in std::fs::read_dir(registry?.path())? {
+            let path = package?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if name.starts_with("tree-sitter-json-") && path.join("src/parser.c").exists() {
+                return Ok(path.join("src"));
+            }
+        }
+    }
+    bail!(
+        "tree-sitter-json sources not found under {}",
+        cargo_home.display()
+    )
+}
+
+/// Compiles the JSON grammar into `dir` as a standalone grammar library.
+fn build_grammar_library(dir: &Path) -> Result<PathBuf> {
+    let src = json_grammar_sources()?;
+    let library = dir.join("libtree-sitter-json.so");
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg("-shared")
+        .arg("-fPIC")
+        .arg("-I")
+        .arg(&src)
+        .arg(src.join("parser.c"))
+        .arg("-o")
+        .arg(&library)
+        .status()?;
+    if !status.success() {
+        bail!("compiling the grammar failed");
+    }
+    Ok(library)
+}
+
+#[test]
+fn test_custom_grammar_chunks_files_with_its_extension() -> Result<()> {
+    let temp_dir = tempfile::TempDir::new()?;
+    let library = build_grammar_library(temp_dir.path())?;
+
+    assert!(CodeChunker::get_language("cfgx").is_none());
+    let grammars = HashMap::from([
+        ("cfgx".to_string(), library.to_string_lossy().to_string()),
+        (
+            "missing".to_string(),
+            "/nonexistent/libtree-sitter-missing.so".to_string(),
+        ),
+        // Built-in grammars are not replaced
+        ("rs".to_string(), library.to_string_lossy().to_string()),
+    ]);
+    assert_eq!(load_custom_grammars(&grammars), 1);
+    assert!(CodeChunker::get_language("cfgx").is_some());
+    assert!(CodeChunker::get_language("missing").is_none());
+
+    let content = r#"{
+  "retry_policy": { "max_attempts": 5, "backoff_ms": 250 },
+  "endpoints": ["https://a.example", "https://b.example"]
+}
+"#;
+    let chunks = CodeChunker::default().chunk_file(
+        "config/service.cfgx",
+        &mut Cursor::new(content.as_bytes()),
+        0,
+    )?;
+    assert!(!chunks.is_empty(), "custom grammar produced no chunks");
+    assert!(chunks.iter().any(|c| c.code.contains("max_attempts")));
+
+    // The Rust grammar still parses .rs files
+    let chunks = CodeChunker::default().chunk_file(
+        "src/lib.rs",
+        &mut Cursor::new("fn retry() -> u32 { 5 }\n".as_bytes()),
+        0,
+    )?;
+    assert_eq!(chunks.len(), 1);
+    assert!(chunks[0].code.starts_with("fn retry"));
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+fn index(db_path: &std::path::Path, path: &std::path::Path, extra: &[&str]) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+    cmd.env("CODE_RAG__DB_PATH", db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(path)
+        .args(extra);
+    cmd
+}
+
+fn search(db_path: &std::path::Path, query: &str) -> Result<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg(query)
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_index_refuses_to_start_without_free_space() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project_dir = temp_dir.path().join("project");
+    fs::create_dir_all(&project_dir)?;
+    fs::write(project_dir.join("a.rs"), "fn alpha() {}")?;
+
+    index(&db_path, &project_dir, &[]).assert().success();
+
+    // No disk has an exabyte to spare, so the update must stop before writing
+    fs::write(project_dir.join("b.rs"), "fn beta_gamma() {}")?;
+    let output = index(&db_path, &project_dir, &["--update"])
+        .env("CODE_RAG__MIN_FREE_DISK_BYTES", "1000000000000000000")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Not enough disk space"), "{}", stderr);
+
+    // The index is as it was
+    assert!(search(&db_path, "alpha")?.contains("a.rs"));
+    assert!(!search(&db_path, "beta_gamma")?.contains("b.rs"));
+
+    Ok(())
+}
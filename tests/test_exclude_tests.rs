@@ -0,0 +1,60 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn search(db_path: &Path, filter: Option<&str>) -> Result<String> {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+    cmd.env("CODE_RAG__DB_PATH", db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("parse config")
+        .arg("--json")
+        .arg("--no-rerank");
+    if let Some(flag) = filter {
+        cmd.arg(flag);
+    }
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_exclude_tests_drops_test_file_chunks() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(project.join("src"))?;
+    fs::create_dir_all(project.join("tests"))?;
+    fs::write(
+        project.join("src").join("foo.rs"),
+        "pub fn parse_config(input: &str) -> usize { input.len() }",
+    )?;
+    fs::write(
+        project.join("tests").join("foo.rs"),
+        "#[test]\nfn test_parse_config() { assert_eq!(parse_config(\"ab\"), 2); }",
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let all = search(&db_path, None)?;
+    assert!(all.contains("tests/foo.rs") || all.contains("tests\\\\foo.rs"));
+    assert!(all.contains("\"is_test\": true"));
+
+    let excluded = search(&db_path, Some("--exclude-tests"))?;
+    assert!(excluded.contains("foo.rs"));
+    assert!(!excluded.contains("tests/foo.rs") && !excluded.contains("tests\\\\foo.rs"));
+
+    let only = search(&db_path, Some("--only-tests"))?;
+    assert!(only.contains("foo.rs"));
+    assert!(!only.contains("src/foo.rs") && !only.contains("src\\\\foo.rs"));
+
+    Ok(())
+}
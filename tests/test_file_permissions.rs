@@ -0,0 +1,104 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn search(db_path: &Path, extra: &[&str]) -> Result<Vec<serde_json::Value>> {
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("deploy script")
+        .arg("--json")
+        .arg("--no-rerank")
+        .args(extra)
+        .output()?;
+    assert!(output.status.success());
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn file_name(result: &serde_json::Value) -> String {
+    let filename = result["filename"].as_str().unwrap().replace('\\', "/");
+    filename.rsplit('/').next().unwrap().to_string()
+}
+
+#[test]
+fn test_index_records_file_permissions() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(&project)?;
+    let deploy = project.join("deploy.sh");
+    let build = project.join("build.sh");
+    fs::write(
+        &deploy,
+        "deploy_release() {\n  rsync -a dist/ \"$HOST\":/srv/app\n}\n",
+    )?;
+    fs::write(&build, "build_release() {\n  cargo build --release\n}\n")?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&deploy, fs::Permissions::from_mode(0o777))?;
+        fs::set_permissions(&build, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("CODE_RAG__INDEX_FILE_PERMISSIONS", "true")
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let results = search(&db_path, &[])?;
+    assert_eq!(results.len(), 2);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let owner = fs::metadata(&deploy)?.uid();
+        for result in &results {
+            let expected = if file_name(result) == "deploy.sh" {
+                0o777
+            } else {
+                0o755
+            };
+            assert_eq!(result["permissions"]["mode"].as_u64(), Some(expected));
+            assert_eq!(result["permissions"]["owner"].as_u64(), Some(owner as u64));
+        }
+
+        // World-writable scripts only
+        let writable = search(&db_path, &["--perm", "002"])?;
+        assert_eq!(writable.len(), 1);
+        assert_eq!(file_name(&writable[0]), "deploy.sh");
+
+        // chmod leaves the modification time alone, --update still picks it up
+        use std::os::unix::fs::PermissionsExt;
+        let mtime = fs::metadata(&deploy)?.modified()?;
+        fs::set_permissions(&deploy, fs::Permissions::from_mode(0o755))?;
+        assert_eq!(fs::metadata(&deploy)?.modified()?, mtime);
+        Command::new(env!("CARGO_BIN_EXE_code-rag"))
+            .env("CODE_RAG__DB_PATH", &db_path)
+            .env("CODE_RAG__INDEX_FILE_PERMISSIONS", "true")
+            .arg("index")
+            .arg("--path")
+            .arg(&project)
+            .arg("--update")
+            .assert()
+            .success();
+        assert!(search(&db_path, &["--perm", "002"])?.is_empty());
+    }
+
+    // Nothing is recorded where files have no Unix permission bits
+    #[cfg(not(unix))]
+    {
+        for result in &results {
+            assert!(result.get("permissions").is_none(), "{}", file_name(result));
+        }
+        assert!(search(&db_path, &["--perm", "002"])?.is_empty());
+    }
+
+    Ok(())
+}
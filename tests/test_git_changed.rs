@@ -0,0 +1,90 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use code_rag::git::{changed_files, GitSelection};
+use git2::{Repository, Signature};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Creates a repo with `a.rs` and `b.rs` committed, then modifies `a.rs`.
+fn repo_with_one_change(dir: &Path) -> Result<Repository> {
+    let repo = Repository::init(dir)?;
+    fs::write(dir.join("a.rs"), "fn alpha() {}")?;
+    fs::write(dir.join("b.rs"), "fn beta() {}")?;
+
+    let mut index = repo.index()?;
+    index.add_path(Path::new("a.rs"))?;
+    index.add_path(Path::new("b.rs"))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let sig = Signature::now("test", "test@example.com")?;
+    repo.commit(Some("HEAD"), &sig, &sig, "initial", &tree, &[])?;
+    drop(tree);
+
+    fs::write(dir.join("a.rs"), "fn alpha_renamed() {}")?;
+    Ok(repo)
+}
+
+#[test]
+fn test_changed_files_selects_modified_untracked_and_deleted() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let repo_dir = temp_dir.path();
+    repo_with_one_change(repo_dir)?;
+
+    let files = changed_files(repo_dir, &GitSelection::Changed)?;
+    assert_eq!(files.changed, vec![PathBuf::from("a.rs")]);
+    assert!(files.deleted.is_empty());
+
+    fs::write(repo_dir.join("c.rs"), "fn gamma() {}")?;
+    fs::remove_file(repo_dir.join("b.rs"))?;
+
+    let files = changed_files(repo_dir, &GitSelection::Since("HEAD".into()))?;
+    assert_eq!(
+        files.changed,
+        vec![PathBuf::from("a.rs"), PathBuf::from("c.rs")]
+    );
+    assert_eq!(files.deleted, vec![PathBuf::from("b.rs")]);
+
+    Ok(())
+}
+
+#[test]
+fn test_changed_files_outside_repo_errors() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let err = changed_files(temp_dir.path(), &GitSelection::Changed).unwrap_err();
+    assert!(err.to_string().contains("not inside a git repository"));
+    Ok(())
+}
+
+#[test]
+fn test_index_git_changed_indexes_only_modified_file() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let repo_dir = temp_dir.path().join("project");
+    fs::create_dir_all(&repo_dir)?;
+    repo_with_one_change(&repo_dir)?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&repo_dir)
+        .arg("--git-changed")
+        .assert()
+        .success();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("fn")
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("a.rs"));
+    assert!(!stdout.contains("b.rs"), "unchanged file was indexed");
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_in_file_restricts_search_to_one_file() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(&project)?;
+    fs::write(
+        project.join("a.rs"),
+        "pub fn read_header(input: &str) -> usize { input.len() }",
+    )?;
+    // b.rs is the better match for the query
+    fs::write(
+        project.join("b.rs"),
+        "pub fn retry_with_backoff(attempts: u32) -> u32 { attempts * 2 }",
+    )?;
+    // Only a trailing path match on a directory boundary counts
+    fs::write(project.join("data.rs"), "pub fn retry_data() {}")?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("retry with backoff")
+        .arg("--json")
+        .arg("--in-file")
+        .arg("a.rs")
+        .output()?;
+    assert!(output.status.success());
+
+    let results: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    assert!(!results.is_empty());
+    for result in &results {
+        let filename = result["filename"].as_str().unwrap().replace('\\', "/");
+        assert!(filename.ends_with("/a.rs"), "unexpected file {}", filename);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use serde_json::Value;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_include_extensions_indexes_only_listed_types() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project_dir = temp_dir.path().join("project");
+    fs::create_dir_all(project_dir.join("nested"))?;
+    fs::write(project_dir.join("lib.rs"), "fn parse_rust() {}")?;
+    fs::write(project_dir.join("nested/util.rs"), "fn helper_rust() {}")?;
+    fs::write(
+        project_dir.join("script.py"),
+        "def parse_python():\n    pass\n",
+    )?;
+    fs::write(project_dir.join("app.js"), "function parseJs() {}")?;
+
+    let config_path = temp_dir.path().join("code-rag.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "db_path = '{}'\ninclude_extensions = [\"rs\"]\n",
+            db_path.display()
+        ),
+    )?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("RUST_LOG", "off")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project_dir)
+        .arg("--metrics-json")
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    let metrics: Value = stderr
+        .lines()
+        .find_map(|line| serde_json::from_str::<Value>(line).ok())
+        .ok_or_else(|| anyhow::anyhow!("No metrics JSON on stderr: {}", stderr))?;
+    assert_eq!(metrics["files_indexed"], 2);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("RUST_LOG", "off")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("search")
+        .arg("parse")
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    let results: Vec<Value> = serde_json::from_slice(&output.stdout)?;
+    assert!(!results.is_empty());
+    for result in &results {
+        let filename = result["filename"].as_str().unwrap();
+        assert!(filename.ends_with(".rs"), "unexpected file {}", filename);
+    }
+
+    Ok(())
+}
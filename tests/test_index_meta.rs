@@ -0,0 +1,65 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_search_detects_old_index_format() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(&project)?;
+    fs::write(
+        project.join("config.rs"),
+        "pub fn load_config(path: &str) -> String {\n    std::fs::read_to_string(path).unwrap()\n}\n",
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let meta_path = db_path.join("index_meta.json");
+    let mut meta: serde_json::Value = serde_json::from_slice(&fs::read(&meta_path)?)?;
+    assert_eq!(meta["format_version"], 1);
+    assert_eq!(meta["embedding_model"], "nomic-embed-text-v1.5");
+
+    // Pretend the index was written by a release with an older layout
+    meta["format_version"] = 0.into();
+    fs::write(&meta_path, serde_json::to_vec(&meta)?)?;
+
+    let search = |check: &str| {
+        Command::new(env!("CARGO_BIN_EXE_code-rag"))
+            .env("CODE_RAG__DB_PATH", &db_path)
+            .env("CODE_RAG__INDEX_VERSION_CHECK", check)
+            .env("RUST_LOG", "warn")
+            .arg("search")
+            .arg("load config")
+            .arg("--json")
+            .arg("--no-rerank")
+            .output()
+    };
+
+    let output = search("error")?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("index format version is 0 in the index but 1 now"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("code-rag index --force"), "{}", stderr);
+
+    // The default only warns
+    let output = search("warn")?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("may give wrong results"), "{}", stderr);
+    let results: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    assert!(!results.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_indexed_grep_finds_code_deleted_from_disk() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(&project)?;
+    fs::write(
+        project.join("gone.rs"),
+        "fn setup() {}\n\npub fn vanishing_helper_42() -> u32 {\n    42\n}\n",
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    fs::remove_file(project.join("gone.rs"))?;
+
+    let grep = |indexed: bool| -> Result<Vec<String>> {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+        cmd.current_dir(&project)
+            .env("CODE_RAG__DB_PATH", &db_path)
+            .env("RUST_LOG", "off")
+            .arg("grep")
+            .arg(r"vanishing_helper_\d+")
+            .arg("--json");
+        if indexed {
+            cmd.arg("--indexed");
+        }
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+
+    // The file is gone, so walking the disk finds nothing
+    assert!(grep(false)?.is_empty());
+
+    let matches = grep(true)?;
+    assert_eq!(matches.len(), 1, "unexpected matches {:?}", matches);
+    let normalized = matches[0].replace('\\', "/");
+    assert!(
+        normalized.contains("gone.rs:3: pub fn vanishing_helper_42() -> u32 {"),
+        "unexpected match {}",
+        normalized
+    );
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+fn index(db_path: &std::path::Path, path: &std::path::Path, extra: &[&str]) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+    cmd.env("CODE_RAG__DB_PATH", db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(path)
+        .args(extra);
+    cmd
+}
+
+#[test]
+fn test_update_from_empty_dir_triggers_mass_delete_guard() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project_dir = temp_dir.path().join("project");
+    let empty_dir = temp_dir.path().join("empty");
+
+    fs::create_dir_all(&project_dir)?;
+    fs::create_dir_all(&empty_dir)?;
+    fs::write(project_dir.join("a.rs"), "fn alpha() {}")?;
+    fs::write(project_dir.join("b.rs"), "fn beta() {}")?;
+
+    index(&db_path, &project_dir, &[]).assert().success();
+
+    // Pointing the update at the wrong directory must not wipe the index
+    let output = index(&db_path, &empty_dir, &["--update"]).output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-mass-delete"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("alpha")
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("a.rs"));
+
+    // The explicit override performs the cleanup and lists what was removed
+    let output = index(&db_path, &empty_dir, &["--update", "--allow-mass-delete"]).output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Removed 2 stale files"));
+    assert!(stdout.contains("a.rs") && stdout.contains("b.rs"));
+
+    Ok(())
+}
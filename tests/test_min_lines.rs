@@ -0,0 +1,76 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_min_lines_drops_short_chunks() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(&project)?;
+    // short.rs is the better match for the query, but spans only 2 lines
+    fs::write(
+        project.join("short.rs"),
+        "pub fn retry_with_backoff(attempts: u32) -> u32 {\n    attempts * 2 }\n",
+    )?;
+    let body: String = (0..18)
+        .map(|i| format!("    delay += attempts * {};\n", i))
+        .collect();
+    fs::write(
+        project.join("long.rs"),
+        format!(
+            "pub fn retry_delay(attempts: u32) -> u32 {{\n    let mut delay = 0;\n{}    delay }}\n",
+            body
+        ),
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let search = |min_lines: Option<&str>| -> Result<Vec<serde_json::Value>> {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+        command
+            .env("CODE_RAG__DB_PATH", &db_path)
+            .env("RUST_LOG", "off")
+            .arg("search")
+            .arg("retry with backoff")
+            .arg("--json")
+            .arg("--no-rerank");
+        if let Some(n) = min_lines {
+            command.arg("--min-lines").arg(n);
+        }
+        let output = command.output()?;
+        assert!(output.status.success());
+        Ok(serde_json::from_slice(&output.stdout)?)
+    };
+    let file_of = |result: &serde_json::Value| {
+        result["filename"]
+            .as_str()
+            .unwrap()
+            .replace('\\', "/")
+            .rsplit('/')
+            .next()
+            .unwrap()
+            .to_string()
+    };
+
+    let all = search(None)?;
+    assert!(all.iter().any(|r| file_of(r) == "short.rs"));
+
+    let filtered = search(Some("5"))?;
+    assert!(!filtered.is_empty());
+    for result in &filtered {
+        assert_eq!(file_of(result), "long.rs");
+        let lines =
+            result["line_end"].as_i64().unwrap() - result["line_start"].as_i64().unwrap() + 1;
+        assert!(lines >= 5, "{} lines", lines);
+    }
+
+    Ok(())
+}
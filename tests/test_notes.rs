@@ -0,0 +1,56 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_note_is_searchable_and_returned_with_anchor() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    fs::create_dir_all(project.join("src"))?;
+    fs::create_dir_all(project.join(".code-rag"))?;
+    fs::write(
+        project.join("src").join("client.rs"),
+        "pub fn send(req: Request) -> Response {\n    let mut delay = 100;\n    loop {\n        match try_send(&req) {\n            Ok(r) => return r,\n            Err(_) => sleep(delay * 2),\n        }\n    }\n}\n",
+    )?;
+    fs::write(
+        project.join(".code-rag").join("notes.jsonl"),
+        "{\"file\": \"src/client.rs\", \"line_start\": 3, \"line_end\": 7, \"note\": \"Exponential backoff was chosen because the payment gateway rate-limits bursts of retries.\"}\n",
+    )?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("why does the payment gateway rate-limit retries")
+        .arg("--json")
+        .output()?;
+    assert!(output.status.success());
+
+    let results: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    let note = results
+        .iter()
+        .find(|r| r["kind"] == "note")
+        .expect("note not returned");
+    assert_eq!(results[0]["kind"], "note");
+    assert!(note["code"].as_str().unwrap().contains("payment gateway"));
+    let anchor = &note["anchor"];
+    assert!(anchor["filename"]
+        .as_str()
+        .unwrap()
+        .replace('\\', "/")
+        .ends_with("src/client.rs"));
+    assert_eq!(anchor["line_start"], 3);
+    assert_eq!(anchor["line_end"], 7);
+
+    Ok(())
+}
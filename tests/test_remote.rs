@@ -0,0 +1,108 @@
+use anyhow::Result;
+use code_rag::remote::{is_remote, RemoteSource};
+
+#[test]
+fn test_parse_remote_source() -> Result<()> {
+    assert!(is_remote("ssh://dev@build01/srv/app"));
+    assert!(!is_remote("/srv/app"));
+
+    let source = RemoteSource::parse("ssh://dev@build01:2222/srv/app/")?;
+    assert_eq!(
+        source,
+        RemoteSource {
+            user: "dev".to_string(),
+            host: "build01".to_string(),
+            port: 2222,
+            path: "/srv/app".to_string(),
+        }
+    );
+    assert_eq!(
+        source.qualify("/srv/app/src/main.rs"),
+        "build01:/srv/app/src/main.rs"
+    );
+    assert_eq!(RemoteSource::parse("ssh://dev@build01/")?.port, 22);
+
+    for bad in [
+        "ssh://build01/srv/app",
+        "ssh://dev@build01",
+        "ssh://dev@build01:ssh/srv",
+    ] {
+        let err = RemoteSource::parse(bad).unwrap_err();
+        assert!(err.to_string().contains("Invalid remote source"), "{}", err);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "sftp"))]
+#[test]
+fn test_remote_index_needs_sftp_feature() -> Result<()> {
+    use assert_cmd::Command;
+
+    let temp_dir = tempfile::TempDir::new()?;
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", temp_dir.path().join("lancedb"))
+        .env("RUST_LOG", "off")
+        .arg("index")
+        .arg("--path")
+        .arg("ssh://dev@build01/srv/app")
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("built without the `sftp` feature"),
+        "{}",
+        stderr
+    );
+    Ok(())
+}
+
+/// Indexes a directory served by a local SFTP server and searches it.
+///
+/// Set `CODE_RAG_TEST_SFTP_URL` to an `ssh://user@127.0.0.1:port/dir` source whose
+/// directory is writable locally, with its host key in `CODE_RAG__SSH_KNOWN_HOSTS` (or
+/// `~/.ssh/known_hosts`) and credentials as for `code-rag index`, then run
+/// `cargo test --features sftp --test test_remote -- --ignored`.
+#[cfg(feature = "sftp")]
+#[test]
+#[ignore]
+fn test_index_remote_directory_over_sftp() -> Result<()> {
+    use assert_cmd::Command;
+    use std::fs;
+
+    let url = std::env::var("CODE_RAG_TEST_SFTP_URL")?;
+    let source = RemoteSource::parse(&url)?;
+    fs::create_dir_all(format!("{}/src", source.path))?;
+    let remote_file = format!("{}/src/remote_only.rs", source.path);
+    fs::write(
+        &remote_file,
+        "pub fn reconcile_remote_ledger(entries: &[i64]) -> i64 { entries.iter().sum() }\n",
+    )?;
+
+    let temp_dir = tempfile::TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&url)
+        .assert()
+        .success();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("reconcile remote ledger")
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    let results: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        results[0]["filename"].as_str(),
+        Some(source.qualify(&remote_file).as_str())
+    );
+
+    fs::remove_file(&remote_file)?;
+    Ok(())
+}
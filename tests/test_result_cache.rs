@@ -0,0 +1,101 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn search(db_path: &Path, extra_env: &[(&str, &Path)]) -> Result<(String, String)> {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_code-rag"));
+    cmd.env("CODE_RAG__DB_PATH", db_path)
+        .env("CODE_RAG__RESULT_CACHE", "true")
+        .env("RUST_LOG", "off");
+    for (key, value) in extra_env {
+        cmd.env(key, value);
+    }
+    let output = cmd
+        .arg("search")
+        .arg("parse config")
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    Ok((
+        String::from_utf8(output.stdout)?,
+        String::from_utf8(output.stderr)?,
+    ))
+}
+
+#[test]
+fn test_result_cache_serves_repeated_search_without_models() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let project = temp_dir.path().join("project");
+    let empty_model_cache = temp_dir.path().join("no-models");
+    fs::create_dir_all(&project)?;
+    fs::create_dir_all(&empty_model_cache)?;
+    fs::write(
+        project.join("config.rs"),
+        "pub fn parse_config(path: &str) -> Config { load_toml(path) }",
+    )?;
+    fs::write(project.join("log.rs"), "pub fn write_log(line: &str) {}")?;
+
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .assert()
+        .success();
+
+    let (first, _) = search(&db_path, &[])?;
+    assert!(first.contains("config.rs"));
+    assert!(db_path.join("result_cache").exists());
+
+    // A new process that cannot load the embedding model: a cache miss would fall
+    // back to keyword search and report degraded mode
+    let (second, stderr) = search(
+        &db_path,
+        &[
+            ("CODE_RAG__OFFLINE", Path::new("true")),
+            ("CODE_RAG__MODEL_CACHE_DIR", &empty_model_cache),
+        ],
+    )?;
+    assert!(
+        !stderr.contains("Degraded mode"),
+        "cache missed: {}",
+        stderr
+    );
+    assert_eq!(first, second);
+
+    // Any setting that changes the results is part of the key
+    let (_, stderr) = search(
+        &db_path,
+        &[
+            ("CODE_RAG__OFFLINE", Path::new("true")),
+            ("CODE_RAG__MODEL_CACHE_DIR", &empty_model_cache),
+            ("CODE_RAG__NORMALIZE_IDENTIFIERS", Path::new("true")),
+        ],
+    )?;
+    assert!(stderr.contains("Degraded mode"));
+
+    // Re-indexing changes the index version and invalidates the entry
+    fs::write(project.join("extra.rs"), "pub fn extra() {}")?;
+    Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&project)
+        .arg("--update")
+        .assert()
+        .success();
+    let (_, stderr) = search(
+        &db_path,
+        &[
+            ("CODE_RAG__OFFLINE", Path::new("true")),
+            ("CODE_RAG__MODEL_CACHE_DIR", &empty_model_cache),
+        ],
+    )?;
+    assert!(stderr.contains("Degraded mode"));
+
+    Ok(())
+}
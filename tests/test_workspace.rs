@@ -160,3 +160,50 @@ fn test_workspace_isolation() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[allow(deprecated)]
+fn test_workspace_custom_db_path() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let custom_db = temp_dir.path().join("other_volume").join("big_index");
+    let project_dir = temp_dir.path().join("big_project");
+
+    fs::create_dir_all(&project_dir)?;
+    fs::write(project_dir.join("big.rs"), "fn big_workspace_fn() {}")?;
+
+    let config_path = temp_dir.path().join("code-rag.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "db_path = '{}'\n\n[workspaces]\nbig = {{ source = '{}', db = '{}' }}\n",
+            db_path.display(),
+            project_dir.display(),
+            custom_db.display()
+        ),
+    )?;
+
+    // No --workspace: indexes every configured workspace
+    Command::cargo_bin("code-rag")?
+        .arg("--config")
+        .arg(&config_path)
+        .arg("index")
+        .assert()
+        .success();
+
+    assert!(custom_db.join("code_chunks.lance").exists());
+    assert!(!db_path.join("big").exists());
+
+    Command::cargo_bin("code-rag")?
+        .arg("--config")
+        .arg(&config_path)
+        .arg("search")
+        .arg("big_workspace_fn")
+        .arg("--workspace")
+        .arg("big")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("big.rs"));
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use assert_cmd::Command;
+use code_rag::git::worktrees;
+use git2::{Repository, RepositoryInitOptions, Signature, WorktreeAddOptions};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Writes `auth.rs` in the working tree of `repo` and commits it on the checked-out branch.
+fn commit_auth(repo: &Repository, code: &str) -> Result<()> {
+    let workdir = repo.workdir().unwrap();
+    fs::write(workdir.join("auth.rs"), code)?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new("auth.rs"))?;
+    index.write()?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let sig = Signature::now("test", "test@example.com")?;
+    let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, "update auth", &tree, &parents)?;
+    Ok(())
+}
+
+fn search_workspace(db_path: &Path, workspace: &str) -> Result<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", db_path)
+        .env("RUST_LOG", "off")
+        .arg("search")
+        .arg("check the user's password")
+        .arg("--workspace")
+        .arg(workspace)
+        .arg("--json")
+        .arg("--no-rerank")
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_worktrees_index_each_branch_as_workspace() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let db_path = temp_dir.path().join("lancedb");
+    let main_dir = temp_dir.path().join("app");
+    let feature_dir = temp_dir.path().join("app-feature-auth");
+
+    let mut init = RepositoryInitOptions::new();
+    init.initial_head("main");
+    let repo = Repository::init_opts(&main_dir, &init)?;
+    commit_auth(
+        &repo,
+        "pub fn check_password_plain(input: &str, stored: &str) -> bool {\n    input == stored\n}\n",
+    )?;
+
+    // feature/auth diverges in its own worktree
+    let head = repo.head()?.peel_to_commit()?;
+    let branch = repo.branch("feature/auth", &head, false)?;
+    let mut add = WorktreeAddOptions::new();
+    add.reference(Some(branch.get()));
+    repo.worktree("feature-auth", &feature_dir, Some(&add))?;
+    commit_auth(
+        &Repository::open(&feature_dir)?,
+        "pub fn check_password_bcrypt(input: &str, hash: &str) -> bool {\n    bcrypt::verify(input, hash).unwrap_or(false)\n}\n",
+    )?;
+
+    let trees = worktrees(&main_dir)?;
+    let names: Vec<&str> = trees.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["feature-auth", "main"]);
+    assert_eq!(trees[0].branch.as_deref(), Some("feature/auth"));
+    assert_eq!(trees, worktrees(&feature_dir)?);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_code-rag"))
+        .env("CODE_RAG__DB_PATH", &db_path)
+        .arg("index")
+        .arg("--path")
+        .arg(&main_dir)
+        .arg("--worktrees")
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Workspace 'main'"), "{}", stdout);
+    assert!(stdout.contains("Workspace 'feature-auth'"), "{}", stdout);
+
+    let main = search_workspace(&db_path, "main")?;
+    assert!(main.contains("check_password_plain"));
+    assert!(!main.contains("check_password_bcrypt"));
+
+    let feature = search_workspace(&db_path, "feature-auth")?;
+    assert!(feature.contains("check_password_bcrypt"));
+    assert!(!feature.contains("check_password_plain"));
+
+    Ok(())
+}
+
+#[test]
+fn test_worktree_names_that_collide_get_a_hash_suffix() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let main_dir = temp_dir.path().join("app");
+
+    let mut init = RepositoryInitOptions::new();
+    init.initial_head("default");
+    let repo = Repository::init_opts(&main_dir, &init)?;
+    commit_auth(&repo, "pub fn check() {}\n")?;
+
+    // Both branches would be named feature-auth
+    let head = repo.head()?.peel_to_commit()?;
+    for (branch_name, dir) in [("feature/auth", "slash"), ("feature-auth", "dash")] {
+        let branch = repo.branch(branch_name, &head, false)?;
+        let mut add = WorktreeAddOptions::new();
+        add.reference(Some(branch.get()));
+        repo.worktree(dir, &temp_dir.path().join(dir), Some(&add))?;
+    }
+
+    let trees = worktrees(&main_dir)?;
+    let names: Vec<&str> = trees.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names.len(), 3);
+    // `default` is the workspace of the unnamed index
+    assert!(!names.contains(&"default"), "{:?}", names);
+    assert!(!names.contains(&"feature-auth"), "{:?}", names);
+    for name in &names {
+        let (base, hash) = name.rsplit_once('-').unwrap();
+        assert!(["default", "feature-auth"].contains(&base), "{}", name);
+        assert_eq!(hash.len(), 8, "{}", name);
+    }
+    assert_ne!(names[1], names[2]);
+
+    // The same worktree keeps its name
+    assert_eq!(trees, worktrees(&main_dir)?);
+    Ok(())
+}